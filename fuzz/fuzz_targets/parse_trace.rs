@@ -0,0 +1,17 @@
+//! Feeds arbitrary bytes to the trace file parser, which must never panic.
+//! Run with `cargo +nightly fuzz run parse_trace` from the repository root.
+#![no_main]
+
+use hitrace_bench::parser::{self, HitraceVersion, ParseErrorPolicy};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for policy in [ParseErrorPolicy::Fail, ParseErrorPolicy::Skip] {
+        let _ = parser::parse_bytes(data, policy);
+    }
+    if let Ok(line) = std::str::from_utf8(data) {
+        for version in [HitraceVersion::V1, HitraceVersion::V5, HitraceVersion::Unknown] {
+            let _ = parser::parse_line(version, line);
+        }
+    }
+});
@@ -0,0 +1,337 @@
+//! Archived sessions and the `analyze` subcommand, which recomputes their metrics with the current filters.
+//!
+//! With `--artifacts` every try directory also gets the trace of the try and a `try.json` with the run it belongs
+//! to, so the artifacts directory of a session is an archive of it. `analyze` reads a directory of such archives,
+//! analyzes all their tries in parallel and writes one report per session, i.e., to fill the history of a filter
+//! that was added after the sessions ran.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use anyhow::{Context, Result, anyhow};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    analyze_trace_file,
+    args::{AnalyzeArgs, Args},
+    canonical,
+    json_report::RunReport,
+    parser,
+    runconfig::RunConfig,
+    tries::TryRow,
+    utils::RunResults,
+};
+
+/// Name of the archived trace in a try directory
+pub(crate) const TRACE_FILE: &str = "trace.ftrace";
+
+/// Name of the metadata in a try directory
+const METADATA_FILE: &str = "try.json";
+
+/// What we need to know about an archived try to analyze it again
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct TryMetadata {
+    /// Url of the run config of the try
+    url: String,
+    /// Name of the run config of the try
+    name: Option<String>,
+    /// When the try started on the host
+    #[serde(with = "time::serde::rfc3339")]
+    started: OffsetDateTime,
+}
+
+/// Writes the metadata of a try of `run_config` that started at `started` into its artifacts directory `dir`
+pub(crate) fn archive_metadata(
+    dir: &Path,
+    run_config: &RunConfig,
+    started: OffsetDateTime,
+) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+    let metadata = TryMetadata {
+        url: run_config.run_args.url.clone(),
+        name: run_config.name.clone(),
+        started,
+    };
+    let path = dir.join(METADATA_FILE);
+    fs::write(&path, serde_json::to_string_pretty(&metadata)?)
+        .with_context(|| format!("Could not write {}", path.display()))
+}
+
+/// Copies the received `trace` of a try into its artifacts directory `dir`
+pub(crate) fn archive_trace(dir: &Path, trace: &Path) -> Result<()> {
+    fs::copy(trace, dir.join(TRACE_FILE))
+        .with_context(|| format!("Could not archive {}", trace.display()))?;
+    Ok(())
+}
+
+/// A try found in a directory of archived sessions
+#[derive(Debug)]
+struct ArchivedTry {
+    /// Name of the session directory
+    session: String,
+    /// Directory of the try
+    dir: PathBuf,
+    metadata: TryMetadata,
+}
+
+/// The sorted subdirectories of `dir`
+fn subdirectories(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Could not read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Adds all archived tries below `dir` to `tries`
+fn find_tries(session: &str, dir: &Path, tries: &mut Vec<ArchivedTry>) -> Result<()> {
+    let metadata_path = dir.join(METADATA_FILE);
+    if metadata_path.exists() {
+        let content = fs::read_to_string(&metadata_path)
+            .with_context(|| format!("Could not read {}", metadata_path.display()))?;
+        let metadata = serde_json::from_str(&content)
+            .with_context(|| format!("Could not parse {}", metadata_path.display()))?;
+        tries.push(ArchivedTry {
+            session: session.to_owned(),
+            dir: dir.to_owned(),
+            metadata,
+        });
+        return Ok(());
+    }
+    for subdirectory in subdirectories(dir)? {
+        find_tries(session, &subdirectory, tries)?;
+    }
+    Ok(())
+}
+
+/// All archived tries in the session directories of `sessions`, in order of sessions
+fn archived_tries(sessions: &Path) -> Result<Vec<ArchivedTry>> {
+    let mut tries = Vec::new();
+    for dir in subdirectories(sessions)? {
+        let session = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        find_tries(&session, &dir, &mut tries)?;
+    }
+    Ok(tries)
+}
+
+/// Index of the run config an archived try belongs to, by name and url
+fn run_config_index(run_configs: &[RunConfig], metadata: &TryMetadata) -> Option<usize> {
+    run_configs
+        .iter()
+        .position(|r| r.run_args.url == metadata.url && r.name == metadata.name)
+}
+
+/// Recomputes the metrics of an archived try with `run_config`
+fn analyze_try(run_config: &RunConfig, archived: &ArchivedTry) -> Result<RunResults> {
+    let trace_file = parser::read_file(
+        &archived.dir.join(TRACE_FILE),
+        run_config.args.parse_error_policy,
+    )?;
+    let mut results = RunResults::default();
+    analyze_trace_file(run_config, &trace_file, &mut results)?;
+    Ok(results)
+}
+
+/// The recomputed reports of an archived session
+#[derive(Debug, Serialize)]
+struct SessionReport {
+    session: String,
+    /// Start of the first try of the session
+    #[serde(with = "time::serde::rfc3339::option")]
+    started: Option<OffsetDateTime>,
+    runs: Vec<RunReport>,
+    /// Tries we could not analyze with their directory and the reason
+    skipped: Vec<(PathBuf, String)>,
+}
+
+/// Analyzes `tries` with `run_configs` on `jobs` threads. The results are in the order of `tries`.
+fn analyze_tries(
+    run_configs: &[RunConfig],
+    tries: &[ArchivedTry],
+    jobs: usize,
+) -> Vec<Result<RunResults>> {
+    let next = AtomicUsize::new(0);
+    let next = &next;
+    let mut results: Vec<(usize, Result<RunResults>)> = thread::scope(|s| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                s.spawn(move || {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(archived) = tries.get(index) else {
+                            break done;
+                        };
+                        let result = match run_config_index(run_configs, &archived.metadata) {
+                            Some(i) => analyze_try(&run_configs[i], archived),
+                            None => Err(anyhow!(
+                                "No run config with url {} and name {:?}",
+                                archived.metadata.url,
+                                archived.metadata.name
+                            )),
+                        };
+                        done.push((index, result));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Analyzing a try panicked"))
+            .collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Groups the analyzed `tries` into one report per session and run config
+fn session_reports(
+    run_configs: &[RunConfig],
+    tries: &[ArchivedTry],
+    results: Vec<Result<RunResults>>,
+) -> Vec<SessionReport> {
+    let mut reports: Vec<SessionReport> = Vec::new();
+    let mut session_results: Vec<Option<RunResults>> = Vec::new();
+    let finish = |report: &mut SessionReport, session_results: &mut Vec<Option<RunResults>>| {
+        report.runs = run_configs
+            .iter()
+            .zip(session_results.drain(..))
+            .filter_map(|(run_config, results)| Some(RunReport::new(run_config, results?, &[])))
+            .collect();
+    };
+    for (archived, result) in tries.iter().zip(results) {
+        if reports.last().is_none_or(|r| r.session != archived.session) {
+            if let Some(report) = reports.last_mut() {
+                finish(report, &mut session_results);
+            }
+            reports.push(SessionReport {
+                session: archived.session.clone(),
+                started: None,
+                runs: Vec::new(),
+                skipped: Vec::new(),
+            });
+            session_results = run_configs.iter().map(|_| None).collect();
+        }
+        let report = reports.last_mut().expect("Pushed above");
+        let started = archived.metadata.started;
+        report.started = Some(report.started.map_or(started, |s| s.min(started)));
+        match (result, run_config_index(run_configs, &archived.metadata)) {
+            (Ok(try_results), Some(index)) => {
+                let run_results = session_results[index].get_or_insert_with(RunResults::default);
+                run_results.tries.push(TryRow::new(&try_results, started));
+                run_results.merge(try_results);
+            }
+            (result, _) => {
+                let reason = result.err().map_or_else(String::new, |e| format!("{e:#}"));
+                warn!("Skipping {}: {reason}", archived.dir.display());
+                report.skipped.push((archived.dir.clone(), reason));
+            }
+        }
+    }
+    if let Some(report) = reports.last_mut() {
+        finish(report, &mut session_results);
+    }
+    reports
+}
+
+/// Recomputes the metrics of all archived sessions in `analyze_args.sessions` with `run_configs` and writes the
+/// reports of all sessions as one json array
+pub(crate) fn run(
+    args: &Args,
+    analyze_args: &AnalyzeArgs,
+    run_configs: &[RunConfig],
+) -> Result<()> {
+    let tries = archived_tries(&analyze_args.sessions)?;
+    let jobs = analyze_args
+        .jobs
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, |jobs| jobs.get());
+    info!("Analyzing {} archived tries on {jobs} threads", tries.len());
+    let results = analyze_tries(run_configs, &tries, jobs);
+    let history = canonical::to_string(
+        &session_reports(run_configs, &tries, results),
+        args.canonical_json,
+    )?;
+    match analyze_args.output {
+        Some(ref output) => fs::write(output, history)
+            .with_context(|| format!("Could not write {}", output.display())),
+        None => {
+            println!("{history}");
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn test_analyze_archived_sessions() {
+    use crate::{args::RunArgs, filter::Filter, trace::Trace};
+    use time::macros::datetime;
+
+    let filter = Filter {
+        name: String::from("Load"),
+        first: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
+        last: Box::new(|t: &Trace| t.function.contains("PageLoadEndedPrompt")),
+        measure: None,
+        expected_range: None,
+    };
+    let trace = PathBuf::from("testdata/v5_1_1.ftrace");
+    let run_config = RunConfig::new(
+        Args::test_default(trace.clone()),
+        RunArgs::default(),
+        vec![filter],
+        vec![],
+    );
+    let sessions =
+        std::env::temp_dir().join(format!("hitrace-bench-sessions-{}", std::process::id()));
+    let try_dir = |session: &str, name: &str| sessions.join(session).join("run").join(name);
+    for (session, name, started) in [
+        ("a", "try-1", datetime!(2025-06-01 12:00:05 UTC)),
+        ("a", "try-2", datetime!(2025-06-01 12:00:00 UTC)),
+        ("b", "try-1", datetime!(2025-06-02 12:00:00 UTC)),
+    ] {
+        let dir = try_dir(session, name);
+        archive_metadata(&dir, &run_config, started).unwrap();
+        archive_trace(&dir, &trace).unwrap();
+    }
+    // An archive of a run we no longer have
+    let mut other = RunConfig::new(
+        Args::test_default(trace.clone()),
+        RunArgs::default(),
+        vec![],
+        vec![],
+    );
+    other.name = Some(String::from("removed"));
+    archive_metadata(&try_dir("b", "try-2"), &other, OffsetDateTime::UNIX_EPOCH).unwrap();
+
+    let run_configs = [run_config];
+    let tries = archived_tries(&sessions).unwrap();
+    assert_eq!(tries.len(), 4);
+    let results = analyze_tries(&run_configs, &tries, 2);
+    let reports = serde_json::to_value(session_reports(&run_configs, &tries, results)).unwrap();
+    fs::remove_dir_all(&sessions).unwrap();
+
+    assert_eq!(reports[0]["session"], "a");
+    assert_eq!(reports[0]["started"], "2025-06-01T12:00:00Z");
+    assert_eq!(
+        reports[0]["runs"][0]["metrics"]["Load"]["samples"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+    assert_eq!(reports[1]["session"], "b");
+    assert_eq!(reports[1]["runs"][0]["tries"].as_array().unwrap().len(), 1);
+    assert_eq!(reports[1]["skipped"].as_array().unwrap().len(), 1);
+}
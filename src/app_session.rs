@@ -0,0 +1,99 @@
+//! Splitting the trace of a try at app restarts. When the app crashes and comes back during a try, the traces of
+//! both processes would confuse the filters, so every app session, the traces of one main process, gets its own
+//! results and every restart counts as a crash.
+use crate::{trace::Trace, utils::RunResults};
+
+/// The first trace of a started app, its pid is the pid of the main process
+pub(crate) const APP_START_MARKER: &str = "on_surface_created_cb";
+
+/// The traces of one app session
+#[derive(Debug)]
+pub(crate) struct AppSession<'a> {
+    /// `None` for the session the try started with, whose results keep their names
+    pub(crate) label: Option<String>,
+    /// The pid of the main process, `None` if the app never marked its start
+    pub(crate) pid: Option<&'a str>,
+    pub(crate) traces: &'a [Trace],
+}
+
+impl AppSession<'_> {
+    /// Appends the label to all result names of `results`
+    pub(crate) fn label_results(&self, mut results: RunResults) -> RunResults {
+        let Some(ref label) = self.label else {
+            return results;
+        };
+        results.rename_keys(|key| format!("{key} ({label})"));
+        results
+    }
+}
+
+/// Splits `traces` wherever the app marks its start from a new main process. A start from the same process,
+/// i.e., a recreated surface, is not a restart.
+pub(crate) fn split(traces: &[Trace]) -> Vec<AppSession<'_>> {
+    let starts = traces
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.function.contains(APP_START_MARKER))
+        .map(|(i, t)| (i, t.number.as_str()));
+    let mut sessions = vec![AppSession {
+        label: None,
+        pid: None,
+        traces,
+    }];
+    let mut start = 0;
+    for (i, pid) in starts {
+        let current = sessions.last_mut().expect("There is always a session");
+        match current.pid {
+            None => current.pid = Some(pid),
+            Some(current_pid) if current_pid == pid => {}
+            Some(_) => {
+                current.traces = &traces[start..i];
+                start = i;
+                sessions.push(AppSession {
+                    label: Some(format!("session {}", sessions.len() + 1)),
+                    pid: Some(pid),
+                    traces: &traces[i..],
+                });
+            }
+        }
+    }
+    sessions
+}
+
+#[test]
+fn test_split() {
+    use crate::parser::{HitraceVersion, parse_line};
+
+    let traces: Vec<Trace> = [
+        (10, "B|7|H:on_surface_created_cb|M62"),
+        (11, "B|7|H:load status changed Head|M62"),
+        (12, "B|7|H:on_surface_created_cb|M62"),
+        (13, "B|9|H:on_surface_created_cb|M62"),
+        (14, "B|1370|H:RSMainThread::DoComposition|M62"),
+        (15, "B|11|H:on_surface_created_cb|M62"),
+    ]
+    .iter()
+    .map(|(seconds, marker)| {
+        let line =
+            format!("<...>-1   (-------) [010] .... {seconds}.0: tracing_mark_write: {marker}");
+        parse_line(HitraceVersion::V5, &line).unwrap().unwrap()
+    })
+    .collect();
+    let sessions = split(&traces);
+    let summary: Vec<_> = sessions
+        .iter()
+        .map(|s| (s.label.as_deref(), s.pid, s.traces.len()))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (None, Some("7"), 3),
+            (Some("session 2"), Some("9"), 2),
+            (Some("session 3"), Some("11"), 1)
+        ]
+    );
+
+    let sessions = split(&traces[1..2]);
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].pid, None);
+}
@@ -1,16 +1,38 @@
 use anyhow::anyhow;
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    num::NonZeroUsize,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
-use clap::{Parser, Subcommand};
-use serde::Deserialize;
+use clap::{Parser, Subcommand, ValueEnum};
+use env_logger::WriteStyle;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use yansi::Condition;
 
-#[derive(Clone, Parser, Debug)]
+use crate::{
+    bencher::{MetricNameTemplate, Precision},
+    change_point, frames,
+    host_config::RunDefaults,
+    navigation,
+    parser::ParseErrorPolicy,
+    script::{Step, deserialize_script},
+    units::UnitSetting,
+};
+
+#[derive(Clone, Parser, Debug, Serialize)]
 #[command(version, about, long_about = None)]
 pub(crate) struct Args {
     /// Completely describes runs in the a file with the `RunConfig` json format.
     #[arg(short, long)]
     pub(crate) run_file: Option<PathBuf>,
 
+    /// Fail on unknown fields in the run file, i.e., `point_fliters`, instead of warning about them
+    #[arg(long, default_value_t = false)]
+    pub(crate) strict_config: bool,
+
     /// Allowed to move files to a directory on the phone.
     #[arg(long, default_value_t = false)]
     pub(crate) is_rooted: bool,
@@ -28,46 +50,571 @@ pub(crate) struct Args {
     #[arg(long, default_value_t = false)]
     pub(crate) bencher: bool,
 
+    /// The output format of the normal mode. With `json` the results of all runs, including every sample and
+    /// error, are printed as a json array once the session finished.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub(crate) format: OutputFormat,
+
+    /// Print json with sorted keys, a fixed number of decimals and without the working and home directory, so it
+    /// only changes if the results do. Applies to `--format json` and the bencher output.
+    #[arg(long, default_value_t = false)]
+    pub(crate) canonical_json: bool,
+
+    /// The order of the metrics in the text output, `value` puts the largest average first
+    #[arg(long, value_enum, default_value_t = SortBy::Name)]
+    pub(crate) sort_by: SortBy,
+
+    /// When to color the output and the log
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    pub(crate) color: ColorChoice,
+
+    /// Print memory as raw numbers of bytes instead of binary units
+    #[arg(long, default_value_t = false)]
+    pub(crate) no_humanize: bool,
+
+    /// Display a metric in a unit on the console, `[METRIC=]UNIT` with one of ns, us, ms, s, KiB, MiB, kB, MB or
+    /// raw. Without a metric the unit applies to all metrics it fits. Can be given several times.
+    #[arg(long = "unit")]
+    #[serde(serialize_with = "serialize_strings")]
+    pub(crate) units: Vec<UnitSetting>,
+
+    /// Show the progress and the metrics in an interactive terminal UI while the session runs.
+    /// The log goes to `hitrace-bench.log` and the report is printed after closing the UI.
+    #[arg(long, default_value_t = false)]
+    pub(crate) tui: bool,
+
+    /// In the normal output, print a table with the metrics of every try below the results of a run
+    #[arg(long, default_value_t = false)]
+    pub(crate) per_try: bool,
+
+    /// Print which traces every filter matched in the first try of a run and why a filter found nothing
+    #[arg(long, default_value_t = false)]
+    pub(crate) explain_filters: bool,
+
+    /// Add the standard deviation and the number of samples as extra measures to every bencher entry
+    #[arg(long, default_value_t = false)]
+    pub(crate) bencher_statistics: bool,
+
+    /// Add the value of every try as `samples` to every bencher measure, so it can be analyzed again later
+    #[arg(long, default_value_t = false)]
+    pub(crate) bencher_samples: bool,
+
+    /// Round the values of a measure in the bencher output to whole units, `MEASURE=UNIT` with one of ns, us, ms,
+    /// s, KiB, MiB, kB, MB, i.e., `Latency=us` or `Memory=KiB`. Can be given several times.
+    #[arg(long)]
+    #[serde(serialize_with = "serialize_strings")]
+    pub(crate) bencher_precision: Vec<Precision>,
+
+    /// What to do if several run configs produce a metric with the same name in the bencher output, the
+    /// `merge_policy` of a run config overrides it
+    #[arg(long, value_enum, default_value_t = DuplicateMetrics::Suffix)]
+    pub(crate) duplicate_metrics: DuplicateMetrics,
+
+    /// Name the metrics of every run in the bencher output after this template instead of the whole url of the
+    /// run, i.e., `{host}/{path}/{filter}`. See `bencher::MetricNameTemplate` for the placeholders.
+    #[arg(long)]
+    pub(crate) metric_name: Option<MetricNameTemplate>,
+
+    /// POST a JSON summary of all results to this url at the end of the session
+    #[arg(long)]
+    #[serde(serialize_with = "serialize_redacted")]
+    pub(crate) webhook_url: Option<String>,
+
+    /// Repeat the whole set of runs this many times in round-robin order, each run doing its `tries` per round.
+    /// This spreads drift of the device state evenly over the runs we compare.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) rounds: u32,
+
+    /// Write a csv comparing every metric across all runs to this file
+    #[arg(long)]
+    pub(crate) session_summary: Option<PathBuf>,
+
+    /// Stop the session if the first try of a run finds nothing for all of its filters and point filters
+    #[arg(long, default_value_t = false)]
+    pub(crate) fail_fast: bool,
+
+    /// Metrics with fewer valid samples than this, i.e., because tries failed, are reported as insufficient data
+    /// and left out of the bencher output
+    #[arg(long, default_value_t = 1)]
+    pub(crate) min_samples: usize,
+
+    /// Drop the tries of a metric that drift, i.e., get slower each try, except the first one of the drifting tail
+    #[arg(long, default_value_t = false)]
+    pub(crate) discard_drift: bool,
+
+    /// Write a GitHub PR comment payload comparing the results with `--baseline` to this file.
+    /// Only available with `--bencher`.
+    #[arg(long, requires_all = ["baseline", "bencher"])]
+    pub(crate) pr_comment: Option<PathBuf>,
+
+    /// A bench.json of the base branch to compare against
+    #[arg(long)]
+    pub(crate) baseline: Option<PathBuf>,
+
+    /// Fail the session if the mean of a metric is outside of its threshold in this file from `thresholds`
+    #[arg(long)]
+    pub(crate) thresholds: Option<PathBuf>,
+
+    /// Stop starting new tries after this many minutes and report what we have so far
+    #[arg(long)]
+    pub(crate) max_session_minutes: Option<u64>,
+
+    /// When the session has to stop starting new tries, from `max_session_minutes`
+    #[arg(skip)]
+    #[serde(skip)]
+    pub(crate) session_deadline: Option<Instant>,
+
+    /// Receive and analyze the trace of a try while the next try already runs. Not with `--wait-device` or
+    /// `--reboot-every`, a reboot would kill receiving the trace of the previous try, so these run one try after
+    /// the other.
+    #[arg(long, default_value_t = false)]
+    pub(crate) pipeline: bool,
+
+    /// Discard tries during which processes other than the app used more than this percent of the cpu
+    #[arg(long, value_name = "PERCENT")]
+    pub(crate) max_background_load: Option<f64>,
+
+    /// Reboot the device after every this many tries, so no state of a try pollutes the next ones.
+    /// The device has to unlock with a swipe.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub(crate) reboot_every: Option<u32>,
+
+    /// If a device drops off during a try, wait this many seconds for it to come back and repeat the try
+    #[arg(long, value_name = "SECS")]
+    pub(crate) wait_device: Option<u64>,
+
+    /// Repeat at most this many tries in the whole session, over all runs and devices. The final summary shows how
+    /// much of it was used.
+    #[arg(long, value_name = "RETRIES")]
+    pub(crate) max_total_retries: Option<usize>,
+
+    /// Archive the trace, the log and the profiler output of the app of every try in this directory,
+    /// in `<run name or url>/try-<n>`, every hdc command of the session in `commands.log` and every error of the
+    /// session in `errors.json`.
+    /// The `analyze` subcommand can recompute the metrics of such archives.
+    #[arg(long)]
+    pub(crate) artifacts: Option<PathBuf>,
+
+    /// The hdc binary to use instead of the one in the path
+    #[arg(long)]
+    pub(crate) hdc_path: Option<PathBuf>,
+
+    /// Print the configuration after applying the host config, the run file and the command line as json and exit
+    #[arg(long, default_value_t = false)]
+    pub(crate) print_config: bool,
+
+    /// Select a profile of the host config, i.e., `ci`. See `host_config.rs`.
+    #[arg(long)]
+    pub(crate) profile: Option<String>,
+
+    /// Defaults for all runs from the host config and its profile
+    #[arg(skip)]
+    #[serde(skip)]
+    pub(crate) run_defaults: RunDefaults,
+
     /// Read traces from a file
     #[arg(long)]
     pub(crate) trace_file: Option<PathBuf>,
 
+    /// Cache the parsed traces of `trace_file` so analyzing it again with different filters is fast
+    #[arg(long, default_value_t = false)]
+    pub(crate) cache_traces: bool,
+
+    /// Fail if more than this ratio of `tracing_mark_write` lines could not be parsed.
+    #[arg(long, default_value_t = 0.1)]
+    pub(crate) max_unmatched_ratio: f64,
+
+    /// What to do with trace lines that look like traces but cannot be parsed
+    #[arg(long, value_enum, default_value_t = ParseErrorPolicy::Fail)]
+    pub(crate) parse_error_policy: ParseErrorPolicy,
+
+    /// Whether tries with filter errors or crashes fail the session. Runs that fail as a whole always do.
+    #[arg(long, value_enum, default_value_t = ErrorPolicy::Warn)]
+    pub(crate) error_policy: ErrorPolicy,
+
     #[clap(subcommand)]
+    #[serde(skip)]
     per_run: Option<PerRun>,
 }
 
 impl Args {
+    /// Starts the time budget of the session. A budget too large for a deadline is no budget.
+    pub(crate) fn start_session_budget(&mut self) {
+        self.session_deadline = self
+            .max_session_minutes
+            .and_then(|m| m.checked_mul(60))
+            .and_then(|secs| Instant::now().checked_add(Duration::from_secs(secs)));
+    }
+
+    /// If the time budget of the session is used up and we should not start new tries
+    pub(crate) fn budget_exhausted(&self) -> bool {
+        self.session_deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
     #[cfg(test)]
     pub(crate) fn test_default(path: PathBuf) -> Args {
         Args {
             run_file: None,
+            strict_config: false,
             is_rooted: false,
             quiet: false,
             prepend: None,
             bencher: true,
+            bencher_statistics: false,
+            bencher_samples: false,
+            bencher_precision: Vec::new(),
+            duplicate_metrics: DuplicateMetrics::Suffix,
+            metric_name: None,
+            per_try: false,
+            explain_filters: false,
+            format: OutputFormat::Text,
+            canonical_json: false,
+            sort_by: SortBy::Name,
+            color: ColorChoice::Auto,
+            no_humanize: false,
+            units: Vec::new(),
+            tui: false,
+            webhook_url: None,
+            rounds: 1,
+            min_samples: 1,
+            fail_fast: false,
+            session_summary: None,
+            discard_drift: false,
+            pr_comment: None,
+            baseline: None,
+            thresholds: None,
+            max_session_minutes: None,
+            session_deadline: None,
+            pipeline: false,
+            max_background_load: None,
+            reboot_every: None,
+            wait_device: None,
+            max_total_retries: None,
+            artifacts: None,
+            hdc_path: None,
+            print_config: false,
+            profile: None,
+            run_defaults: RunDefaults::default(),
             trace_file: Some(path),
+            cache_traces: false,
+            max_unmatched_ratio: 0.1,
+            parse_error_policy: ParseErrorPolicy::Fail,
+            error_policy: ErrorPolicy::Warn,
             per_run: None,
         }
     }
 }
 
+/// How the normal mode prints the results
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum OutputFormat {
+    /// Human readable text
+    Text,
+    /// A json array with one entry per run
+    Json,
+}
+
+/// The order of the metrics in the text output
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum SortBy {
+    /// Alphabetically by name
+    Name,
+    /// By the average, the largest first
+    Value,
+}
+
+/// What to do with a metric that an earlier run config produced already
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum DuplicateMetrics {
+    /// Keep both separate by appending the number of the run config to the name, i.e., `Load #2`
+    #[value(alias = "separate")]
+    #[serde(alias = "separate")]
+    Suffix,
+    /// Combine the samples of both into one metric, i.e., for the same page loaded from the cache and the network
+    Combine,
+    /// Fail the session
+    Error,
+}
+
+/// How tries with filter errors or crashes affect the exit code
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ErrorPolicy {
+    /// Never fail because of them
+    Ignore,
+    /// Warn about runs with such tries but do not fail
+    Warn,
+    /// Fail if any try of any run had an error, i.e., for strict CI jobs
+    FailAny,
+    /// Fail only if all tries of a run had errors, i.e., for smoke tests
+    FailAll,
+}
+
+/// When to color the output
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ColorChoice {
+    /// Only if we write to a terminal that supports colors
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// The condition for yansi
+    pub(crate) fn condition(self) -> Condition {
+        match self {
+            ColorChoice::Auto => Condition::TTY_AND_COLOR,
+            ColorChoice::Always => Condition::ALWAYS,
+            ColorChoice::Never => Condition::NEVER,
+        }
+    }
+
+    /// The style of the log
+    pub(crate) fn write_style(self) -> WriteStyle {
+        match self {
+            ColorChoice::Auto => WriteStyle::Auto,
+            ColorChoice::Always => WriteStyle::Always,
+            ColorChoice::Never => WriteStyle::Never,
+        }
+    }
+}
+
+// `per-run` is the name of the subcommand on the command line
+#[allow(clippy::enum_variant_names)]
 #[derive(Clone, Debug, Subcommand)]
 enum PerRun {
-    PerRun(RunArgs),
+    PerRun(Box<RunArgs>),
+    /// Check that hdc, the devices and the app are ready for benchmarking
+    Doctor(DoctorArgs),
+    /// Rewrite a run file of an older schema_version in the current one
+    Migrate(MigrateArgs),
+    /// Write a synthetic trace from a scenario file, to test filters without a device
+    Synthesize(SynthesizeArgs),
+    /// Recompute the metrics of archived `--artifacts` sessions with the current filters
+    Analyze(AnalyzeArgs),
+    /// Find the first commit that made a metric worse with `git bisect`, benchmarking every candidate
+    Bisect(BisectArgs),
+    /// Chart a metric over the sessions of an `analyze` history
+    Trend(TrendArgs),
+    /// Write the thresholds of all metrics from their variance in an `analyze` history
+    Thresholds(ThresholdsArgs),
+    /// Print filters for the prominent markers and long spans of a trace, to start a run file for a new app
+    Suggest(SuggestArgs),
+    /// Check that every filter with a `reference_trace` in the run file still matches exactly once in it
+    CheckFilters,
+}
+
+impl Args {
+    /// The arguments of the `doctor` subcommand if we should run it instead of benchmarking
+    pub(crate) fn doctor(&self) -> Option<&DoctorArgs> {
+        match &self.per_run {
+            Some(PerRun::Doctor(doctor_args)) => Some(doctor_args),
+            _ => None,
+        }
+    }
+
+    /// The arguments of the `migrate` subcommand if we should run it instead of benchmarking
+    pub(crate) fn migrate(&self) -> Option<&MigrateArgs> {
+        match &self.per_run {
+            Some(PerRun::Migrate(migrate_args)) => Some(migrate_args),
+            _ => None,
+        }
+    }
+
+    /// The arguments of the `synthesize` subcommand if we should run it instead of benchmarking
+    pub(crate) fn synthesize(&self) -> Option<&SynthesizeArgs> {
+        match &self.per_run {
+            Some(PerRun::Synthesize(synthesize_args)) => Some(synthesize_args),
+            _ => None,
+        }
+    }
+
+    /// The arguments of the `analyze` subcommand if we should run it instead of benchmarking
+    pub(crate) fn analyze(&self) -> Option<&AnalyzeArgs> {
+        match &self.per_run {
+            Some(PerRun::Analyze(analyze_args)) => Some(analyze_args),
+            _ => None,
+        }
+    }
+
+    /// The arguments of the `bisect` subcommand if we should run it instead of benchmarking
+    pub(crate) fn bisect(&self) -> Option<&BisectArgs> {
+        match &self.per_run {
+            Some(PerRun::Bisect(bisect_args)) => Some(bisect_args),
+            _ => None,
+        }
+    }
+
+    /// The arguments of the `trend` subcommand if we should run it instead of benchmarking
+    pub(crate) fn trend(&self) -> Option<&TrendArgs> {
+        match &self.per_run {
+            Some(PerRun::Trend(trend_args)) => Some(trend_args),
+            _ => None,
+        }
+    }
+
+    /// The arguments of the `thresholds` subcommand if we should run it instead of benchmarking
+    pub(crate) fn thresholds(&self) -> Option<&ThresholdsArgs> {
+        match &self.per_run {
+            Some(PerRun::Thresholds(thresholds_args)) => Some(thresholds_args),
+            _ => None,
+        }
+    }
+
+    /// The arguments of the `suggest` subcommand if we should run it instead of benchmarking
+    pub(crate) fn suggest(&self) -> Option<&SuggestArgs> {
+        match &self.per_run {
+            Some(PerRun::Suggest(suggest_args)) => Some(suggest_args),
+            _ => None,
+        }
+    }
+
+    /// If we should run the `check-filters` subcommand instead of benchmarking
+    pub(crate) fn check_filters(&self) -> bool {
+        matches!(self.per_run, Some(PerRun::CheckFilters))
+    }
+}
+
+#[derive(Clone, Parser, Debug)]
+pub(crate) struct DoctorArgs {
+    /// Name of the app bundle that has to be installed
+    #[arg(short, long, default_value_t = default_bundle_name())]
+    pub(crate) bundle_name: String,
+
+    /// Trace Buffer size in KB that has to fit into the free space of the device
+    #[arg(short = 't', long, default_value_t = default_trace_buffer())]
+    pub(crate) trace_buffer: u64,
+}
+
+#[derive(Clone, Parser, Debug)]
+pub(crate) struct MigrateArgs {
+    /// The run file to migrate. It is rewritten in place without its comments unless `output` is given.
+    pub(crate) run_file: PathBuf,
+
+    /// Write the migrated run file here instead
+    #[arg(short, long)]
+    pub(crate) output: Option<PathBuf>,
+}
+
+#[derive(Clone, Parser, Debug)]
+pub(crate) struct SynthesizeArgs {
+    /// The json5 scenario with the events of the trace
+    pub(crate) scenario: PathBuf,
+
+    /// Write the trace here instead of stdout, it can then be read with `--trace-file`
+    #[arg(short, long)]
+    pub(crate) output: Option<PathBuf>,
+}
+
+#[derive(Clone, Parser, Debug)]
+pub(crate) struct AnalyzeArgs {
+    /// Directory with one `--artifacts` directory per session
+    pub(crate) sessions: PathBuf,
+
+    /// Write the reports of all sessions here instead of stdout
+    #[arg(short, long)]
+    pub(crate) output: Option<PathBuf>,
+
+    /// Number of tries analyzed in parallel, the number of cpus by default
+    #[arg(short, long)]
+    pub(crate) jobs: Option<NonZeroUsize>,
+}
+
+#[derive(Clone, Parser, Debug)]
+pub(crate) struct BisectArgs {
+    /// Name of the filter or point filter to bisect
+    #[arg(short, long)]
+    pub(crate) metric: String,
+
+    /// A commit is bad if the mean of the metric is above this, in milliseconds for filters
+    #[arg(short, long)]
+    pub(crate) threshold: f64,
+
+    /// Shell command installing the build of `{commit}` on the device
+    #[arg(short, long)]
+    pub(crate) install: String,
+
+    /// A commit without the regression
+    #[arg(long)]
+    pub(crate) good: String,
+
+    /// A commit with the regression
+    #[arg(long, default_value = "HEAD")]
+    pub(crate) bad: String,
+
+    /// The git repository to bisect
+    #[arg(long, default_value = ".")]
+    pub(crate) repo: PathBuf,
+}
+
+#[derive(Clone, Parser, Debug)]
+pub(crate) struct TrendArgs {
+    /// Name of the filter or point filter to chart
+    pub(crate) metric: String,
+
+    /// The history written by `analyze`
+    #[arg(long)]
+    pub(crate) history: PathBuf,
+
+    /// Write the chart as svg here instead of printing it
+    #[arg(long)]
+    pub(crate) svg: Option<PathBuf>,
+
+    /// Only mark a change point if the level of the metric moved by more than this many percent
+    #[arg(long, default_value_t = 5.0)]
+    pub(crate) min_change: f64,
+
+    /// Significance level of the permutation test of the change points
+    #[arg(long, default_value_t = change_point::SIGNIFICANCE)]
+    pub(crate) significance: f64,
+}
+
+#[derive(Clone, Parser, Debug)]
+pub(crate) struct ThresholdsArgs {
+    /// The history written by `analyze`
+    #[arg(long)]
+    pub(crate) history: PathBuf,
+
+    /// Boundaries are this many standard deviations around the mean
+    #[arg(short = 'k', long, default_value_t = 3.0)]
+    pub(crate) sigmas: f64,
+
+    /// Write the thresholds here instead of stdout
+    #[arg(short, long)]
+    pub(crate) output: Option<PathBuf>,
+}
+
+#[derive(Clone, Parser, Debug)]
+pub(crate) struct SuggestArgs {
+    /// The ftrace file of the app
+    pub(crate) trace: PathBuf,
+
+    /// Only suggest filters measuring at least this many milliseconds
+    #[arg(long, default_value_t = 10)]
+    pub(crate) min_ms: i64,
+
+    /// Suggest at most this many filters
+    #[arg(long, default_value_t = 10)]
+    pub(crate) limit: usize,
 }
 
 impl TryFrom<&Args> for RunArgs {
     fn try_from(value: &Args) -> Result<Self, Self::Error> {
         match &value.per_run {
-            Some(PerRun::PerRun(run_args)) => Ok(run_args.to_owned()),
-            None => Err(anyhow!("Could not convert")),
+            Some(PerRun::PerRun(run_args)) => Ok(run_args.as_ref().to_owned()),
+            _ => Err(anyhow!("Could not convert")),
         }
     }
 
     type Error = anyhow::Error;
 }
 
-#[derive(Clone, Parser, Debug, Deserialize)]
+#[derive(Clone, Parser, Debug, Deserialize, Serialize)]
 #[command(version, about, long_about = None)]
 /// Run servo on an open harmony device and collect timing information
 pub(crate) struct RunArgs {
@@ -110,6 +657,152 @@ pub(crate) struct RunArgs {
     #[arg(long, default_value_t = false)]
     #[serde(default = "default_mitmproxy")]
     pub(crate) mitmproxy: bool,
+
+    /// Servo preferences as `key=value`, i.e., `--pref js_disable_jit=false`. In the run file this is a map.
+    /// They override our defaults, which only disable the JIT.
+    #[arg(long = "pref")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_prefs",
+        serialize_with = "serialize_prefs"
+    )]
+    pub(crate) prefs: Vec<Pref>,
+
+    /// Value of the `--tracing-filter` argument of servo
+    #[arg(long, default_value_t = default_tracing_filter())]
+    #[serde(default = "default_tracing_filter")]
+    pub(crate) tracing_filter: String,
+
+    /// Directory with the page of a `file:///` url and its subresources. It is pushed to the device as a whole
+    /// and the url is relative to it, i.e., `file:///index.html` for `<asset_dir>/index.html`.
+    #[arg(long)]
+    #[serde(default)]
+    pub(crate) asset_dir: Option<PathBuf>,
+
+    /// Serve this directory with a http server on the host the device reaches through a reverse port forward.
+    /// The url is then the path of the page in it, i.e., `index.html`.
+    #[arg(long)]
+    #[serde(default)]
+    pub(crate) serve: Option<PathBuf>,
+
+    /// Serial of the device to run on as listed by `hdc list targets`.
+    /// If not given or `any`, the run can be scheduled on any connected device.
+    #[arg(long)]
+    #[serde(default)]
+    pub(crate) device: Option<String>,
+
+    /// Steps interacting with the app after it started, i.e., `--step "swipe 300 1500 300 300 400"`.
+    /// They run before the sleep. See `script::Step` for all steps.
+    #[arg(long = "step")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_script",
+        serialize_with = "serialize_strings"
+    )]
+    pub(crate) script: Vec<Step>,
+
+    /// A predefined benchmark. It brings its own script if `script` is empty and reports its own metrics.
+    #[arg(long, value_enum)]
+    #[serde(default)]
+    pub(crate) preset: Option<Preset>,
+
+    /// Reload the page this many times after it loaded, without restarting the app. Every reload gets its
+    /// own results, i.e., `Load->Compl (reload 1)`, and we report how much memory grew over the reloads.
+    #[arg(long, default_value_t = 0)]
+    #[serde(default)]
+    pub(crate) reloads: u32,
+
+    /// Open these urls one after the other in the running app after the page and its reloads loaded.
+    /// Every navigation gets its own results named after its url, i.e., `Load->Compl (https://servo.org)`.
+    #[arg(long = "open")]
+    #[serde(default)]
+    pub(crate) open: Vec<String>,
+}
+
+/// Benchmarks that need more than filters on a page load
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Preset {
+    /// Flings down the loaded page and reports frame time percentiles and dropped frames
+    ScrollJank,
+}
+
+impl RunArgs {
+    /// The steps to run after the app started, the ones of the preset if none were given
+    pub(crate) fn script(&self) -> Vec<Step> {
+        let mut script = match self.preset {
+            Some(Preset::ScrollJank) if self.script.is_empty() => frames::scroll_jank_script(),
+            _ => self.script.clone(),
+        };
+        script.extend(navigation::navigation_script(self.reloads, &self.open));
+        script
+    }
+
+    /// The device this run has to run on, `None` if any device will do
+    pub(crate) fn pinned_device(&self) -> Option<&str> {
+        self.device.as_deref().filter(|d| *d != "any")
+    }
+}
+
+/// A servo preference given to the app on start
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Pref {
+    pub(crate) key: String,
+    pub(crate) value: String,
+}
+
+impl FromStr for Pref {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Preference {s} is not of the form key=value"))?;
+        Ok(Pref {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        })
+    }
+}
+
+/// Reads the `prefs` map of the run file, values can be strings, numbers or booleans
+fn deserialize_prefs<'de, D>(deserializer: D) -> Result<Vec<Pref>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let prefs = BTreeMap::<String, serde_json::Value>::deserialize(deserializer)?;
+    Ok(prefs
+        .into_iter()
+        .map(|(key, value)| Pref {
+            key,
+            value: match value {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            },
+        })
+        .collect())
+}
+
+/// Prefs as the map of the run file
+fn serialize_prefs<S: Serializer>(prefs: &[Pref], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(prefs.iter().map(|p| (&p.key, &p.value)))
+}
+
+/// Values in the syntax of the command line
+fn serialize_strings<S, T>(values: &[T], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: std::fmt::Display,
+{
+    serializer.collect_seq(values.iter().map(|v| v.to_string()))
+}
+
+/// Urls of webhooks usually contain a secret
+fn serialize_redacted<S: Serializer>(
+    value: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    value.as_ref().map(|_| "<redacted>").serialize(serializer)
 }
 
 impl Default for RunArgs {
@@ -123,6 +816,15 @@ impl Default for RunArgs {
             bundle_name: default_bundle_name(),
             commands: default_commands(),
             mitmproxy: false,
+            prefs: Vec::new(),
+            tracing_filter: default_tracing_filter(),
+            asset_dir: None,
+            serve: None,
+            device: None,
+            script: Vec::new(),
+            preset: None,
+            reloads: 0,
+            open: Vec::new(),
         }
     }
 }
@@ -159,3 +861,32 @@ fn default_commands() -> Option<Vec<String>> {
 fn default_mitmproxy() -> bool {
     false
 }
+
+fn default_tracing_filter() -> String {
+    String::from("trace")
+}
+
+#[test]
+fn test_prefs() {
+    let run_args: RunArgs =
+        json5::from_str(r#"{prefs: {js_disable_jit: false, "layout.threads": 2, name: "a=b"}}"#)
+            .unwrap();
+    assert_eq!(
+        run_args.prefs,
+        [
+            Pref::from_str("js_disable_jit=false").unwrap(),
+            Pref::from_str("layout.threads=2").unwrap(),
+            Pref::from_str("name=a=b").unwrap(),
+        ]
+    );
+    assert_eq!(run_args.tracing_filter, "trace");
+    assert!(Pref::from_str("js_disable_jit").is_err());
+}
+
+#[test]
+fn test_script() {
+    let run_args: RunArgs =
+        json5::from_str(r#"{script: ["wait-for-marker LCP", "swipe 300 1500 300 300"]}"#).unwrap();
+    assert_eq!(run_args.script.len(), 2);
+    assert!(json5::from_str::<RunArgs>(r#"{script: ["jump"]}"#).is_err());
+}
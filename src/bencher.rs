@@ -1,11 +1,14 @@
-use std::{collections::HashMap, fs::File, io::BufWriter};
+use std::{collections::BTreeMap, fmt::Display, fs, str::FromStr, sync::OnceLock};
 
-use anyhow::Context;
-use rust_decimal::Decimal;
+use anyhow::{Context, anyhow};
+use rust_decimal::{
+    Decimal,
+    prelude::{FromPrimitive, ToPrimitive},
+};
 use serde::Serialize;
 use time::Duration;
 
-use crate::{avg_min_max, utils::RunResults};
+use crate::{avg_min_max, canonical, units::DisplayUnit, utils::RunResults};
 
 #[derive(Debug, Serialize)]
 /// Struct for bencher json
@@ -16,6 +19,24 @@ struct Latency {
     lower_value: Decimal,
     #[serde(with = "rust_decimal::serde::float")]
     upper_value: Decimal,
+    /// The value of every try, an extension of the bencher format with `--bencher-samples`
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_samples"
+    )]
+    samples: Option<Vec<Decimal>>,
+}
+
+/// Serializes the samples as floats like the other values
+fn serialize_samples<S: serde::Serializer>(
+    samples: &Option<Vec<Decimal>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let floats = samples
+        .iter()
+        .flatten()
+        .map(|sample| sample.to_f64().unwrap_or_default());
+    serializer.collect_seq(floats)
 }
 
 /// Converts duration to bencher Decimal representation. Duration has precision of nanoseconds
@@ -24,11 +45,56 @@ fn difference_to_bencher_decimal(dur: &Duration) -> Decimal {
     Decimal::from_i128_with_scale(number, 0)
 }
 
-type BencherLatency<'a> = HashMap<&'a str, Latency>;
+impl Latency {
+    /// A measure that has the same value for all three fields
+    fn exact(value: Decimal) -> Self {
+        Latency {
+            value,
+            lower_value: value,
+            upper_value: value,
+            samples: None,
+        }
+    }
+}
+
+type BencherLatency = BTreeMap<String, Latency>;
 #[derive(Serialize)]
 #[serde(untagged)]
-enum Bencher<'a> {
-    Latency(BencherLatency<'a>),
+enum Bencher {
+    Latency(BencherLatency),
+}
+
+/// Population standard deviation of the samples
+fn standard_deviation(samples: &[i128]) -> Decimal {
+    let n = samples.len() as f64;
+    let mean = samples.iter().map(|s| *s as f64).sum::<f64>() / n;
+    let variance = samples
+        .iter()
+        .map(|s| (*s as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    Decimal::from_f64(variance.sqrt().round()).unwrap_or_default()
+}
+
+/// The samples as bencher values if `samples` is set
+fn sample_values(samples: bool, values: impl Iterator<Item = i128>) -> Option<Vec<Decimal>> {
+    samples.then(|| {
+        values
+            .map(|value| Decimal::from_i128_with_scale(value, 0))
+            .collect()
+    })
+}
+
+/// Adds the `<measure>-stddev` and `<measure>-samples` measures for the samples of `measure`
+fn insert_statistics(map: &mut BencherLatency, measure: &str, samples: &[i128]) {
+    map.insert(
+        format!("{measure}-stddev"),
+        Latency::exact(standard_deviation(samples)),
+    );
+    map.insert(
+        format!("{measure}-samples"),
+        Latency::exact(Decimal::from(samples.len())),
+    );
 }
 
 /// Creates a bencher key adding the E2E and prepend result
@@ -41,27 +107,47 @@ fn bencher_key(result: &RunResults, key: &str) -> String {
 }
 
 /// Creates an iterator for the filter results with the appropriate map
-fn filter_iterator(result: &RunResults) -> impl std::iter::Iterator<Item = (String, Bencher<'_>)> {
-    result.filter_results.iter().map(|(key, dur_vec)| {
+fn filter_iterator(
+    result: &RunResults,
+    statistics: bool,
+    samples: bool,
+) -> impl std::iter::Iterator<Item = (String, Bencher)> {
+    result.filter_results.iter().map(move |(key, dur_vec)| {
         let avg_min_max = avg_min_max::<Duration, u16>(dur_vec);
         // yes we need this hashmap for the correct json
-        let mut map = HashMap::new();
+        let mut map = BTreeMap::new();
+        let name = result
+            .measures
+            .get(key)
+            .map(String::as_str)
+            .unwrap_or("Latency");
         map.insert(
-            "Latency",
+            name.to_owned(),
             Latency {
                 value: difference_to_bencher_decimal(&avg_min_max.avg),
                 lower_value: difference_to_bencher_decimal(&avg_min_max.min),
                 upper_value: difference_to_bencher_decimal(&avg_min_max.max),
+                samples: sample_values(samples, dur_vec.iter().map(|d| d.whole_nanoseconds())),
             },
         );
+        if statistics {
+            let samples: Vec<i128> = dur_vec.iter().map(|d| d.whole_nanoseconds()).collect();
+            insert_statistics(&mut map, name, &samples);
+        }
         (bencher_key(result, key), Bencher::Latency(map))
     })
 }
 
 /// Creates an iterator for the point results with the appropriate map
-fn points_iterator(result: &RunResults) -> impl std::iter::Iterator<Item = (String, Bencher<'_>)> {
-    result.point_results.iter().map(|(key, points)| {
-        let name = if key.contains("LargestContentfulPaint/paint_time")
+fn points_iterator(
+    result: &RunResults,
+    statistics: bool,
+    samples: bool,
+) -> impl std::iter::Iterator<Item = (String, Bencher)> {
+    result.point_results.iter().map(move |(key, points)| {
+        let name = if let Some(measure) = result.measures.get(key) {
+            measure.as_str()
+        } else if key.contains("LargestContentfulPaint/paint_time")
             || key.contains("FirstContentfulPaint/paint_time")
         {
             "Nanoseconds"
@@ -72,46 +158,406 @@ fn points_iterator(result: &RunResults) -> impl std::iter::Iterator<Item = (Stri
         } else {
             "Memory"
         };
-        let mut map = HashMap::new();
-        let avg_min_max = avg_min_max::<u64, u64>(&points.result);
+        let mut map = BTreeMap::new();
+        let avg_min_max = avg_min_max::<i64, i64>(&points.result);
         map.insert(
-            name,
+            name.to_owned(),
             Latency {
                 value: Decimal::from_i128_with_scale(avg_min_max.avg as i128, 0),
                 lower_value: Decimal::from_i128_with_scale(avg_min_max.min as i128, 0),
                 upper_value: Decimal::from_i128_with_scale(avg_min_max.max as i128, 0),
+                samples: sample_values(samples, points.result.iter().map(|v| *v as i128)),
             },
         );
+        if statistics {
+            let samples: Vec<i128> = points.result.iter().map(|v| *v as i128).collect();
+            insert_statistics(&mut map, name, &samples);
+        }
         (bencher_key(result, key), Bencher::Latency(map))
     })
 }
 
+/// One `--bencher-precision`, `MEASURE=UNIT`. Values of the measure are rounded to whole units but stay in
+/// nanoseconds or bytes, i.e., `Latency=us` or `Memory=KiB`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Precision {
+    measure: String,
+    unit: DisplayUnit,
+}
+
+impl FromStr for Precision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (measure, unit) = s
+            .rsplit_once('=')
+            .ok_or_else(|| anyhow!("Precision {s} is not of the form MEASURE=UNIT"))?;
+        Ok(Precision {
+            measure: measure.to_owned(),
+            unit: unit.parse()?,
+        })
+    }
+}
+
+impl Display for Precision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.measure, self.unit)
+    }
+}
+
+static PRECISION: OnceLock<Vec<Precision>> = OnceLock::new();
+
+/// Sets the precision of the measures from `--bencher-precision`
+pub(crate) fn set_precision(precision: Vec<Precision>) {
+    let _ = PRECISION.set(precision);
+}
+
+/// Rounds the values of `latency` to the precision of `measure`, the last setting for it wins
+fn round(precision: &[Precision], measure: &str, latency: &mut Latency) {
+    let Some(setting) = precision.iter().rev().find(|p| p.measure == measure) else {
+        return;
+    };
+    let step = Decimal::from(setting.unit.step());
+    for value in [
+        &mut latency.value,
+        &mut latency.lower_value,
+        &mut latency.upper_value,
+    ]
+    .into_iter()
+    .chain(latency.samples.iter_mut().flatten())
+    {
+        *value = (*value / step).round() * step;
+    }
+}
+
+/// Placeholders of a `MetricNameTemplate`
+const TEMPLATE_PLACEHOLDERS: [&str; 4] = ["url", "host", "path", "filter"];
+
+/// How metrics of a run are named in the bencher output, i.e., `{host}/{path}/{filter}`.
+/// `{url}` is the url of the run or its alias, `{host}` and `{path}` are its parts without scheme, query and fragment and
+/// `{filter}` is the name of the metric. Segments that end up empty are left out.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct MetricNameTemplate(String);
+
+impl FromStr for MetricNameTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow!("Unclosed `{{` in metric name template {s}"))?;
+            let placeholder = &rest[start + 1..start + end];
+            if !TEMPLATE_PLACEHOLDERS.contains(&placeholder) {
+                return Err(anyhow!(
+                    "Unknown placeholder {{{placeholder}}} in metric name template {s}, expected one of {}",
+                    TEMPLATE_PLACEHOLDERS.map(|p| format!("{{{p}}}")).join(", ")
+                ));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        if !s.contains("{filter}") {
+            return Err(anyhow!(
+                "Metric name template {s} needs {{filter}}, otherwise all metrics of a run get the same name"
+            ));
+        }
+        Ok(MetricNameTemplate(s.to_owned()))
+    }
+}
+
+/// The url without query, fragment and trailing slashes, which do not change what page a run loads as far as
+/// metric names are concerned
+pub(crate) fn sanitize_url(url: &str) -> &str {
+    url.split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/')
+}
+
+impl MetricNameTemplate {
+    /// The name of the metric `filter` of a run of `url`. `{url}` is `alias` if there is one.
+    fn render(&self, url: &str, alias: Option<&str>, filter: &str) -> String {
+        let sanitized = sanitize_url(url);
+        let without_scheme = sanitized
+            .split_once("://")
+            .map_or(sanitized, |(_, rest)| rest);
+        let (host, path) = without_scheme
+            .split_once('/')
+            .unwrap_or((without_scheme, ""));
+        self.0
+            .split('/')
+            .map(|segment| {
+                segment
+                    .replace("{url}", alias.unwrap_or(url))
+                    .replace("{host}", host)
+                    .replace("{path}", path.trim_start_matches('/'))
+                    .replace("{filter}", filter)
+            })
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Renames the metrics of a run of `url` in `result` for the bencher output. With a `template` all metrics are
+/// named after it, otherwise the alias of the url replaces the url in front of the metrics that have it.
+pub(crate) fn name_run_metrics(
+    result: &mut RunResults,
+    url: &str,
+    alias: Option<&str>,
+    template: Option<&MetricNameTemplate>,
+) {
+    let prefix = format!("{url}/");
+    match (template, alias) {
+        (Some(template), _) => result.rename_keys(|key| {
+            let filter = key.strip_prefix(&prefix).unwrap_or(&key);
+            template.render(url, alias, filter)
+        }),
+        (None, Some(alias)) => result.rename_keys(|key| match key.strip_prefix(&prefix) {
+            Some(filter) => format!("{alias}/{filter}"),
+            None => key,
+        }),
+        (None, None) => {}
+    }
+}
+
 /// Output in bencher json format to bench.json
 /// We also will append it to the bench.json file instead of overwriting it so supsequent runs can be recorded.
 /// We also add some custom strings to the filter.
-pub(crate) fn write_results(result: RunResults) -> anyhow::Result<()> {
-    let b = generate_results_hashmap(&result);
-
-    let file = File::create("bench.json").context("Could not create bench.json file")?;
-    let writer = BufWriter::new(file);
-    serde_json::to_writer_pretty(writer, &b).context("Could not serialize results")?;
-    println!(
-        "{}",
-        serde_json::to_string_pretty(&b).context("Could not serialize results")?
-    );
+/// With `statistics` every entry also gets the standard deviation and number of samples as measures.
+/// With `samples` every measure also has the value of every try.
+/// With `canonical` the json is canonical, see `canonical.rs`.
+pub(crate) fn write_results(
+    result: RunResults,
+    statistics: bool,
+    samples: bool,
+    canonical: bool,
+) -> anyhow::Result<()> {
+    let b = generate_results_hashmap(&result, statistics, samples);
+    let json = canonical::to_string(&b, canonical)?;
+    fs::write("bench.json", &json).context("Could not write bench.json file")?;
+    println!("{json}");
     Ok(())
 }
 
+/// The bencher json of the results as a value, i.e., to compare it with an older bench.json
+pub(crate) fn generate_result_json_value(
+    result: &RunResults,
+    statistics: bool,
+) -> anyhow::Result<serde_json::Value> {
+    serde_json::to_value(generate_results_hashmap(result, statistics, false))
+        .context("Could not serialize results")
+}
+
 #[cfg(test)]
 pub(crate) fn generate_result_json_str(result: RunResults) -> anyhow::Result<String> {
-    let b = generate_results_hashmap(&result);
+    let b = generate_results_hashmap(&result, false, false);
     serde_json::to_string_pretty(&b).context("Could not serialize results")
 }
 
-fn generate_results_hashmap<'a>(result: &'a RunResults) -> HashMap<String, Bencher<'a>> {
-    let filters_iter = filter_iterator(result);
-    let points_iter = points_iterator(result);
+fn generate_results_hashmap(
+    result: &RunResults,
+    statistics: bool,
+    samples: bool,
+) -> BTreeMap<String, Bencher> {
+    let precision = PRECISION.get().map(Vec::as_slice).unwrap_or_default();
+    rounded_results_hashmap(result, statistics, samples, precision)
+}
+
+fn rounded_results_hashmap(
+    result: &RunResults,
+    statistics: bool,
+    samples: bool,
+    precision: &[Precision],
+) -> BTreeMap<String, Bencher> {
+    let filters_iter = filter_iterator(result, statistics, samples);
+    let points_iter = points_iterator(result, statistics, samples);
+
+    filters_iter
+        .chain(points_iter)
+        .map(|(key, Bencher::Latency(mut map))| {
+            for (measure, latency) in map.iter_mut() {
+                round(precision, measure, latency);
+            }
+            (key, Bencher::Latency(map))
+        })
+        .collect()
+}
+
+#[test]
+fn test_statistics_measures() {
+    let mut result = RunResults::default();
+    result.filter_results.insert(
+        String::from("Load"),
+        vec![Duration::nanoseconds(2), Duration::nanoseconds(4)],
+    );
+    result.point_results.insert(
+        String::from("Resident"),
+        crate::utils::PointResult {
+            no_unit_conversion: false,
+            result: vec![10, 10, 40],
+        },
+    );
+    let json = serde_json::to_value(generate_results_hashmap(&result, true, false)).unwrap();
+    assert_eq!(json["E2E/Load"]["Latency"]["value"], 3.0);
+    assert_eq!(json["E2E/Load"]["Latency-stddev"]["value"], 1.0);
+    assert_eq!(json["E2E/Load"]["Latency-samples"]["value"], 2.0);
+    assert_eq!(json["E2E/Resident"]["Memory-stddev"]["upper_value"], 14.0);
+    assert_eq!(json["E2E/Resident"]["Memory-samples"]["value"], 3.0);
+
+    let json = serde_json::to_value(generate_results_hashmap(&result, false, false)).unwrap();
+    assert_eq!(json["E2E/Load"].as_object().unwrap().len(), 1);
+    assert!(json["E2E/Load"]["Latency"].get("samples").is_none());
+}
+
+#[test]
+fn test_samples() {
+    let mut result = RunResults::default();
+    result.filter_results.insert(
+        String::from("Load"),
+        vec![Duration::nanoseconds(2), Duration::nanoseconds(4)],
+    );
+    result.point_results.insert(
+        String::from("Resident"),
+        crate::utils::PointResult {
+            no_unit_conversion: false,
+            result: vec![10, 10, 40],
+        },
+    );
+    let json = serde_json::to_value(generate_results_hashmap(&result, true, true)).unwrap();
+    assert_eq!(
+        json["E2E/Load"]["Latency"]["samples"],
+        serde_json::json!([2.0, 4.0])
+    );
+    assert_eq!(
+        json["E2E/Resident"]["Memory"]["samples"],
+        serde_json::json!([10.0, 10.0, 40.0])
+    );
+    assert!(json["E2E/Load"]["Latency-stddev"].get("samples").is_none());
+
+    let precision: Vec<Precision> = vec!["Memory=kB".parse().unwrap()];
+    let json =
+        serde_json::to_value(rounded_results_hashmap(&result, false, true, &precision)).unwrap();
+    assert_eq!(
+        json["E2E/Resident"]["Memory"]["samples"],
+        serde_json::json!([0.0, 0.0, 0.0])
+    );
+}
+
+#[test]
+fn test_key_order() {
+    let mut result = RunResults::default();
+    for key in ["Stability/crashes", "JS/non-heap", "Load"] {
+        result
+            .filter_results
+            .insert(String::from(key), vec![Duration::nanoseconds(2)]);
+    }
+    result.point_results.insert(
+        String::from("LayoutThread/stacking-context-tree"),
+        crate::utils::PointResult {
+            no_unit_conversion: false,
+            result: vec![10],
+        },
+    );
+    let json = generate_result_json_str(result.clone()).unwrap();
+    let keys: Vec<usize> = [
+        "E2E/JS/non-heap",
+        "E2E/LayoutThread/stacking-context-tree",
+        "E2E/Load",
+        "E2E/Stability/crashes",
+    ]
+    .iter()
+    .map(|key| json.find(&format!("\"{key}\"")).unwrap())
+    .collect();
+    assert!(keys.is_sorted());
+    assert_eq!(json, generate_result_json_str(result).unwrap());
+}
+
+#[test]
+fn test_metric_name_template() {
+    let template: MetricNameTemplate = "{host}/{path}/{filter}".parse().unwrap();
+    assert_eq!(
+        template.render("https://servo.org/blog/?page=2#top", None, "Load"),
+        "servo.org/blog/Load"
+    );
+    assert_eq!(
+        template.render("https://servo.org", None, "Load"),
+        "servo.org/Load"
+    );
+    assert_eq!(
+        template.render("file:///index.html", None, "Load"),
+        "index.html/Load"
+    );
+
+    let mut result = RunResults::default();
+    result
+        .filter_results
+        .insert(String::from("Load"), vec![Duration::milliseconds(5)]);
+    result.point_results.insert(
+        String::from("https://servo.org/?a=1/Resident"),
+        crate::utils::PointResult {
+            no_unit_conversion: false,
+            result: vec![10],
+        },
+    );
+    let mut aliased = result.clone();
+    name_run_metrics(&mut result, "https://servo.org/?a=1", None, Some(&template));
+    assert!(result.filter_results.contains_key("servo.org/Load"));
+    assert!(result.point_results.contains_key("servo.org/Resident"));
+
+    name_run_metrics(&mut aliased, "https://servo.org/?a=1", Some("servo"), None);
+    assert!(aliased.filter_results.contains_key("Load"));
+    assert!(aliased.point_results.contains_key("servo/Resident"));
+    let template: MetricNameTemplate = "{url}/{filter}".parse().unwrap();
+    assert_eq!(
+        template.render("https://servo.org", Some("servo"), "Load"),
+        "servo/Load"
+    );
+    assert_eq!(
+        sanitize_url("https://www.google.com/?hl=en#top"),
+        "https://www.google.com"
+    );
+
+    assert!(
+        "{host}/{url}/{filter}"
+            .parse::<MetricNameTemplate>()
+            .is_ok()
+    );
+    assert!("{host}/{path}".parse::<MetricNameTemplate>().is_err());
+    assert!("{site}/{filter}".parse::<MetricNameTemplate>().is_err());
+    assert!("{host/{filter}".parse::<MetricNameTemplate>().is_err());
+}
+
+#[test]
+fn test_precision() {
+    let mut result = RunResults::default();
+    result.filter_results.insert(
+        String::from("Load"),
+        vec![
+            Duration::nanoseconds(1_234_567),
+            Duration::nanoseconds(1_500),
+        ],
+    );
+    result.point_results.insert(
+        String::from("Resident"),
+        crate::utils::PointResult {
+            no_unit_conversion: false,
+            result: vec![5000],
+        },
+    );
+    let precision: Vec<Precision> = ["Latency=ms", "Latency=us", "Memory=KiB"]
+        .iter()
+        .map(|p| p.parse().unwrap())
+        .collect();
+    let json =
+        serde_json::to_value(rounded_results_hashmap(&result, true, false, &precision)).unwrap();
+    assert_eq!(json["E2E/Load"]["Latency"]["value"], 618_000.0);
+    assert_eq!(json["E2E/Load"]["Latency"]["lower_value"], 2_000.0);
+    assert_eq!(json["E2E/Load"]["Latency"]["upper_value"], 1_235_000.0);
+    assert_eq!(json["E2E/Load"]["Latency-samples"]["value"], 2.0);
+    assert_eq!(json["E2E/Resident"]["Memory"]["value"], 5120.0);
 
-    // let b: HashMap<String, Bencher> = filters_iter.chain(points_iter).collect();
-    filters_iter.chain(points_iter).collect()
+    assert!("Latency".parse::<Precision>().is_err());
+    assert!("Latency=minutes".parse::<Precision>().is_err());
 }
@@ -0,0 +1,194 @@
+//! The `bisect` subcommand, which drives `git bisect` by installing and benchmarking every candidate commit.
+//! A commit is bad if the mean of the metric is above the threshold, commits without the metric are skipped.
+use std::{path::Path, process::Command};
+
+use anyhow::{Context, Result, anyhow};
+use log::{info, warn};
+use yansi::Paint;
+
+use crate::{
+    args::BisectArgs, run_runconfig, runconfig::RunConfig, throughput::is_metric, utils::RunResults,
+};
+
+/// What we tell `git bisect` about a commit
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Good(f64),
+    Bad(f64),
+    /// The metric was not found
+    Skip,
+}
+
+impl Verdict {
+    /// The `git bisect` subcommand of the verdict
+    fn command(&self) -> &'static str {
+        match self {
+            Verdict::Good(_) => "good",
+            Verdict::Bad(_) => "bad",
+            Verdict::Skip => "skip",
+        }
+    }
+}
+
+/// The mean of `metric` in `results`, durations in milliseconds and points in their unit
+fn mean(results: &RunResults, metric: &str) -> Option<f64> {
+    let durations = results
+        .filter_results
+        .iter()
+        .find(|(key, _)| is_metric(key, metric))
+        .map(|(_, durations)| {
+            durations
+                .iter()
+                .map(|d| d.as_seconds_f64() * 1000.0)
+                .collect::<Vec<_>>()
+        });
+    let samples = durations.or_else(|| {
+        results
+            .point_results
+            .iter()
+            .find(|(key, _)| is_metric(key, metric))
+            .map(|(_, points)| points.result.iter().map(|&p| p as f64).collect())
+    })?;
+    (!samples.is_empty()).then(|| samples.iter().sum::<f64>() / samples.len() as f64)
+}
+
+/// Whether `results` are good or bad for `metric` and `threshold`
+fn verdict(results: &RunResults, metric: &str, threshold: f64) -> Verdict {
+    match mean(results, metric) {
+        Some(value) if value > threshold => Verdict::Bad(value),
+        Some(value) => Verdict::Good(value),
+        None => Verdict::Skip,
+    }
+}
+
+/// Runs `git bisect` with `args` in `repo` and returns its stdout
+fn git_bisect(repo: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .arg("bisect")
+        .args(args)
+        .output()
+        .context("Could not run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git bisect {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The commit `git bisect` is at in `repo`
+fn current_commit(repo: &Path) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .context("Could not run git")?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// The end of a bisection in the output of `git bisect good/bad/skip`, with its first line, i.e.,
+/// `<commit> is the first bad commit`
+fn finished(output: &str) -> Option<&str> {
+    output.lines().find(|line| {
+        line.contains("is the first bad commit") || line.contains("only skipped commits left")
+    })
+}
+
+/// Installs the build of `commit` with the install command of `args`
+fn install(args: &BisectArgs, commit: &str) -> Result<()> {
+    let command = args.install.replace("{commit}", commit);
+    info!("Installing {commit}: {command}");
+    let status = Command::new("sh")
+        .args(["-c", &command])
+        .status()
+        .with_context(|| format!("Could not run {command}"))?;
+    if !status.success() {
+        return Err(anyhow!("{command} failed with {status}"));
+    }
+    Ok(())
+}
+
+/// Benchmarks the installed build with all `run_configs`
+fn benchmark(run_configs: &[RunConfig]) -> RunResults {
+    let mut results = RunResults::default();
+    for run_config in run_configs {
+        let mut run_results = RunResults::default();
+        match run_runconfig(run_config, &mut run_results, None) {
+            Ok(()) => results.merge(run_results),
+            Err(e) => warn!("Run {} failed: {e:?}", run_config.run_args.url),
+        }
+    }
+    results
+}
+
+/// Benchmarks the commits `git bisect` checks out until it is done, `output` is the output of its last command.
+/// Returns the line with the result of the bisection.
+fn bisect(args: &BisectArgs, run_configs: &[RunConfig], mut output: String) -> Result<String> {
+    loop {
+        if let Some(line) = finished(&output) {
+            return Ok(line.to_owned());
+        }
+        let commit = current_commit(&args.repo)?;
+        let verdict = match install(args, &commit) {
+            Ok(()) => verdict(&benchmark(run_configs), &args.metric, args.threshold),
+            Err(e) => {
+                warn!("Could not install {commit}: {e:?}");
+                Verdict::Skip
+            }
+        };
+        match verdict {
+            Verdict::Good(value) => println!("{} {commit}: {value:.2}", "[good]".green()),
+            Verdict::Bad(value) => println!("{} {commit}: {value:.2}", "[bad]".red()),
+            Verdict::Skip => println!("{} {commit}: no {}", "[skip]".yellow(), args.metric),
+        }
+        output = git_bisect(&args.repo, &[verdict.command()])?;
+    }
+}
+
+/// Bisects between the good and the bad commit of `args` until `git bisect` found the first bad commit.
+/// The repository is reset to where it was before in the end.
+pub(crate) fn run(args: &BisectArgs, run_configs: &[RunConfig]) -> Result<()> {
+    let output = git_bisect(&args.repo, &["start", &args.bad, &args.good])?;
+    let result = bisect(args, run_configs, output);
+    let reset = git_bisect(&args.repo, &["reset"]);
+    println!("{}", result?);
+    reset?;
+    Ok(())
+}
+
+#[test]
+fn test_bisect_verdict() {
+    use crate::utils::PointResult;
+    use time::Duration;
+
+    let mut results = RunResults::default();
+    results.filter_results.insert(
+        String::from("https://servo.org/Load"),
+        vec![Duration::milliseconds(100), Duration::milliseconds(300)],
+    );
+    results.point_results.insert(
+        String::from("https://servo.org/Resident"),
+        PointResult {
+            no_unit_conversion: false,
+            result: vec![1_000],
+        },
+    );
+    assert_eq!(verdict(&results, "Load", 250.0), Verdict::Good(200.0));
+    assert_eq!(verdict(&results, "Load", 150.0), Verdict::Bad(200.0));
+    assert_eq!(verdict(&results, "Resident", 999.0), Verdict::Bad(1000.0));
+    assert_eq!(verdict(&results, "Missing", 0.0), Verdict::Skip);
+
+    assert_eq!(
+        finished("Bisecting: 3 revisions left to test after this"),
+        None
+    );
+    assert_eq!(
+        finished("abc123 is the first bad commit\ncommit abc123\n"),
+        Some("abc123 is the first bad commit")
+    );
+}
@@ -0,0 +1,103 @@
+//! Canonical json for `--canonical-json`, stable enough for golden files: keys are sorted, floats always have
+//! `FLOAT_DECIMALS` decimals and the working and home directory are replaced by `.` and `~` in strings.
+use std::{fmt::Write, path::PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Number of decimals of every float
+const FLOAT_DECIMALS: usize = 6;
+
+/// Host specific directories and what we replace them with, the longest first
+fn host_paths() -> Vec<(String, &'static str)> {
+    let mut paths: Vec<(String, &str)> = [
+        (std::env::current_dir().ok(), "."),
+        (std::env::var_os("HOME").map(PathBuf::from), "~"),
+    ]
+    .into_iter()
+    .filter_map(|(path, replacement)| Some((path?.to_str()?.to_owned(), replacement)))
+    .filter(|(path, _)| path.len() > 1)
+    .collect();
+    paths.sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+    paths
+}
+
+/// `value` as pretty json, canonical if `canonical` is set
+pub(crate) fn to_string(value: &impl Serialize, canonical: bool) -> Result<String> {
+    if !canonical {
+        return serde_json::to_string_pretty(value).context("Could not serialize results");
+    }
+    let value = serde_json::to_value(value).context("Could not serialize results")?;
+    let mut out = String::new();
+    write_value(&mut out, &value, 0, &host_paths());
+    Ok(out)
+}
+
+fn write_value(out: &mut String, value: &Value, indent: usize, paths: &[(String, &str)]) {
+    let pad = |n: usize| "  ".repeat(n);
+    match value {
+        Value::Null | Value::Bool(_) => out.push_str(&value.to_string()),
+        Value::Number(n) => match (n.as_i64(), n.as_u64(), n.as_f64()) {
+            (Some(i), _, _) => write!(out, "{i}").unwrap(),
+            (_, Some(u), _) => write!(out, "{u}").unwrap(),
+            (_, _, Some(f)) => write!(out, "{f:.FLOAT_DECIMALS$}").unwrap(),
+            _ => out.push_str(&n.to_string()),
+        },
+        Value::String(s) => {
+            let s = paths.iter().fold(s.clone(), |s, (path, replacement)| {
+                s.replace(path, replacement)
+            });
+            out.push_str(&Value::String(s).to_string());
+        }
+        Value::Array(values) if values.is_empty() => out.push_str("[]"),
+        Value::Array(values) => {
+            out.push_str("[\n");
+            for (i, v) in values.iter().enumerate() {
+                out.push_str(&pad(indent + 1));
+                write_value(out, v, indent + 1, paths);
+                out.push_str(if i + 1 < values.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&pad(indent));
+            out.push(']');
+        }
+        Value::Object(map) if map.is_empty() => out.push_str("{}"),
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push_str("{\n");
+            for (i, key) in keys.iter().enumerate() {
+                out.push_str(&pad(indent + 1));
+                out.push_str(&Value::String((*key).clone()).to_string());
+                out.push_str(": ");
+                write_value(out, &map[*key], indent + 1, paths);
+                out.push_str(if i + 1 < keys.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&pad(indent));
+            out.push('}');
+        }
+    }
+}
+
+#[test]
+fn test_canonical_json() {
+    let cwd = std::env::current_dir().unwrap();
+    let value = serde_json::json!({
+        "b": [1.5, 2, -3],
+        "a": {"trace": cwd.join("trace.ftrace"), "empty": []},
+    });
+    assert_eq!(
+        to_string(&value, true).unwrap(),
+        r#"{
+  "a": {
+    "empty": [],
+    "trace": "./trace.ftrace"
+  },
+  "b": [
+    1.500000,
+    2,
+    -3
+  ]
+}"#
+    );
+}
@@ -0,0 +1,174 @@
+//! E-divisive change point detection, which finds where the distribution of a series shifted instead of comparing
+//! single values against a threshold. The series is split where the energy distance between both sides is largest,
+//! a permutation test decides if the split is significant, and both sides are split again.
+//! See Matteson and James, "A Nonparametric Approach for Multiple Change Point Analysis of Multivariate Data".
+
+/// Both sides of a change point have at least this many values
+const MIN_SEGMENT: usize = 2;
+
+/// Number of permutations of the significance test
+const PERMUTATIONS: usize = 199;
+
+/// A split is significant if at most this share of the permutations had a larger energy distance
+pub(crate) const SIGNIFICANCE: f64 = 0.05;
+
+/// Seed of the permutations, so the same history always gives the same change points
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// A xorshift generator, good enough to shuffle and without a dependency
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Fisher-Yates shuffle of `values`
+    fn shuffle(&mut self, values: &mut [f64]) {
+        for i in (1..values.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            values.swap(i, j);
+        }
+    }
+}
+
+/// The sum of the distances between all pairs of `values`
+fn pair_distances(values: &[f64]) -> f64 {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, x)| values[i + 1..].iter().map(|y| (x - y).abs()).sum::<f64>())
+        .sum()
+}
+
+/// The scaled energy distance between a left side of `m` and a right side of `n` values from the sums of the
+/// distances `between` both sides and `within` each side
+fn energy(m: usize, n: usize, between: f64, within: (f64, f64)) -> f64 {
+    let mean_within = |sum: f64, len: usize| {
+        let pairs = len * len.saturating_sub(1) / 2;
+        if pairs == 0 { 0.0 } else { sum / pairs as f64 }
+    };
+    let (m_f, n_f) = (m as f64, n as f64);
+    m_f * n_f / (m_f + n_f)
+        * (2.0 * between / (m_f * n_f) - mean_within(within.0, m) - mean_within(within.1, n))
+}
+
+/// The split of `values` with the largest energy distance and that distance.
+/// The distance sums are updated while the split moves right, so all splits together take O(n²).
+fn best_split(values: &[f64]) -> Option<(usize, f64)> {
+    let last = values.len().checked_sub(MIN_SEGMENT)?;
+    let (mut left, mut right, mut between) = (0.0, pair_distances(values), 0.0);
+    let mut best: Option<(usize, f64)> = None;
+    for split in 0..=last {
+        if split >= MIN_SEGMENT {
+            let distance = energy(split, values.len() - split, between, (left, right));
+            if best.is_none_or(|(_, best)| distance >= best) {
+                best = Some((split, distance));
+            }
+        }
+        // Moves `values[split]` from the right to the left side
+        let moved = values[split];
+        let to_left: f64 = values[..split].iter().map(|x| (x - moved).abs()).sum();
+        let to_right: f64 = values[split + 1..].iter().map(|x| (x - moved).abs()).sum();
+        left += to_left;
+        right -= to_right;
+        between += to_right - to_left;
+    }
+    best
+}
+
+/// Whether a split of `values` with energy distance `observed` is significant: the share of shuffled `values`
+/// whose best split has at least the same distance is at most `significance`
+fn is_significant(values: &[f64], observed: f64, significance: f64, rng: &mut XorShift) -> bool {
+    let mut shuffled = values.to_vec();
+    let as_large = (0..PERMUTATIONS)
+        .filter(|_| {
+            rng.shuffle(&mut shuffled);
+            best_split(&shuffled).is_some_and(|(_, energy)| energy >= observed)
+        })
+        .count();
+    (as_large + 1) as f64 / (PERMUTATIONS + 1) as f64 <= significance
+}
+
+/// Adds the change points of `values`, which start at `offset` in the whole series, to `changes`
+fn divide(
+    values: &[f64],
+    offset: usize,
+    significance: f64,
+    rng: &mut XorShift,
+    changes: &mut Vec<usize>,
+) {
+    let Some((split, observed)) = best_split(values) else {
+        return;
+    };
+    if observed <= 0.0 || !is_significant(values, observed, significance, rng) {
+        return;
+    }
+    divide(&values[..split], offset, significance, rng, changes);
+    changes.push(offset + split);
+    divide(&values[split..], offset + split, significance, rng, changes);
+}
+
+/// The indices where the distribution of `values` shifted, in order. A change point is the first value after
+/// the shift.
+pub(crate) fn e_divisive(values: &[f64], significance: f64) -> Vec<usize> {
+    let mut changes = Vec::new();
+    divide(values, 0, significance, &mut XorShift(SEED), &mut changes);
+    changes
+}
+
+#[test]
+fn test_e_divisive() {
+    let noise = [0.0, 1.5, -1.0, 0.5, -0.5, 1.0, -1.5, 0.0];
+    let series = |levels: &[f64]| -> Vec<f64> {
+        levels
+            .iter()
+            .flat_map(|level| noise.iter().map(move |n| level + n))
+            .collect()
+    };
+    assert_eq!(
+        e_divisive(&series(&[100.0]), SIGNIFICANCE),
+        Vec::<usize>::new()
+    );
+    assert_eq!(e_divisive(&series(&[100.0, 120.0]), SIGNIFICANCE), vec![8]);
+    assert_eq!(
+        e_divisive(&series(&[100.0, 120.0, 90.0]), SIGNIFICANCE),
+        vec![8, 16]
+    );
+    // a single outlier is not a shift of the distribution
+    let mut outlier = series(&[100.0, 100.0]);
+    outlier[5] = 150.0;
+    assert_eq!(e_divisive(&outlier, SIGNIFICANCE), Vec::<usize>::new());
+    assert_eq!(
+        e_divisive(&[1.0, 2.0, 3.0], SIGNIFICANCE),
+        Vec::<usize>::new()
+    );
+}
+
+#[test]
+fn test_best_split() {
+    let values: [f64; 8] = [3.0, 1.0, 4.0, 1.0, 5.0, 9.0, 2.0, 6.0];
+    let direct = |split: usize| {
+        let (l, r) = values.split_at(split);
+        let between: f64 = l
+            .iter()
+            .flat_map(|x| r.iter().map(move |y| (x - y).abs()))
+            .sum();
+        energy(
+            l.len(),
+            r.len(),
+            between,
+            (pair_distances(l), pair_distances(r)),
+        )
+    };
+    let (split, distance) = best_split(&values).unwrap();
+    let expected = (MIN_SEGMENT..=values.len() - MIN_SEGMENT)
+        .max_by(|a, b| direct(*a).total_cmp(&direct(*b)))
+        .unwrap();
+    assert_eq!(split, expected);
+    assert!((distance - direct(expected)).abs() < 1e-9);
+    assert_eq!(best_split(&[1.0, 2.0, 3.0]), None);
+}
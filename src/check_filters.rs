@@ -0,0 +1,68 @@
+//! The `check-filters` subcommand, which checks that every filter with a `reference_trace` still matches exactly
+//! one trace for its start and its end in it. Run in CI it catches renamed log strings of the app before they
+//! silently stop a metric on the dashboards.
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{Result, anyhow};
+use yansi::Paint;
+
+use crate::{
+    filter::Filter,
+    parser::{self, TraceFile},
+    runconfig::RunConfig,
+};
+
+/// Why `filter` does not match exactly once in `trace_file`, `None` if it does
+fn problem(filter: &Filter, trace_file: &TraceFile) -> Option<String> {
+    let (first, last) = filter.matching(&trace_file.traces);
+    (first.len() != 1 || last.len() != 1).then(|| {
+        format!(
+            "start matched {} and end matched {} traces, both have to match exactly one",
+            first.len(),
+            last.len()
+        )
+    })
+}
+
+/// Checks all filters of `run_configs` with a reference trace and prints the result of every filter.
+/// Fails if any filter does not match exactly once.
+pub(crate) fn run(run_configs: &[RunConfig]) -> Result<()> {
+    let mut traces: HashMap<&PathBuf, Result<TraceFile>> = HashMap::new();
+    let (mut checked, mut failed) = (0, 0);
+    for run_config in run_configs {
+        for filter in &run_config.filters {
+            let Some(path) = run_config.reference_traces.get(&filter.name) else {
+                continue;
+            };
+            let trace_file = traces
+                .entry(path)
+                .or_insert_with(|| parser::read_file(path, run_config.args.parse_error_policy));
+            let problem = match trace_file {
+                Ok(trace_file) => problem(filter, trace_file),
+                Err(e) => Some(format!("could not read {}: {e:#}", path.display())),
+            };
+            checked += 1;
+            match problem {
+                None => println!("{} {} in {}", "[ok]".green(), filter.name, path.display()),
+                Some(problem) => {
+                    failed += 1;
+                    println!(
+                        "{} {} in {}: {problem}",
+                        "[failed]".red(),
+                        filter.name,
+                        path.display()
+                    );
+                }
+            }
+        }
+    }
+    if checked == 0 {
+        return Err(anyhow!("No filter has a reference_trace"));
+    }
+    if failed > 0 {
+        return Err(anyhow!(
+            "{failed} of {checked} filters do not match their reference trace"
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,165 @@
+//! Instants of the different clocks in a trace file and the conversion between them.
+//! Hitrace timestamps use the boot clock while the paint times of LCP and FCP are `CrossProcessInstant`s of the
+//! monotonic clock, which stops during suspend. Values of both clocks can only be combined after converting
+//! them into the same domain.
+use std::sync::LazyLock;
+
+use anyhow::{Context, Result, anyhow};
+use regex::Regex;
+
+use crate::trace::Trace;
+
+/// The clock an instant was taken with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ClockDomain {
+    /// The boot clock of the trace timestamps
+    Trace,
+    /// The monotonic clock of `CrossProcessInstant`, i.e., LCP and FCP paint times
+    CrossProcess,
+}
+
+/// An instant in nanoseconds of a clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Instant {
+    pub(crate) nanos: i64,
+    pub(crate) domain: ClockDomain,
+}
+
+impl Instant {
+    /// The timestamp of `trace`
+    pub(crate) fn of_trace(trace: &Trace) -> Self {
+        Instant {
+            nanos: trace.timestamp.seconds as i64 * 1_000_000_000 + trace.timestamp.nanos as i64,
+            domain: ClockDomain::Trace,
+        }
+    }
+
+    /// A `CrossProcessInstant` value
+    pub(crate) fn cross_process(nanos: i64) -> Self {
+        Instant {
+            nanos,
+            domain: ClockDomain::CrossProcess,
+        }
+    }
+
+    /// Nanoseconds from `earlier` to this instant. Instants of different domains are converted with `correlation`
+    /// and fail without one.
+    pub(crate) fn since(
+        self,
+        earlier: Instant,
+        correlation: Option<&ClockCorrelation>,
+    ) -> Result<i64> {
+        let earlier = if earlier.domain == self.domain {
+            earlier
+        } else {
+            correlation
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Can not compare instants of the {:?} and {:?} clocks without a correlation",
+                        self.domain,
+                        earlier.domain
+                    )
+                })?
+                .convert(earlier, self.domain)
+        };
+        Ok(self.nanos - earlier.nanos)
+    }
+}
+
+/// `CrossProcessInstant { value: 231277222481376 }` anywhere in a trace
+static CROSS_PROCESS_INSTANT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"CrossProcessInstant\s*\{\s*value:\s*(\d+)\s*\}").expect("Could not parse regexp")
+});
+
+/// How the clocks of one trace file relate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ClockCorrelation {
+    /// Trace clock minus cross process clock in nanoseconds
+    offset: i64,
+    /// Whether the offset comes from a correlation marker and not from an estimate
+    pub(crate) exact: bool,
+}
+
+impl ClockCorrelation {
+    /// The correlation from the first trace containing `marker`. It has to contain a `CrossProcessInstant` taken
+    /// when the trace was emitted, i.e., `clock_sync|CrossProcessInstant { value: 231277100000000 }`.
+    pub(crate) fn from_marker(traces: &[Trace], marker: &str) -> Result<Self> {
+        let trace = traces
+            .iter()
+            .find(|t| t.function.contains(marker))
+            .with_context(|| format!("Could not find clock correlation marker {marker}"))?;
+        let value: i64 = CROSS_PROCESS_INSTANT
+            .captures(&trace.function)
+            .and_then(|groups| groups[1].parse().ok())
+            .with_context(|| {
+                format!("Clock correlation marker {marker} has no CrossProcessInstant")
+            })?;
+        Ok(ClockCorrelation {
+            offset: Instant::of_trace(trace).nanos - value,
+            exact: true,
+        })
+    }
+
+    /// An estimate from traces carrying a `CrossProcessInstant` that was taken shortly before the trace, i.e.,
+    /// LCP and FCP. The smallest difference between both is the best estimate of the offset.
+    pub(crate) fn estimate<'a>(instants: impl Iterator<Item = (&'a Trace, i64)>) -> Option<Self> {
+        instants
+            .map(|(trace, value)| Instant::of_trace(trace).nanos - value)
+            .min()
+            .map(|offset| ClockCorrelation {
+                offset,
+                exact: false,
+            })
+    }
+
+    /// `instant` in the clock `domain`
+    pub(crate) fn convert(&self, instant: Instant, domain: ClockDomain) -> Instant {
+        let nanos = match (instant.domain, domain) {
+            (ClockDomain::Trace, ClockDomain::CrossProcess) => instant.nanos - self.offset,
+            (ClockDomain::CrossProcess, ClockDomain::Trace) => instant.nanos + self.offset,
+            _ => instant.nanos,
+        };
+        Instant { nanos, domain }
+    }
+}
+
+#[test]
+fn test_clock_correlation() {
+    use crate::parser::{HitraceVersion, parse_line};
+
+    let trace = |time: &str, function: &str| {
+        parse_line(
+            HitraceVersion::V5,
+            &format!("<...>-1   (-------) [010] .... {time}: tracing_mark_write: B|1|H:{function}"),
+        )
+        .unwrap()
+        .unwrap()
+    };
+    let traces = [
+        trace("100.5", "on_surface_created_cb"),
+        trace(
+            "101.0",
+            "clock_sync|CrossProcessInstant { value: 40000000000 }",
+        ),
+        trace("101.200", "FirstContentfulPaint"),
+    ];
+    let correlation = ClockCorrelation::from_marker(&traces, "clock_sync").unwrap();
+    assert!(correlation.exact);
+    assert!(ClockCorrelation::from_marker(&traces, "on_surface_created_cb").is_err());
+
+    let paint = Instant::cross_process(40_200_000_000);
+    let start = Instant::of_trace(&traces[0]);
+    assert_eq!(paint.since(start, Some(&correlation)).unwrap(), 700_000_000);
+    assert!(paint.since(start, None).is_err());
+    assert_eq!(
+        correlation.convert(paint, ClockDomain::Trace),
+        Instant::of_trace(&traces[2])
+    );
+
+    let estimate = ClockCorrelation::estimate(
+        [(&traces[2], 40_100_000_000), (&traces[2], 40_150_000_000)].into_iter(),
+    )
+    .unwrap();
+    assert!(!estimate.exact);
+    assert_eq!(paint.since(start, Some(&estimate)).unwrap(), 750_000_000);
+}
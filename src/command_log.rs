@@ -0,0 +1,128 @@
+//! The `commands.log` in the `--artifacts` directory with every hdc command of the session, when it started, how
+//! long it took, its exit code and the start of its output, so a failed CI session can be reconstructed exactly.
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    process::{Command, Output},
+    sync::Mutex,
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+/// Name of the log in the artifacts directory
+const COMMAND_LOG: &str = "commands.log";
+
+/// We keep this many bytes of stdout and stderr, the output of `hitrace` or `file recv` can be huge
+const MAX_OUTPUT: usize = 2000;
+
+/// The log of the session, `None` without `--artifacts`
+static LOG: Mutex<Option<File>> = Mutex::new(None);
+
+/// Starts a new command log in `artifacts`
+pub(crate) fn start(artifacts: &Path) -> Result<()> {
+    fs::create_dir_all(artifacts)
+        .with_context(|| format!("Could not create {}", artifacts.display()))?;
+    let path = artifacts.join(COMMAND_LOG);
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&path)
+        .with_context(|| format!("Could not create {}", path.display()))?;
+    *LOG.lock().expect("Command log poisoned") = Some(file);
+    Ok(())
+}
+
+/// At most `MAX_OUTPUT` bytes of `bytes`, indented below the command
+fn truncated(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let mut end = text.len().min(MAX_OUTPUT);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut shown = text[..end].trim_end().replace('\n', "\n    ");
+    if end < text.len() {
+        shown.push_str(&format!(" [{} more bytes]", text.len() - end));
+    }
+    shown
+}
+
+/// The entry of `command` started at `started`, which took `seconds` and ended with `result`
+fn entry(
+    command: &Command,
+    started: OffsetDateTime,
+    seconds: f64,
+    result: &std::io::Result<Output>,
+) -> String {
+    let line = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let started = started
+        .format(&Rfc3339)
+        .unwrap_or_else(|_| started.to_string());
+    match result {
+        Ok(output) => {
+            let exit = output
+                .status
+                .code()
+                .map_or_else(|| String::from("signal"), |c| c.to_string());
+            let mut entry = format!("{started} {seconds:.3}s exit {exit}: {line}\n");
+            for (name, bytes) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+                if !bytes.is_empty() {
+                    entry.push_str(&format!("  {name}:\n    {}\n", truncated(bytes)));
+                }
+            }
+            entry
+        }
+        Err(e) => format!("{started} {seconds:.3}s failed to run: {line}\n  {e}\n"),
+    }
+}
+
+/// `Command::output` that also logs the command
+pub(crate) trait LoggedOutput {
+    fn logged_output(&mut self) -> std::io::Result<Output>;
+}
+
+impl LoggedOutput for Command {
+    fn logged_output(&mut self) -> std::io::Result<Output> {
+        let started = OffsetDateTime::now_utc();
+        let timer = Instant::now();
+        let result = self.output();
+        if let Some(ref mut file) = *LOG.lock().expect("Command log poisoned") {
+            let entry = entry(self, started, timer.elapsed().as_secs_f64(), &result);
+            // A broken log must not break the session
+            if let Err(e) = file.write_all(entry.as_bytes()) {
+                log::error!("Could not write the command log: {e}");
+            }
+        }
+        result
+    }
+}
+
+#[test]
+fn test_command_log_entry() {
+    let mut command = Command::new("hdc");
+    command.args(["-t", "SERIAL", "shell", "pidof", "org.servo.servo"]);
+    let started = time::macros::datetime!(2025-06-01 12:00:00 UTC);
+    let output = Output {
+        status: std::process::ExitStatus::default(),
+        stdout: Vec::new(),
+        stderr: "x".repeat(MAX_OUTPUT + 5).into_bytes(),
+    };
+    let entry_ok = entry(&command, started, 0.25, &Ok(output));
+    let mut lines = entry_ok.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "2025-06-01T12:00:00Z 0.250s exit 0: hdc -t SERIAL shell pidof org.servo.servo"
+    );
+    assert_eq!(lines.next().unwrap(), "  stderr:");
+    assert!(lines.next().unwrap().ends_with(" [5 more bytes]"));
+
+    let error = std::io::Error::new(std::io::ErrorKind::NotFound, "not found");
+    assert!(entry(&command, started, 0.0, &Err(error)).contains("failed to run: hdc -t SERIAL"));
+}
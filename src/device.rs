@@ -1,31 +1,277 @@
 //! Functions to handle the device
 use anyhow::{Context, Result, anyhow};
-use log::info;
+use log::{info, warn};
+use rust_decimal::Decimal;
+use sha2::{Digest, Sha256};
 use std::{
-    path::PathBuf,
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
     process::{Child, Command, Stdio},
     str::FromStr,
+    sync::{Mutex, OnceLock},
     thread,
     time::Duration,
 };
 
-use crate::args::RunArgs;
+use crate::{
+    args::{Pref, RunArgs},
+    command_log::LoggedOutput,
+    host,
+    noise::{BackgroundLoad, CpuSnapshot},
+    script, serve,
+    shutdown::{self, TracePull},
+    trace::ClockOffset,
+};
 
 const PROXY_PORT: &str = "8080";
 
+/// Where hitrace writes the trace on the device
+const DEVICE_TRACE_PATH: &str = "/data/local/tmp/ohtrace.txt";
+
+/// Name of the log of the app in the artifacts directory of a try
+pub(crate) const APP_LOG: &str = "hilog.txt";
+
+/// The ability we start in the app
+pub(crate) const ENTRY_ABILITY: &str = "EntryAbility";
+
+/// Number of traces a device can hold at once, one being received while the next try traces
+const TRACE_SLOTS: usize = 2;
+
+/// Where hitrace writes the trace of a try using `slot` on the device
+fn device_trace_path(slot: usize) -> String {
+    match slot {
+        0 => String::from(DEVICE_TRACE_PATH),
+        slot => format!("/data/local/tmp/ohtrace-{slot}.txt"),
+    }
+}
+
+/// Ports we forwarded from the devices to the host and have to remove the forward of again
+static FORWARDED_PORTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// hdc given with `--hdc-path`, otherwise we look for it in the path
+static HDC_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use the hdc at `path` instead of the one in the path
+pub(crate) fn set_hdc_path(path: PathBuf) {
+    let _ = HDC_PATH.set(path);
+}
+
+/// The hdc binary we use
+pub(crate) fn hdc_path() -> Result<PathBuf> {
+    match HDC_PATH.get() {
+        Some(path) => Ok(path.clone()),
+        None => host::find_hdc().ok_or_else(|| {
+            anyhow!("Is hdc in the path or OHOS_SDK_HOME set? Otherwise pass --hdc-path")
+        }),
+    }
+}
+
+/// Version of hdc as printed by `hdc -v`, i.e., `Ver: 3.1.0e`
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct HdcVersion {
+    major: u32,
+    minor: u32,
+    patch: u32,
+    revision: Option<char>,
+}
+
+/// The oldest hdc that knows all flags we use, i.e., `file send -b` and `rport`
+pub(crate) const MIN_HDC_VERSION: HdcVersion = HdcVersion {
+    major: 3,
+    minor: 1,
+    patch: 0,
+    revision: Some('a'),
+};
+
+impl FromStr for HdcVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let version = s.trim();
+        let version = version.strip_prefix("Ver:").unwrap_or(version).trim();
+        let error = || anyhow!("Could not parse hdc version `{version}`");
+        let mut parts = version.split('.');
+        let mut number = || -> Result<&str> { parts.next().ok_or_else(error) };
+        let major = number()?.parse().map_err(|_| error())?;
+        let minor = number()?.parse().map_err(|_| error())?;
+        let last = number()?;
+        let digits = last
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(last.len());
+        let patch = last[..digits].parse().map_err(|_| error())?;
+        let revision = match &last[digits..] {
+            "" => None,
+            r if r.len() == 1 => r.chars().next(),
+            _ => return Err(error()),
+        };
+        Ok(HdcVersion {
+            major,
+            minor,
+            patch,
+            revision,
+        })
+    }
+}
+
+impl std::fmt::Display for HdcVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(revision) = self.revision {
+            write!(f, "{revision}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The version of the hdc we use
+pub(crate) fn hdc_version() -> Result<HdcVersion> {
+    hdc_output(None, &["-v"])?.parse()
+}
+
+/// Fails with what to do if our hdc is too old for the flags we use
+pub(crate) fn check_hdc_version() -> Result<()> {
+    let version = hdc_version()?;
+    if version < MIN_HDC_VERSION {
+        return Err(anyhow!(
+            "hdc {version} at {:?} is too old, we need at least {MIN_HDC_VERSION}. \
+             Update the OpenHarmony SDK or pass a newer hdc with --hdc-path.",
+            hdc_path()?
+        ));
+    }
+    Ok(())
+}
+
+/// A hdc command talking to `device`, or to the only connected device if `None`
+fn hdc_command(device: Option<&str>) -> Result<Command> {
+    let mut cmd = Command::new(hdc_path()?);
+    if let Some(device) = device {
+        cmd.args(["-t", device]);
+    }
+    Ok(cmd)
+}
+
+/// Runs `args` with hdc on `device` and returns its stdout
+pub(crate) fn hdc_output(device: Option<&str>, args: &[&str]) -> Result<String> {
+    let output = hdc_command(device)?
+        .args(args)
+        .logged_output()
+        .context("Could not execute hdc")?;
+    String::from_utf8(output.stdout).context("Hdc reported weird characters")
+}
+
+/// The serials of all connected devices
+pub(crate) fn list_devices() -> Result<Vec<String>> {
+    let output = hdc_command(None)?
+        .args(["list", "targets"])
+        .logged_output()?;
+    let output = String::from_utf8(output.stdout).context("Hdc reported weird characters")?;
+    Ok(output
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && *l != "[Empty]")
+        .map(str::to_owned)
+        .collect())
+}
+
+/// If `device` is connected, or any device if `None`
+pub(crate) fn is_connected(device: Option<&str>) -> bool {
+    list_devices().is_ok_and(|devices| match device {
+        Some(device) => devices.iter().any(|d| d == device),
+        None => !devices.is_empty(),
+    })
+}
+
+/// Polls until `device` is connected again. Returns false if it did not come back within `timeout`.
+pub(crate) fn wait_for_device(device: Option<&str>, timeout: Duration) -> bool {
+    let start = std::time::Instant::now();
+    while start.elapsed() < timeout {
+        if is_connected(device) {
+            return true;
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+    is_connected(device)
+}
+
+/// How long a device may take to reboot
+const REBOOT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Tries run on a device since we last rebooted it, by serial or the empty string for the only device
+static TRIES_SINCE_REBOOT: Mutex<BTreeMap<String, u32>> = Mutex::new(BTreeMap::new());
+
+/// Counts a try about to start on `device` and tells if we have to reboot before it
+fn reboot_due(tries_since_reboot: &mut u32, every: u32) -> bool {
+    if *tries_since_reboot >= every {
+        *tries_since_reboot = 1;
+        true
+    } else {
+        *tries_since_reboot += 1;
+        false
+    }
+}
+
+/// Reboots `device` before a try if `every` tries ran on it since the last reboot
+pub(crate) fn reboot_if_due(device: Option<&str>, every: u32) -> Result<()> {
+    let due = reboot_due(
+        TRIES_SINCE_REBOOT
+            .lock()
+            .unwrap()
+            .entry(device.unwrap_or_default().to_owned())
+            .or_default(),
+        every,
+    );
+    if due { reboot(device) } else { Ok(()) }
+}
+
+/// Reboots `device`, waits until it booted completely and unlocks it
+fn reboot(device: Option<&str>) -> Result<()> {
+    info!("Rebooting {}", device.unwrap_or("device"));
+    hdc_command(device)?
+        .args(["target", "boot"])
+        .logged_output()
+        .context("Could not reboot")?;
+    let start = std::time::Instant::now();
+    // Give the device time to go away before we wait for it
+    thread::sleep(Duration::from_secs(5));
+    if !wait_for_device(device, REBOOT_TIMEOUT) {
+        return Err(anyhow!("Device did not come back after rebooting"));
+    }
+    while hdc_output(
+        device,
+        &["shell", "param", "get", "bootevent.boot.completed"],
+    )
+    .is_ok_and(|o| o.trim() != "true")
+    {
+        if start.elapsed() > REBOOT_TIMEOUT {
+            return Err(anyhow!("Device did not finish booting"));
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+    hdc_output(device, &["shell", "power-shell", "wakeup"])?;
+    // Swipe up on the lock screen
+    hdc_output(
+        device,
+        &["shell", "uinput", "-T", "-m", "300", "1500", "300", "300"],
+    )?;
+    info!("Rebooted in {:?}", start.elapsed());
+    Ok(())
+}
+
 /// We test if the device is reachable, i.e., the list of hdc list targets is non empty.
 /// It can happen that another IDE is connected to it and then we cannot reach it (and no command fails)
 pub(crate) fn is_device_reachable() -> Result<bool> {
-    let hdc = which::which("hdc").context("Is hdc in the path?")?;
-    let cmd = Command::new(&hdc).args(["list", "targets"]).output()?;
-    Ok(!cmd.stdout.is_empty())
+    Ok(!list_devices()?.is_empty())
 }
 
 /// We sometimes want to stop the trace because we interrupted the program
-pub(crate) fn stop_tracing(buffer: u64) -> Result<()> {
-    let hdc = which::which("hdc").context("Is hdc in the path?")?;
-    // stop trace
-    Command::new(&hdc)
+pub(crate) fn stop_tracing(device: Option<&str>, buffer: u64) -> Result<()> {
+    finish_tracing(device, buffer, DEVICE_TRACE_PATH)
+}
+
+/// Stops tracing and writes the trace to `output` on the device
+fn finish_tracing(device: Option<&str>, buffer: u64, output: &str) -> Result<()> {
+    hdc_command(device)?
         .args([
             "shell",
             "hitrace",
@@ -33,37 +279,251 @@ pub(crate) fn stop_tracing(buffer: u64) -> Result<()> {
             &buffer.to_string(),
             "--trace_finish",
             "-o",
-            "/data/local/tmp/ohtrace.txt",
+            output,
         ])
-        .output()
+        .logged_output()
         .map(|_| ())
         .map_err(|_| anyhow!("Could not stop trace"))
 }
 
-/// Take a screenshot and return the Path on the host, not the phone. Currently the path is fixed.
-fn take_screenshot() -> Result<PathBuf> {
-    let hdc = which::which("hdc").context("Is hdc in the path?")?;
+/// Stops tracing and the apps, removes the trace from the device and the port forwards we added.
+/// Every step runs even if an earlier one failed, i.e., stopping the trace fails if nothing traces.
+pub(crate) fn cleanup(device: Option<&str>, bundle_names: &[String], buffer: u64) -> Result<()> {
+    let hdc = |args: &[String], what: &str| -> Result<()> {
+        hdc_command(device)?
+            .args(args)
+            .logged_output()
+            .map(|_| ())
+            .context(what.to_owned())
+    };
+    let mut steps = vec![stop_tracing(device, buffer)];
+    for bundle_name in bundle_names {
+        steps.push(hdc(
+            &[
+                "shell".into(),
+                "aa".into(),
+                "force-stop".into(),
+                bundle_name.clone(),
+            ],
+            "Could not stop app",
+        ));
+    }
+    for slot in 0..TRACE_SLOTS {
+        steps.push(hdc(
+            &[
+                "shell".into(),
+                "rm".into(),
+                "-f".into(),
+                device_trace_path(slot),
+            ],
+            "Could not remove trace from device",
+        ));
+    }
+    // The handler might have interrupted a thread holding the lock
+    let ports = FORWARDED_PORTS
+        .try_lock()
+        .map(|p| p.clone())
+        .unwrap_or_default();
+    for port in ports {
+        steps.push(hdc(
+            &[
+                "fport".into(),
+                "rm".into(),
+                format!("tcp:{port}"),
+                format!("tcp:{port}"),
+            ],
+            "Could not remove port forward",
+        ));
+    }
+    let failed: Vec<String> = steps
+        .into_iter()
+        .filter_map(Result::err)
+        .map(|e| {
+            warn!("Cleanup step failed: {e:?}");
+            format!("{e:#}")
+        })
+        .collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} cleanup steps failed: {}",
+            failed.len(),
+            failed.join("; ")
+        ))
+    }
+}
+
+/// Lets the device reach `port` of the host on its own `port`, unless that is already forwarded
+fn reverse_forward(device: Option<&str>, port: &str) -> Result<()> {
+    let ports_forwarded = hdc_command(device)?.args(["fport", "ls"]).logged_output()?;
+    let output =
+        String::from_utf8(ports_forwarded.stdout).context("Hdc reported weird characters")?;
+    if !output.contains(&format!("tcp:{port}")) {
+        hdc_command(device)?
+            .args(["rport".into(), format!("tcp:{port}"), format!("tcp:{port}")])
+            .logged_output()
+            .context("Could not forward port")?;
+        let mut ports = FORWARDED_PORTS.lock().unwrap();
+        if !ports.iter().any(|p| p == port) {
+            ports.push(port.to_owned());
+        }
+    }
+    Ok(())
+}
+
+/// The url of `page` on the host http server on `port`. `page` is a path relative to the served directory.
+fn served_url(page: &str, port: u16) -> String {
+    let path = page.trim_start_matches("file:///").trim_start_matches('/');
+    format!("http://127.0.0.1:{port}/{path}")
+}
+
+/// Path on the host we receive the trace of `device` using `slot` to
+fn host_trace_path(device: Option<&str>, slot: usize) -> PathBuf {
+    // Devices of a farm trace at the same time so they need their own file
+    let device = device.map(|d| format!("-{d}")).unwrap_or_default();
+    host::temp_path(&match slot {
+        0 => format!("app{device}.ftrace"),
+        slot => format!("app{device}-{slot}.ftrace"),
+    })
+}
+
+/// Removes the traces we received from all devices
+pub(crate) fn remove_host_traces() {
+    let devices = list_devices().unwrap_or_default();
+    for slot in 0..TRACE_SLOTS {
+        for path in devices
+            .iter()
+            .map(|d| host_trace_path(Some(d), slot))
+            .chain(std::iter::once(host_trace_path(None, slot)))
+        {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Take a screenshot and return the Path on the host, not the phone
+fn take_screenshot(device: Option<&str>) -> Result<PathBuf> {
     const DEVICE_PATH: &str = "/data/local/tmp/servo.jpeg";
+    let host_path = host::temp_path(&match device {
+        Some(device) => format!("servo-{device}.jpeg"),
+        None => String::from("servo.jpeg"),
+    });
     // if the delete does not work we do not really care
-    let _ = Command::new(&hdc)
+    let _ = hdc_command(device)?
         .args(["rm", "-f", DEVICE_PATH])
-        .output()
+        .logged_output()
         .map(|_| ());
-    Command::new(&hdc)
+    hdc_command(device)?
         .args(["shell", "snapshot_display", "-f", DEVICE_PATH])
-        .output()
+        .logged_output()
         .map(|_| ())
         .map_err(|_| anyhow!("Could not take screenshot"))?;
-    Command::new(&hdc)
-        .args(["file", "recv", DEVICE_PATH, "/tmp/servo.jpeg"])
-        .output()
+    hdc_command(device)?
+        .args(["file", "recv", DEVICE_PATH])
+        .arg(&host_path)
+        .logged_output()
         .map(|_| ())
         .map_err(|_| anyhow!("Could not transfer screenshot"))?;
 
-    PathBuf::from_str("/tmp/servo.jpg").map_err(|_| anyhow!("Could not convert file"))
+    Ok(host_path)
+}
+
+/// Reads the realtime clock and the uptime of the device in one shell call and computes the offset between them.
+fn read_clock_offset(device: Option<&str>) -> Result<ClockOffset> {
+    let output = hdc_command(device)?
+        .args(["shell", "date +%s.%N; cat /proc/uptime"])
+        .logged_output()
+        .context("Could not read device clock")?;
+    let output = String::from_utf8(output.stdout).context("Hdc reported weird characters")?;
+    let mut lines = output.lines();
+    let realtime: Decimal = lines
+        .next()
+        .and_then(|l| l.trim().parse().ok())
+        .ok_or_else(|| anyhow!("Could not parse device realtime from {output:?}"))?;
+    let uptime: Decimal = lines
+        .next()
+        .and_then(|l| l.split_whitespace().next())
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| anyhow!("Could not parse device uptime from {output:?}"))?;
+    ClockOffset::from_realtime_and_uptime(realtime, uptime)
+}
+
+/// `bm dump -n <bundle>` prints the bundle info as json or an error if it is not installed
+fn bundle_in_dump(output: &str, bundle_name: &str) -> bool {
+    output.contains(&format!("\"{bundle_name}\"")) && !output.contains("error:")
+}
+
+/// Checks with the bundle manager if `bundle_name` is installed on `device`
+pub(crate) fn is_bundle_installed(device: Option<&str>, bundle_name: &str) -> Result<bool> {
+    let output = hdc_output(device, &["shell", "bm", "dump", "-n", bundle_name])?;
+    Ok(bundle_in_dump(&output, bundle_name))
+}
+
+/// The app we should benchmark is not installed on the device
+#[derive(Debug)]
+pub(crate) struct BundleNotInstalled(String);
+
+impl std::fmt::Display for BundleNotInstalled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Bundle {} is not installed. Install it with `hdc install <path to the hap>`.",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for BundleNotInstalled {}
+
+/// The app did not start or crashed during a try
+#[derive(Debug)]
+pub(crate) struct AppCrashed(String);
+
+impl std::fmt::Display for AppCrashed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} did not start or crashed. Please check the application logs.",
+            self.0
+        )
+    }
 }
 
+impl std::error::Error for AppCrashed {}
+
+/// The result of tracing on the device. The trace is still on the device until we `receive` it.
 #[derive(Debug)]
+pub(crate) struct DeviceTrace {
+    /// Path of the trace on the device
+    device_path: String,
+    /// Path we receive the trace to on the host
+    pub(crate) path: PathBuf,
+    /// Offset of the device boot clock to the wall clock at trace start
+    pub(crate) clock_offset: Option<ClockOffset>,
+    /// Load of other processes while we waited for the app
+    pub(crate) background_load: Option<BackgroundLoad>,
+    /// An interruption waits until we are done with the trace
+    _pull: TracePull,
+}
+
+impl DeviceTrace {
+    /// Receives the trace from `device` to `path`
+    pub(crate) fn receive(&self, device: Option<&str>) -> Result<()> {
+        info!("Writing ftrace to {}", self.path.display());
+        let mut recv = hdc_command(device)?;
+        // Ctrl-C goes to the whole process group, hdc has to keep receiving while we wait for it
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut recv, 0);
+        recv.args(["file", "recv", &self.device_path])
+            .arg(&self.path)
+            .logged_output()
+            .context("Could not receive trace")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
 struct DeviceFilePaths {
     /// The file path to the file on disk
     stem: String,
@@ -74,66 +534,314 @@ struct DeviceFilePaths {
 }
 
 /// Depending on root or non-rooted we will have different file paths. This gives us these paths.
-fn device_file_paths(file_name: &str, bundle_name: &str, is_rooted: bool) -> DeviceFilePaths {
+/// The file, or the whole `asset_dir` the file is in, ends up in the cache directory of the app.
+/// Rooted we write there directly, for that we need the user the app is installed for.
+/// Non-rooted hdc can only write into the sandbox of a debuggable app, the path is relative to the sandbox.
+fn device_file_paths(
+    file_name: &str,
+    bundle_name: &str,
+    user: Option<&str>,
+    asset_dir: Option<&Path>,
+) -> DeviceFilePaths {
     let real_file_name = file_name.trim_start_matches("file:///");
+    let base_name = |path: &Path| {
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default()
+            .to_owned()
+    };
+    let (stem, pushed, in_app) = match asset_dir {
+        Some(asset_dir) => {
+            let pushed = base_name(asset_dir);
+            let in_app = format!("{pushed}/{}", host::device_path(Path::new(real_file_name)));
+            (asset_dir.to_string_lossy().into_owned(), pushed, in_app)
+        }
+        None => {
+            let pushed = base_name(Path::new(real_file_name));
+            (real_file_name.to_owned(), pushed.clone(), pushed)
+        }
+    };
 
-    if is_rooted {
-        DeviceFilePaths {
-            stem: real_file_name.to_owned(),
-            in_app: format!("file:///data/storage/el2/base/cache/{real_file_name}"),
-            on_device: format!("/data/app/el2/100/base/{bundle_name}/cache/{real_file_name}"),
+    DeviceFilePaths {
+        stem,
+        in_app: format!("file:///data/storage/el2/base/cache/{in_app}"),
+        on_device: match user {
+            Some(user) => format!("/data/app/el2/{user}/base/{bundle_name}/cache/{pushed}"),
+            None => format!("data/storage/el2/base/cache/{pushed}"),
+        },
+    }
+}
+
+/// The user in the output of `ls -d /data/app/el2/*/base/<bundle>`
+fn user_from_app_dir(output: &str) -> Option<&str> {
+    output
+        .lines()
+        .next()
+        .and_then(|l| l.trim().strip_prefix("/data/app/el2/"))
+        .and_then(|l| l.split('/').next())
+        .filter(|user| !user.is_empty() && user.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// The user `bundle_name` is installed for, we can only look that up on rooted devices
+fn app_user(device: Option<&str>, bundle_name: &str) -> Result<String> {
+    let output = hdc_output(
+        device,
+        &[
+            "shell",
+            &format!("ls -d /data/app/el2/*/base/{bundle_name}"),
+        ],
+    )?;
+    user_from_app_dir(&output)
+        .map(str::to_owned)
+        .ok_or_else(|| anyhow!("Could not find the data directory of {bundle_name}"))
+}
+
+/// Sha256 of every file below `path` by their path relative to `path`, or of `path` itself if it is a file
+fn tree_hashes(path: &Path) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+    let mut hashes = BTreeMap::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        // Joining an empty path would add a trailing slash
+        let full = if relative.as_os_str().is_empty() {
+            path.to_owned()
+        } else {
+            path.join(&relative)
+        };
+        if full.is_dir() {
+            for entry in
+                std::fs::read_dir(&full).with_context(|| format!("Could not read {full:?}"))?
+            {
+                pending.push(relative.join(entry?.file_name()));
+            }
+        } else {
+            let content =
+                std::fs::read(&full).with_context(|| format!("Could not read {full:?}"))?;
+            hashes.insert(relative, Sha256::digest(content).to_vec());
+        }
+    }
+    Ok(hashes)
+}
+
+/// A local file or asset directory pushed to the device for a try. It is removed from the device again when dropped,
+/// as far as we can.
+struct PushedFile<'a> {
+    device: Option<&'a str>,
+    bundle_name: &'a str,
+    is_rooted: bool,
+    paths: DeviceFilePaths,
+}
+
+impl<'a> PushedFile<'a> {
+    /// Pushes the file of the `file:///` url, or the `asset_dir` it is in, and checks it arrived intact by
+    /// pulling it again
+    fn push(
+        device: Option<&'a str>,
+        url: &str,
+        asset_dir: Option<&Path>,
+        bundle_name: &'a str,
+        is_rooted: bool,
+    ) -> Result<PushedFile<'a>> {
+        let user = if is_rooted {
+            Some(app_user(device, bundle_name)?)
+        } else {
+            None
+        };
+        let paths = device_file_paths(url, bundle_name, user.as_deref(), asset_dir);
+        info!(
+            "Uploading to {} visible as {}",
+            paths.on_device, paths.in_app
+        );
+        let pushed = PushedFile {
+            device,
+            bundle_name,
+            is_rooted,
+            paths,
+        };
+        hdc_command(device)?
+            .args(pushed.file_args(
+                "send",
+                pushed.paths.stem.as_ref(),
+                pushed.paths.on_device.as_ref(),
+            ))
+            .logged_output()
+            .context("Could not push file")?;
+
+        let check_path = host::temp_path("hitrace-bench-pushed-file");
+        let _ = std::fs::remove_dir_all(&check_path);
+        let _ = std::fs::remove_file(&check_path);
+        hdc_command(device)?
+            .args(pushed.file_args(
+                "recv",
+                pushed.paths.on_device.as_ref(),
+                check_path.as_os_str(),
+            ))
+            .logged_output()
+            .context("Could not pull pushed file")?;
+        let intact = tree_hashes(&check_path)? == tree_hashes(Path::new(&pushed.paths.stem))?;
+        let _ = std::fs::remove_dir_all(&check_path);
+        let _ = std::fs::remove_file(&check_path);
+        if !intact {
+            return Err(anyhow!(
+                "{} did not arrive intact on the device",
+                pushed.paths.stem
+            ));
+        }
+        Ok(pushed)
+    }
+
+    fn file_args(&self, direction: &str, from: &OsStr, to: &OsStr) -> Vec<OsString> {
+        file_args(self.bundle_name, self.is_rooted, direction, from, to)
+    }
+
+    /// Removes only the pushed file from the device. Non-rooted the shell cannot reach into the sandbox of the
+    /// app, and clearing the whole cache of the app would change what the next tries measure.
+    fn remove(&self) -> Result<()> {
+        if !self.is_rooted {
+            return Err(anyhow!(
+                "Cannot remove {} from the sandbox of {} on a non-rooted device without clearing its cache, \
+                 it stays there until the next push overwrites it",
+                self.paths.on_device,
+                self.bundle_name
+            ));
         }
+        hdc_command(self.device)?
+            .args(["shell", "rm", "-rf", &self.paths.on_device])
+            .logged_output()
+            .with_context(|| {
+                format!("Could not remove {} from the device", self.paths.on_device)
+            })?;
+        Ok(())
+    }
+}
+
+/// Arguments for `hdc file send/recv`, non-rooted we have to go through the sandbox of the app
+fn file_args(
+    bundle_name: &str,
+    is_rooted: bool,
+    direction: &str,
+    from: &OsStr,
+    to: &OsStr,
+) -> Vec<OsString> {
+    let mut args = vec![OsString::from("file"), OsString::from(direction)];
+    if !is_rooted {
+        args.extend([OsString::from("-b"), OsString::from(bundle_name)]);
+    }
+    args.extend([from.to_owned(), to.to_owned()]);
+    args
+}
+
+/// Clears the log of the device so the log we collect as artifact only contains the next try
+pub(crate) fn clear_app_log(device: Option<&str>) -> Result<()> {
+    hdc_command(device)?
+        .args(["shell", "hilog", "-r"])
+        .logged_output()
+        .context("Could not clear log")?;
+    Ok(())
+}
+
+/// Saves the log of the device, where the app writes its stdout, stderr and panics, and the files directory of
+/// the app, where the profilers write their output, to `target`
+pub(crate) fn collect_artifacts(
+    device: Option<&str>,
+    run_args: &RunArgs,
+    is_rooted: bool,
+    target: &Path,
+) -> Result<()> {
+    std::fs::create_dir_all(target).with_context(|| format!("Could not create {target:?}"))?;
+    let log = hdc_output(device, &["shell", "hilog", "-x"])?;
+    std::fs::write(target.join(APP_LOG), log).context("Could not write log")?;
+
+    let files_dir = if is_rooted {
+        let user = app_user(device, &run_args.bundle_name)?;
+        format!("/data/app/el2/{user}/base/{}/files", run_args.bundle_name)
     } else {
-        DeviceFilePaths {
-            stem: real_file_name.to_owned(),
-            in_app: format!(
-                "file:///data/storage/el1/bundle/servoshell/resources/resfile/{real_file_name}"
-            ),
-            on_device: String::new(),
+        String::from("data/storage/el2/base/files")
+    };
+    let target_files = target.join("files");
+    hdc_command(device)?
+        .args(file_args(
+            &run_args.bundle_name,
+            is_rooted,
+            "recv",
+            files_dir.as_ref(),
+            target_files.as_os_str(),
+        ))
+        .logged_output()
+        .context("Could not receive the files of the app")?;
+    Ok(())
+}
+
+impl Drop for PushedFile<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.remove() {
+            warn!("{e:?}");
         }
     }
 }
 
-/// Execute the hdc commands on the device.
-pub(crate) fn exec_hdc_commands(run_args: &RunArgs, is_rooted: bool) -> Result<PathBuf> {
+/// Arguments for `aa start` setting the servo preferences, `prefs` override our defaults
+fn pref_args(prefs: &[Pref]) -> Vec<String> {
+    let mut merged = BTreeMap::from([(String::from("js_disable_jit"), String::from("true"))]);
+    for pref in prefs {
+        merged.insert(pref.key.clone(), pref.value.clone());
+    }
+    merged
+        .into_iter()
+        .flat_map(|(key, value)| [String::from("--ps=--pref"), format!("{key}={value}")])
+        .collect()
+}
+
+/// Execute the hdc commands on the device, or on the only connected device if `device` is `None`.
+/// The trace is written to `slot` on the device, tries using different slots can be received at the same time.
+pub(crate) fn exec_hdc_commands(
+    run_args: &RunArgs,
+    is_rooted: bool,
+    device: Option<&str>,
+    slot: usize,
+) -> Result<DeviceTrace> {
     info!("Executing hdc commands");
-    let hdc = which::which("hdc").context("Is hdc in the path?")?;
+    // Otherwise we would only notice after tracing and sleeping, and report it as a crash
+    if !is_bundle_installed(device, &run_args.bundle_name)? {
+        return Err(BundleNotInstalled(run_args.bundle_name.clone()).into());
+    }
     // stop the app before starting the test
-    Command::new(&hdc)
+    hdc_command(device)?
         .args(["shell", "aa", "force-stop", &run_args.bundle_name])
-        .output()
+        .logged_output()
         .context("Could not execute hdc")?;
 
-    let url = if run_args.url.contains("file:///") {
-        let device_file_path = device_file_paths(&run_args.url, &run_args.bundle_name, is_rooted);
-
-        if is_rooted {
-            info!(
-                "Uploading to {} visible as {}",
-                device_file_path.on_device, device_file_path.in_app
-            );
-            Command::new(&hdc)
-                .args([
-                    "file",
-                    "send",
-                    &device_file_path.stem,
-                    &device_file_path.on_device,
-                ])
-                .output()?;
+    let served_url = match run_args.serve {
+        Some(ref dir) => {
+            let port = serve::serve(dir)?;
+            reverse_forward(device, &port.to_string())?;
+            Some(served_url(&run_args.url, port))
         }
-        device_file_path.in_app
+        None => None,
+    };
+    // Kept on the device until tracing stopped at the end of this function, the app does not need it after that
+    let pushed_file = if served_url.is_none() && run_args.url.contains("file:///") {
+        Some(PushedFile::push(
+            device,
+            &run_args.url,
+            run_args.asset_dir.as_deref(),
+            &run_args.bundle_name,
+            is_rooted,
+        )?)
     } else {
-        run_args.url.clone()
+        None
     };
+    let url = served_url
+        .or_else(|| pushed_file.as_ref().map(|f| f.paths.in_app.clone()))
+        .unwrap_or_else(|| run_args.url.clone());
 
     let _mitmproxy = if run_args.mitmproxy {
-        MitmProxy::new().ok()
+        MitmProxy::new(device).ok()
     } else {
         None
     };
 
     // start trace
-    Command::new(&hdc)
+    hdc_command(device)?
         .args([
             "shell",
             "hitrace",
@@ -147,26 +855,30 @@ pub(crate) fn exec_hdc_commands(run_args: &RunArgs, is_rooted: bool) -> Result<P
             "memory",
             "--trace_begin",
         ])
-        .output()?;
+        .logged_output()?;
+
+    // Not being able to correlate with the wall clock should not stop the benchmark.
+    let clock_offset = read_clock_offset(device)
+        .inspect_err(|e| log::error!("Could not read device clock offset: {e:?}"))
+        .ok();
 
     // start the ability
-    let mut ability_start_arg = Command::new(&hdc);
+    let mut ability_start_arg = hdc_command(device)?;
     ability_start_arg.args([
         "shell",
         "aa",
         "start",
         "-a",
-        "EntryAbility",
+        ENTRY_ABILITY,
         "-b",
         &run_args.bundle_name,
         "-U",
         &url,
-        "--ps=--pref",
-        "js_disable_jit=true",
         "--ps=--tracing-filter",
-        "trace",
+        &run_args.tracing_filter,
         "--psn=--pref=largest_contentful_paint_enabled=true",
     ]);
+    ability_start_arg.args(pref_args(&run_args.prefs));
     if let Some(ref v) = run_args.commands {
         for i in v {
             ability_start_arg.arg(i);
@@ -186,19 +898,44 @@ pub(crate) fn exec_hdc_commands(run_args: &RunArgs, is_rooted: bool) -> Result<P
         ability_start_arg.arg("--psn=--ignore-certificate-errors");
     }
 
-    ability_start_arg.output()?;
+    ability_start_arg.logged_output()?;
     // Getting app pid is a simple test if the app perhaps crashed during the benchmark / test.
     // Because teh app might finish rendering really fast, we need to be fast to check for the pid.
     std::thread::sleep(std::time::Duration::from_millis(100));
-    let cmd = Command::new(&hdc)
+    let cmd = hdc_command(device)?
         .args(["shell", "pidof", &run_args.bundle_name])
-        .output()
+        .logged_output()
         .with_context(|| format!("Is `{}` installed?", run_args.bundle_name))?;
+    let cpu_before = CpuSnapshot::read(device);
+    if !cmd.stdout.is_empty()
+        && let Err(e) = script::run(
+            device,
+            &run_args.script(),
+            &script::App {
+                bundle_name: &run_args.bundle_name,
+                url: &url,
+            },
+        )
+    {
+        stop_tracing(device, run_args.trace_buffer)?;
+        return Err(e);
+    }
     info!("Sleeping for {}", run_args.sleep);
     std::thread::sleep(std::time::Duration::from_secs(run_args.sleep));
+    let background_load = match (cpu_before, CpuSnapshot::read(device)) {
+        (Ok(before), Ok(after)) => Some(BackgroundLoad::between(
+            &before,
+            &after,
+            &run_args.bundle_name,
+        )),
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("Could not read the cpu load: {e:?}");
+            None
+        }
+    };
 
     if cmd.stdout.is_empty() {
-        Command::new(&hdc)
+        hdc_command(device)?
             .args([
                 "shell",
                 "hitrace",
@@ -206,52 +943,32 @@ pub(crate) fn exec_hdc_commands(run_args: &RunArgs, is_rooted: bool) -> Result<P
                 &run_args.trace_buffer.to_string(),
                 "--trace_finish",
                 "-o",
-                "/data/local/tmp/ohtrace.txt",
+                DEVICE_TRACE_PATH,
             ])
-            .output()?;
-        let path = take_screenshot()?;
+            .logged_output()?;
+        let path = take_screenshot(device)?;
         println!("Took screenshot {path:?}");
-        return Err(anyhow!(
-            "{} did not start or crashed. Please check the application logs.",
-            run_args.bundle_name
-        ));
+        return Err(AppCrashed(run_args.bundle_name.clone()).into());
     }
-    stop_tracing(run_args.trace_buffer)?;
+    // An interruption from now on waits for this try to finish
+    let pull = shutdown::begin_trace_pull();
+    let device_path = device_trace_path(slot % TRACE_SLOTS);
+    finish_tracing(device, run_args.trace_buffer, &device_path)?;
 
-    let mut tmp_path = std::env::temp_dir();
-    tmp_path.push("app.ftrace");
-    info!("Writing ftrace to {}", tmp_path.to_str().unwrap());
-    // Receive trace
-    Command::new(&hdc)
-        .args([
-            "file",
-            "recv",
-            "/data/local/tmp/ohtrace.txt",
-            tmp_path.to_str().unwrap(),
-        ])
-        .output()?;
-
-    Ok(tmp_path)
+    Ok(DeviceTrace {
+        device_path,
+        path: host_trace_path(device, slot % TRACE_SLOTS),
+        clock_offset,
+        background_load,
+        _pull: pull,
+    })
 }
 
 struct MitmProxy(Child);
 
 impl MitmProxy {
-    fn new() -> Result<Self> {
-        let hdc = which::which("hdc").context("Is hdc in the path?")?;
-        let ports_forwarded = Command::new(&hdc).args(["fport", "ls"]).output()?;
-        let output =
-            String::from_utf8(ports_forwarded.stdout).context("Hdc reported weird characters")?;
-        if !output.contains(PROXY_PORT) {
-            Command::new(&hdc)
-                .args([
-                    "rport".into(),
-                    format!("tcp:{}", PROXY_PORT),
-                    format!("tcp:{}", PROXY_PORT),
-                ])
-                .output()
-                .context("Could not forward port")?;
-        }
+    fn new(device: Option<&str>) -> Result<Self> {
+        reverse_forward(device, PROXY_PORT)?;
 
         let mitmdump = which::which("mitmdump").context("Is mitmdump in path?")?;
         let mut mitmdump_cmd = Command::new(mitmdump);
@@ -284,3 +1001,123 @@ impl Drop for MitmProxy {
         }
     }
 }
+
+#[test]
+fn test_bundle_in_dump() {
+    assert!(bundle_in_dump(
+        "org.servo.servo:\n{\n\"name\": \"org.servo.servo\"\n}",
+        "org.servo.servo"
+    ));
+    assert!(!bundle_in_dump(
+        "error: failed to get information and the parameters may be wrong.",
+        "org.servo.servo"
+    ));
+}
+
+#[test]
+fn test_device_file_paths() {
+    assert_eq!(
+        device_file_paths(
+            "file:///pages/index.html",
+            "org.servo.servo",
+            Some("100"),
+            None
+        ),
+        DeviceFilePaths {
+            stem: String::from("pages/index.html"),
+            in_app: String::from("file:///data/storage/el2/base/cache/index.html"),
+            on_device: String::from("/data/app/el2/100/base/org.servo.servo/cache/index.html"),
+        }
+    );
+    assert_eq!(
+        device_file_paths("file:///index.html", "org.servo.servo", None, None).on_device,
+        "data/storage/el2/base/cache/index.html"
+    );
+    assert_eq!(
+        device_file_paths(
+            "file:///pages/index.html",
+            "org.servo.servo",
+            None,
+            Some(Path::new("assets/site"))
+        ),
+        DeviceFilePaths {
+            stem: String::from("assets/site"),
+            in_app: String::from("file:///data/storage/el2/base/cache/site/pages/index.html"),
+            on_device: String::from("data/storage/el2/base/cache/site"),
+        }
+    );
+    assert_eq!(
+        user_from_app_dir("/data/app/el2/101/base/org.servo.servo\n"),
+        Some("101")
+    );
+    assert_eq!(
+        user_from_app_dir("ls: /data/app/el2/*/base/org.servo.servo: No such file or directory"),
+        None
+    );
+}
+
+#[test]
+fn test_tree_hashes() {
+    let dir = std::env::temp_dir().join(format!("hitrace-bench-tree-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("css")).unwrap();
+    std::fs::write(dir.join("index.html"), "<html>").unwrap();
+    std::fs::write(dir.join("css/style.css"), "body {}").unwrap();
+    let hashes = tree_hashes(&dir).unwrap();
+    assert_eq!(
+        hashes.keys().collect::<Vec<_>>(),
+        [Path::new("css/style.css"), Path::new("index.html")]
+    );
+    assert_eq!(
+        tree_hashes(&dir.join("index.html")).unwrap()[Path::new("")],
+        hashes[Path::new("index.html")]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_served_url() {
+    assert_eq!(
+        served_url("file:///pages/index.html", 8000),
+        "http://127.0.0.1:8000/pages/index.html"
+    );
+    assert_eq!(
+        served_url("index.html", 8000),
+        "http://127.0.0.1:8000/index.html"
+    );
+}
+
+#[test]
+fn test_pref_args() {
+    assert_eq!(pref_args(&[]), ["--ps=--pref", "js_disable_jit=true"]);
+    let prefs = [
+        "js_disable_jit=false".parse().unwrap(),
+        "dom_webgpu_enabled=true".parse().unwrap(),
+    ];
+    assert_eq!(
+        pref_args(&prefs),
+        [
+            "--ps=--pref",
+            "dom_webgpu_enabled=true",
+            "--ps=--pref",
+            "js_disable_jit=false"
+        ]
+    );
+}
+
+#[test]
+fn test_reboot_due() {
+    let mut tries = 0;
+    let due: Vec<bool> = (0..7).map(|_| reboot_due(&mut tries, 3)).collect();
+    assert_eq!(due, [false, false, false, true, false, false, true]);
+}
+
+#[test]
+fn test_hdc_version() {
+    let version: HdcVersion = "Ver: 3.1.0e\n".parse().unwrap();
+    assert_eq!(version.to_string(), "3.1.0e");
+    assert!(version > MIN_HDC_VERSION);
+    assert!("2.0.0".parse::<HdcVersion>().unwrap() < MIN_HDC_VERSION);
+    assert!("3.1.0".parse::<HdcVersion>().unwrap() < MIN_HDC_VERSION);
+    assert!("3.2.1".parse::<HdcVersion>().unwrap() > MIN_HDC_VERSION);
+    assert!("[Fail] unknown".parse::<HdcVersion>().is_err());
+}
@@ -0,0 +1,221 @@
+//! Snapshots of the state of the devices before and after a session, to explain anomalous results later
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::device;
+
+/// Conditions of a device that influence the results. Everything we could not read is `None`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub(crate) struct DeviceState {
+    free_memory_kb: Option<i64>,
+    free_storage_kb: Option<i64>,
+    processes: Option<i64>,
+    uptime_s: Option<i64>,
+    temperature_mc: Option<i64>,
+}
+
+impl DeviceState {
+    /// Reads the state of `device`, or of the only connected device if `None`
+    pub(crate) fn read(device: Option<&str>) -> DeviceState {
+        let shell =
+            |command: &str| device::hdc_output(device, &["shell", command]).unwrap_or_default();
+        DeviceState {
+            free_memory_kb: parse_meminfo(&shell("cat /proc/meminfo")),
+            free_storage_kb: parse_df(&shell("df -k /data")),
+            processes: parse_ps(&shell("ps -A")),
+            uptime_s: parse_uptime(&shell("cat /proc/uptime")),
+            temperature_mc: parse_temperature(&shell("cat /sys/class/thermal/thermal_zone0/temp")),
+        }
+    }
+
+    /// The fields with their values for printing
+    fn fields(&self) -> [(&'static str, Option<i64>); 5] {
+        [
+            ("free memory (KB)", self.free_memory_kb),
+            ("free storage (KB)", self.free_storage_kb),
+            ("processes", self.processes),
+            ("uptime (s)", self.uptime_s),
+            ("temperature (m°C)", self.temperature_mc),
+        ]
+    }
+
+    /// `after - self` for every value we could read both times
+    fn delta(&self, after: &DeviceState) -> DeviceState {
+        let delta = |before: Option<i64>, after: Option<i64>| Some(after? - before?);
+        DeviceState {
+            free_memory_kb: delta(self.free_memory_kb, after.free_memory_kb),
+            free_storage_kb: delta(self.free_storage_kb, after.free_storage_kb),
+            processes: delta(self.processes, after.processes),
+            uptime_s: delta(self.uptime_s, after.uptime_s),
+            temperature_mc: delta(self.temperature_mc, after.temperature_mc),
+        }
+    }
+}
+
+/// `MemAvailable` of `/proc/meminfo`
+fn parse_meminfo(output: &str) -> Option<i64> {
+    output
+        .lines()
+        .find_map(|l| l.strip_prefix("MemAvailable:"))
+        .and_then(|l| l.split_whitespace().next())
+        .and_then(|v| v.parse().ok())
+}
+
+/// The available column of `df -k`
+fn parse_df(output: &str) -> Option<i64> {
+    output
+        .lines()
+        .nth(1)
+        .and_then(|l| l.split_whitespace().nth(3))
+        .and_then(|v| v.parse().ok())
+}
+
+/// Number of lines of `ps -A` without the header
+fn parse_ps(output: &str) -> Option<i64> {
+    let lines = output.lines().filter(|l| !l.trim().is_empty()).count();
+    (lines > 1).then(|| lines as i64 - 1)
+}
+
+/// Whole seconds since boot of `/proc/uptime`
+fn parse_uptime(output: &str) -> Option<i64> {
+    output
+        .split_whitespace()
+        .next()
+        .and_then(|v| v.parse::<f64>().ok())
+        .map(|v| v as i64)
+}
+
+/// Thermal zones report millidegree Celsius
+fn parse_temperature(output: &str) -> Option<i64> {
+    output.trim().parse().ok()
+}
+
+/// State of a device before and after the session
+#[derive(Debug, Serialize)]
+pub(crate) struct DeviceStateReport {
+    before: DeviceState,
+    after: DeviceState,
+    delta: DeviceState,
+}
+
+/// The reports of all devices of a session by serial
+pub(crate) type DeviceStates = BTreeMap<String, DeviceStateReport>;
+
+/// Reads the state of all `devices`, taken before the session
+pub(crate) fn read_all(devices: &[String]) -> Vec<DeviceState> {
+    devices.iter().map(|d| DeviceState::read(Some(d))).collect()
+}
+
+/// Reads the state of all `devices` again after the session and compares it with `before`
+pub(crate) fn compare(devices: &[String], before: Vec<DeviceState>) -> DeviceStates {
+    devices
+        .iter()
+        .zip(before)
+        .map(|(device, before)| {
+            let after = DeviceState::read(Some(device));
+            let delta = before.delta(&after);
+            (
+                device.clone(),
+                DeviceStateReport {
+                    before,
+                    after,
+                    delta,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Before, after and the difference for every device as text
+pub(crate) fn to_text(states: &DeviceStates) -> String {
+    let value = |v: Option<i64>| {
+        v.map(|v| v.to_string())
+            .unwrap_or_else(|| String::from("-"))
+    };
+    let mut text = String::new();
+    for (device, report) in states {
+        text.push_str(&format!(
+            "-----------Device {device}-------------------------\n"
+        ));
+        for (((name, before), (_, after)), (_, delta)) in report
+            .before
+            .fields()
+            .into_iter()
+            .zip(report.after.fields())
+            .zip(report.delta.fields())
+        {
+            text.push_str(&format!(
+                "{name}: {} -> {} ({})\n",
+                value(before),
+                value(after),
+                delta
+                    .map(|d| format!("{d:+}"))
+                    .unwrap_or_else(|| String::from("-"))
+            ));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[test]
+fn test_device_state_parsing() {
+    let state = DeviceState {
+        free_memory_kb: parse_meminfo("MemTotal: 11803564 kB\nMemAvailable: 5012345 kB\n"),
+        free_storage_kb: parse_df(
+            "Filesystem 1K-blocks Used Available Use% Mounted on\n/dev/block/dm-6 1000 100 900 10% /data\n",
+        ),
+        processes: parse_ps("USER PID PPID\nroot 1 0\nroot 2 0\n"),
+        uptime_s: parse_uptime("3600.52 7000.12\n"),
+        temperature_mc: parse_temperature("41500\n"),
+    };
+    assert_eq!(
+        state,
+        DeviceState {
+            free_memory_kb: Some(5012345),
+            free_storage_kb: Some(900),
+            processes: Some(2),
+            uptime_s: Some(3600),
+            temperature_mc: Some(41500),
+        }
+    );
+    let after = DeviceState {
+        free_memory_kb: Some(5000000),
+        temperature_mc: Some(45000),
+        ..Default::default()
+    };
+    assert_eq!(
+        state.delta(&after),
+        DeviceState {
+            free_memory_kb: Some(-12345),
+            temperature_mc: Some(3500),
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_to_text() {
+    let before = DeviceState {
+        free_memory_kb: Some(5000),
+        ..Default::default()
+    };
+    let after = DeviceState {
+        free_memory_kb: Some(4000),
+        ..Default::default()
+    };
+    let delta = before.delta(&after);
+    let states = DeviceStates::from([(
+        String::from("SERIAL"),
+        DeviceStateReport {
+            before,
+            after,
+            delta,
+        },
+    )]);
+    let text = to_text(&states);
+    assert!(text.starts_with("-----------Device SERIAL"));
+    assert!(text.contains("free memory (KB): 5000 -> 4000 (-1000)\n"));
+    assert!(text.contains("free storage (KB): - -> - (-)\n"));
+}
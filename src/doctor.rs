@@ -0,0 +1,223 @@
+//! The `doctor` subcommand checking everything a session needs before it starts
+use anyhow::{Result, anyhow};
+use yansi::Paint;
+
+use crate::{
+    args::DoctorArgs,
+    device::{self, HdcVersion, MIN_HDC_VERSION},
+};
+
+/// The outcome of a single check, with what to do about it if it failed
+#[derive(Debug, PartialEq)]
+enum Check {
+    Ok(String),
+    Failed { problem: String, fix: String },
+}
+
+impl Check {
+    fn failed(problem: impl Into<String>, fix: impl Into<String>) -> Check {
+        Check::Failed {
+            problem: problem.into(),
+            fix: fix.into(),
+        }
+    }
+}
+
+/// Prints the check and returns if it passed
+fn report(name: &str, check: &Check) -> bool {
+    match check {
+        Check::Ok(detail) => {
+            println!("{} {name}: {detail}", "[ok]".green());
+            true
+        }
+        Check::Failed { problem, fix } => {
+            println!("{} {name}: {problem}", "[failed]".red());
+            println!("         {fix}");
+            false
+        }
+    }
+}
+
+/// `hdc -v` prints something like `Ver: 3.1.0a`
+fn check_hdc_version(output: &str) -> Check {
+    match output.parse::<HdcVersion>() {
+        Ok(version) if version >= MIN_HDC_VERSION => Check::Ok(version.to_string()),
+        Ok(version) => Check::failed(
+            format!("{version} is older than {MIN_HDC_VERSION}"),
+            "Update the OpenHarmony SDK or pass a newer hdc with --hdc-path",
+        ),
+        Err(e) => Check::failed(
+            format!("{e}"),
+            "Install hdc from the OpenHarmony SDK toolchains",
+        ),
+    }
+}
+
+/// The value of the `const.security.developermode.state` parameter
+fn check_developer_mode(output: &str) -> Check {
+    if output.trim() == "true" {
+        Check::Ok(String::from("enabled"))
+    } else {
+        Check::failed(
+            "disabled",
+            "Enable developer options in the settings of the device",
+        )
+    }
+}
+
+/// If the bundle manager of the device knows `bundle_name`
+fn check_bundle(installed: Result<bool>, bundle_name: &str) -> Check {
+    match installed {
+        Ok(true) => Check::Ok(String::from("installed")),
+        Ok(false) => Check::failed(
+            format!("{bundle_name} is not installed"),
+            "Install it with `hdc install <path to the hap>` or pass the right --bundle-name",
+        ),
+        Err(e) => Check::failed(format!("{e}"), "Check that the device is still connected"),
+    }
+}
+
+/// `df -k <dir>` prints a header and a line whose fourth column is the free space in KB
+fn check_free_space(output: &str, trace_buffer: u64) -> Check {
+    let available = output
+        .lines()
+        .nth(1)
+        .and_then(|l| l.split_whitespace().nth(3))
+        .and_then(|a| a.parse::<u64>().ok());
+    match available {
+        Some(available) if available >= trace_buffer => Check::Ok(format!("{available} KB free")),
+        Some(available) => Check::failed(
+            format!("only {available} KB free, the trace buffer needs {trace_buffer} KB"),
+            "Delete old files in /data/local/tmp or use a smaller --trace-buffer",
+        ),
+        None => Check::failed(
+            format!("unexpected df output `{}`", output.trim()),
+            "Check that /data/local/tmp exists on the device",
+        ),
+    }
+}
+
+/// `hitrace -l` lists the available tags, we need the ones we trace
+fn check_hitrace(output: &str) -> Check {
+    let missing: Vec<&str> = ["app", "graphic", "ohos"]
+        .into_iter()
+        .filter(|tag| !output.split_whitespace().any(|w| w == *tag))
+        .collect();
+    if missing.is_empty() {
+        Check::Ok(String::from("all tags available"))
+    } else {
+        Check::failed(
+            format!("tags {} are missing", missing.join(", ")),
+            "Use a device image that supports hitrace",
+        )
+    }
+}
+
+/// Runs all checks and prints what to fix. Fails if any check failed.
+pub(crate) fn run(doctor_args: &DoctorArgs) -> Result<()> {
+    let hdc = match device::hdc_path() {
+        Ok(_) => device::hdc_output(None, &["-v"])
+            .map(|output| check_hdc_version(&output))
+            .unwrap_or_else(|e| Check::failed(format!("{e}"), "Check your hdc installation")),
+        Err(_) => Check::failed(
+            "not found",
+            "Install the OpenHarmony SDK and add the toolchains directory to your PATH or pass --hdc-path",
+        ),
+    };
+    if !report("hdc", &hdc) {
+        return Err(anyhow!("hdc is not usable"));
+    }
+
+    let devices = device::list_devices()?;
+    let reachable = if devices.is_empty() {
+        Check::failed(
+            "no device reachable",
+            "Connect a device and close other IDEs that might be connected to it",
+        )
+    } else {
+        Check::Ok(devices.join(", "))
+    };
+    let mut failed = usize::from(!report("devices", &reachable));
+
+    for device in &devices {
+        let shell = |args: &[&str]| {
+            device::hdc_output(Some(device), &[&["shell"], args].concat()).unwrap_or_default()
+        };
+        println!("Device {device}");
+        let checks = [
+            (
+                "developer mode",
+                check_developer_mode(&shell(&[
+                    "param",
+                    "get",
+                    "const.security.developermode.state",
+                ])),
+            ),
+            (
+                "bundle",
+                check_bundle(
+                    device::is_bundle_installed(Some(device), &doctor_args.bundle_name),
+                    &doctor_args.bundle_name,
+                ),
+            ),
+            (
+                "free space",
+                check_free_space(
+                    &shell(&["df", "-k", "/data/local/tmp"]),
+                    doctor_args.trace_buffer,
+                ),
+            ),
+            ("hitrace", check_hitrace(&shell(&["hitrace", "-l"]))),
+        ];
+        failed += checks
+            .iter()
+            .filter(|(name, check)| !report(name, check))
+            .count();
+    }
+
+    if failed > 0 {
+        return Err(anyhow!("{failed} checks failed"));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_checks() {
+    assert_eq!(
+        check_hdc_version("Ver: 3.1.0a\n"),
+        Check::Ok(String::from("3.1.0a"))
+    );
+    assert!(matches!(check_hdc_version("[Fail]"), Check::Failed { .. }));
+    assert!(matches!(
+        check_hdc_version("Ver: 2.0.0a"),
+        Check::Failed { .. }
+    ));
+    assert_eq!(
+        check_developer_mode("true\n"),
+        Check::Ok(String::from("enabled"))
+    );
+    assert!(matches!(
+        check_developer_mode("false\n"),
+        Check::Failed { .. }
+    ));
+    assert!(matches!(
+        check_bundle(Ok(false), "org.servo.servo"),
+        Check::Failed { .. }
+    ));
+    let df = "Filesystem 1K-blocks Used Available Use% Mounted on\n/dev/block/dm-6 1000 100 900 10% /data\n";
+    assert_eq!(
+        check_free_space(df, 512),
+        Check::Ok(String::from("900 KB free"))
+    );
+    assert!(matches!(check_free_space(df, 1024), Check::Failed { .. }));
+    assert_eq!(
+        check_hitrace(
+            "tagName: description:\n app - APP Module\n graphic - Graphic Module\n ohos - OpenHarmony\n"
+        ),
+        Check::Ok(String::from("all tags available"))
+    );
+    assert!(matches!(
+        check_hitrace(" app - APP Module\n"),
+        Check::Failed { .. }
+    ));
+}
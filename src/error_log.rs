@@ -0,0 +1,100 @@
+//! The `errors.json` in the `--artifacts` directory with every error of the session, which metric and try it
+//! belongs to and where the trace and the log of the try are, so failures can be analyzed without the console
+//! output.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::{analyze, canonical, device, runconfig::RunConfig, utils::RunResults};
+
+/// Name of the error log in the artifacts directory
+const ERROR_LOG: &str = "errors.json";
+
+/// One error of a session
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct ErrorEntry {
+    /// Url of the run config
+    url: String,
+    /// The metric, or the url of the run for errors of a whole try
+    metric: String,
+    /// Number of the try in the run, `None` if the whole run failed
+    #[serde(rename = "try")]
+    try_index: Option<usize>,
+    /// I.e., `no-start-marker`, `run-failure` if the whole run failed
+    category: String,
+    message: String,
+    /// How often the error happened in the try
+    count: u32,
+    /// When the first trace of the try happened on the device, if we know the clock offset of the device
+    #[serde(with = "time::serde::rfc3339::option")]
+    trace_started: Option<OffsetDateTime>,
+    /// The trace of the try, if we kept it
+    trace: Option<PathBuf>,
+    /// The log of the app during the try, if we kept it
+    log: Option<PathBuf>,
+}
+
+/// `path` if it exists
+fn existing(path: PathBuf) -> Option<PathBuf> {
+    path.exists().then_some(path)
+}
+
+/// The errors of `try_results`, the results of the `i`th try of `run_config`
+pub(crate) fn try_entries(
+    run_config: &RunConfig,
+    i: usize,
+    try_results: &RunResults,
+) -> Vec<ErrorEntry> {
+    let trace = match try_results.artifacts {
+        Some(ref dir) => existing(dir.join(analyze::TRACE_FILE)),
+        None => run_config.args.trace_file.clone(),
+    };
+    let log = try_results
+        .artifacts
+        .as_ref()
+        .and_then(|dir| existing(dir.join(device::APP_LOG)));
+    try_results
+        .errors
+        .iter()
+        .flat_map(|(metric, counts)| {
+            counts.iter().map(|(kind, count)| ErrorEntry {
+                url: run_config.run_args.url.clone(),
+                metric: metric.clone(),
+                try_index: Some(i),
+                category: kind.to_string(),
+                message: kind.description().to_owned(),
+                count: *count,
+                trace_started: try_results.trace_started,
+                trace: trace.clone(),
+                log: log.clone(),
+            })
+        })
+        .collect()
+}
+
+/// The entry of a run of `run_config` that failed with `error`
+pub(crate) fn run_failure(run_config: &RunConfig, error: &anyhow::Error) -> ErrorEntry {
+    ErrorEntry {
+        url: run_config.run_args.url.clone(),
+        metric: run_config.run_args.url.clone(),
+        try_index: None,
+        category: String::from("run-failure"),
+        message: format!("{error:#}"),
+        count: 1,
+        trace_started: None,
+        trace: None,
+        log: None,
+    }
+}
+
+/// Writes `entries` to the error log in `artifacts`
+pub(crate) fn write(artifacts: &Path, entries: &[ErrorEntry], canonical_json: bool) -> Result<()> {
+    let path = artifacts.join(ERROR_LOG);
+    let json = canonical::to_string(&entries, canonical_json)?;
+    fs::write(&path, json).with_context(|| format!("Could not write {}", path.display()))
+}
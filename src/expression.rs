@@ -0,0 +1,344 @@
+//! A tiny expression language for the `start` and `end` of filters in run files, for predicates substring
+//! matching can not express, i.e., `function ~ "load status" && pid == constellation_pid && marker == B`.
+//!
+//! - Fields of the trace: `function`, `shorthand`, `thread`, `tid`, `pid`, `cpu` and `marker`
+//! - Comparisons: `==`, `!=`, `~` (contains), `!~` (does not contain) and `<`, `<=`, `>`, `>=` for numbers
+//! - Values: `"strings"`, numbers, the markers `B`, `E`, `S`, `F`, `C` and `<thread>_pid`, the pid of the
+//!   process the thread `<thread>` belongs to, i.e., `constellation_pid`
+//! - `&&`, `||`, `!` and parentheses
+use anyhow::{Result, anyhow};
+
+use crate::{
+    filter::{Predicate, TraceContext},
+    trace::{Trace, TraceMarker},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(i64),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+/// Operators, longer ones first so `!=` is not read as `!`
+const OPERATORS: [&str; 11] = ["&&", "||", "==", "!=", "!~", "<=", ">=", "~", "<", ">", "!"];
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let (token, len) = if c == '(' {
+            (Token::Open, 1)
+        } else if c == ')' {
+            (Token::Close, 1)
+        } else if c == '"' {
+            let end = rest[1..]
+                .find('"')
+                .ok_or_else(|| anyhow!("Unterminated string in `{input}`"))?;
+            (Token::Str(rest[1..=end].to_owned()), end + 2)
+        } else if c.is_ascii_digit() || c == '-' {
+            let len = rest[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(rest.len(), |i| i + 1);
+            let number = rest[..len]
+                .parse()
+                .map_err(|_| anyhow!("Invalid number `{}` in `{input}`", &rest[..len]))?;
+            (Token::Number(number), len)
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (Token::Ident(rest[..len].to_owned()), len)
+        } else {
+            let op = OPERATORS
+                .into_iter()
+                .find(|op| rest.starts_with(op))
+                .ok_or_else(|| anyhow!("Unexpected `{c}` in `{input}`"))?;
+            (Token::Op(op), op.len())
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Field {
+    Function,
+    Shorthand,
+    Thread,
+    Tid,
+    Pid,
+    Cpu,
+    Marker,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "function" => Field::Function,
+            "shorthand" => Field::Shorthand,
+            "thread" => Field::Thread,
+            "tid" => Field::Tid,
+            "pid" => Field::Pid,
+            "cpu" => Field::Cpu,
+            "marker" => Field::Marker,
+            _ => return None,
+        })
+    }
+
+    fn value(self, trace: &Trace) -> String {
+        match self {
+            Field::Function => trace.function.clone(),
+            Field::Shorthand => trace.shorthand.clone(),
+            Field::Thread => trace.name.clone(),
+            Field::Tid => trace.tid.to_string(),
+            Field::Pid => trace.number.clone(),
+            Field::Cpu => trace.cpu.to_string(),
+            Field::Marker => String::from(match trace.trace_marker {
+                TraceMarker::StartSync => "B",
+                TraceMarker::EndSync => "E",
+                TraceMarker::StartAsync => "S",
+                TraceMarker::EndAsync => "F",
+                TraceMarker::Dot => "C",
+            }),
+        }
+    }
+}
+
+/// The right side of a comparison
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Literal(String),
+    /// The pid of the named thread
+    ThreadPid(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Comparison {
+    Equal,
+    NotEqual,
+    Contains,
+    NotContains,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+/// A parsed expression
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Expression {
+    Compare(Field, Comparison, Value),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+/// Recursive descent over the tokens, `||` binds weaker than `&&` which binds weaker than `!`
+struct Parser<'a> {
+    tokens: &'a [Token],
+    input: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<&'a Token> {
+        let (token, rest) = self.tokens.split_first()?;
+        self.tokens = rest;
+        Some(token)
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.tokens.first(), Some(Token::Op(o)) if *o == op) {
+            self.tokens = &self.tokens[1..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, expected: &str) -> anyhow::Error {
+        match self.tokens.first() {
+            Some(token) => anyhow!(
+                "Expected {expected} but found {token:?} in `{}`",
+                self.input
+            ),
+            None => anyhow!("Expected {expected} at the end of `{}`", self.input),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expression> {
+        let mut left = self.and()?;
+        while self.eat("||") {
+            left = Expression::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expression> {
+        let mut left = self.unary()?;
+        while self.eat("&&") {
+            left = Expression::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression> {
+        if self.eat("!") {
+            return Ok(Expression::Not(Box::new(self.unary()?)));
+        }
+        if self.tokens.first() == Some(&Token::Open) {
+            self.next();
+            let expression = self.or()?;
+            return match self.next() {
+                Some(Token::Close) => Ok(expression),
+                _ => Err(anyhow!("Missing `)` in `{}`", self.input)),
+            };
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expression> {
+        let field = match self.tokens.first() {
+            Some(Token::Ident(name)) => Field::parse(name).ok_or_else(|| {
+                anyhow!(
+                    "Unknown field `{name}` in `{}`, expected function, shorthand, thread, tid, pid, cpu or marker",
+                    self.input
+                )
+            })?,
+            _ => return Err(self.error("a field")),
+        };
+        self.next();
+        let comparison = match self.tokens.first() {
+            Some(Token::Op(op)) => match *op {
+                "==" => Comparison::Equal,
+                "!=" => Comparison::NotEqual,
+                "~" => Comparison::Contains,
+                "!~" => Comparison::NotContains,
+                "<" => Comparison::Less,
+                "<=" => Comparison::LessEqual,
+                ">" => Comparison::Greater,
+                ">=" => Comparison::GreaterEqual,
+                _ => return Err(self.error("a comparison")),
+            },
+            _ => return Err(self.error("a comparison")),
+        };
+        self.next();
+        let value = match self.next() {
+            Some(Token::Str(s)) => Value::Literal(s.clone()),
+            Some(Token::Number(n)) => Value::Literal(n.to_string()),
+            Some(Token::Ident(marker)) if ["B", "E", "S", "F", "C"].contains(&marker.as_str()) => {
+                Value::Literal(marker.clone())
+            }
+            Some(Token::Ident(name)) if name.ends_with("_pid") => {
+                Value::ThreadPid(name.trim_end_matches("_pid").to_owned())
+            }
+            Some(Token::Ident(name)) => {
+                return Err(anyhow!(
+                    "Unknown value `{name}` in `{}`, strings need quotes",
+                    self.input
+                ));
+            }
+            _ => return Err(anyhow!("Expected a value in `{}`", self.input)),
+        };
+        Ok(Expression::Compare(field, comparison, value))
+    }
+}
+
+impl Expression {
+    pub(crate) fn parse(input: &str) -> Result<Self> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            input,
+        };
+        let expression = parser.or()?;
+        if !parser.tokens.is_empty() {
+            return Err(parser.error("`&&` or `||`"));
+        }
+        Ok(expression)
+    }
+
+    fn evaluate(&self, trace: &Trace, context: &TraceContext) -> bool {
+        match self {
+            Expression::Compare(field, comparison, value) => {
+                let value = match value {
+                    Value::Literal(value) => value.as_str(),
+                    Value::ThreadPid(thread) => match context.thread_pid(thread) {
+                        Some(pid) => pid,
+                        None => return false,
+                    },
+                };
+                let field = field.value(trace);
+                let numbers = || Some((field.parse::<i64>().ok()?, value.parse::<i64>().ok()?));
+                match comparison {
+                    Comparison::Equal => field == value,
+                    Comparison::NotEqual => field != value,
+                    Comparison::Contains => field.contains(value),
+                    Comparison::NotContains => !field.contains(value),
+                    Comparison::Less => numbers().is_some_and(|(f, v)| f < v),
+                    Comparison::LessEqual => numbers().is_some_and(|(f, v)| f <= v),
+                    Comparison::Greater => numbers().is_some_and(|(f, v)| f > v),
+                    Comparison::GreaterEqual => numbers().is_some_and(|(f, v)| f >= v),
+                }
+            }
+            Expression::Not(expression) => !expression.evaluate(trace, context),
+            Expression::And(left, right) => {
+                left.evaluate(trace, context) && right.evaluate(trace, context)
+            }
+            Expression::Or(left, right) => {
+                left.evaluate(trace, context) || right.evaluate(trace, context)
+            }
+        }
+    }
+}
+
+impl Predicate for Expression {
+    fn matches(&self, trace: &Trace, context: &TraceContext) -> bool {
+        self.evaluate(trace, context)
+    }
+}
+
+#[test]
+fn test_expression() {
+    use crate::parser::{HitraceVersion, parse_line};
+
+    let traces: Vec<Trace> = [
+        "constellation-7   (-------) [010] .... 10.0: tracing_mark_write: B|7|H:load status changed Head|M62",
+        "<...>-9   (-------) [010] .... 11.0: tracing_mark_write: B|9|H:load status changed Head|M62",
+        "<...>-9   (-------) [010] .... 12.0: tracing_mark_write: E|7|",
+    ]
+    .iter()
+    .map(|line| parse_line(HitraceVersion::V5, line).unwrap().unwrap())
+    .collect();
+    let context = TraceContext::new(&traces);
+    let matching = |input: &str| {
+        let expression = Expression::parse(input).unwrap();
+        traces
+            .iter()
+            .map(|t| expression.matches(t, &context))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        matching(r#"function ~ "load status" && pid == constellation_pid && marker == B"#),
+        [true, false, false]
+    );
+    assert_eq!(
+        matching(r#"!(pid == 7) || (tid >= 9 && marker != B)"#),
+        [false, true, true]
+    );
+    assert_eq!(matching("pid == compositor_pid"), [false, false, false]);
+
+    for invalid in [
+        r#"function = "x""#,
+        r#"name == "x""#,
+        "function == load",
+        r#"(function == "x""#,
+        r#"function == "x" pid"#,
+    ] {
+        assert!(Expression::parse(invalid).is_err(), "{invalid}");
+    }
+}
@@ -0,0 +1,112 @@
+//! Schedule run configs across a pool of connected devices
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use log::info;
+
+use crate::{
+    BrokenRunConfig,
+    args::Args,
+    run_with_dependencies,
+    runconfig::{Dependencies, RunConfig, RunState},
+    utils::RunResults,
+};
+
+/// Runs every pending run config on its pinned device or on the next free device.
+/// Each device runs one config at a time and a config only starts once its dependencies are done.
+/// The results are in the order of `run_configs` and `None` for runs that were not pending.
+/// All devices stop after their current run on a `BrokenRunConfig`.
+pub(crate) fn run_on_devices(
+    args: &Args,
+    run_configs: &[RunConfig],
+    devices: &[String],
+    states: &[RunState],
+) -> Result<Vec<Option<Result<RunResults>>>> {
+    if let Some(run_config) = run_configs.iter().find(|r| {
+        r.run_args
+            .pinned_device()
+            .is_some_and(|d| !devices.iter().any(|serial| serial == d))
+    }) {
+        return Err(anyhow!(
+            "Device {:?} of run {} is not connected, we have {:?}",
+            run_config.run_args.device,
+            run_config.run_args.url,
+            devices
+        ));
+    }
+
+    let states = Mutex::new(states.to_vec());
+    let results: Mutex<Vec<Option<Result<RunResults>>>> =
+        Mutex::new(run_configs.iter().map(|_| None).collect());
+    let abort = AtomicBool::new(false);
+    let (states, results_ref, abort) = (&states, &results, &abort);
+    thread::scope(|s| {
+        for device in devices {
+            s.spawn(move || {
+                while !abort.load(Ordering::Relaxed) {
+                    let (next, waiting) = {
+                        let mut states = states.lock().unwrap();
+                        let mut waiting = false;
+                        let next = run_configs.iter().enumerate().position(|(i, r)| {
+                            if states[i] != RunState::Pending
+                                || r.run_args.pinned_device().is_some_and(|d| d != device)
+                            {
+                                return false;
+                            }
+                            let ready =
+                                r.dependencies(run_configs, &states) != Dependencies::Waiting;
+                            waiting |= !ready;
+                            ready
+                        });
+                        if let Some(index) = next {
+                            states[index] = RunState::Running;
+                        }
+                        (next.map(|index| (index, states.clone())), waiting)
+                    };
+                    let Some((index, snapshot)) = next else {
+                        if waiting {
+                            // another device still runs a dependency
+                            thread::sleep(Duration::from_secs(1));
+                            continue;
+                        }
+                        break;
+                    };
+                    let run_config = &run_configs[index];
+                    info!("Running {} on {device}", run_config.run_args.url);
+                    let result = run_with_dependencies(
+                        args,
+                        run_config,
+                        run_configs,
+                        &snapshot,
+                        Some(device),
+                    );
+                    if result.as_ref().is_err_and(|e| e.is::<BrokenRunConfig>()) {
+                        abort.store(true, Ordering::Relaxed);
+                    }
+                    states.lock().unwrap()[index] = if result.is_ok() {
+                        RunState::Succeeded
+                    } else {
+                        RunState::Failed
+                    };
+                    results_ref.lock().unwrap()[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    if let Some(broken) = results
+        .iter_mut()
+        .find(|r| matches!(r, Some(Err(e)) if e.is::<BrokenRunConfig>()))
+    {
+        return Err(broken.take().unwrap().unwrap_err());
+    }
+    Ok(results)
+}
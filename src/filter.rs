@@ -1,43 +1,141 @@
-use anyhow::{Result, anyhow};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::OnceLock};
 use time::Duration;
 
-use crate::{Trace, trace::difference_of_traces};
+use crate::{Trace, trace::difference_of_traces, utils::ErrorKind};
+
+/// The traces a filter looks at, for predicates that need more than the trace they decide on
+pub(crate) struct TraceContext<'a> {
+    traces: &'a [Trace],
+    /// The pid of every thread by its lowercase name
+    thread_pids: OnceLock<HashMap<String, &'a str>>,
+}
+
+impl<'a> TraceContext<'a> {
+    pub(crate) fn new(traces: &'a [Trace]) -> Self {
+        TraceContext {
+            traces,
+            thread_pids: OnceLock::new(),
+        }
+    }
+
+    /// The pid of the process of the first trace of the thread `name`, ignoring case
+    pub(crate) fn thread_pid(&self, name: &str) -> Option<&'a str> {
+        self.thread_pids
+            .get_or_init(|| {
+                let mut pids = HashMap::new();
+                for trace in self.traces {
+                    pids.entry(trace.name.to_lowercase())
+                        .or_insert(trace.number.as_str());
+                }
+                pids
+            })
+            .get(&name.to_lowercase())
+            .copied()
+    }
+}
+
+/// Decides whether a trace starts or ends a filter. Every `Fn(&Trace) -> bool` is one.
+pub(crate) trait Predicate: Send + Sync {
+    fn matches(&self, trace: &Trace, context: &TraceContext) -> bool;
+}
+
+impl<F: Fn(&Trace) -> bool + Send + Sync> Predicate for F {
+    fn matches(&self, trace: &Trace, _context: &TraceContext) -> bool {
+        self(trace)
+    }
+}
+
+/// `--explain-filters` lists at most this many matched traces per side of a filter
+const MAX_EXPLAINED: usize = 10;
 
 /// Way to construct filters
 pub(crate) struct Filter {
     /// A name for the filter that will be output
     pub(crate) name: String,
     /// A function taking a trace and deciding if it should be the start of the timing
-    pub(crate) first: Box<dyn Fn(&Trace) -> bool>,
+    pub(crate) first: Box<dyn Predicate>,
     /// A function taking a trace and deciding if it should be the end of the timing
-    pub(crate) last: Box<dyn Fn(&Trace) -> bool>,
+    pub(crate) last: Box<dyn Predicate>,
+    /// The bencher measure the result is reported under, `Latency` if `None`
+    pub(crate) measure: Option<String>,
+    /// Results outside of this range in milliseconds are suspect and left out
+    pub(crate) expected_range: Option<ExpectedRange>,
+}
+
+/// The plausible values of a metric, i.e., a page load between 50ms and 60s. Values outside of it usually
+/// come from a filter that matched the wrong traces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub(crate) struct ExpectedRange {
+    #[serde(default)]
+    pub(crate) min: Option<f64>,
+    #[serde(default)]
+    pub(crate) max: Option<f64>,
+}
+
+impl ExpectedRange {
+    /// Whether `value` is plausible
+    pub(crate) fn contains(&self, value: f64) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
 }
 
 impl Filter {
-    /// Turn a filter into a str and Result<Duration>
-    fn filter_to_duration(&self, v: &[Trace]) -> (&str, Result<Duration>) {
+    /// The traces of `v` matching `first` and the ones matching `last`
+    pub(crate) fn matching<'t>(&self, v: &'t [Trace]) -> (Vec<&'t Trace>, Vec<&'t Trace>) {
+        let context = TraceContext::new(v);
         let first = v
             .iter()
-            .filter(|t| (self.first)(t))
+            .filter(|t| self.first.matches(t, &context))
             .collect::<Vec<&Trace>>();
-        let last = v.iter().filter(|t| (self.last)(t)).collect::<Vec<&Trace>>();
-
-        let result = if first.len() != 1 || last.len() != 1 {
-            Err(anyhow!(
-                "Your filter functions are not specific or over specific, we got the following number of results: name: {}, first: {}, last: {}",
-                self.name,
-                first.len(),
-                last.len()
-            ))
-        } else {
-            let first_trace = first.first().unwrap();
-            let last_trace = last.first().unwrap();
-
-            Ok(difference_of_traces(last_trace, first_trace))
+        let last = v
+            .iter()
+            .filter(|t| self.last.matches(t, &context))
+            .collect::<Vec<&Trace>>();
+        (first, last)
+    }
+
+    /// Turn a filter into a str and the Duration or why there is none
+    fn filter_to_duration(&self, v: &[Trace]) -> (&str, Result<Duration, ErrorKind>) {
+        let (first, last) = self.matching(v);
+        (&self.name, duration(&first, &last))
+    }
+
+    /// Which traces of `v` the filter matched and what it made of them, for `--explain-filters`
+    pub(crate) fn explain(&self, v: &[Trace]) -> String {
+        let (first, last) = self.matching(v);
+        let verdict = match duration(&first, &last) {
+            Ok(d) => d.to_string(),
+            Err(ErrorKind::NoStartMarker) => String::from("no trace matched first"),
+            Err(ErrorKind::NoEndMarker) => String::from("no trace matched last"),
+            Err(kind) => format!(
+                "{kind}, first and last have to match exactly one trace each, make them more specific"
+            ),
         };
+        let mut explanation = format!("Filter {}: {verdict}\n", self.name);
+        for (side, traces) in [("first", &first), ("last", &last)] {
+            explanation.push_str(&format!("  {side} matched {} traces\n", traces.len()));
+            for trace in traces.iter().take(MAX_EXPLAINED) {
+                explanation.push_str(&format!(
+                    "    {} {}-{}: {}\n",
+                    trace.timestamp, trace.name, trace.tid, trace.function
+                ));
+            }
+            if traces.len() > MAX_EXPLAINED {
+                explanation.push_str(&format!("    ... {} more\n", traces.len() - MAX_EXPLAINED));
+            }
+        }
+        explanation
+    }
+}
 
-        (&self.name, result)
+/// The duration from the `first` to the `last` trace of a filter if both are unique
+fn duration(first: &[&Trace], last: &[&Trace]) -> Result<Duration, ErrorKind> {
+    match (first, last) {
+        ([first_trace], [last_trace]) => Ok(difference_of_traces(last_trace, first_trace)),
+        ([], _) => Err(ErrorKind::NoStartMarker),
+        (_, []) => Err(ErrorKind::NoEndMarker),
+        _ => Err(ErrorKind::AmbiguousMatch),
     }
 }
 
@@ -45,9 +143,58 @@ impl Filter {
 pub(crate) fn find_notable_differences<'a>(
     v: &[Trace],
     filters: &'a [Filter],
-) -> HashMap<&'a str, Result<Duration>> {
+) -> HashMap<&'a str, Result<Duration, ErrorKind>> {
     filters
         .iter()
         .map(|filter| filter.filter_to_duration(v))
         .collect()
 }
+
+#[test]
+fn test_explain() {
+    use crate::parser::{HitraceVersion, parse_line};
+
+    let traces: Vec<Trace> = [
+        ("10.0", "B|7|H:load status changed Head|M62"),
+        ("10.5", "B|7|H:PageLoadEndedPrompt|M62"),
+        ("11.0", "B|7|H:PageLoadEndedPrompt|M62"),
+    ]
+    .iter()
+    .map(|(timestamp, marker)| {
+        let line =
+            format!("<...>-1   (-------) [010] .... {timestamp}: tracing_mark_write: {marker}");
+        parse_line(HitraceVersion::V5, &line).unwrap().unwrap()
+    })
+    .collect();
+    let filter = |last: &'static str| Filter {
+        name: String::from("Load"),
+        first: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
+        last: Box::new(move |t: &Trace| t.function.contains(last)),
+        measure: None,
+        expected_range: None,
+    };
+    let ambiguous = filter("PageLoadEndedPrompt").explain(&traces);
+    let mut lines = ambiguous.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "Filter Load: ambiguous-match, first and last have to match exactly one trace each, make them more specific"
+    );
+    assert_eq!(lines.next().unwrap(), "  first matched 1 traces");
+    assert_eq!(
+        lines.next().unwrap(),
+        "    10.000000000 <...>-1: load status changed Head|M62"
+    );
+    assert_eq!(lines.next().unwrap(), "  last matched 2 traces");
+    assert_eq!(
+        filter("Missing").explain(&traces).lines().next().unwrap(),
+        "Filter Load: no trace matched last"
+    );
+    assert_eq!(
+        filter("PageLoadEndedPrompt")
+            .explain(&traces[..2])
+            .lines()
+            .next()
+            .unwrap(),
+        "Filter Load: 500ms"
+    );
+}
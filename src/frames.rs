@@ -0,0 +1,155 @@
+//! Frame metrics of the scroll jank preset and the input to paint latency, computed from the frames the render
+//! service composed.
+use std::str::FromStr;
+
+use time::Duration;
+
+use crate::{
+    navigation::LOAD_END_MARKER,
+    script::{INPUT_MARKER, Step},
+    trace::{Trace, TraceMarker, difference_of_traces},
+    utils::{PointResult, RunResults},
+};
+
+/// The render service starts this for every frame it composes, i.e., every frame the scrolled page produced
+const FRAME_MARKER: &str = "RSMainThread::DoComposition";
+
+/// A longer gap between two frames means nothing changed on screen, i.e., the fling ended, and is not jank
+const IDLE_GAP: Duration = Duration::milliseconds(250);
+
+/// The frame time percentiles we report
+const PERCENTILES: [u8; 3] = [50, 90, 99];
+
+/// Name of the dropped frames metric
+pub(crate) const DROPPED_FRAMES: &str = "Dropped frames";
+
+/// Name of the input to paint latency metric
+pub(crate) const INPUT_TO_PAINT: &str = "Input to paint";
+
+/// The script of the scroll jank preset: wait for the page, then fling down a few times
+pub(crate) fn scroll_jank_script() -> Vec<Step> {
+    [
+        "wait-for-marker PageLoadEndedPrompt 30000",
+        "wait 1000",
+        "swipe 600 2000 600 600 100",
+        "wait 1500",
+        "swipe 600 2000 600 600 100",
+        "wait 1500",
+        "swipe 600 2000 600 600 100",
+        "wait 1500",
+    ]
+    .into_iter()
+    .map(|s| Step::from_str(s).expect("Preset steps are valid"))
+    .collect()
+}
+
+/// Name of the frame time metric of a percentile
+fn frame_time_name(percentile: u8) -> String {
+    format!("Frame time p{percentile}")
+}
+
+/// The time between consecutive composed frames after the page loaded, leaving out idle gaps
+fn frame_intervals(traces: &[Trace]) -> Vec<Duration> {
+    let load_end = traces
+        .iter()
+        .rposition(|t| t.function.contains(LOAD_END_MARKER))
+        .map_or(0, |i| i + 1);
+    let frames: Vec<&Trace> = traces[load_end..]
+        .iter()
+        .filter(|t| t.trace_marker == TraceMarker::StartSync && t.function.contains(FRAME_MARKER))
+        .collect();
+    frames
+        .windows(2)
+        .map(|w| difference_of_traces(w[1], w[0]))
+        .filter(|interval| *interval <= IDLE_GAP)
+        .collect()
+}
+
+/// The nearest-rank percentile of sorted values
+fn percentile(sorted: &[Duration], percentile: u8) -> Duration {
+    let rank = (sorted.len() * percentile as usize).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// Frames the display had to repeat because the next frame was late. The median interval is the vsync
+/// period, an interval of `n` periods means `n - 1` dropped frames.
+fn dropped_frames(sorted: &[Duration]) -> i64 {
+    let period = percentile(sorted, 50).as_seconds_f64();
+    sorted
+        .iter()
+        .map(|interval| ((interval.as_seconds_f64() / period).round() as i64 - 1).max(0))
+        .sum()
+}
+
+/// Adds the frame time percentiles and the dropped frames of one try to `results`.
+/// Returns the number of metrics found, which is 0 if the trace does not contain at least two frames.
+pub(crate) fn record_frame_metrics(traces: &[Trace], results: &mut RunResults) -> usize {
+    let mut intervals = frame_intervals(traces);
+    if intervals.is_empty() {
+        log::error!("Found no frames after the page loaded, did the scroll happen?");
+        return 0;
+    }
+    intervals.sort();
+    for p in PERCENTILES {
+        results
+            .filter_results
+            .entry(frame_time_name(p))
+            .or_default()
+            .push(percentile(&intervals, p));
+    }
+    results
+        .point_results
+        .entry(String::from(DROPPED_FRAMES))
+        .or_insert(PointResult {
+            no_unit_conversion: true,
+            result: Vec::new(),
+        })
+        .result
+        .push(dropped_frames(&intervals));
+    results
+        .measures
+        .insert(String::from(DROPPED_FRAMES), String::from("frames"));
+    PERCENTILES.len() + 1
+}
+
+/// Adds the time from every `input-tap` of the script to the next composed frame to `results`.
+/// Returns the number of taps we found a frame for.
+pub(crate) fn record_input_latency(traces: &[Trace], results: &mut RunResults) -> usize {
+    let mut found = 0;
+    for (i, input) in traces
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.function.contains(INPUT_MARKER))
+    {
+        match traces[i + 1..]
+            .iter()
+            .find(|t| t.trace_marker == TraceMarker::StartSync && t.function.contains(FRAME_MARKER))
+        {
+            Some(frame) => {
+                results
+                    .filter_results
+                    .entry(String::from(INPUT_TO_PAINT))
+                    .or_default()
+                    .push(difference_of_traces(frame, input));
+                found += 1;
+            }
+            None => log::error!("No frame was composed after the tap at {}", input.timestamp),
+        }
+    }
+    found
+}
+
+#[test]
+fn test_frame_metrics() {
+    let ms = |v: &[i64]| {
+        v.iter()
+            .map(|v| Duration::microseconds(*v))
+            .collect::<Vec<_>>()
+    };
+    let sorted = ms(&[16667, 16667, 16667, 16667, 16667, 16667, 50000]);
+    assert_eq!(percentile(&sorted, 50), Duration::microseconds(16667));
+    assert_eq!(percentile(&sorted, 90), Duration::microseconds(50000));
+    assert_eq!(dropped_frames(&sorted), 2);
+    assert_eq!(dropped_frames(&ms(&[16667])), 0);
+    assert_eq!(scroll_jank_script().len(), 8);
+}
@@ -0,0 +1,86 @@
+//! Generate a GitHub PR comment comparing the results against a baseline of the base branch
+use std::{fmt::Write, fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::{bencher, utils::RunResults};
+
+/// The payload of the GitHub `POST /repos/{owner}/{repo}/issues/{number}/comments` endpoint
+#[derive(Debug, Serialize)]
+struct PrComment {
+    body: String,
+}
+
+/// Relative change from `baseline` to `current` formatted as percentage
+fn change(baseline: f64, current: f64) -> String {
+    if baseline == 0.0 {
+        String::from("n/a")
+    } else {
+        format!("{:+.2}%", (current - baseline) / baseline * 100.0)
+    }
+}
+
+/// Creates a markdown table of all measures of `current` together with their value in `baseline`.
+/// Both are bencher jsons.
+fn comment_body(current: &serde_json::Value, baseline: &serde_json::Value) -> String {
+    let mut body = String::from(
+        "### hitrace-bench results\n\n| Benchmark | Measure | Baseline | Current | Change |\n|---|---|---:|---:|---:|\n",
+    );
+    let Some(benchmarks) = current.as_object() else {
+        return body;
+    };
+    let mut keys: Vec<_> = benchmarks.keys().collect();
+    keys.sort();
+    for key in keys {
+        let Some(measures) = benchmarks[key].as_object() else {
+            continue;
+        };
+        let mut measure_names: Vec<_> = measures.keys().collect();
+        measure_names.sort();
+        for measure in measure_names {
+            let Some(value) = measures[measure]["value"].as_f64() else {
+                continue;
+            };
+            let (baseline_value, change) = match baseline[key][measure]["value"].as_f64() {
+                Some(b) => (b.to_string(), change(b, value)),
+                None => (String::from("-"), String::from("new")),
+            };
+            let _ = writeln!(
+                body,
+                "| `{key}` | {measure} | {baseline_value} | {value} | {change} |"
+            );
+        }
+    }
+    body
+}
+
+/// Writes the PR comment payload comparing `results` to the bench.json at `baseline` into `output`
+pub(crate) fn write_pr_comment(results: &RunResults, baseline: &Path, output: &Path) -> Result<()> {
+    let baseline: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(baseline).context("Could not read baseline")?)
+            .context("Could not parse baseline")?;
+    let current = bencher::generate_result_json_value(results, false)?;
+    let comment = PrComment {
+        body: comment_body(&current, &baseline),
+    };
+    fs::write(
+        output,
+        serde_json::to_string_pretty(&comment).context("Could not serialize PR comment")?,
+    )
+    .context("Could not write PR comment")
+}
+
+#[test]
+fn test_comment_body() {
+    let baseline = serde_json::json!({
+        "E2E/Load": {"Latency": {"value": 200.0, "lower_value": 100.0, "upper_value": 300.0}},
+    });
+    let current = serde_json::json!({
+        "E2E/Load": {"Latency": {"value": 250.0, "lower_value": 200.0, "upper_value": 300.0}},
+        "E2E/Resident": {"Memory": {"value": 10.0, "lower_value": 10.0, "upper_value": 10.0}},
+    });
+    let body = comment_body(&current, &baseline);
+    assert!(body.contains("| `E2E/Load` | Latency | 200 | 250 | +25.00% |"));
+    assert!(body.contains("| `E2E/Resident` | Memory | - | 10 | new |"));
+}
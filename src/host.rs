@@ -0,0 +1,83 @@
+//! Files and binaries on the host, so we behave the same on Linux, macOS and Windows hosts.
+//! Paths on the device always use `/`, paths on the host use whatever the host uses.
+use std::{
+    env::consts::EXE_SUFFIX,
+    path::{Component, Path, PathBuf},
+};
+
+/// Environment variables pointing to an OpenHarmony SDK, their `toolchains` directory contains hdc
+const SDK_VARIABLES: &[&str] = &["OHOS_SDK_HOME", "OHOS_BASE_SDK_HOME", "DEVECO_SDK_HOME"];
+
+/// `name` with every character that is not allowed in a file name on some host replaced,
+/// i.e., the `:` of the serial of a device connected over tcp is not allowed on Windows
+pub(crate) fn file_name_safe(name: &str) -> String {
+    name.replace(
+        |c: char| {
+            matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*') || c.is_control()
+        },
+        "_",
+    )
+}
+
+/// A file of ours in the temp directory of the host
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(file_name_safe(name))
+}
+
+/// A relative host path as it has to look on the device
+pub(crate) fn device_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(c) => Some(c.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Where hdc could be in the SDKs the environment points to. `var` reads an environment variable.
+fn sdk_hdc_candidates(var: impl Fn(&str) -> Option<String>) -> Vec<PathBuf> {
+    let hdc = format!("hdc{EXE_SUFFIX}");
+    SDK_VARIABLES
+        .iter()
+        .filter_map(|v| var(v))
+        .map(PathBuf::from)
+        .flat_map(|sdk| {
+            [
+                sdk.join("toolchains").join(&hdc),
+                sdk.join("default")
+                    .join("openharmony")
+                    .join("toolchains")
+                    .join(&hdc),
+            ]
+        })
+        .collect()
+}
+
+/// Looks for hdc, `hdc.exe` on Windows, in the path and then in the SDKs the environment points to
+pub(crate) fn find_hdc() -> Option<PathBuf> {
+    which::which("hdc").ok().or_else(|| {
+        sdk_hdc_candidates(|v| std::env::var(v).ok())
+            .into_iter()
+            .find(|p| p.is_file())
+    })
+}
+
+#[test]
+fn test_host_paths() {
+    assert_eq!(file_name_safe("192.168.0.2:5555"), "192.168.0.2_5555");
+    assert_eq!(file_name_safe("app-FMR0223.ftrace"), "app-FMR0223.ftrace");
+    assert_eq!(
+        device_path(&Path::new("pages").join("css").join("style.css")),
+        "pages/css/style.css"
+    );
+    assert_eq!(device_path(Path::new("./index.html")), "index.html");
+    let candidates = sdk_hdc_candidates(|v| (v == "OHOS_SDK_HOME").then(|| String::from("sdk")));
+    assert_eq!(
+        candidates[0],
+        Path::new("sdk")
+            .join("toolchains")
+            .join(format!("hdc{EXE_SUFFIX}"))
+    );
+    assert_eq!(candidates.len(), 2);
+}
@@ -0,0 +1,283 @@
+//! Defaults of a host, i.e., a lab machine, from `~/.config/hitrace-bench/config.toml` and `HITRACE_BENCH_*`
+//! environment variables. Flags on the command line win over the profile selected with `--profile`, which wins
+//! over the environment, which wins over the file.
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+use clap::{ArgMatches, ValueEnum, parser::ValueSource};
+use serde::Deserialize;
+
+use crate::args::{Args, ColorChoice, OutputFormat, RunArgs};
+
+/// Overrides the path of the config file
+const CONFIG_ENV: &str = "HITRACE_BENCH_CONFIG";
+
+/// The defaults of the host, every field can also be set with `HITRACE_BENCH_<FIELD>`, i.e.,
+/// `HITRACE_BENCH_HDC_PATH`
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct HostConfig {
+    /// See `Args::hdc_path`
+    hdc_path: Option<PathBuf>,
+    /// The device of runs that do not name one, see `RunArgs::device`
+    device: Option<String>,
+    /// The directory of the artifacts, see `Args::artifacts`
+    artifacts: Option<PathBuf>,
+    /// See `Args::prepend`
+    prepend: Option<String>,
+    /// See `Args::color`
+    #[serde(default, deserialize_with = "deserialize_value_enum")]
+    color: Option<ColorChoice>,
+    /// Named sets of options, i.e., `[profiles.ci]`
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+/// Options selected together with `--profile`
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Profile {
+    /// See `RunArgs::tries`
+    tries: Option<usize>,
+    /// See `RunArgs::sleep`
+    sleep: Option<u64>,
+    /// See `RunArgs::trace_buffer`
+    trace_buffer: Option<u64>,
+    /// See `Args::bencher`
+    bencher: Option<bool>,
+    /// See `Args::format`
+    #[serde(default, deserialize_with = "deserialize_value_enum")]
+    format: Option<OutputFormat>,
+    /// See `Args::per_try`
+    per_try: Option<bool>,
+    /// See `Args::canonical_json`
+    canonical_json: Option<bool>,
+    /// See `Args::quiet`
+    quiet: Option<bool>,
+}
+
+/// Defaults for the run args of all runs, which runs only keep if they set another value than the default
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct RunDefaults {
+    device: Option<String>,
+    tries: Option<usize>,
+    sleep: Option<u64>,
+    trace_buffer: Option<u64>,
+}
+
+impl RunDefaults {
+    /// Sets the fields of `run_args` that still have their default value
+    pub(crate) fn apply(&self, run_args: &mut RunArgs) {
+        let defaults = RunArgs::default();
+        if run_args.device.is_none() {
+            run_args.device = self.device.clone();
+        }
+        if let Some(tries) = self.tries
+            && run_args.tries == defaults.tries
+        {
+            run_args.tries = tries;
+        }
+        if let Some(sleep) = self.sleep
+            && run_args.sleep == defaults.sleep
+        {
+            run_args.sleep = sleep;
+        }
+        if let Some(trace_buffer) = self.trace_buffer
+            && run_args.trace_buffer == defaults.trace_buffer
+        {
+            run_args.trace_buffer = trace_buffer;
+        }
+    }
+}
+
+/// A `ValueEnum` by its name in the command line
+fn deserialize_value_enum<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: ValueEnum,
+{
+    let value = String::deserialize(deserializer)?;
+    T::from_str(&value, true)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// `$XDG_CONFIG_HOME/hitrace-bench/config.toml`, `~/.config/hitrace-bench/config.toml` without it
+fn default_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config| config.join("hitrace-bench").join("config.toml"))
+}
+
+impl HostConfig {
+    /// Reads the config file if there is one
+    fn read(path: &Path) -> Result<HostConfig> {
+        if !path.exists() {
+            return Ok(HostConfig::default());
+        }
+        let config = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+        toml::from_str(&config)
+            .with_context(|| format!("Could not parse config file {}", path.display()))
+    }
+
+    /// Overrides the fields that `var` returns a value for, `var` gets the name of the environment variable
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        if let Some(hdc_path) = var("HITRACE_BENCH_HDC_PATH") {
+            self.hdc_path = Some(PathBuf::from(hdc_path));
+        }
+        if let Some(device) = var("HITRACE_BENCH_DEVICE") {
+            self.device = Some(device);
+        }
+        if let Some(artifacts) = var("HITRACE_BENCH_ARTIFACTS") {
+            self.artifacts = Some(PathBuf::from(artifacts));
+        }
+        if let Some(prepend) = var("HITRACE_BENCH_PREPEND") {
+            self.prepend = Some(prepend);
+        }
+        if let Some(color) = var("HITRACE_BENCH_COLOR") {
+            self.color = Some(
+                ColorChoice::from_str(&color, true)
+                    .map_err(|e| anyhow!("Invalid HITRACE_BENCH_COLOR: {e}"))?,
+            );
+        }
+        Ok(())
+    }
+
+    /// The config of this host, from the file layered under the environment
+    pub(crate) fn load() -> Result<HostConfig> {
+        let path = std::env::var_os(CONFIG_ENV)
+            .map(PathBuf::from)
+            .or_else(default_path);
+        let mut config = match path {
+            Some(path) => HostConfig::read(&path)?,
+            None => HostConfig::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        Ok(config)
+    }
+
+    /// Sets all fields of `args` that were not given on the command line, `matches` are the matches `args` came
+    /// from. Fails if the profile of `args` does not exist.
+    pub(crate) fn apply(mut self, args: &mut Args, matches: &ArgMatches) -> Result<()> {
+        let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        args.hdc_path = args.hdc_path.take().or(self.hdc_path);
+        args.artifacts = args.artifacts.take().or(self.artifacts);
+        args.prepend = args.prepend.take().or(self.prepend);
+        if let Some(color) = self.color
+            && !from_cli("color")
+        {
+            args.color = color;
+        }
+        args.run_defaults.device = self.device;
+
+        let Some(ref name) = args.profile else {
+            return Ok(());
+        };
+        let profile = self.profiles.remove(name).with_context(|| {
+            let names: Vec<&String> = self.profiles.keys().collect();
+            format!("There is no profile {name} in the host config, only {names:?}")
+        })?;
+        let flags = [
+            ("bencher", profile.bencher, &mut args.bencher),
+            ("per_try", profile.per_try, &mut args.per_try),
+            (
+                "canonical_json",
+                profile.canonical_json,
+                &mut args.canonical_json,
+            ),
+            ("quiet", profile.quiet, &mut args.quiet),
+        ];
+        for (id, value, flag) in flags {
+            if let Some(value) = value
+                && !from_cli(id)
+            {
+                *flag = value;
+            }
+        }
+        if let Some(format) = profile.format
+            && !from_cli("format")
+        {
+            args.format = format;
+        }
+        args.run_defaults.tries = profile.tries;
+        args.run_defaults.sleep = profile.sleep;
+        args.run_defaults.trace_buffer = profile.trace_buffer;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_host_config() {
+    use clap::{CommandFactory, FromArgMatches};
+
+    let mut config: HostConfig = toml::from_str(
+        r#"
+        hdc-path = "/opt/hdc"
+        prepend = "lab"
+        color = "never"
+        "#,
+    )
+    .unwrap();
+    config
+        .apply_env(|name| (name == "HITRACE_BENCH_PREPEND").then(|| String::from("env")))
+        .unwrap();
+    assert!(toml::from_str::<HostConfig>("colour = \"never\"").is_err());
+
+    let matches = Args::command()
+        .try_get_matches_from(["hitrace-bench", "--color", "always"])
+        .unwrap();
+    let mut args = Args::from_arg_matches(&matches).unwrap();
+    config.apply(&mut args, &matches).unwrap();
+    assert_eq!(args.hdc_path, Some(PathBuf::from("/opt/hdc")));
+    assert_eq!(args.prepend.as_deref(), Some("env"));
+    assert_eq!(args.color, ColorChoice::Always);
+}
+
+#[test]
+fn test_profiles() {
+    use clap::{CommandFactory, FromArgMatches};
+
+    let config = || -> HostConfig {
+        toml::from_str(
+            r#"
+            device = "lab-1"
+
+            [profiles.ci]
+            tries = 20
+            sleep = 5
+            bencher = true
+            format = "json"
+            "#,
+        )
+        .unwrap()
+    };
+    let args = |cli: &[&str]| -> Result<Args> {
+        let matches = Args::command()
+            .try_get_matches_from(std::iter::once("hitrace-bench").chain(cli.iter().copied()))
+            .unwrap();
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        config().apply(&mut args, &matches)?;
+        Ok(args)
+    };
+
+    let ci = args(&["--profile", "ci", "--format", "text"]).unwrap();
+    assert!(ci.bencher);
+    assert_eq!(ci.format, OutputFormat::Text);
+    let mut run_args = RunArgs {
+        sleep: 3,
+        ..Default::default()
+    };
+    ci.run_defaults.apply(&mut run_args);
+    assert_eq!(run_args.tries, 20);
+    assert_eq!(run_args.sleep, 3);
+    assert_eq!(run_args.device.as_deref(), Some("lab-1"));
+
+    assert!(!args(&[]).unwrap().bencher);
+    assert!(args(&["--profile", "soak"]).is_err());
+}
@@ -0,0 +1,188 @@
+//! The results of the normal mode as json for `--format json`.
+//! Durations are in nanoseconds and memory in bytes.
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    canonical,
+    device_state::DeviceStates,
+    parser::TraceHeader,
+    retries::RetryUsage,
+    runconfig::RunConfig,
+    tries::TryRow,
+    utils::{ErrorCounts, RunResults},
+};
+
+/// The unit of the samples of a metric
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Unit {
+    Nanoseconds,
+    Bytes,
+    /// Point filters with `no_unit_conversion`
+    None,
+}
+
+/// All samples of a metric, one per try in order
+#[derive(Debug, PartialEq, Serialize)]
+struct Metric {
+    unit: Unit,
+    samples: Vec<i64>,
+}
+
+/// The results of one run config
+#[derive(Debug, Serialize)]
+pub(crate) struct RunReport {
+    url: String,
+    name: Option<String>,
+    trace_header: Option<TraceHeader>,
+    metrics: BTreeMap<String, Metric>,
+    errors: BTreeMap<String, ErrorCounts>,
+    tries: Vec<TryRow>,
+    /// Metrics we dropped because they had fewer than `--min-samples` samples, with their number of samples
+    insufficient: BTreeMap<String, usize>,
+}
+
+impl RunReport {
+    pub(crate) fn new(
+        run_config: &RunConfig,
+        results: RunResults,
+        insufficient: &[(String, usize)],
+    ) -> RunReport {
+        let durations = results.filter_results.into_iter().map(|(key, durations)| {
+            let samples = durations
+                .iter()
+                .map(|d| d.whole_nanoseconds() as i64)
+                .collect();
+            (
+                key,
+                Metric {
+                    unit: Unit::Nanoseconds,
+                    samples,
+                },
+            )
+        });
+        let points = results.point_results.into_iter().map(|(key, points)| {
+            let unit = if points.no_unit_conversion {
+                Unit::None
+            } else {
+                Unit::Bytes
+            };
+            (
+                key,
+                Metric {
+                    unit,
+                    samples: points.result,
+                },
+            )
+        });
+        RunReport {
+            url: run_config.run_args.url.clone(),
+            name: run_config.name.clone(),
+            trace_header: results.trace_header,
+            metrics: durations.chain(points).collect(),
+            errors: results.errors.into_iter().collect(),
+            tries: results.tries,
+            insufficient: insufficient.iter().cloned().collect(),
+        }
+    }
+}
+
+/// The report of a whole session
+#[derive(Debug, Serialize)]
+struct SessionReport<'a> {
+    runs: &'a [RunReport],
+    /// How many tries were repeated of the `--max-total-retries`
+    retries: RetryUsage,
+    /// State of the devices before and after the session
+    device_states: &'a DeviceStates,
+    /// `expected_metrics` of the runs that are not in the results
+    missing_metrics: &'a [String],
+}
+
+/// Prints the reports of all run configs, what the session used of the retry budget, the states of the devices
+/// and the missing expected metrics as json, canonical with `canonical`
+pub(crate) fn print(
+    reports: &[RunReport],
+    retries: RetryUsage,
+    device_states: &DeviceStates,
+    missing_metrics: &[String],
+    canonical: bool,
+) -> Result<()> {
+    let report = SessionReport {
+        runs: reports,
+        retries,
+        device_states,
+        missing_metrics,
+    };
+    println!("{}", canonical::to_string(&report, canonical)?);
+    Ok(())
+}
+
+#[test]
+fn test_run_report() {
+    use crate::{
+        args::{Args, RunArgs},
+        utils::{ErrorKind, PointResult, record_error},
+    };
+    use time::Duration;
+
+    let run_config = RunConfig::new(
+        Args::test_default(std::path::PathBuf::new()),
+        RunArgs::default(),
+        vec![],
+        vec![],
+    );
+    let mut results = RunResults::default();
+    results.filter_results.insert(
+        String::from("Load"),
+        vec![Duration::milliseconds(1), Duration::milliseconds(2)],
+    );
+    results.point_results.insert(
+        String::from("Resident"),
+        PointResult {
+            no_unit_conversion: false,
+            result: vec![1024],
+        },
+    );
+    record_error(
+        &mut results.errors,
+        String::from("Load"),
+        ErrorKind::NoEndMarker,
+    );
+    results.trace_started = Some(time::macros::datetime!(2025-06-01 12:00:01 UTC));
+    results.tries.push(TryRow::new(
+        &results,
+        time::macros::datetime!(2025-06-01 12:00:00.5 UTC),
+    ));
+    let report = RunReport::new(&run_config, results, &[(String::from("JS"), 1)]);
+    let session = serde_json::to_value(SessionReport {
+        runs: std::slice::from_ref(&report),
+        retries: RetryUsage::default(),
+        device_states: &DeviceStates::new(),
+        missing_metrics: &[String::from("Renamed of https://servo.org")],
+    })
+    .unwrap();
+    assert_eq!(session["retries"]["used"], 0);
+    assert_eq!(
+        session["missing_metrics"],
+        serde_json::json!(["Renamed of https://servo.org"])
+    );
+    assert_eq!(session["device_states"], serde_json::json!({}));
+    let json = &session["runs"][0];
+    assert_eq!(json["url"], "https://servo.org");
+    assert_eq!(json["metrics"]["Load"]["unit"], "nanoseconds");
+    assert_eq!(
+        json["metrics"]["Load"]["samples"],
+        serde_json::json!([1000000, 2000000])
+    );
+    assert_eq!(json["metrics"]["Resident"]["unit"], "bytes");
+    assert_eq!(json["errors"]["Load"]["no-end-marker"], 1);
+    assert_eq!(json["tries"][0]["started"], "2025-06-01T12:00:00.5Z");
+    assert_eq!(json["tries"][0]["failure"], "no-end-marker");
+    assert_eq!(json["tries"][0]["trace_started"], "2025-06-01T12:00:01Z");
+    assert_eq!(json["tries"][0]["metrics"]["Load"], 1500000);
+    assert_eq!(json["insufficient"]["JS"], 1);
+}
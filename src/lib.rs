@@ -0,0 +1,8 @@
+//! The trace parser of hitrace-bench, for fuzzing and property-based tests. It is only built with the
+//! `testing` feature, hitrace-bench itself is the binary.
+#![cfg(feature = "testing")]
+
+#[allow(dead_code)]
+pub mod parser;
+#[allow(dead_code)]
+pub mod trace;
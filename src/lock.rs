@@ -0,0 +1,174 @@
+//! Locking devices so two sessions, i.e., of two CI jobs, do not trace on the same device at the same time.
+//! Their traces would end up interleaved in each other's trace files.
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+use log::warn;
+
+/// Lock file on the host for `device`, it contains the pid of the session holding it
+fn lock_path(device: &str) -> PathBuf {
+    crate::host::temp_path(&format!("hitrace-bench-{device}.lock"))
+}
+
+/// Checks if the process holding a lock is still alive. We can only tell on linux.
+fn is_alive(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        PathBuf::from(format!("/proc/{pid}")).exists()
+    } else {
+        true
+    }
+}
+
+/// A device we have exclusive use of until this is dropped
+#[derive(Debug)]
+pub(crate) struct DeviceLock {
+    path: PathBuf,
+}
+
+/// `path` with `suffix` appended, i.e., `device.lock.breaking`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// The pid in the lock at `path`, `None` if the lock is gone. A lock without a pid is never considered stale,
+/// we cannot tell if its session is still running.
+fn owner(path: &Path) -> Result<Option<u32>> {
+    let owner = match fs::read_to_string(path) {
+        Ok(owner) => owner,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Could not read lock file {path:?}")),
+    };
+    owner.trim().parse().map(Some).map_err(|_| {
+        anyhow!(
+            "Lock {path:?} does not contain the pid of a session. \
+             Remove it if no session uses the device."
+        )
+    })
+}
+
+/// Removes the lock at `path` of the dead session `pid`. Only one session may do this at a time, otherwise a
+/// slower one could remove the lock a faster one just created.
+fn remove_stale(path: &Path, pid_file: &Path, pid: u32) -> Result<()> {
+    let breaker = with_suffix(path, ".breaking");
+    match fs::hard_link(pid_file, &breaker) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            return Err(anyhow!(
+                "Another session is removing the stale lock {path:?}. \
+                 Remove {breaker:?} if that is not the case."
+            ));
+        }
+        Err(e) => return Err(e).with_context(|| format!("Could not create {breaker:?}")),
+    }
+    // The lock might have been replaced before we got the breaker
+    let removed = match owner(path) {
+        Ok(Some(owner)) if owner == pid => {
+            warn!("Removing stale lock {path:?} of {pid}");
+            fs::remove_file(path).with_context(|| format!("Could not remove stale lock {path:?}"))
+        }
+        Ok(_) => Ok(()),
+        Err(e) => Err(e),
+    };
+    let _ = fs::remove_file(&breaker);
+    removed
+}
+
+impl DeviceLock {
+    /// Locks `device` or fails if another session holds its lock
+    pub(crate) fn acquire(device: &str) -> Result<DeviceLock> {
+        let path = lock_path(device);
+        // The lock is linked to a file that already contains our pid, so it never exists without one
+        let pid_file = with_suffix(&path, &format!(".{}.tmp", std::process::id()));
+        fs::write(&pid_file, std::process::id().to_string())
+            .with_context(|| format!("Could not write lock file {pid_file:?}"))?;
+        let lock = Self::link(device, path, &pid_file);
+        let _ = fs::remove_file(&pid_file);
+        lock
+    }
+
+    /// Links `pid_file` to the lock `path` of `device`
+    fn link(device: &str, path: PathBuf, pid_file: &Path) -> Result<DeviceLock> {
+        // We retry once after removing a stale lock
+        for _ in 0..2 {
+            match fs::hard_link(pid_file, &path) {
+                Ok(()) => return Ok(DeviceLock { path }),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => match owner(&path)? {
+                    Some(pid) if is_alive(pid) => {
+                        let unknown = if cfg!(target_os = "linux") {
+                            ""
+                        } else {
+                            " We cannot tell if a session is still running on this host, \
+                             so stale locks are never removed automatically."
+                        };
+                        return Err(anyhow!(
+                            "Device {device} is used by another session (pid {pid}). \
+                             Remove {path:?} if that is not the case.{unknown}"
+                        ));
+                    }
+                    Some(pid) => remove_stale(&path, pid_file, pid)?,
+                    // Released in the meantime
+                    None => {}
+                },
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Could not create lock file {path:?}"));
+                }
+            }
+        }
+        Err(anyhow!("Could not lock device {device}"))
+    }
+}
+
+impl Drop for DeviceLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Removes the locks this process holds, for when we exit without dropping them
+pub(crate) fn release_all(devices: &[String]) {
+    let pid = std::process::id().to_string();
+    for path in devices.iter().map(|d| lock_path(d)) {
+        if fs::read_to_string(&path).is_ok_and(|owner| owner.trim() == pid) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[test]
+fn test_device_lock() {
+    let device = format!("test-device-{}", std::process::id());
+    let lock = DeviceLock::acquire(&device).unwrap();
+    assert!(DeviceLock::acquire(&device).is_err());
+    drop(lock);
+    assert!(!lock_path(&device).exists());
+
+    // A lock without a pid is reported, not removed
+    for content in ["", "not a pid"] {
+        fs::write(lock_path(&device), content).unwrap();
+        let Err(err) = DeviceLock::acquire(&device) else {
+            panic!("A lock without a pid should not be removed");
+        };
+        assert!(
+            err.to_string().contains("does not contain the pid"),
+            "{err}"
+        );
+        assert!(lock_path(&device).exists());
+    }
+    fs::remove_file(lock_path(&device)).unwrap();
+
+    // A lock of a session that is gone does not block us
+    if cfg!(target_os = "linux") {
+        fs::write(lock_path(&device), u32::MAX.to_string()).unwrap();
+        let lock = DeviceLock::acquire(&device).unwrap();
+        assert!(!with_suffix(&lock_path(&device), ".breaking").exists());
+        release_all(std::slice::from_ref(&device));
+        assert!(!lock_path(&device).exists());
+        drop(lock);
+    }
+}
@@ -1,38 +1,112 @@
 use anyhow::{Context, Result, anyhow};
-use args::Args;
-use clap::Parser;
+use args::{Args, DuplicateMetrics, OutputFormat, SortBy};
+use clap::{CommandFactory, FromArgMatches};
 use filter::Filter;
-use humanize_bytes::humanize_bytes_binary;
-use log::{error, info};
-use runconfig::RunConfig;
-use std::collections::HashMap;
-use time::Duration;
+use itertools::Itertools;
+use log::{error, info, warn};
+use parser::TraceFile;
+use runconfig::{Dependencies, RunConfig, RunState};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    thread,
+};
+use time::{Duration, OffsetDateTime};
 use trace::Trace;
-use utils::{FilterErrors, FilterResults, PointResults, RunResults, avg_min_max};
-use yansi::{Condition, Paint};
+use tries::TryRow;
+use utils::{
+    AvgMingMax, ErrorKind, FilterErrors, FilterResults, PointResults, RunResults, avg_min_max,
+    record_error, sparkline,
+};
+use yansi::Paint;
 
 use crate::{
-    args::RunArgs,
+    args::{Preset, RunArgs},
     point_filters::{PointFilter, PointFilterType},
     utils::PointResult,
 };
 
+mod analyze;
+mod app_session;
 mod args;
 mod bencher;
+mod bisect;
+mod canonical;
+mod change_point;
+mod check_filters;
+mod clock_domain;
+mod command_log;
 mod device;
+mod device_state;
+mod doctor;
+mod error_log;
+mod expression;
+mod farm;
 mod filter;
+mod frames;
+mod github;
+mod host;
+mod host_config;
+mod json_report;
+mod lock;
+mod metric_script;
+mod navigation;
+mod noise;
+mod parser;
+mod plugin;
 mod point_filters;
+mod retries;
 mod runconfig;
+mod schema;
+mod script;
+mod serve;
+mod session;
+mod shutdown;
+mod startup;
+mod suggest;
+mod synthesize;
 mod test;
+mod testcase;
+mod thresholds;
+mod throughput;
 mod trace;
+mod trend;
+mod tries;
+mod tui;
+mod units;
 mod utils;
+mod webhook;
+
+/// Where the log goes while the TUI runs
+const TUI_LOG_PATH: &str = "hitrace-bench.log";
+
+/// Sorts rows of a metric, its values and their average, minimum and maximum by `sort_by`.
+/// The rows come from ordered maps, so they are sorted by name already.
+fn sort_rows<K, V, T: Ord + Copy>(rows: &mut [(K, V, AvgMingMax<T>)], sort_by: SortBy) {
+    if sort_by == SortBy::Value {
+        rows.sort_by(|x, y| y.2.avg.cmp(&x.2.avg));
+    }
+}
 
 /// Print the differences
-fn print_differences(args: &RunArgs, results: RunResults) {
+/// `insufficient` are the metrics that were dropped because they had too few samples.
+/// With `per_try` the table of the metrics of every try follows.
+fn print_differences(
+    args: &RunArgs,
+    results: RunResults,
+    insufficient: &[(String, usize)],
+    per_try: bool,
+    sort_by: SortBy,
+) {
     if !results.errors.is_empty() {
         println!("The following things broke with errors");
-        for (key, val) in results.errors.iter() {
-            println!("{key}: {val} errors");
+        for (key, counts) in results.errors.iter() {
+            let counts = counts
+                .iter()
+                .map(|(kind, count)| format!("{count} {kind}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!("{key}: {counts}");
         }
     }
 
@@ -44,46 +118,75 @@ fn print_differences(args: &RunArgs, results: RunResults) {
         args.tries,
         args.url
     );
-    for (key, val) in results.filter_results.iter() {
-        let avg_min_max = avg_min_max::<Duration, u16>(val);
+    if let Some(ref header) = results.trace_header {
+        println!("Trace file: {header}");
+    }
+    let mut filter_results: Vec<_> = results
+        .filter_results
+        .iter()
+        .map(|(key, val)| (key, val, avg_min_max::<Duration, u16>(val)))
+        .collect();
+    sort_rows(&mut filter_results, sort_by);
+    for (key, val, avg_min_max) in filter_results {
         println!(
-            "{}: {} {} {}  ({} runs)",
+            "{}: {} {} {}  ({} runs) {}",
             key,
-            avg_min_max.avg.yellow().whenever(Condition::TTY_AND_COLOR),
-            avg_min_max.min.green().whenever(Condition::TTY_AND_COLOR),
-            avg_min_max.max.red().whenever(Condition::TTY_AND_COLOR),
+            units::format_duration(key, avg_min_max.avg).yellow(),
+            units::format_duration(key, avg_min_max.min).green(),
+            units::format_duration(key, avg_min_max.max).red(),
             avg_min_max.number,
+            sparkline(&val.iter().map(|d| d.as_seconds_f64()).collect::<Vec<_>>()),
         );
     }
 
+    for (key, samples) in insufficient {
+        println!("{key}: insufficient data ({samples} runs)");
+    }
+
+    if per_try {
+        tries::print(&results.tries);
+    }
+
     if !results.point_results.is_empty() {
         println!("-----------Points-------------------------");
-        let mut sorted_points: Vec<_> = results.point_results.into_iter().collect();
-        sorted_points.sort_by(|x, y| x.0.cmp(&y.0));
-        for (key, val) in sorted_points {
-            let avg_min_max = avg_min_max::<u64, u64>(&val.result);
-            if val.no_unit_conversion {
+        let mut points: Vec<_> = results
+            .point_results
+            .into_iter()
+            .map(|(key, val)| {
+                let avg_min_max = avg_min_max::<i64, i64>(&val.result);
+                (key, val, avg_min_max)
+            })
+            .collect();
+        sort_rows(&mut points, sort_by);
+        for (key, val, avg_min_max) in points {
+            let sparkline = sparkline(&val.result.iter().map(|v| *v as f64).collect::<Vec<_>>());
+            if results.measures.get(&key).map(String::as_str)
+                == Some(throughput::THROUGHPUT_MEASURE)
+            {
                 println!(
-                    "{}: {} {} {} ({} runs)",
+                    "{}: {}/s {}/s {}/s  ({} runs) {sparkline}",
                     key,
-                    avg_min_max.avg.yellow().whenever(Condition::TTY_AND_COLOR),
-                    avg_min_max.min.green().whenever(Condition::TTY_AND_COLOR),
-                    avg_min_max.max.red().whenever(Condition::TTY_AND_COLOR),
+                    units::format_memory(&key, avg_min_max.avg).yellow(),
+                    units::format_memory(&key, avg_min_max.min).green(),
+                    units::format_memory(&key, avg_min_max.max).red(),
+                    avg_min_max.number,
+                );
+            } else if val.no_unit_conversion {
+                println!(
+                    "{}: {} {} {} ({} runs) {sparkline}",
+                    key,
+                    avg_min_max.avg.yellow(),
+                    avg_min_max.min.green(),
+                    avg_min_max.max.red(),
                     avg_min_max.number
                 );
             } else {
                 println!(
-                    "{}: {} {} {}  ({} runs)",
+                    "{}: {} {} {}  ({} runs) {sparkline}",
                     key,
-                    humanize_bytes_binary!(avg_min_max.avg)
-                        .yellow()
-                        .whenever(Condition::TTY_AND_COLOR),
-                    humanize_bytes_binary!(avg_min_max.min)
-                        .green()
-                        .whenever(Condition::TTY_AND_COLOR),
-                    humanize_bytes_binary!(avg_min_max.max)
-                        .red()
-                        .whenever(Condition::TTY_AND_COLOR),
+                    units::format_memory(&key, avg_min_max.avg).yellow(),
+                    units::format_memory(&key, avg_min_max.min).green(),
+                    units::format_memory(&key, avg_min_max.max).red(),
                     avg_min_max.number,
                 );
             }
@@ -93,136 +196,998 @@ fn print_differences(args: &RunArgs, results: RunResults) {
 }
 
 /// Process the filters from traces. These are the traces per run_config
+/// Returns the number of filters that found a result.
 fn run_runconfig_filters(
     run_config: &RunConfig,
     traces: &[Trace],
     results: &mut FilterResults,
     errors: &mut FilterErrors,
-) {
+    measures: &mut BTreeMap<String, String>,
+) -> usize {
+    let result_key = |name: &str| {
+        if run_config.args.run_file.is_some() {
+            format!("{}/{}", run_config.run_args.url, name)
+        } else {
+            name.to_owned()
+        }
+    };
+    for filter in &run_config.filters {
+        if let Some(ref measure) = filter.measure {
+            measures.insert(result_key(&filter.name), measure.to_owned());
+        }
+    }
+
     // Collect differences
     let differences = filter::find_notable_differences(traces, &run_config.filters);
+    let mut found = 0;
     for (original_key, value) in differences.into_iter() {
+        let key = result_key(original_key);
+        let range = run_config
+            .filters
+            .iter()
+            .find(|f| f.name == original_key)
+            .and_then(|f| f.expected_range);
+        match value {
+            Ok(d) if range.is_some_and(|r| !r.contains(d.as_seconds_f64() * 1000.0)) => {
+                warn!("{key}: {d} is outside of the expected range, leaving it out");
+                record_error(errors, key, ErrorKind::OutOfRange);
+            }
+            Ok(d) => {
+                found += 1;
+                results
+                    .entry(key)
+                    .and_modify(|v| v.push(d))
+                    .or_insert(vec![(d)]);
+            }
+            Err(kind) => record_error(errors, key, kind),
+        }
+    }
+    found
+}
+
+/// Process the points from thre traces. These are the traces per run_config.
+/// Traces a point filter could not use are counted as errors of that point filter.
+/// Returns the number of points found.
+fn run_runconfig_points(
+    run_config: &RunConfig,
+    traces: &[Trace],
+    points: &mut PointResults,
+    errors: &mut FilterErrors,
+    measures: &mut BTreeMap<String, String>,
+) -> usize {
+    let mut found = 0;
+    for filter in &run_config.point_filters {
+        let (new_points, new_errors) = filter.pointfilter_to_point(traces, run_config);
+        for (kind, e) in new_errors {
+            error!("{e:?}");
+            record_error(
+                errors,
+                run_config.run_args.url.to_owned() + "/" + filter.name.as_str(),
+                kind,
+            );
+        }
+
+        found += new_points.len();
+        for p in new_points {
+            let key = p.name.to_owned();
+            let value = p.point_type.numeric_value().unwrap();
+            if let Some(range) = filter.expected_range
+                && !range.contains(value as f64)
+            {
+                warn!("{key}: {value} is outside of the expected range, leaving it out");
+                record_error(errors, key, ErrorKind::OutOfRange);
+                continue;
+            }
+            if let Some(ref measure) = filter.measure {
+                measures.insert(key.clone(), measure.to_owned());
+            }
+            points
+                .entry(key)
+                .and_modify(|v| v.result.push(value))
+                .or_insert(PointResult {
+                    no_unit_conversion: p.no_unit_conversion,
+                    result: vec![value],
+                });
+        }
+    }
+    found
+}
+
+/// Adds the stability of a run, how many of its tries crashed or failed, as metrics so they trend in bencher
+/// like the performance metrics. Restarts of the app during a try count as crashes, too.
+fn record_stability(run_config: &RunConfig, results: &mut RunResults) {
+    let tries = results.tries.len();
+    if tries == 0 {
+        return;
+    }
+    let crashes: u32 = results
+        .errors
+        .values()
+        .filter_map(|counts| counts.get(&ErrorKind::AppCrash))
+        .sum();
+    let failed = results.tries.iter().filter(|t| t.failed()).count();
+    let crash_rate = (f64::from(crashes) * 100.0 / tries as f64).round() as i64;
+    for (name, measure, value) in [
+        ("crash_rate", "Percent", crash_rate),
+        ("crashes", "Crashes", i64::from(crashes)),
+        ("failed_tries", "Tries", failed as i64),
+    ] {
         let key = if run_config.args.run_file.is_some() {
-            format!("{}/{}", run_config.run_args.url, original_key)
+            format!("{}/Stability/{name}", run_config.run_args.url)
         } else {
-            original_key.to_owned()
+            format!("Stability/{name}")
         };
-        if let Ok(d) = value {
-            results
-                .entry(key)
-                .and_modify(|v| v.push(d))
-                .or_insert(vec![(d)]);
-        } else {
-            errors.entry(key).and_modify(|v| *v += 1).or_insert(1);
-        }
+        results.measures.insert(key.clone(), String::from(measure));
+        results.point_results.insert(
+            key,
+            PointResult {
+                no_unit_conversion: true,
+                result: vec![value],
+            },
+        );
     }
 }
 
-/// Process the points from thre traces. These are the traces per run_config.
-fn run_runconfig_points(run_config: &RunConfig, traces: &[Trace], points: &mut PointResults) {
-    let new_points: Vec<_> = run_config
-        .point_filters
-        .iter()
-        .map(|f| f.pointfilter_to_point(traces, run_config))
-        .collect();
+/// A run config whose first try did not produce a single result, i.e., because of a wrong bundle name.
+/// With `--fail-fast` this stops the whole session.
+#[derive(Debug)]
+pub(crate) struct BrokenRunConfig(String);
 
-    for p in new_points.into_iter().flatten() {
-        let key = p.name.to_owned();
-        points
-            .entry(key)
-            .and_modify(|v| v.result.push(p.point_type.numeric_value().unwrap()))
-            .or_insert(PointResult {
-                no_unit_conversion: p.no_unit_conversion,
-                result: vec![p.point_type.numeric_value().unwrap()],
-            });
+impl std::fmt::Display for BrokenRunConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "No filter or point filter found anything in the first try of {}",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for BrokenRunConfig {}
+
+/// The exit code of a session whose results lack some `expected_metrics`
+const MISSING_METRICS_EXIT_CODE: i32 = 3;
+
+/// Metrics of the `expected_metrics` of the runs that are not in the results. We still write the results, with the
+/// missing metrics flagged, but exit with `MISSING_METRICS_EXIT_CODE`.
+#[derive(Debug)]
+struct MissingMetrics(Vec<String>);
+
+impl std::fmt::Display for MissingMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Expected metrics are missing: {}", self.0.join(", "))
     }
 }
 
-/// Runs one RunConfig and append the results to the results, errors and points
+impl std::error::Error for MissingMetrics {}
+
+/// The `expected_metrics` of `run_config` that are not in `results`, with the url of the run
+fn missing_metrics(run_config: &RunConfig, results: &RunResults) -> Vec<String> {
+    run_config
+        .expected_metrics
+        .iter()
+        .filter(|expected| {
+            !results
+                .filter_results
+                .keys()
+                .chain(results.point_results.keys())
+                .any(|key| throughput::is_metric(key, expected))
+        })
+        .map(|expected| format!("{} of {}", expected, run_config.run_args.url))
+        .collect()
+}
+
+/// Runs one RunConfig and append the results to the results
+/// `device` is the serial of the device to run on, the only connected device if `None`.
 pub(crate) fn run_runconfig(
     run_config: &RunConfig,
-    results: &mut FilterResults,
-    filter_errors: &mut FilterErrors,
-    points: &mut PointResults,
+    results: &mut RunResults,
+    device: Option<&str>,
 ) -> Result<()> {
     info!("Running Test url {}", run_config.run_args.url);
+    // Repeating a try after the device dropped off needs the result of the try and a reboot before the next try
+    // must not kill receiving the trace of the previous one
+    if run_config.args.pipeline
+        && run_config.args.wait_device.is_none()
+        && run_config.args.reboot_every.is_none()
+    {
+        return run_pipelined(run_config, results, device);
+    }
     for i in 1..run_config.run_args.tries + 1 {
+        if run_config.args.budget_exhausted() {
+            warn!(
+                "Time budget used up, skipping the remaining tries of {}",
+                run_config.run_args.url
+            );
+            break;
+        }
         info!("Running test {i}");
-        let traces = if let Some(ref file) = run_config.args.trace_file {
-            trace::read_file(file)?
+        tui::try_started(&run_config.run_args.url, i, run_config.run_args.tries);
+        let mut reconnects = 0;
+        let (try_results, result, started) = loop {
+            let started = OffsetDateTime::now_utc();
+            let mut try_results = RunResults::default();
+            let result = run_try(run_config, i, device, &mut try_results);
+            if reconnects < MAX_RECONNECTS && reconnected(run_config, device) {
+                if retries::take() {
+                    reconnects += 1;
+                    warn!("Repeating try {i} of {}", run_config.run_args.url);
+                    continue;
+                }
+                warn!(
+                    "The retry budget of the session is used up, keeping try {i} of {}",
+                    run_config.run_args.url
+                );
+            }
+            break (try_results, result, started);
+        };
+        // Keep what we have so we can save it if the session gets interrupted
+        shutdown::record_try(&try_results);
+        results.tries.push(TryRow::new(&try_results, started));
+        results
+            .error_log
+            .extend(error_log::try_entries(run_config, i, &try_results));
+        tui::try_finished(&run_config.run_args.url, &try_results);
+        results.merge(try_results);
+        // Only now an interruption may stop the session
+        drop(result?);
+    }
+    Ok(())
+}
+
+/// A try whose trace is analyzed while the next try runs
+type PendingTry<'scope> =
+    thread::ScopedJoinHandle<'scope, (RunResults, Result<()>, Capture, OffsetDateTime, usize)>;
+
+/// Like `run_runconfig` but receives and analyzes the trace of a try while the next try already runs on the
+/// device
+fn run_pipelined(
+    run_config: &RunConfig,
+    results: &mut RunResults,
+    device: Option<&str>,
+) -> Result<()> {
+    let finish = |results: &mut RunResults, pending: PendingTry| {
+        let (try_results, result, capture, started, i) =
+            pending.join().expect("Analyzing a try panicked");
+        shutdown::record_try(&try_results);
+        results.tries.push(TryRow::new(&try_results, started));
+        results
+            .error_log
+            .extend(error_log::try_entries(run_config, i, &try_results));
+        tui::try_finished(&run_config.run_args.url, &try_results);
+        results.merge(try_results);
+        drop(capture);
+        result
+    };
+    thread::scope(|s| {
+        let mut pending: Option<PendingTry> = None;
+        for i in 1..run_config.run_args.tries + 1 {
+            if run_config.args.budget_exhausted() {
+                warn!(
+                    "Time budget used up, skipping the remaining tries of {}",
+                    run_config.run_args.url
+                );
+                break;
+            }
+            info!("Running test {i}");
+            tui::try_started(&run_config.run_args.url, i, run_config.run_args.tries);
+            let started = OffsetDateTime::now_utc();
+            let mut try_results = RunResults::default();
+            // The slot of the previous try is still being received
+            let capture = capture_try(run_config, i, device, i, &mut try_results);
+            if let Some(previous) = pending.take() {
+                finish(results, previous)?;
+            }
+            let capture = match capture {
+                Ok(Some(capture)) => capture,
+                result => {
+                    shutdown::record_try(&try_results);
+                    results.tries.push(TryRow::new(&try_results, started));
+                    results
+                        .error_log
+                        .extend(error_log::try_entries(run_config, i, &try_results));
+                    results.merge(try_results);
+                    result?;
+                    continue;
+                }
+            };
+            let analyzing = s.spawn(move || {
+                let result = analyze_try(run_config, i, device, &capture, &mut try_results);
+                (try_results, result, capture, started, i)
+            });
+            // With fail fast we have to know if the first try found anything before we go on
+            if i == 1 && run_config.args.fail_fast {
+                finish(results, analyzing)?;
+            } else {
+                pending = Some(analyzing);
+            }
+        }
+        match pending {
+            Some(last) => finish(results, last),
+            None => Ok(()),
+        }
+    })
+}
+
+/// How often a single try is repeated because the device dropped off
+const MAX_RECONNECTS: usize = 3;
+
+/// With `--wait-device`, checks if the device dropped off during the last try and came back in time.
+/// Then the try is not valid and has to be repeated.
+fn reconnected(run_config: &RunConfig, device: Option<&str>) -> bool {
+    let Some(wait) = run_config.args.wait_device else {
+        return false;
+    };
+    if run_config.args.trace_file.is_some() || device::is_connected(device) {
+        return false;
+    }
+    warn!(
+        "Device {} dropped off, waiting {wait}s for it to come back",
+        device.unwrap_or("of the run")
+    );
+    device::wait_for_device(device, std::time::Duration::from_secs(wait))
+}
+
+/// A new directory for the artifacts of the `i`th try of `run_config`.
+/// With `--rounds` there are several tries with the same number, they get a suffix.
+fn try_artifacts_dir(artifacts: &Path, run_config: &RunConfig, i: usize) -> PathBuf {
+    let run = run_config
+        .name
+        .as_deref()
+        .unwrap_or(&run_config.run_args.url)
+        .replace(
+            |c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-',
+            "_",
+        );
+    let base = artifacts.join(run).join(format!("try-{i}"));
+    std::iter::once(base.clone())
+        .chain((2..).map(|n| base.with_file_name(format!("try-{i}-{n}"))))
+        .find(|dir| !dir.exists())
+        .expect("There is always a free directory")
+}
+
+/// The trace of a try that is not analyzed yet
+enum Capture {
+    /// The trace is in `--trace-file`
+    File,
+    /// The trace is still on the device, with the artifacts directory of the try to archive it in
+    Device(device::DeviceTrace, Option<PathBuf>),
+}
+
+/// Runs the `i`th try of a run config and puts its results into `try_results`.
+/// Returns the capture of the try, drop it after recording the results.
+fn run_try(
+    run_config: &RunConfig,
+    i: usize,
+    device: Option<&str>,
+    try_results: &mut RunResults,
+) -> Result<Option<Capture>> {
+    let Some(capture) = capture_try(run_config, i, device, 0, try_results)? else {
+        return Ok(None);
+    };
+    analyze_try(run_config, i, device, &capture, try_results)?;
+    Ok(Some(capture))
+}
+
+/// The part of the `i`th try that needs the device for itself: runs the app and traces it into `slot`.
+/// Returns `None` if the try produced no trace, the reason is in the errors of `try_results`.
+fn capture_try(
+    run_config: &RunConfig,
+    i: usize,
+    device: Option<&str>,
+    slot: usize,
+    try_results: &mut RunResults,
+) -> Result<Option<Capture>> {
+    if run_config.args.trace_file.is_some() {
+        return Ok(Some(Capture::File));
+    }
+    let started = OffsetDateTime::now_utc();
+    if let Some(every) = run_config.args.reboot_every {
+        device::reboot_if_due(device, every)?;
+    }
+    if run_config.args.artifacts.is_some() {
+        device::clear_app_log(device)?;
+    }
+    let device_trace = device::exec_hdc_commands(
+        &run_config.run_args,
+        run_config.args.is_rooted,
+        device,
+        slot,
+    );
+    let mut archive = None;
+    if let Some(ref artifacts) = run_config.args.artifacts {
+        let target = try_artifacts_dir(artifacts, run_config, i);
+        try_results.artifacts = Some(target.clone());
+        if let Err(e) = analyze::archive_metadata(&target, run_config, started) {
+            error!("Could not archive the try: {e:?}");
+        }
+        // Missing artifacts should not fail the try
+        match device::collect_artifacts(
+            device,
+            &run_config.run_args,
+            run_config.args.is_rooted,
+            &target,
+        ) {
+            Ok(()) => info!("Saved artifacts to {target:?}"),
+            Err(e) => error!("Could not collect artifacts: {e:?}"),
+        }
+        archive = Some(target);
+    }
+    let device_trace = match device_trace {
+        Err(e) if e.is::<device::AppCrashed>() => {
+            if i == 1 && run_config.args.fail_fast {
+                return Err(BrokenRunConfig(run_config.run_args.url.clone()).into());
+            }
+            // A crash is most likely a flaky device, so we count it and try again
+            error!("{e}");
+            record_error(
+                &mut try_results.errors,
+                run_config.run_args.url.clone(),
+                ErrorKind::AppCrash,
+            );
+            return Ok(None);
+        }
+        device_trace => device_trace?,
+    };
+    if let Some(ref load) = device_trace.background_load {
+        info!("Background load {load}");
+        if run_config
+            .args
+            .max_background_load
+            .is_some_and(|max| load.percent > max)
+        {
+            warn!("Discarding try {i}, other processes loaded the device with {load}");
+            record_error(
+                &mut try_results.errors,
+                run_config.run_args.url.clone(),
+                ErrorKind::Contaminated,
+            );
+            return Ok(None);
+        }
+    }
+    Ok(Some(Capture::Device(device_trace, archive)))
+}
+
+/// The part of the `i`th try that does not need the device for itself: receives and analyzes the trace
+fn analyze_try(
+    run_config: &RunConfig,
+    i: usize,
+    device: Option<&str>,
+    capture: &Capture,
+    try_results: &mut RunResults,
+) -> Result<()> {
+    let trace_file = match capture {
+        Capture::File => {
+            let file = run_config
+                .args
+                .trace_file
+                .as_ref()
+                .expect("Captured from a trace file");
+            if run_config.args.cache_traces {
+                parser::read_file_cached(file, run_config.args.parse_error_policy)?
+            } else {
+                parser::read_file(file, run_config.args.parse_error_policy)?
+            }
+        }
+        Capture::Device(device_trace, archive) => {
+            device_trace.receive(device)?;
+            if let Some(dir) = archive
+                && let Err(e) = analyze::archive_trace(dir, &device_trace.path)
+            {
+                error!("Could not archive the trace: {e:?}");
+            }
+            let mut trace_file =
+                parser::read_file(&device_trace.path, run_config.args.parse_error_policy)?;
+            if let Some(offset) = device_trace.clock_offset {
+                info!("Device boot clock started at {}", offset.0);
+                trace::apply_clock_offset(&mut trace_file.traces, offset);
+            }
+            trace_file
+        }
+    };
+    if i == 1 && run_config.args.explain_filters {
+        explain_filters(run_config, &trace_file.traces);
+    }
+    let found = analyze_trace_file(run_config, &trace_file, try_results)?;
+    if i == 1 && run_config.args.fail_fast && found == 0 {
+        return Err(BrokenRunConfig(run_config.run_args.url.clone()).into());
+    }
+
+    if run_config.run_args.tries == 1 && run_config.run_args.all_traces {
+        if let Some(ref header) = try_results.trace_header {
+            println!("Trace file: {header}");
+        }
+        println!("Printing {} traces", &trace_file.traces.len());
+        for i in &trace_file.traces {
+            println!("{i:?}");
+        }
+        println!("----------------------------------------------------------\n\n");
+    }
+    Ok(())
+}
+
+/// Prints which traces the filters of `run_config` matched in every navigation of `traces`
+fn explain_filters(run_config: &RunConfig, traces: &[Trace]) {
+    for navigation in navigation::split(traces) {
+        if let Some(ref label) = navigation.label {
+            println!("Navigation {label}:");
+        }
+        for filter in &run_config.filters {
+            print!("{}", filter.explain(navigation.traces));
+        }
+    }
+}
+
+/// Checks a parsed trace of `run_config` and computes all its metrics into `try_results`, once per app session.
+/// Returns the number of metrics found.
+pub(crate) fn analyze_trace_file(
+    run_config: &RunConfig,
+    trace_file: &TraceFile,
+    try_results: &mut RunResults,
+) -> Result<usize> {
+    info!("Read trace file: {}", trace_file.header);
+    trace_file.check_unmatched(run_config.args.max_unmatched_ratio)?;
+    if trace_file.header.lost_entries() {
+        record_error(
+            &mut try_results.errors,
+            run_config.run_args.url.clone(),
+            ErrorKind::TraceOverflow,
+        );
+    }
+    let traces = &trace_file.traces;
+    try_results.trace_header = Some(trace_file.header.clone());
+    try_results.trace_started = traces.first().and_then(|t| t.wall_clock);
+    tui::matched(run_config, traces);
+    let sessions = app_session::split(traces);
+    let mut found = 0;
+    for session in &sessions {
+        if session.label.is_some() {
+            warn!(
+                "{} restarted with pid {}",
+                run_config.run_args.bundle_name,
+                session.pid.unwrap_or_default()
+            );
+            record_error(
+                &mut try_results.errors,
+                run_config.run_args.url.clone(),
+                ErrorKind::AppCrash,
+            );
+        }
+        let mut session_results = RunResults::default();
+        found += analyze_traces(run_config, session.traces, &mut session_results)?;
+        try_results.merge(session.label_results(session_results));
+    }
+    Ok(found)
+}
+
+/// Computes all metrics of `run_config` from the traces of one app session.
+/// Returns the number of metrics found.
+fn analyze_traces(
+    run_config: &RunConfig,
+    traces: &[Trace],
+    results: &mut RunResults,
+) -> Result<usize> {
+    let navigations = navigation::split(traces);
+    let mut found = 0;
+    for navigation in &navigations {
+        let mut navigation_results = RunResults::default();
+        found += run_runconfig_filters(
+            run_config,
+            navigation.traces,
+            &mut navigation_results.filter_results,
+            &mut navigation_results.errors,
+            &mut navigation_results.measures,
+        );
+        found += run_runconfig_points(
+            run_config,
+            navigation.traces,
+            &mut navigation_results.point_results,
+            &mut navigation_results.errors,
+            &mut navigation_results.measures,
+        );
+        found += throughput::record_throughputs(run_config, &mut navigation_results);
+        results.merge(navigation.label_results(navigation_results));
+    }
+    navigation::record_memory_growth(&navigations, results);
+    Ok(found
+        + match run_config.run_args.preset {
+            Some(Preset::ScrollJank) => frames::record_frame_metrics(traces, results),
+            None => 0,
+        }
+        + frames::record_input_latency(traces, results)
+        + startup::record_startup_time(traces, results)
+        + startup::record_first_frame(traces, results)
+        + testcase::record_testcase_durations(traces, results)
+        + plugin::record_plugin_metrics(run_config, traces, results)?
+        + metric_script::record_script_metrics(run_config, traces, results)?)
+}
+
+/// Writes the results of all run configs in bencher format
+fn output_bencher(args: &Args, results: RunResults, summary: &mut webhook::Summary) -> Result<()> {
+    summary.add(&results);
+    if let (Some(pr_comment), Some(baseline)) = (&args.pr_comment, &args.baseline) {
+        github::write_pr_comment(&results, baseline, pr_comment)
+            .context("Error in writing PR comment")?;
+    }
+    bencher::write_results(
+        results,
+        args.bencher_statistics,
+        args.bencher_samples,
+        args.canonical_json,
+    )
+    .context("Error in writing bencher results")
+}
+
+/// Runs `run_config` into fresh results unless one of its dependencies failed.
+/// `states` are the states of `run_configs` in the same order.
+pub(crate) fn run_with_dependencies(
+    args: &Args,
+    run_config: &RunConfig,
+    run_configs: &[RunConfig],
+    states: &[RunState],
+    device: Option<&str>,
+) -> Result<RunResults> {
+    if let Dependencies::Failed(dependency) = run_config.dependencies(run_configs, states) {
+        return Err(anyhow!("Skipped because {dependency} failed"));
+    }
+    let mut results = RunResults {
+        prepend: args.prepend.clone(),
+        ..Default::default()
+    };
+    run_runconfig(run_config, &mut results, device)?;
+    Ok(results)
+}
+
+/// Runs every pending run config once, one after the other.
+/// The results are in the order of `run_configs` and `None` for runs that were not pending.
+/// Stops at a `BrokenRunConfig`.
+pub(crate) fn run_sequential(
+    args: &Args,
+    run_configs: &[RunConfig],
+    states: &[RunState],
+) -> Result<Vec<Option<Result<RunResults>>>> {
+    let mut states = states.to_vec();
+    let mut results = Vec::new();
+    for (index, run_config) in run_configs.iter().enumerate() {
+        if states[index] != RunState::Pending {
+            results.push(None);
+            continue;
+        }
+        let result = run_with_dependencies(
+            args,
+            run_config,
+            run_configs,
+            &states,
+            run_config.run_args.pinned_device(),
+        );
+        if let Err(e) = result.as_ref()
+            && e.is::<BrokenRunConfig>()
+        {
+            return Err(result.unwrap_err());
+        }
+        states[index] = if result.is_ok() {
+            RunState::Succeeded
         } else {
-            let log_path =
-                device::exec_hdc_commands(&run_config.run_args, run_config.args.is_rooted)?;
-            trace::read_file(&log_path)?
+            RunState::Failed
         };
-        run_runconfig_filters(run_config, &traces, results, filter_errors);
-        run_runconfig_points(run_config, &traces, points);
+        results.push(Some(result));
+    }
+    Ok(results)
+}
 
-        if run_config.run_args.tries == 1 && run_config.run_args.all_traces {
-            println!("Printing {} traces", &traces.len());
-            for i in &traces {
-                println!("{i:?}");
+/// Runs all rounds of the session and merges the results of every run config into `all_results`
+fn run_rounds(
+    args: &Args,
+    run_configs: &[RunConfig],
+    devices: &[String],
+    states: &mut [RunState],
+    all_results: &mut [Result<RunResults>],
+) -> Result<()> {
+    for round in 1..=args.rounds {
+        if args.budget_exhausted() {
+            break;
+        }
+        if args.rounds > 1 {
+            info!("Running round {round} of {}", args.rounds);
+        }
+        let round_results = if devices.len() > 1 {
+            info!("Scheduling runs on devices {devices:?}");
+            farm::run_on_devices(args, run_configs, devices, states)?
+        } else {
+            run_sequential(args, run_configs, states)?
+        };
+        for ((state, results), round_result) in states
+            .iter_mut()
+            .zip(all_results.iter_mut())
+            .zip(round_results)
+        {
+            match round_result {
+                Some(Ok(round_result)) => {
+                    if let Ok(results) = results {
+                        results.merge(round_result);
+                    }
+                    *state = RunState::Pending;
+                }
+                Some(Err(e)) => {
+                    *results = Err(e);
+                    *state = RunState::Failed;
+                }
+                None => {}
             }
-            println!("----------------------------------------------------------\n\n");
         }
     }
     Ok(())
 }
 
+/// Merges the `results` of the run at `index` into `bencher_results`, metrics that an earlier run produced
+/// already are handled according to `policy`
+fn merge_run_results(
+    bencher_results: &mut RunResults,
+    mut results: RunResults,
+    index: usize,
+    url: &str,
+    policy: DuplicateMetrics,
+) -> Result<()> {
+    let duplicates = bencher_results.duplicate_keys(&results);
+    if !duplicates.is_empty() {
+        let duplicates_list = duplicates.join(", ");
+        match policy {
+            DuplicateMetrics::Error => {
+                return Err(anyhow!(
+                    "Run {} ({url}) produced metrics that an earlier run produced already: {duplicates_list}",
+                    index + 1,
+                ));
+            }
+            DuplicateMetrics::Suffix => {
+                warn!(
+                    "Run {} ({url}) produced metrics that an earlier run produced already, appending #{} to: {duplicates_list}",
+                    index + 1,
+                    index + 1
+                );
+                results.rename_keys(|key| {
+                    if duplicates.contains(&key) {
+                        format!("{key} #{}", index + 1)
+                    } else {
+                        key
+                    }
+                });
+            }
+            DuplicateMetrics::Combine => {
+                info!(
+                    "Run {} ({url}) combines its samples with an earlier run for: {duplicates_list}",
+                    index + 1
+                );
+            }
+        }
+    }
+    bencher_results.merge(results);
+    Ok(())
+}
+
 /// Runs runconfigs
 /// Bencher has to be treated separately because it wants a valid json output.
-fn run_runconfigs(args: &Args, run_configs: &Vec<RunConfig>, use_bencher: bool) -> Result<()> {
+/// With more than one connected device the run configs are scheduled across all of them.
+/// With `--rounds` the whole set of run configs is repeated, a run that failed once is not repeated.
+/// A failed run does not stop the others, except the ones depending on it, but fails the session in the end.
+/// All connected devices are locked for the session so no other session can trace on them.
+fn run_runconfigs(args: &Args, run_configs: &[RunConfig], use_bencher: bool) -> Result<()> {
     info!("Running with Args {args:?}");
 
+    let devices = if args.trace_file.is_none() {
+        device::list_devices()?
+    } else {
+        Vec::new()
+    };
+    // Held until the end of the session
+    let _locks = devices
+        .iter()
+        .map(|d| lock::DeviceLock::acquire(d))
+        .collect::<Result<Vec<_>>>()?;
+    let thresholds = args
+        .thresholds
+        .as_deref()
+        .map(thresholds::read)
+        .transpose()?;
+    let device_states_before = device_state::read_all(&devices);
+    let mut states = vec![RunState::Pending; run_configs.len()];
+    let mut all_results: Vec<Result<RunResults>> = run_configs
+        .iter()
+        .map(|_| {
+            Ok(RunResults {
+                prepend: args.prepend.clone(),
+                ..Default::default()
+            })
+        })
+        .collect();
+    if args.tui {
+        tui::run(|| run_rounds(args, run_configs, &devices, &mut states, &mut all_results))??;
+    } else {
+        run_rounds(args, run_configs, &devices, &mut states, &mut all_results)?;
+    }
+
+    let device_states = device_state::compare(&devices, device_states_before);
+    let mut summary = webhook::Summary::default();
+    let mut failed = 0;
+    let mut exceeded = 0;
+    let mut erroneous = 0;
+    let mut missing = Vec::new();
+    let mut errors = Vec::new();
     // bencher needs all runs, while a normal output can have the runs one after the other
+    let mut bencher_results = RunResults {
+        prepend: args.prepend.clone(),
+        ..Default::default()
+    };
+    let mut session_table = session::SessionTable::default();
+    let mut json_reports = Vec::new();
+    for (index, (run_config, result)) in run_configs.iter().zip(all_results).enumerate() {
+        match result {
+            Ok(mut results) => {
+                errors.append(&mut results.error_log);
+                if tries::fail_on_errors(
+                    args.error_policy,
+                    &run_config.run_args.url,
+                    &results.tries,
+                ) {
+                    erroneous += 1;
+                }
+                results.check_drift(args.discard_drift);
+                let insufficient = results.remove_insufficient(args.min_samples);
+                for (key, samples) in &insufficient {
+                    warn!(
+                        "{key}: insufficient data, only {samples} of {} samples",
+                        args.min_samples
+                    );
+                }
+                session_table.add(run_config, &results);
+                if let Some(ref thresholds) = thresholds {
+                    let violations = thresholds::violations(thresholds, &results);
+                    for violation in &violations {
+                        error!("Threshold exceeded: {violation}");
+                    }
+                    exceeded += violations.len();
+                    summary.add_violations(violations);
+                }
+                missing.extend(missing_metrics(run_config, &results));
+                if use_bencher {
+                    record_stability(run_config, &mut results);
+                    bencher::name_run_metrics(
+                        &mut results,
+                        &run_config.run_args.url,
+                        run_config.url_alias.as_deref(),
+                        args.metric_name.as_ref(),
+                    );
+                    let policy = run_config.merge_policy.unwrap_or(args.duplicate_metrics);
+                    merge_run_results(
+                        &mut bencher_results,
+                        results,
+                        index,
+                        &run_config.run_args.url,
+                        policy,
+                    )?;
+                } else if args.format == OutputFormat::Json {
+                    summary.add(&results);
+                    json_reports.push(json_report::RunReport::new(
+                        run_config,
+                        results,
+                        &insufficient,
+                    ));
+                } else {
+                    summary.add(&results);
+                    print_differences(
+                        &run_config.run_args,
+                        results,
+                        &insufficient,
+                        args.per_try,
+                        args.sort_by,
+                    );
+                }
+            }
+            Err(e) => {
+                error!("Run {} failed: {e:?}", run_config.run_args.url);
+                errors.push(error_log::run_failure(run_config, &e));
+                failed += 1;
+            }
+        }
+    }
+    if let Some(ref artifacts) = args.artifacts {
+        error_log::write(artifacts, &errors, args.canonical_json)?;
+    }
+    let retry_usage = retries::usage();
     if use_bencher {
-        let mut filter_results = HashMap::new();
-        let mut filter_errors = HashMap::new();
-        let mut point_results = HashMap::new();
-        for run_config in run_configs {
-            run_runconfig(
-                run_config,
-                &mut filter_results,
-                &mut filter_errors,
-                &mut point_results,
-            )?;
-        }
-
-        bencher::write_results(RunResults {
-            prepend: args.prepend.clone(),
-            filter_results,
-            errors: filter_errors,
-            point_results,
-        })
-        .context("Error in writing bencher results")?
+        output_bencher(args, bencher_results, &mut summary)?;
+        // stdout is the bencher json
+        eprint!("{}", device_state::to_text(&device_states));
+        if retry_usage.is_relevant() {
+            eprintln!("{retry_usage}");
+        }
+    } else if args.format == OutputFormat::Json {
+        json_report::print(
+            &json_reports,
+            retry_usage,
+            &device_states,
+            &missing,
+            args.canonical_json,
+        )?;
     } else {
-        for run_config in run_configs {
-            let mut filter_results = HashMap::new();
-            let mut errors = HashMap::new();
-            let mut point_results = HashMap::new();
-            run_runconfig(
-                run_config,
-                &mut filter_results,
-                &mut errors,
-                &mut point_results,
-            )?;
-            print_differences(
-                &run_config.run_args,
-                RunResults {
-                    prepend: args.prepend.clone(),
-                    filter_results,
-                    errors,
-                    point_results,
-                },
-            );
+        if run_configs.len() > 1 {
+            session_table.print();
         }
+        print!("{}", device_state::to_text(&device_states));
+        if retry_usage.is_relevant() {
+            println!("{retry_usage}");
+        }
+    }
+    summary.set_device_states(device_states);
+    summary.set_retries(retry_usage);
+    summary.set_missing_metrics(missing.clone());
+    if let Some(ref path) = args.session_summary {
+        session_table.write_csv(path)?;
+    }
+    if let Some(ref url) = args.webhook_url {
+        // A failing notification should not lose the results we already have
+        if let Err(e) = webhook::post_summary(url, &summary) {
+            error!("Could not notify webhook: {e:?}");
+        }
+    }
+    if args.budget_exhausted() {
+        warn!(
+            "The time budget of {} minutes was used up, the results do not contain all tries",
+            args.max_session_minutes.unwrap_or_default()
+        );
+    }
+    if !missing.is_empty() {
+        return Err(MissingMetrics(missing).into());
+    }
+    if failed > 0 {
+        return Err(anyhow!("{failed} of {} runs failed", run_configs.len()));
+    }
+    if exceeded > 0 {
+        return Err(anyhow!("{exceeded} metrics exceeded their thresholds"));
+    }
+    if erroneous > 0 {
+        return Err(anyhow!(
+            "{erroneous} of {} runs had tries with errors, see --error-policy",
+            run_configs.len()
+        ));
     }
     Ok(())
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
-    let run_configs = {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches)?;
+    host_config::HostConfig::load()?.apply(&mut args, &matches)?;
+    yansi::whenever(args.color.condition());
+    utils::set_humanize(!args.no_humanize);
+    units::set_units(args.units.clone());
+    bencher::set_precision(args.bencher_precision.clone());
+    retries::set_budget(args.max_total_retries);
+    args.start_session_budget();
+    if let Some(ref hdc_path) = args.hdc_path {
+        device::set_hdc_path(hdc_path.clone());
+    }
+    if let Some(ref artifacts) = args.artifacts {
+        command_log::start(artifacts)?;
+    }
+    if let Some(doctor_args) = args.doctor() {
+        return doctor::run(doctor_args);
+    }
+    if let Some(migrate_args) = args.migrate() {
+        return schema::migrate_file(migrate_args);
+    }
+    if let Some(synthesize_args) = args.synthesize() {
+        return synthesize::run(synthesize_args);
+    }
+    if let Some(trend_args) = args.trend() {
+        return trend::run(trend_args);
+    }
+    if let Some(suggest_args) = args.suggest() {
+        return suggest::run(suggest_args, args.parse_error_policy);
+    }
+    if let Some(thresholds_args) = args.thresholds() {
+        return thresholds::run(thresholds_args, args.canonical_json);
+    }
+    let mut run_configs = {
         if let Some(ref file) = args.run_file {
             runconfig::read_run_file(file, &args)?
         } else {
@@ -231,11 +1196,15 @@ fn main() -> Result<()> {
                     name: String::from("Surface->LoadStart"),
                     first: Box::new(|t: &Trace| t.function.contains("on_surface_created_cb")),
                     last: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
+                    measure: None,
+                    expected_range: None,
                 },
                 Filter {
                     name: String::from("Load->Compl"),
                     first: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
                     last: Box::new(|t: &Trace| t.function.contains("PageLoadEndedPrompt")),
+                    measure: None,
+                    expected_range: None,
                 },
             ];
             let point_filters = vec![
@@ -244,6 +1213,7 @@ fn main() -> Result<()> {
                     match_str: String::from("explicit"),
                     no_unit_conversion: false,
                     point_filter_type: PointFilterType::Default,
+                    ..Default::default()
                 },
                 PointFilter::new(String::from("Resident"), String::from("resident")),
                 PointFilter::new(String::from("LayoutThread"), String::from("layout-thread")),
@@ -254,6 +1224,7 @@ fn main() -> Result<()> {
                     match_str: String::from("resident-according-to-smaps"),
                     no_unit_conversion: false,
                     point_filter_type: PointFilterType::Combined,
+                    ..Default::default()
                 },
             ];
 
@@ -266,6 +1237,21 @@ fn main() -> Result<()> {
         }
     };
 
+    for run_config in &mut run_configs {
+        args.run_defaults.apply(&mut run_config.run_args);
+    }
+    if args.print_config {
+        println!("{}", runconfig::effective_config(&args, &run_configs)?);
+        return Ok(());
+    }
+    if args.check_filters() {
+        return check_filters::run(&run_configs);
+    }
+    if let Some(analyze_args) = args.analyze() {
+        return analyze::run(&args, analyze_args, &run_configs);
+    }
+
+    device::check_hdc_version()?;
     if !device::is_device_reachable().context("Testing reachability of device")? {
         return Err(anyhow!("No phone seems to be reachable"));
     }
@@ -288,13 +1274,36 @@ fn main() -> Result<()> {
         log::LevelFilter::Info
     };
 
-    env_logger::builder().filter_level(be_loud_filter).init();
+    let mut logger = env_logger::builder();
+    logger
+        .filter_level(be_loud_filter)
+        .write_style(args.color.write_style());
+    // Logging to the terminal would draw over the TUI
+    if args.tui {
+        logger.target(env_logger::Target::Pipe(Box::new(
+            std::fs::File::create(TUI_LOG_PATH).context("Could not create the log file")?,
+        )));
+    }
+    logger.init();
 
-    ctrlc::set_handler(move || {
-        device::stop_tracing(trace_buffer).expect("Could not stop tracing");
-    })?;
+    let bundle_names: Vec<String> = run_configs
+        .iter()
+        .map(|r| r.run_args.bundle_name.clone())
+        .unique()
+        .collect();
+    shutdown::start_session(args.prepend.clone(), bundle_names, trace_buffer);
+    ctrlc::set_handler(shutdown::request)?;
 
-    run_runconfigs(&args, &run_configs, all_bencher)?;
+    if let Some(bisect_args) = args.bisect() {
+        return bisect::run(bisect_args, &run_configs);
+    }
+    if let Err(e) = run_runconfigs(&args, &run_configs, all_bencher) {
+        if e.is::<MissingMetrics>() {
+            error!("{e}");
+            std::process::exit(MISSING_METRICS_EXIT_CODE);
+        }
+        return Err(e);
+    }
 
     Ok(())
 }
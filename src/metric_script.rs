@@ -0,0 +1,99 @@
+//! Custom metrics from Rhai scripts listed in the `metric_scripts` of a run, for one-off metrics that do not
+//! warrant a compiled plugin. A script sees the `url` of the run and the `traces` of a try, in the form plugins
+//! get them, and returns a map like plugins do:
+//! ```rhai
+//! let starts = traces.filter(|t| t.function.contains("load status changed Head"));
+//! let ends = traces.filter(|t| t.function.contains("PageLoadEndedPrompt"));
+//! #{durations: #{"Load": [ends[0].timestamp_ns - starts[0].timestamp_ns]}}
+//! ```
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow};
+use rhai::{Engine, Scope};
+
+use crate::{
+    plugin::{PluginOutput, PluginTrace},
+    runconfig::RunConfig,
+    trace::Trace,
+    utils::RunResults,
+};
+
+/// Scripts that run longer than this many operations are stuck, i.e., in an endless loop
+const MAX_OPERATIONS: u64 = 100_000_000;
+
+/// Runs the script at `path` on `traces`
+fn run_script(path: &Path, url: &str, traces: &[Trace]) -> Result<PluginOutput> {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    let ast = engine
+        .compile_file(path.to_path_buf())
+        .map_err(|e| anyhow!("Could not compile: {e}"))?;
+    let traces: Vec<PluginTrace> = traces.iter().map(PluginTrace::from).collect();
+    let mut scope = Scope::new();
+    scope.push_constant("url", url.to_owned());
+    scope.push_constant(
+        "traces",
+        rhai::serde::to_dynamic(traces).map_err(|e| anyhow!("{e}"))?,
+    );
+    let output = engine
+        .eval_ast_with_scope(&mut scope, &ast)
+        .map_err(|e| anyhow!("{e}"))?;
+    rhai::serde::from_dynamic(&output)
+        .map_err(|e| anyhow!("The script has to return a map of durations, points and memory: {e}"))
+}
+
+/// Runs the metric scripts of `run_config` on the traces of a try and records their metrics.
+/// Returns the number of metrics.
+pub(crate) fn record_script_metrics(
+    run_config: &RunConfig,
+    traces: &[Trace],
+    results: &mut RunResults,
+) -> Result<usize> {
+    let mut found = 0;
+    for path in &run_config.metric_scripts {
+        let output = run_script(path, &run_config.run_args.url, traces)
+            .with_context(|| format!("Metric script {} failed", path.display()))?;
+        found += output.record(results);
+    }
+    Ok(found)
+}
+
+#[test]
+fn test_metric_script() {
+    use time::Duration;
+
+    let trace_file = crate::parser::read_file(
+        Path::new("testdata/v5_1_1.ftrace"),
+        crate::parser::ParseErrorPolicy::Fail,
+    )
+    .unwrap();
+    let output = run_script(
+        Path::new("testdata/metric_script.rhai"),
+        "https://servo.org",
+        &trace_file.traces,
+    )
+    .unwrap();
+    let mut results = RunResults::default();
+    assert_eq!(output.record(&mut results), 2);
+    assert_eq!(
+        results.filter_results["Load"],
+        vec![Duration::microseconds(2_074_614)]
+    );
+    assert_eq!(
+        results.point_results["https://servo.org/Counters"].result,
+        vec![
+            trace_file
+                .traces
+                .iter()
+                .filter(|t| t.counter.is_some())
+                .count() as i64
+        ]
+    );
+
+    let endless =
+        std::env::temp_dir().join(format!("hitrace-bench-endless-{}.rhai", std::process::id()));
+    std::fs::write(&endless, "loop {}").unwrap();
+    let result = run_script(&endless, "", &[]);
+    std::fs::remove_file(&endless).unwrap();
+    assert!(result.is_err());
+}
@@ -0,0 +1,144 @@
+//! Splitting the trace of a try with several navigations, i.e., reloads or other pages opened in the same app
+//! session, so every navigation gets its own results.
+use std::time::Duration;
+
+use crate::{
+    script::{NAVIGATION_MARKER, Step},
+    trace::Trace,
+    utils::{PointResult, RunResults},
+};
+
+/// The page finished loading, a reload only starts after this
+pub(crate) const LOAD_END_MARKER: &str = "PageLoadEndedPrompt";
+
+/// How long we wait for a page to load before navigating again
+const LOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The counter whose growth over the navigations we report
+const RESIDENT_COUNTER: &str = "servo_memory_profiling:resident";
+
+/// Name of the memory growth metric
+pub(crate) const MEMORY_GROWTH: &str = "Memory growth";
+
+/// The traces of one navigation
+#[derive(Debug)]
+pub(crate) struct Navigation<'a> {
+    /// `None` for the navigation the app started with, whose results keep their names
+    pub(crate) label: Option<String>,
+    pub(crate) traces: &'a [Trace],
+}
+
+impl Navigation<'_> {
+    /// Appends the label to all result names of `results`
+    pub(crate) fn label_results(&self, mut results: RunResults) -> RunResults {
+        let Some(ref label) = self.label else {
+            return results;
+        };
+        results.rename_keys(|key| format!("{key} ({label})"));
+        results
+    }
+
+    /// The last value of the resident memory counter in this navigation
+    fn resident(&self) -> Option<i64> {
+        self.traces
+            .iter()
+            .rev()
+            .filter_map(|t| t.counter.as_ref())
+            .find(|c| c.name == RESIDENT_COUNTER)
+            .map(|c| c.value)
+    }
+}
+
+/// Splits `traces` at every navigation marker of the script. A label that shows up more than once is numbered,
+/// i.e., `reload 1`, `reload 2`.
+pub(crate) fn split(traces: &[Trace]) -> Vec<Navigation<'_>> {
+    let markers: Vec<(usize, &str)> = traces
+        .iter()
+        .enumerate()
+        .filter_map(|(i, t)| {
+            t.function
+                .split_once(NAVIGATION_MARKER)
+                .map(|(_, label)| (i, label.trim()))
+        })
+        .collect();
+    let mut navigations = vec![Navigation {
+        label: None,
+        traces: &traces[..markers.first().map_or(traces.len(), |(i, _)| *i)],
+    }];
+    for (n, (start, label)) in markers.iter().enumerate() {
+        let end = markers.get(n + 1).map_or(traces.len(), |(i, _)| *i);
+        let same_label = markers.iter().filter(|(_, l)| l == label).count();
+        let label = if same_label > 1 {
+            let number = markers[..=n].iter().filter(|(_, l)| l == label).count();
+            format!("{label} {number}")
+        } else {
+            label.to_string()
+        };
+        navigations.push(Navigation {
+            label: Some(label),
+            traces: &traces[*start..end],
+        });
+    }
+    navigations
+}
+
+/// Adds how much the resident memory grew from the first to the last navigation to `results`
+pub(crate) fn record_memory_growth(navigations: &[Navigation], results: &mut RunResults) {
+    let (Some(first), Some(last)) = (navigations.first(), navigations.last()) else {
+        return;
+    };
+    if navigations.len() < 2 {
+        return;
+    }
+    if let (Some(before), Some(after)) = (first.resident(), last.resident()) {
+        results
+            .point_results
+            .entry(String::from(MEMORY_GROWTH))
+            .or_insert(PointResult {
+                no_unit_conversion: false,
+                result: Vec::new(),
+            })
+            .result
+            .push(after - before);
+    }
+}
+
+/// The steps reloading the page `reloads` times and then opening `urls`, each after the previous load finished
+pub(crate) fn navigation_script(reloads: u32, urls: &[String]) -> Vec<Step> {
+    let wait_for_load = |count: usize| Step::WaitForMarker {
+        marker: String::from(LOAD_END_MARKER),
+        timeout: LOAD_TIMEOUT,
+        count: count as u32,
+    };
+    let navigations: Vec<Step> = std::iter::repeat_n(Step::Reload, reloads as usize)
+        .chain(urls.iter().map(|url| Step::Open(url.clone())))
+        .collect();
+    let mut script: Vec<Step> = navigations
+        .iter()
+        .enumerate()
+        .flat_map(|(n, navigation)| [wait_for_load(n + 1), navigation.clone()])
+        .collect();
+    if !navigations.is_empty() {
+        script.push(wait_for_load(navigations.len() + 1));
+    }
+    script
+}
+
+#[test]
+fn test_navigation_script() {
+    assert!(navigation_script(0, &[]).is_empty());
+    let script: Vec<String> = navigation_script(1, &[String::from("https://servo.org")])
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(
+        script,
+        [
+            "wait-for-marker PageLoadEndedPrompt 30000",
+            "reload",
+            "wait-for-marker PageLoadEndedPrompt 30000 2",
+            "open https://servo.org",
+            "wait-for-marker PageLoadEndedPrompt 30000 3",
+        ]
+    );
+}
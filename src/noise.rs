@@ -0,0 +1,154 @@
+//! Monitoring the load other processes put on the device while a try measures.
+//! We compare the cpu time of every process at the start and the end of the measurement window.
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+
+use crate::device;
+
+/// Processes that are part of the benchmark besides the app itself
+const BENCHMARK_PROCESSES: &[&str] = &["render_service", "hitrace", "hdcd"];
+
+/// How many of the busiest other processes we report
+const TOP_PROCESSES: usize = 3;
+
+/// Cpu time of the device and of every process at one point in time, in jiffies
+#[derive(Debug, Default)]
+pub(crate) struct CpuSnapshot {
+    /// Busy and idle time of all cpus together
+    total: u64,
+    /// Busy time by pid, together with the name of the process
+    processes: HashMap<u32, (String, u64)>,
+}
+
+impl CpuSnapshot {
+    /// Reads `/proc/stat` and the stat file of every process in one shell call
+    pub(crate) fn read(device: Option<&str>) -> Result<CpuSnapshot> {
+        let output = device::hdc_output(
+            device,
+            &[
+                "shell",
+                "head -n 1 /proc/stat; cat /proc/[0-9]*/stat 2>/dev/null",
+            ],
+        )?;
+        CpuSnapshot::parse(&output)
+    }
+
+    fn parse(output: &str) -> Result<CpuSnapshot> {
+        let mut lines = output.lines();
+        let total = lines
+            .next()
+            .and_then(|l| l.strip_prefix("cpu "))
+            .map(|l| {
+                l.split_whitespace()
+                    .filter_map(|v| v.parse::<u64>().ok())
+                    .sum()
+            })
+            .ok_or_else(|| anyhow!("Could not parse /proc/stat"))?;
+        let processes = lines.filter_map(parse_process_stat).collect();
+        Ok(CpuSnapshot { total, processes })
+    }
+}
+
+/// `pid (name) state ...` where field 14 and 15 are the user and system time.
+/// The name can contain spaces and parentheses so we split at the last `)`.
+fn parse_process_stat(line: &str) -> Option<(u32, (String, u64))> {
+    let (start, rest) = line.rsplit_once(')')?;
+    let (pid, name) = start.split_once(" (")?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    // `rest` starts at the third field
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some((pid.trim().parse().ok()?, (name.to_owned(), utime + stime)))
+}
+
+/// Load of processes that are not part of the benchmark during the measurement window
+#[derive(Debug, PartialEq)]
+pub(crate) struct BackgroundLoad {
+    /// Percent of the whole cpu time of the device
+    pub(crate) percent: f64,
+    /// The busiest of these processes with their percent
+    pub(crate) top: Vec<(String, f64)>,
+}
+
+impl BackgroundLoad {
+    /// The load between `before` and `after` of all processes except `bundle_name` and `BENCHMARK_PROCESSES`
+    pub(crate) fn between(
+        before: &CpuSnapshot,
+        after: &CpuSnapshot,
+        bundle_name: &str,
+    ) -> BackgroundLoad {
+        let total = after.total.saturating_sub(before.total).max(1) as f64;
+        // The kernel truncates names to 15 characters
+        let app: String = bundle_name.chars().take(15).collect();
+        let mut loads: Vec<(String, f64)> = after
+            .processes
+            .iter()
+            .filter(|(_, (name, _))| *name != app && !BENCHMARK_PROCESSES.contains(&name.as_str()))
+            .map(|(pid, (name, time))| {
+                let before = before.processes.get(pid).map(|(_, t)| *t).unwrap_or(0);
+                (
+                    name.clone(),
+                    time.saturating_sub(before) as f64 / total * 100.0,
+                )
+            })
+            .filter(|(_, load)| *load > 0.0)
+            .collect();
+        loads.sort_by(|a, b| b.1.total_cmp(&a.1));
+        BackgroundLoad {
+            percent: loads.iter().map(|(_, load)| load).sum(),
+            top: loads.into_iter().take(TOP_PROCESSES).collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for BackgroundLoad {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let top: Vec<String> = self
+            .top
+            .iter()
+            .map(|(name, load)| format!("{name} {load:.1}%"))
+            .collect();
+        write!(f, "{:.1}% ({})", self.percent, top.join(", "))
+    }
+}
+
+#[test]
+fn test_background_load() {
+    let stat = |pid: u32, name: &str, time: u64| {
+        format!("{pid} ({name}) S 1 1 0 0 -1 4194560 0 0 0 0 {time} 0 0 0 20 0 1 0")
+    };
+    let before = CpuSnapshot::parse(
+        &[
+            String::from("cpu  100 0 100 800 0 0 0 0 0 0"),
+            stat(1, "org.servo.servo", 0),
+            stat(2, "updater (x)", 10),
+            stat(3, "render_service", 0),
+        ]
+        .join("\n"),
+    )
+    .unwrap();
+    let after = CpuSnapshot::parse(
+        &[
+            String::from("cpu  300 0 300 1400 0 0 0 0 0 0"),
+            stat(1, "org.servo.servo", 500),
+            stat(2, "updater (x)", 110),
+            stat(3, "render_service", 200),
+            stat(4, "logger", 50),
+        ]
+        .join("\n"),
+    )
+    .unwrap();
+    let load = BackgroundLoad::between(&before, &after, "org.servo.servo");
+    assert_eq!(
+        load,
+        BackgroundLoad {
+            percent: 15.0,
+            top: vec![
+                (String::from("updater (x)"), 10.0),
+                (String::from("logger"), 5.0)
+            ],
+        }
+    );
+    assert_eq!(load.to_string(), "15.0% (updater (x) 10.0%, logger 5.0%)");
+}
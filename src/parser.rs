@@ -0,0 +1,648 @@
+//! Parsing of hitrace ftrace files into traces
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use log::{error, info, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    ffi::OsStr,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::SystemTime,
+};
+
+use crate::trace::{Counter, TimeStamp, Trace, TraceMarker};
+
+/// There is always one trace per line
+/// This means that having no matched lines is ok and returns None. Having a parsing error returns Some(Err)
+fn line_to_trace(regex: &Regex, line: &str) -> Option<Result<Trace>> {
+    regex
+        .captures_iter(line)
+        .map(|c| c.extract())
+        .map(match_to_trace)
+        .next()
+}
+
+/// Seconds since boot. Larger timestamps are garbage and would overflow when computing durations.
+const MAX_SECONDS: u64 = u32::MAX as u64;
+
+/// The fractional seconds of a timestamp in nanoseconds, i.e., `7166` is 716600000. Digits beyond
+/// nanoseconds are cut off.
+fn parse_nanos(fraction: &str) -> Result<u64> {
+    // The regexes match unicode digits as well, which we cannot slice by bytes
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(anyhow!(
+            "Timestamp fraction {fraction} is not a decimal number"
+        ));
+    }
+    let digits = &fraction[..fraction.len().min(9)];
+    let nanos: u64 = digits.parse()?;
+    Ok(nanos * 10u64.pow(9 - digits.len() as u32))
+}
+
+/// Read a regex matched line into a trace
+fn match_to_trace(
+    (
+        _line,
+        [
+            name,
+            tid,
+            cpu,
+            time1,
+            time2,
+            trace_marker,
+            number,
+            shorthand,
+            msg,
+        ],
+    ): (&str, [&str; 9]),
+) -> Result<Trace> {
+    let seconds = time1.parse()?;
+    if seconds > MAX_SECONDS {
+        return Err(anyhow!("Timestamp {time1}.{time2} is too large"));
+    }
+    let timestamp = TimeStamp {
+        seconds,
+        nanos: parse_nanos(time2)?,
+    };
+    let trace_marker = TraceMarker::from(trace_marker)?;
+    let counter = if trace_marker == TraceMarker::Dot {
+        Counter::parse(msg)
+    } else {
+        None
+    };
+    Ok(Trace {
+        name: name.to_owned(),
+        tid: tid.parse().unwrap_or(0),
+        cpu: cpu.parse().unwrap_or(0),
+        trace_marker,
+        number: number.to_string(),
+        timestamp,
+        shorthand: shorthand.to_owned(),
+        function: msg.to_owned(),
+        counter,
+        wall_clock: None,
+    })
+}
+
+/// The parser for files of unknown versions. It accepts every TGID column we know of.
+/// The last two alternatives are for generic app traces without a `shorthand:` segment, i.e., `B|pid|SomeSpan`.
+/// They capture an empty shorthand so every alternative has the same number of groups.
+const REGEX_STRING: &str = concat!(
+    r"^\s*(.*?)\-(\d+)\s*\(\s*(\d+)\).*?(\d+)\.(\d+): tracing_mark_write: (.)\|(\d+?)\|(.*?):(.*)\s*$",
+    "|",
+    r"^\s*(.*?)\-(\d+)\s*\((.*?)\).*?(\d+)\.(\d+): tracing_mark_write: (.)\|(\d+?)\|(.*?):(.*)\s*$",
+    "|",
+    r"^\s*(.*?)\-(\d+)\s*\(\s*(\d+)\).*?(\d+)\.(\d+): tracing_mark_write: (.)\|(\d+?)\|()(.*)\s*$",
+    "|",
+    r"^\s*(.*?)\-(\d+)\s*\((.*?)\).*?(\d+)\.(\d+): tracing_mark_write: (.)\|(\d+?)\|()(.*)\s*$"
+);
+
+/// Parser for v1 files where the TGID is a number
+/// Example: ` org.servo.servo-46512   (  46512) [010] .... 7230472.274412: tracing_mark_write: B|46512|H:on_surface_created_cb`
+const V1_REGEX_STRING: &str = concat!(
+    r"^\s*(.*?)\-(\d+)\s*\(\s*(\d+)\).*?(\d+)\.(\d+): tracing_mark_write: (.)\|(\d+?)\|(.*?):(.*)\s*$",
+    "|",
+    r"^\s*(.*?)\-(\d+)\s*\(\s*(\d+)\).*?(\d+)\.(\d+): tracing_mark_write: (.)\|(\d+?)\|()(.*)\s*$",
+);
+
+/// Parser for v5.x files where the TGID is a number or not known
+/// Example: `<...>-57783   (-------) [010] .... 510487.404088: tracing_mark_write: B|57783|H:on_surface_created_cb|M62`
+const V5_REGEX_STRING: &str = concat!(
+    r"^\s*(.*?)\-(\d+)\s*\(\s*(\d+|-+)\).*?(\d+)\.(\d+): tracing_mark_write: (.)\|(\d+?)\|(.*?):(.*)\s*$",
+    "|",
+    r"^\s*(.*?)\-(\d+)\s*\(\s*(\d+|-+)\).*?(\d+)\.(\d+): tracing_mark_write: (.)\|(\d+?)\|()(.*)\s*$",
+);
+
+static REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(REGEX_STRING).expect("Could not parse regexp"));
+static V1_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(V1_REGEX_STRING).expect("Could not parse regexp"));
+static V5_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(V5_REGEX_STRING).expect("Could not parse regexp"));
+
+/// The hitrace version that produced a file. They differ in the TGID column and the counter format.
+/// Newer versions still print a numeric TGID for some lines, so we need to look at the whole file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum HitraceVersion {
+    /// TGID is given as a number and counters are separated with a space
+    V1,
+    /// TGID is given as `(-------)` and counters and spans have a `|M62` style tag appended
+    V5,
+    #[default]
+    Unknown,
+}
+
+impl HitraceVersion {
+    /// Detect the version from the trace lines of a file
+    fn detect<'a>(lines: impl Iterator<Item = &'a str> + Clone) -> Self {
+        let mut trace_lines = lines.filter(|l| l.contains("tracing_mark_write"));
+        if trace_lines
+            .clone()
+            .any(|l| TGID_DASHES_REGEX.is_match(l) || TAG_REGEX.is_match(l))
+        {
+            HitraceVersion::V5
+        } else if trace_lines.any(|l| TGID_NUMBER_REGEX.is_match(l)) {
+            HitraceVersion::V1
+        } else {
+            HitraceVersion::Unknown
+        }
+    }
+
+    /// The line parser for this version
+    fn regex(&self) -> &'static Regex {
+        match self {
+            HitraceVersion::V1 => &V1_REGEX,
+            HitraceVersion::V5 => &V5_REGEX,
+            HitraceVersion::Unknown => &REGEX,
+        }
+    }
+}
+
+static TGID_DASHES_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*.*?\-\d+\s*\(-+\)").expect("Could not parse regexp"));
+/// v5 appends a tag like `|M62` to spans and counters
+static TAG_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\|[A-Z]\d+\s*$").expect("Could not parse regexp"));
+static TGID_NUMBER_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*.*?\-\d+\s*\(\s*\d+\)").expect("Could not parse regexp"));
+/// Example: `# entries-in-buffer/entries-written: 1234/5678   #P:8`
+static ENTRIES_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^#\s*entries-in-buffer/entries-written:\s*(\d+)/(\d+)\s*#P:(\d+)")
+        .expect("Could not parse regexp")
+});
+
+/// Metadata found in the `#` commented header of an ftrace file
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TraceHeader {
+    /// The tracer, i.e., `nop` from `# tracer: nop`
+    pub tracer: Option<String>,
+    /// The trace clock, i.e., `boot` from `# trace_clock: boot`
+    pub clock: Option<String>,
+    /// Number of entries still in the ring buffer
+    pub entries_in_buffer: Option<u64>,
+    /// Number of entries written. If this is larger than `entries_in_buffer` we lost traces.
+    pub entries_written: Option<u64>,
+    /// Number of cpus
+    pub cpus: Option<u64>,
+    /// The hitrace version we detected from the trace lines
+    pub version: HitraceVersion,
+}
+
+impl TraceHeader {
+    /// Parse one header line. Unknown lines are ignored.
+    fn parse_line(&mut self, line: &str) {
+        if let Some(groups) = ENTRIES_REGEX.captures(line) {
+            self.entries_in_buffer = groups[1].parse().ok();
+            self.entries_written = groups[2].parse().ok();
+            self.cpus = groups[3].parse().ok();
+        } else if let Some((key, value)) = line.trim_start_matches('#').split_once(':') {
+            let value = value.trim().to_owned();
+            match key.trim().to_lowercase().as_str() {
+                "tracer" => self.tracer = Some(value),
+                "trace_clock" | "clock" => self.clock = Some(value),
+                _ => (),
+            }
+        }
+    }
+
+    /// Returns true if the ring buffer overflowed and traces were lost.
+    pub(crate) fn lost_entries(&self) -> bool {
+        matches!((self.entries_in_buffer, self.entries_written), (Some(b), Some(w)) if w > b)
+    }
+}
+
+impl Display for TraceHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "hitrace {:?}", self.version)?;
+        if let Some(ref tracer) = self.tracer {
+            write!(f, ", tracer {tracer}")?;
+        }
+        if let Some(ref clock) = self.clock {
+            write!(f, ", clock {clock}")?;
+        }
+        if let (Some(b), Some(w)) = (self.entries_in_buffer, self.entries_written) {
+            write!(f, ", entries {b}/{w}")?;
+        }
+        if let Some(cpus) = self.cpus {
+            write!(f, ", {cpus} cpus")?;
+        }
+        Ok(())
+    }
+}
+
+/// A parsed trace file
+#[derive(Deserialize, Serialize)]
+pub struct TraceFile {
+    /// The metadata of the file
+    pub header: TraceHeader,
+    /// All traces in the file
+    pub traces: Vec<Trace>,
+    /// Number of `tracing_mark_write` lines in the file
+    pub trace_lines: usize,
+    /// Number of lines we could not read or that are `tracing_mark_write` lines no parser matched
+    pub unmatched_lines: usize,
+}
+
+impl TraceFile {
+    /// Ratio of lines we could not parse
+    pub(crate) fn unmatched_ratio(&self) -> f64 {
+        if self.trace_lines == 0 {
+            0.0
+        } else {
+            self.unmatched_lines as f64 / self.trace_lines as f64
+        }
+    }
+
+    /// A high ratio of unmatched lines usually means the parser no longer fits the format of the device.
+    /// We fail if we are above the threshold and warn if we lost any line.
+    pub(crate) fn check_unmatched(&self, threshold: f64) -> Result<()> {
+        if self.unmatched_lines == 0 {
+            return Ok(());
+        }
+        let ratio = self.unmatched_ratio();
+        if ratio > threshold {
+            Err(anyhow!(
+                "{} of {} trace lines ({:.1}%) could not be parsed, which is above the threshold of {:.1}%. Did the hitrace format change? ({})",
+                self.unmatched_lines,
+                self.trace_lines,
+                ratio * 100.0,
+                threshold * 100.0,
+                self.header
+            ))
+        } else {
+            warn!(
+                "{} of {} trace lines could not be parsed",
+                self.unmatched_lines, self.trace_lines
+            );
+            Ok(())
+        }
+    }
+}
+
+/// What to do with lines that match a parser but contain values we cannot parse, i.e., an unknown trace marker
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParseErrorPolicy {
+    /// Stop reading the file with an error
+    #[default]
+    Fail,
+    /// Log and skip the line. It is counted as unmatched.
+    Skip,
+}
+
+/// Parse one line of a file of `version`. Returns `None` for lines without a trace.
+/// This never panics, whatever the line contains.
+pub fn parse_line(version: HitraceVersion, line: &str) -> Option<Result<Trace>> {
+    line_to_trace(version.regex(), line)
+        .map(|t| t.with_context(|| format!("Could not parse {line:?}")))
+}
+
+/// Parse the content of a trace file. Lines that are not UTF-8, i.e., binary garbage from the device,
+/// are counted as unmatched. This never panics, whatever the content is.
+pub fn parse_bytes(content: &[u8], policy: ParseErrorPolicy) -> Result<TraceFile> {
+    let (valid_lines, invalid_lines): (Vec<_>, Vec<_>) = content
+        .split(|b| *b == b'\n')
+        .map(|l| l.strip_suffix(b"\r").unwrap_or(l))
+        .map(str::from_utf8)
+        .enumerate()
+        .partition(|(_index, l)| l.is_ok());
+
+    if !invalid_lines.is_empty() {
+        error!(
+            "Could not read lines {:?}",
+            invalid_lines
+                .iter()
+                .map(|(index, _l)| index)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    let lines: Vec<&str> = valid_lines
+        .into_iter()
+        .filter_map(|(_, l)| l.ok())
+        .collect();
+    let mut header = TraceHeader::default();
+    for line in lines.iter().filter(|l| l.starts_with('#')) {
+        header.parse_line(line);
+    }
+    header.version = HitraceVersion::detect(lines.iter().copied());
+    if header.lost_entries() {
+        error!("The trace buffer overflowed, traces were lost ({header})");
+    }
+
+    // This is more specific servo tracing with the tracing_mark_write
+    // Example trace: ` org.servo.servo-44962   (  44682) [010] .... 17864.716645: tracing_mark_write: B|44682|ML: do_single_part3_compilation`
+    let trace_lines = lines
+        .iter()
+        .filter(|l| l.contains("tracing_mark_write"))
+        .count()
+        + invalid_lines.len();
+    let traces = lines
+        .iter()
+        .filter_map(|l| parse_line(header.version, l))
+        .filter_map(|t| match (t, policy) {
+            (Ok(t), _) => Some(Ok(t)),
+            (Err(e), ParseErrorPolicy::Fail) => Some(Err(e)),
+            (Err(e), ParseErrorPolicy::Skip) => {
+                warn!("Skipping line: {e:?}");
+                None
+            }
+        })
+        .collect::<Result<Vec<Trace>>>()
+        .context("Could not parse one thing")?;
+    Ok(TraceFile {
+        header,
+        trace_lines,
+        unmatched_lines: trace_lines.saturating_sub(traces.len()),
+        traces,
+    })
+}
+
+/// Read a file into traces
+pub fn read_file(f: &Path, policy: ParseErrorPolicy) -> Result<TraceFile> {
+    let content = std::fs::read(f).context("Could not find hitrace file")?;
+    parse_bytes(&content, policy)
+}
+
+/// Directory where we keep the parsed traces of files we already read. Tests do not touch the cache of real runs,
+/// they only cache in their own directories with `read_file_cached_in`.
+fn cache_dir() -> Option<PathBuf> {
+    (!cfg!(test)).then(|| std::env::temp_dir().join("hitrace-bench-cache"))
+}
+
+/// Bump when the meaning of cached fields changes within a version, i.e., timestamps in nanoseconds
+/// have the same layout as the ones in microseconds
+const CACHE_FORMAT: u32 = 2;
+
+/// How many parsed trace files we keep, the least recently used ones are removed
+const MAX_CACHE_FILES: usize = 16;
+
+/// The cache file for a trace file. It is keyed by the sha256 of the content of the file, the policy and
+/// our version so a changed file or a new parser never reads stale traces.
+fn cache_path(dir: &Path, content: &[u8], policy: ParseErrorPolicy) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(content);
+    hasher.update(format!("{policy:?}"));
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update(CACHE_FORMAT.to_le_bytes());
+    dir.join(format!("{:x}.bin", hasher.finalize()))
+}
+
+/// Removes all but the `keep` most recently used cache files in `dir`
+fn prune_cache(dir: &Path, keep: usize) -> Result<()> {
+    let mut files = std::fs::read_dir(dir)?
+        .map(|entry| {
+            let path = entry?.path();
+            let modified = path.metadata()?.modified()?;
+            Ok((modified, path))
+        })
+        .filter(|entry| {
+            entry.as_ref().map_or(true, |(_, path)| {
+                path.extension() == Some(OsStr::new("bin"))
+            })
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    files.sort_by(|a, b| b.0.cmp(&a.0));
+    for (_, path) in files.into_iter().skip(keep) {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Writes `trace_file` to `cache` in `dir` and prunes `dir`. The copy is written to a temporary file first so
+/// concurrent runs never read a partially written cache file.
+fn write_cache(dir: &Path, cache: &Path, trace_file: &TraceFile) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let tmp = cache.with_extension(format!("{}.tmp", std::process::id()));
+    std::fs::write(&tmp, postcard::to_stdvec(trace_file)?)?;
+    std::fs::rename(&tmp, cache).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })?;
+    prune_cache(dir, MAX_CACHE_FILES)
+}
+
+/// Read a file into traces like `read_file` but keep a compact binary copy of the result.
+/// Reading the same file again, i.e., with different filters, will only deserialize the copy.
+pub(crate) fn read_file_cached(f: &Path, policy: ParseErrorPolicy) -> Result<TraceFile> {
+    match cache_dir() {
+        Some(dir) => read_file_cached_in(&dir, f, policy),
+        None => read_file(f, policy),
+    }
+}
+
+/// `read_file_cached` with the cache in `dir`
+fn read_file_cached_in(dir: &Path, f: &Path, policy: ParseErrorPolicy) -> Result<TraceFile> {
+    let content = std::fs::read(f).context("Could not find hitrace file")?;
+    let cache = cache_path(dir, &content, policy);
+    if let Ok(bytes) = std::fs::read(&cache) {
+        match postcard::from_bytes(&bytes) {
+            Ok(trace_file) => {
+                info!("Using cached traces {cache:?} for {f:?}");
+                // Marks the copy as recently used for pruning, failing only makes it go earlier
+                let _ = std::fs::File::options()
+                    .write(true)
+                    .open(&cache)
+                    .and_then(|file| file.set_modified(SystemTime::now()));
+                return Ok(trace_file);
+            }
+            Err(e) => warn!("Ignoring broken trace cache {cache:?}: {e}"),
+        }
+    }
+
+    let trace_file = parse_bytes(&content, policy)?;
+    // Not being able to write the cache only costs time on the next run
+    if let Err(e) = write_cache(dir, &cache, &trace_file) {
+        warn!("Could not write trace cache {cache:?}: {e}");
+    }
+    Ok(trace_file)
+}
+
+#[test]
+fn test_trace_without_shorthand() {
+    let trace = line_to_trace(
+        &REGEX,
+        "           <...>-57783   (-------) [010] .... 510487.404088: tracing_mark_write: B|57783|SomeSpan",
+    )
+    .expect("Line did not match")
+    .expect("Could not parse line");
+    assert_eq!(trace.shorthand, "");
+    assert_eq!(trace.function, "SomeSpan");
+    assert_eq!(trace.trace_marker, TraceMarker::StartSync);
+
+    let trace = line_to_trace(
+        &REGEX,
+        " org.servo.servo-46512   (  46512) [010] .... 7230472.274412: tracing_mark_write: B|46512|H:on_surface_created_cb",
+    )
+    .expect("Line did not match")
+    .expect("Could not parse line");
+    assert_eq!(trace.shorthand, "H");
+    assert_eq!(trace.function, "on_surface_created_cb");
+}
+
+#[test]
+fn test_header_parsing() {
+    let v1 = read_file(Path::new("testdata/v1.ftrace"), ParseErrorPolicy::Fail).unwrap();
+    assert_eq!(v1.header.version, HitraceVersion::V1);
+    assert_eq!(v1.header.tracer.as_deref(), Some("nop"));
+
+    let v5 = read_file(Path::new("testdata/v5_1_1.ftrace"), ParseErrorPolicy::Fail).unwrap();
+    assert_eq!(v5.header.version, HitraceVersion::V5);
+
+    let mut header = TraceHeader::default();
+    header.parse_line("# entries-in-buffer/entries-written: 1234/5678   #P:8");
+    header.parse_line("# trace_clock: boot");
+    assert_eq!(header.entries_in_buffer, Some(1234));
+    assert_eq!(header.entries_written, Some(5678));
+    assert_eq!(header.cpus, Some(8));
+    assert_eq!(header.clock.as_deref(), Some("boot"));
+    assert!(header.lost_entries());
+}
+
+#[test]
+fn test_unmatched_threshold() {
+    let mut trace_file =
+        read_file(Path::new("testdata/v1.ftrace"), ParseErrorPolicy::Fail).unwrap();
+    assert_eq!(trace_file.unmatched_lines, 0);
+    assert!(trace_file.check_unmatched(0.0).is_ok());
+
+    trace_file.unmatched_lines = 28;
+    assert_eq!(trace_file.unmatched_ratio(), 0.5);
+    assert!(trace_file.check_unmatched(0.1).is_err());
+    assert!(trace_file.check_unmatched(0.5).is_ok());
+}
+
+#[test]
+fn test_parse_error_policy() {
+    let line = "           <...>-57783   (-------) [010] .... 510487.404088: tracing_mark_write: X|57783|H:unknown_marker|M62";
+    assert!(matches!(line_to_trace(&REGEX, line), Some(Err(_))));
+
+    let path = std::env::temp_dir().join(format!(
+        "hitrace-bench-parse-error-policy-{}.ftrace",
+        std::process::id()
+    ));
+    std::fs::write(&path, line).unwrap();
+    let failed = read_file(&path, ParseErrorPolicy::Fail);
+    let skipped = read_file(&path, ParseErrorPolicy::Skip);
+    std::fs::remove_file(&path).unwrap();
+    assert!(failed.is_err());
+    let trace_file = skipped.unwrap();
+    assert!(trace_file.traces.is_empty());
+    assert_eq!(trace_file.unmatched_lines, 1);
+}
+
+#[test]
+fn test_trace_cache() {
+    let path = Path::new("testdata/v5_1_1.ftrace");
+    let parsed = read_file(path, ParseErrorPolicy::Fail).unwrap();
+    let dir = std::env::temp_dir().join(format!("hitrace-bench-cache-{}", std::process::id()));
+    let first = read_file_cached_in(&dir, path, ParseErrorPolicy::Fail).unwrap();
+    let cached = read_file_cached_in(&dir, path, ParseErrorPolicy::Fail).unwrap();
+    let files = std::fs::read_dir(&dir).unwrap().count();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(files, 1);
+    for trace_file in [first, cached] {
+        assert_eq!(trace_file.header, parsed.header);
+        assert_eq!(trace_file.traces.len(), parsed.traces.len());
+        assert_eq!(
+            format!("{:?}", trace_file.traces),
+            format!("{:?}", parsed.traces)
+        );
+    }
+}
+
+#[test]
+fn test_prune_cache() {
+    let dir = std::env::temp_dir().join(format!("hitrace-bench-prune-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let now = SystemTime::now();
+    for (i, name) in ["old.bin", "new.bin", "newer.bin", "other.txt"]
+        .into_iter()
+        .enumerate()
+    {
+        let file = std::fs::File::create(dir.join(name)).unwrap();
+        file.set_modified(now - std::time::Duration::from_secs(100 - i as u64))
+            .unwrap();
+    }
+    prune_cache(&dir, 2).unwrap();
+    let mut left: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    left.sort();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(left, ["new.bin", "newer.bin", "other.txt"]);
+}
+
+#[test]
+fn test_non_ascii_fraction() {
+    let line = "           <...>-57783   (-------) [010] .... 510487.٠١٢٣٤٥: tracing_mark_write: B|57783|H:on_surface_created_cb|M62";
+    assert!(matches!(parse_line(HitraceVersion::V5, line), Some(Err(_))));
+    let trace_file = parse_bytes(line.as_bytes(), ParseErrorPolicy::Skip).unwrap();
+    assert!(trace_file.traces.is_empty());
+    assert!(parse_bytes(line.as_bytes(), ParseErrorPolicy::Fail).is_err());
+}
+
+#[test]
+fn test_parse_garbage() {
+    let line = "           <...>-57783   (-------) [010] .... 510487.404088: tracing_mark_write: B|57783|H:on_surface_created_cb|M62";
+    let mut content = b"# tracer: nop\n\xff\xfe\x00garbage\x80\n".to_vec();
+    content.extend_from_slice(line.as_bytes());
+    content.extend_from_slice(b"\n\x00\x01tracing_mark_write\xc3\n");
+    let trace_file = parse_bytes(&content, ParseErrorPolicy::Fail).unwrap();
+    assert_eq!(trace_file.traces.len(), 1);
+    assert_eq!(trace_file.unmatched_lines, 2);
+
+    for garbage in [
+        "<...>-1   (-------) [010] .... 99999999999999999999.404088: tracing_mark_write: B|1|H:a|M62",
+        "<...>-1   (-------) [010] .... 18446744073.404088: tracing_mark_write: B|1|H:a|M62",
+    ] {
+        assert!(matches!(
+            parse_line(HitraceVersion::V5, garbage),
+            Some(Err(_))
+        ));
+    }
+
+    // Cheap stand-in for the fuzz target: random bytes never panic
+    let mut state: u64 = 0x2545f4914f6cdd1d;
+    for _ in 0..200 {
+        let bytes: Vec<u8> = (0..256)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 56) as u8
+            })
+            .collect();
+        let _ = parse_bytes(&bytes, ParseErrorPolicy::Skip);
+    }
+}
+
+#[test]
+fn test_timestamp_fraction() {
+    assert_eq!(parse_nanos("716645").unwrap(), 716_645_000);
+    assert_eq!(parse_nanos("7166").unwrap(), 716_600_000);
+    assert_eq!(parse_nanos("0716").unwrap(), 71_600_000);
+    assert_eq!(parse_nanos("716645123").unwrap(), 716_645_123);
+    assert_eq!(parse_nanos("7166451239").unwrap(), 716_645_123);
+
+    let trace = |time: &str| {
+        parse_line(
+            HitraceVersion::V5,
+            &format!("<...>-1   (-------) [010] .... {time}: tracing_mark_write: B|1|H:a|M62"),
+        )
+        .unwrap()
+        .unwrap()
+    };
+    assert_eq!(
+        crate::trace::difference_of_traces(&trace("17864.716645"), &trace("17864.7166")),
+        time::Duration::microseconds(45)
+    );
+    assert_eq!(
+        crate::trace::difference_of_traces(&trace("17864.716645123"), &trace("17863.9")),
+        time::Duration::nanoseconds(816_645_123)
+    );
+    assert_eq!(trace("17864.05").timestamp.to_string(), "17864.050000000");
+}
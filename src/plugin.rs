@@ -0,0 +1,261 @@
+//! Metric extractor plugins, shared libraries listed in the `plugins` of a run. They get the parsed traces of
+//! every try and return metrics, so teams can add extractors without forking hitrace-bench.
+//!
+//! A plugin exports three C functions:
+//! ```c
+//! // Has to return PLUGIN_ABI
+//! uint32_t hitrace_bench_plugin_abi(void);
+//! // Gets the json of a `PluginInput` and returns the json of a `PluginOutput`, or NULL on failure
+//! uint8_t *hitrace_bench_extract(const uint8_t *input, size_t input_len, size_t *output_len);
+//! // Frees the output of hitrace_bench_extract
+//! void hitrace_bench_free(uint8_t *output, size_t output_len);
+//! ```
+//! The json is the stable part of the interface, not the layout of our types.
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{Context, Result, anyhow};
+use libloading::{Library, Symbol};
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    clock_domain::Instant,
+    runconfig::RunConfig,
+    trace::{Trace, TraceMarker},
+    utils::{PointResult, RunResults},
+};
+
+/// The version of the plugin interface, it changes with every incompatible change of the json or the functions
+pub(crate) const PLUGIN_ABI: u32 = 1;
+
+type AbiFn = unsafe extern "C" fn() -> u32;
+type ExtractFn = unsafe extern "C" fn(*const u8, usize, *mut usize) -> *mut u8;
+type FreeFn = unsafe extern "C" fn(*mut u8, usize);
+
+/// A trace as plugins and metric scripts see it
+#[derive(Debug, Serialize)]
+pub(crate) struct PluginTrace<'a> {
+    /// Timestamp in nanoseconds of the trace clock
+    timestamp_ns: i64,
+    /// Name of the thread
+    thread: &'a str,
+    tid: u64,
+    /// `B`, `E`, `S`, `F` or `C`
+    marker: &'static str,
+    /// The function including the shorthand, i.e., `H:on_surface_created_cb|M62`
+    function: String,
+    /// Absolute time of the trace if we know the clock offset of the device
+    #[serde(with = "time::serde::rfc3339::option")]
+    wall_clock: Option<OffsetDateTime>,
+}
+
+impl<'a> From<&'a Trace> for PluginTrace<'a> {
+    fn from(trace: &'a Trace) -> Self {
+        PluginTrace {
+            timestamp_ns: Instant::of_trace(trace).nanos,
+            thread: &trace.name,
+            tid: trace.tid,
+            marker: match trace.trace_marker {
+                TraceMarker::StartSync => "B",
+                TraceMarker::EndSync => "E",
+                TraceMarker::StartAsync => "S",
+                TraceMarker::EndAsync => "F",
+                TraceMarker::Dot => "C",
+            },
+            function: if trace.shorthand.is_empty() {
+                trace.function.clone()
+            } else {
+                format!("{}:{}", trace.shorthand, trace.function)
+            },
+            wall_clock: trace.wall_clock,
+        }
+    }
+}
+
+/// What a plugin gets for every try
+#[derive(Debug, Serialize)]
+struct PluginInput<'a> {
+    abi: u32,
+    /// The url of the run
+    url: &'a str,
+    traces: Vec<PluginTrace<'a>>,
+}
+
+/// The metrics a plugin or metric script found in a try
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PluginOutput {
+    /// Durations in nanoseconds, reported like filters
+    #[serde(default)]
+    durations: BTreeMap<String, Vec<i64>>,
+    /// Plain numbers, reported like point filters with `no_unit_conversion`
+    #[serde(default)]
+    points: BTreeMap<String, Vec<i64>>,
+    /// Memory in bytes, reported like memory point filters
+    #[serde(default)]
+    memory: BTreeMap<String, Vec<i64>>,
+}
+
+impl PluginOutput {
+    /// Adds the metrics to `results` and returns how many there were
+    pub(crate) fn record(self, results: &mut RunResults) -> usize {
+        let mut found = 0;
+        for (name, durations) in self.durations {
+            found += durations.len();
+            results
+                .filter_results
+                .entry(name)
+                .or_default()
+                .extend(durations.into_iter().map(Duration::nanoseconds));
+        }
+        for (no_unit_conversion, points) in [(true, self.points), (false, self.memory)] {
+            for (name, values) in points {
+                found += values.len();
+                results
+                    .point_results
+                    .entry(name)
+                    .or_insert(PointResult {
+                        no_unit_conversion,
+                        result: Vec::new(),
+                    })
+                    .result
+                    .extend(values);
+            }
+        }
+        found
+    }
+}
+
+/// A loaded plugin
+struct Plugin {
+    library: Library,
+}
+
+impl Plugin {
+    /// Loads the shared library at `path` and checks its interface version
+    fn load(path: &Path) -> Result<Self> {
+        // Safety: loading runs the initializers of the library, we trust the plugins listed in the run file
+        let library = unsafe { Library::new(path) }
+            .with_context(|| format!("Could not load plugin {}", path.display()))?;
+        // Safety: the type is the one the interface defines
+        let abi = unsafe {
+            let abi: Symbol<AbiFn> = library
+                .get(b"hitrace_bench_plugin_abi")
+                .context("The plugin does not export hitrace_bench_plugin_abi")?;
+            abi()
+        };
+        if abi != PLUGIN_ABI {
+            return Err(anyhow!(
+                "Plugin {} has interface version {abi}, we need {PLUGIN_ABI}",
+                path.display()
+            ));
+        }
+        Ok(Plugin { library })
+    }
+
+    /// Runs the plugin on `input`
+    fn extract(&self, input: &[u8]) -> Result<PluginOutput> {
+        // Safety: the types are the ones the interface defines, the plugin owns the output until we free it
+        unsafe {
+            let extract: Symbol<ExtractFn> = self
+                .library
+                .get(b"hitrace_bench_extract")
+                .context("The plugin does not export hitrace_bench_extract")?;
+            let free: Symbol<FreeFn> = self
+                .library
+                .get(b"hitrace_bench_free")
+                .context("The plugin does not export hitrace_bench_free")?;
+            let mut output_len = 0;
+            let output = extract(input.as_ptr(), input.len(), &mut output_len);
+            if output.is_null() {
+                return Err(anyhow!("The plugin failed"));
+            }
+            let parsed = serde_json::from_slice(std::slice::from_raw_parts(output, output_len))
+                .context("The plugin returned invalid json");
+            free(output, output_len);
+            parsed
+        }
+    }
+}
+
+/// The json the plugins of `run_config` get for `traces`
+fn plugin_input(run_config: &RunConfig, traces: &[Trace]) -> Result<Vec<u8>> {
+    serde_json::to_vec(&PluginInput {
+        abi: PLUGIN_ABI,
+        url: &run_config.run_args.url,
+        traces: traces.iter().map(PluginTrace::from).collect(),
+    })
+    .context("Could not serialize the plugin input")
+}
+
+/// Runs the plugins of `run_config` on the traces of a try and records their metrics.
+/// Returns the number of metrics.
+pub(crate) fn record_plugin_metrics(
+    run_config: &RunConfig,
+    traces: &[Trace],
+    results: &mut RunResults,
+) -> Result<usize> {
+    if run_config.plugins.is_empty() {
+        return Ok(0);
+    }
+    let input = plugin_input(run_config, traces)?;
+    let mut found = 0;
+    for path in &run_config.plugins {
+        let output = Plugin::load(path)
+            .and_then(|plugin| plugin.extract(&input))
+            .with_context(|| format!("Plugin {} failed", path.display()))?;
+        found += output.record(results);
+    }
+    Ok(found)
+}
+
+#[test]
+fn test_plugin_output() {
+    let output: PluginOutput = serde_json::from_str(
+        r#"{"durations": {"Layout": [1500000, 2000000]}, "memory": {"Heap": [4096]}}"#,
+    )
+    .unwrap();
+    let mut results = RunResults::default();
+    assert_eq!(output.record(&mut results), 3);
+    assert_eq!(
+        results.filter_results["Layout"],
+        vec![Duration::microseconds(1500), Duration::milliseconds(2)]
+    );
+    assert!(!results.point_results["Heap"].no_unit_conversion);
+    assert!(serde_json::from_str::<PluginOutput>(r#"{"duration": {}}"#).is_err());
+
+    assert!(Plugin::load(Path::new("testdata/no_such_plugin.so")).is_err());
+}
+
+#[test]
+fn test_plugin_input() {
+    use crate::{args::RunArgs, parser::parse_line};
+
+    let mut trace = parse_line(
+        crate::parser::HitraceVersion::V5,
+        "<...>-7   (-------) [010] .... 10.000000500: tracing_mark_write: B|1|H:on_surface_created_cb|M62",
+    )
+    .unwrap()
+    .unwrap();
+    trace.wall_clock = Some(time::macros::datetime!(2025-06-01 12:00:00 UTC));
+    let run_config = RunConfig::new(
+        crate::args::Args::test_default(std::path::PathBuf::new()),
+        RunArgs::default(),
+        vec![],
+        vec![],
+    );
+    let input: serde_json::Value =
+        serde_json::from_slice(&plugin_input(&run_config, &[trace]).unwrap()).unwrap();
+    assert_eq!(
+        input["traces"][0],
+        serde_json::json!({
+            "timestamp_ns": 10_000_000_500i64,
+            "thread": "<...>",
+            "tid": 7,
+            "marker": "B",
+            "function": "H:on_surface_created_cb|M62",
+            "wall_clock": "2025-06-01T12:00:00Z",
+        })
+    );
+    assert_eq!(input["abi"], PLUGIN_ABI);
+}
@@ -1,13 +1,17 @@
 use std::sync::LazyLock;
 
+use anyhow::{Context, Result, anyhow};
 use itertools::Itertools;
 use log::error;
 use regex::{Captures, Regex};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    clock_domain::{ClockCorrelation, ClockDomain, Instant},
+    filter::ExpectedRange,
     runconfig::RunConfig,
     trace::{Trace, TraceMarker},
+    utils::ErrorKind,
 };
 
 const SERVO_MEMORY_PROFILING_STRING: &str = "servo_memory_profiling";
@@ -15,40 +19,94 @@ const SERVO_LCP_STRING: &str = "LargestContentfulPaint";
 const SERVO_FCP_STRING: &str = "FirstContentfulPaint";
 
 // checked Default, Deserialize^,
-#[derive(Debug, Deserialize, Default, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq)]
 pub(crate) enum PointFilterType {
     #[default]
     Default,
     Combined,
     Largest,
+    /// The smallest of all matched points with the same name
+    Smallest,
+    /// The first matched point with the same name in trace order
+    First,
+    /// The last matched point with the same name in trace order
+    Last,
+    /// The median of all matched points with the same name, the lower middle one for an even number
+    Median,
+    /// The mean of all matched points with the same name, rounded towards zero
+    Mean,
+    /// Matches any counter trace whose counter name contains `match_str` and uses its value
+    Counter,
+    /// The difference of the counter matching `match_str` between the `before` and `after` markers of `delta`
+    Delta,
+}
+
+impl PointFilterType {
+    /// Aggregates the values of all points with the same name into one value.
+    /// `values` are in trace order and never empty.
+    fn aggregate(&self, mut values: Vec<i64>) -> i64 {
+        match self {
+            PointFilterType::Largest => values.into_iter().max().unwrap(),
+            PointFilterType::Smallest => values.into_iter().min().unwrap(),
+            PointFilterType::Combined => values.into_iter().sum(),
+            PointFilterType::First => values[0],
+            PointFilterType::Last => *values.last().unwrap(),
+            PointFilterType::Median => {
+                values.sort_unstable();
+                values[(values.len() - 1) / 2]
+            }
+            PointFilterType::Mean => values.iter().sum::<i64>() / values.len() as i64,
+            PointFilterType::Default | PointFilterType::Counter | PointFilterType::Delta => {
+                panic!("should not be reachable")
+            }
+        }
+    }
+}
+
+/// The markers between which a `PointFilterType::Delta` measures the change of a counter
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub(crate) struct DeltaMarkers {
+    /// The counter value at the first trace whose function contains this is the start value
+    pub(crate) before: String,
+    /// The counter value at the first trace whose function contains this is the end value
+    pub(crate) after: String,
 }
 
 /// We have different type of points which have different regexp.
 /// See the statics for a detailed explanation
 pub(crate) enum PointType {
     /// A memory report that has an url attached, like LayoutThread.
-    MemoryUrl(u64),
+    MemoryUrl(i64),
     /// A simple memory report, corresponding to resident-memory most likely.
-    MemoryReport(u64),
+    MemoryReport(i64),
     /// Report of smaps.
-    Smaps(u64),
+    Smaps(i64),
     /// Report of a testcase point.
-    Testcase(u64),
+    Testcase(i64),
     /// A testcase point that will be the sum of all matches points.
-    Combined(u64),
+    Combined(i64),
     /// LCP
-    LargestContentfulPaint(u64),
+    LargestContentfulPaint(i64),
+    /// A point aggregated from multiple points with `Smallest`, `First`, `Last`, `Median` or `Mean`.
+    Aggregated(i64),
+    /// The value of a generic counter trace.
+    Counter(i64),
+    /// The difference of a counter between two markers.
+    Delta(i64),
 }
 
 impl PointType {
-    pub fn numeric_value(&self) -> Option<u64> {
+    pub fn numeric_value(&self) -> Option<i64> {
         match self {
             PointType::MemoryUrl(v)
             | PointType::MemoryReport(v)
             | PointType::Smaps(v)
             | PointType::Testcase(v)
             | PointType::Combined(v)
-            | PointType::LargestContentfulPaint(v) => Some(*v),
+            | PointType::LargestContentfulPaint(v)
+            | PointType::Aggregated(v)
+            | PointType::Counter(v)
+            | PointType::Delta(v) => Some(*v),
         }
     }
 }
@@ -126,7 +184,7 @@ pub(crate) struct Point<'a> {
 }
 
 /// You might want to extract data points. These do not have a beginning and end, just a point.
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub(crate) struct PointFilter {
     /// The name we will use for this string
     pub(crate) name: String,
@@ -138,6 +196,49 @@ pub(crate) struct PointFilter {
     /// This is more flexible version of "combined", but did not replace it fully due to input json
     #[serde(default)]
     pub(crate) point_filter_type: PointFilterType,
+    /// The markers for `PointFilterType::Delta`
+    #[serde(default)]
+    pub(crate) delta: Option<DeltaMarkers>,
+    /// Roll up memory reports with an url to this depth of the report path and report the subtree sums.
+    /// With depth 2 `js/gc-heap/used` and `js/gc-heap/unused` are reported as `js/gc-heap`.
+    #[serde(default)]
+    pub(crate) depth: Option<usize>,
+    /// Memory reports with an url are only kept if the url contains the url of the run.
+    /// Reports whose url contains one of these are kept as well, i.e., for iframes or redirects.
+    #[serde(default)]
+    pub(crate) urls: Vec<String>,
+    /// Keep memory reports of all urls
+    #[serde(default)]
+    pub(crate) all_urls: bool,
+    /// Report LCP and FCP per pipeline, i.e., for every iframe, instead of collapsing all pipelines into one metric
+    #[serde(default)]
+    pub(crate) per_pipeline: bool,
+    /// Only keep LCP and FCP of this pipeline, i.e., `(1,1)` which is usually the root pipeline
+    #[serde(default)]
+    pub(crate) pipeline_id: Option<String>,
+    /// Report the paint time of LCP and FCP relative to this reference instead of the raw `CrossProcessInstant`
+    #[serde(default)]
+    pub(crate) relative_to: Option<RelativeTo>,
+    /// A trace whose function contains this string and a `CrossProcessInstant` taken when it was emitted.
+    /// It converts `RelativeTo::Marker` exactly instead of estimating the clock offset from the paint traces.
+    #[serde(default)]
+    pub(crate) clock_marker: Option<String>,
+    /// The bencher measure the points are reported under instead of the one derived from their name
+    #[serde(default)]
+    pub(crate) measure: Option<String>,
+    /// Points outside of this range, in bytes or the unconverted unit, are suspect and left out
+    #[serde(default)]
+    pub(crate) expected_range: Option<ExpectedRange>,
+}
+
+/// The navigation start reference for LCP and FCP paint times
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub(crate) enum RelativeTo {
+    /// The timestamp of the first trace whose function contains this string.
+    /// It is converted to the `CrossProcessInstant` clock with the `clock_marker` or the LCP and FCP traces.
+    Marker(String),
+    /// The paint time of the first FirstContentfulPaint
+    FirstContentfulPaint,
 }
 
 impl PointFilter {
@@ -147,7 +248,19 @@ impl PointFilter {
             match_str,
             no_unit_conversion: false,
             point_filter_type: PointFilterType::Default,
+            ..Default::default()
+        }
+    }
+
+    /// Checks that the filter is complete, i.e., that a `PointFilterType::Delta` has its markers
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.point_filter_type == PointFilterType::Delta && self.delta.is_none() {
+            return Err(anyhow!(
+                "Point filter {} is of type Delta but has no delta markers",
+                self.name
+            ));
         }
+        Ok(())
     }
 
     /// This filters sub memory reports with a url attached.
@@ -156,33 +269,45 @@ impl PointFilter {
         run_config: &RunConfig,
         groups: Captures,
         trace: &'a Trace,
-    ) -> Option<Point<'a>> {
+    ) -> Result<Option<Point<'a>>> {
         let mut match_iter = groups.iter().flatten();
         let _whole_match = match_iter.next();
-        let url = match_iter.next().expect("No match").as_str();
-        let subsystem_path = match_iter.next().expect("No match").as_str();
+        let url = match_iter.next().context("No url")?.as_str();
+        let subsystem_path = match_iter.next().context("No subsystem path")?.as_str();
         let value = match_iter
             .next()
-            .expect("No match")
+            .context("No value")?
             .as_str()
             .parse()
-            .expect("Could not parse value");
-        if url.contains(run_config.run_args.url.as_str()) {
-            let mut suffix = subsystem_path.split('/').skip(1).join("/");
+            .context("Could not parse value")?;
+        let is_run_url = url.contains(run_config.run_args.url.as_str());
+        if is_run_url || self.all_urls || self.urls.iter().any(|u| url.contains(u.as_str())) {
+            let mut suffix = subsystem_path
+                .split('/')
+                .take(self.depth.unwrap_or(usize::MAX))
+                .skip(1)
+                .join("/");
             if !suffix.is_empty() {
                 suffix.insert(0, '/');
             }
-            Some(Point {
+            // Reports of other urls, i.e., iframes, need their url in the name to be distinguishable
+            let url_part = if is_run_url {
+                String::new()
+            } else {
+                format!("/url({url})")
+            };
+            Ok(Some(Point {
                 name: run_config.run_args.url.to_owned()
                     + "/"
                     + self.name.as_str()
+                    + url_part.as_str()
                     + suffix.as_str(),
                 no_unit_conversion: self.no_unit_conversion,
                 trace: Some(trace),
                 point_type: PointType::MemoryUrl(value),
-            })
+            }))
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -192,26 +317,26 @@ impl PointFilter {
         run_config: &RunConfig,
         groups: Captures,
         trace: &'a Trace,
-    ) -> Option<Point<'a>> {
+    ) -> Result<Option<Point<'a>>> {
         let mut match_iter = groups.iter().flatten();
         let _whole_match = match_iter.next();
-        let match_str = match_iter.next().unwrap().as_str();
+        let match_str = match_iter.next().context("No smaps name")?.as_str();
         let _fn_name = match_iter.next();
         if match_str != self.match_str {
-            None
+            Ok(None)
         } else {
             let value = match_iter
                 .next()
-                .expect("Could not find match")
+                .context("Could not find match")?
                 .as_str()
                 .parse()
-                .expect("Could not parse");
-            Some(Point {
+                .context("Could not parse")?;
+            Ok(Some(Point {
                 name: run_config.run_args.url.to_owned() + "/" + self.name.as_str(),
                 no_unit_conversion: self.no_unit_conversion,
                 trace: Some(trace),
                 point_type: PointType::Smaps(value),
-            })
+            }))
         }
     }
 
@@ -221,23 +346,23 @@ impl PointFilter {
         run_config: &RunConfig,
         groups: Captures,
         trace: &'a Trace,
-    ) -> Option<Point<'a>> {
+    ) -> Result<Option<Point<'a>>> {
         let mut match_iter = groups.iter().flatten();
         let _whole_match = match_iter.next();
         let _name = match_iter.next();
 
         let value = match_iter
             .next()
-            .expect("Could not find match")
+            .context("Could not find match")?
             .as_str()
             .parse()
-            .expect("Could not parse value");
-        Some(Point {
+            .context("Could not parse value")?;
+        Ok(Some(Point {
             name: run_config.run_args.url.to_owned() + "/" + self.name.as_str(),
             no_unit_conversion: self.no_unit_conversion,
             trace: Some(trace),
             point_type: PointType::MemoryReport(value),
-        })
+        }))
     }
 
     /// This filters test cases
@@ -246,27 +371,27 @@ impl PointFilter {
         run_config: &RunConfig,
         groups: Captures,
         trace: &'a Trace,
-    ) -> Option<Point<'a>> {
+    ) -> Result<Option<Point<'a>>> {
         let mut match_iter = groups.iter().flatten();
         let _whole_match = match_iter.next();
         let name = match_iter.next();
 
-        let case_name = name.expect("Could not find match").as_str();
+        let case_name = name.context("Could not find match")?.as_str();
         let value = match_iter
             .next()
-            .expect("Could not find match")
+            .context("Could not find match")?
             .as_str()
             .parse()
-            .expect("Could not parse value");
+            .context("Could not parse value")?;
         if case_name.contains(&self.match_str) {
-            Some(Point {
+            Ok(Some(Point {
                 name: run_config.run_args.url.to_owned() + "/",
                 no_unit_conversion: self.no_unit_conversion,
                 trace: Some(trace),
                 point_type: PointType::Testcase(value),
-            })
+            }))
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -276,44 +401,62 @@ impl PointFilter {
         run_config: &RunConfig,
         groups: Captures,
         trace: &'a Trace,
-    ) -> Option<Vec<Point<'a>>> {
+        navigation_start: Option<Instant>,
+    ) -> Result<Option<Vec<Point<'a>>>> {
         let mut match_iter = groups.iter().flatten();
         let _whole_match = match_iter.next();
-        let filter_name = match_iter.next().expect("Could not find match").as_str();
-        let key_values = match_iter.next().expect("Could not find match").as_str();
+        let filter_name = match_iter.next().context("Could not find match")?.as_str();
+        let key_values = match_iter.next().context("Could not find match")?.as_str();
+
+        let pipeline_id = parse_pipeline_id(key_values);
+        if self.pipeline_id.is_some() && self.pipeline_id != pipeline_id {
+            return Ok(None);
+        }
+        let pipeline_suffix = match pipeline_id {
+            Some(ref id) if self.per_pipeline => format!("/pipeline{id}"),
+            _ => String::new(),
+        };
+        let point_name = |metric: &str| {
+            run_config.run_args.url.to_owned()
+                + "/"
+                + self.name.as_str()
+                + metric
+                + pipeline_suffix.as_str()
+        };
 
         if filter_name == SERVO_LCP_STRING {
-            let lcp_values = parse_lcp_trace(key_values).expect("Could not parse LCP values");
-            Some(vec![
+            let lcp_values = parse_lcp_trace(key_values).context("Could not parse LCP values")?;
+            Ok(Some(vec![
                 Point {
-                    name: run_config.run_args.url.to_owned()
-                        + "/"
-                        + self.name.as_str()
-                        + "/paint_time",
+                    name: point_name("/paint_time"),
                     no_unit_conversion: self.no_unit_conversion,
                     trace: Some(trace),
-                    point_type: PointType::LargestContentfulPaint(lcp_values.paint_time),
+                    point_type: PointType::LargestContentfulPaint(paint_time(
+                        lcp_values.paint_time,
+                        navigation_start,
+                    )?),
                 },
                 Point {
-                    name: run_config.run_args.url.to_owned() + "/" + self.name.as_str() + "/area",
+                    name: point_name("/area"),
                     no_unit_conversion: self.no_unit_conversion,
                     trace: Some(trace),
                     point_type: PointType::LargestContentfulPaint(lcp_values.area),
                 },
-            ])
+            ]))
         } else if filter_name == SERVO_FCP_STRING {
-            Some(vec![Point {
-                name: run_config.run_args.url.to_owned() + "/" + self.name.as_str() + "/paint_time",
+            Ok(Some(vec![Point {
+                name: point_name("/paint_time"),
                 no_unit_conversion: self.no_unit_conversion,
                 trace: Some(trace),
-                point_type: PointType::LargestContentfulPaint(
+                point_type: PointType::LargestContentfulPaint(paint_time(
                     parse_fcp_trace(key_values)
-                        .expect("Could not parse LCP values")
+                        .context("Could not parse FCP values")?
                         .paint_time,
-                ),
-            }])
+                    navigation_start,
+                )?),
+            }]))
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -322,27 +465,157 @@ impl PointFilter {
         &'a self,
         trace: &'a Trace,
         run_config: &RunConfig,
-    ) -> Option<Vec<Point<'a>>> {
+        navigation_start: Option<Instant>,
+    ) -> Result<Option<Vec<Point<'a>>>> {
         if let Some(groups) = LCP_REGEX.captures(&trace.function) {
-            self.filter_lcp_or_fcp(run_config, groups, trace)
+            self.filter_lcp_or_fcp(run_config, groups, trace, navigation_start)
         } else if let Some(groups) = FCP_REGEX.captures(&trace.function) {
-            self.filter_lcp_or_fcp(run_config, groups, trace)
+            self.filter_lcp_or_fcp(run_config, groups, trace, navigation_start)
         } else {
-            if let Some(groups) = MEMORY_URL_REPORT_REGEX.captures(&trace.function) {
-                self.filter_memory_url(run_config, groups, trace)
-            } else if let Some(groups) = SMAPS_REGEX.captures(&trace.function) {
-                self.filter_smaps(run_config, groups, trace)
-            } else if let Some(groups) = MEMORY_REPORT_REGEX.captures(&trace.function) {
-                self.filter_memory(run_config, groups, trace)
-            } else if let Some(groups) = TESTCASE_REGEX.captures(&trace.function) {
-                self.filter_testcase(run_config, groups, trace)
-            } else {
-                None
-            }
-            .map(|p| vec![p])
+            Ok(
+                if let Some(groups) = MEMORY_URL_REPORT_REGEX.captures(&trace.function) {
+                    self.filter_memory_url(run_config, groups, trace)?
+                } else if let Some(groups) = SMAPS_REGEX.captures(&trace.function) {
+                    self.filter_smaps(run_config, groups, trace)?
+                } else if let Some(groups) = MEMORY_REPORT_REGEX.captures(&trace.function) {
+                    self.filter_memory(run_config, groups, trace)?
+                } else if let Some(groups) = TESTCASE_REGEX.captures(&trace.function) {
+                    self.filter_testcase(run_config, groups, trace)?
+                } else {
+                    None
+                }
+                .map(|p| vec![p]),
+            )
+        }
+        .with_context(|| format!("PointFilter {} could not use {:?}", self.name, trace))
+    }
+
+    /// The reference for `relative_to` in the `CrossProcessInstant` clock.
+    fn navigation_start(&self, traces: &[Trace]) -> Option<Result<Instant>> {
+        match self.relative_to.as_ref()? {
+            RelativeTo::Marker(marker) => Some(
+                traces
+                    .iter()
+                    .find(|t| t.function.contains(marker.as_str()))
+                    .with_context(|| format!("Could not find navigation start marker {marker}"))
+                    .and_then(|t| {
+                        let correlation = self.clock_correlation(traces)?;
+                        Ok(correlation.convert(Instant::of_trace(t), ClockDomain::CrossProcess))
+                    }),
+            ),
+            RelativeTo::FirstContentfulPaint => Some(
+                traces
+                    .iter()
+                    .filter_map(|t| FCP_REGEX.captures(&t.function))
+                    .filter_map(|groups| parse_fcp_trace(groups.get(2)?.as_str()))
+                    .map(|fcp| Instant::cross_process(fcp.paint_time))
+                    .next()
+                    .context("Could not find FirstContentfulPaint"),
+            ),
         }
     }
 
+    /// The relation of the trace clock and the `CrossProcessInstant` clock, from the `clock_marker` if there is
+    /// one and estimated from the LCP and FCP traces otherwise
+    fn clock_correlation(&self, traces: &[Trace]) -> Result<ClockCorrelation> {
+        if let Some(ref marker) = self.clock_marker {
+            return ClockCorrelation::from_marker(traces, marker);
+        }
+        let paint_times = traces.iter().filter_map(|t| {
+            let groups = LCP_REGEX
+                .captures(&t.function)
+                .or_else(|| FCP_REGEX.captures(&t.function))?;
+            Some((t, parse_fcp_trace(groups.get(2)?.as_str())?.paint_time))
+        });
+        ClockCorrelation::estimate(paint_times).context("Could not find any paint trace")
+    }
+
+    /// This filters generic counters without needing a bespoke regex
+    fn filter_counter<'a>(&'a self, run_config: &RunConfig, trace: &'a Trace) -> Option<Point<'a>> {
+        let counter = trace.counter.as_ref()?;
+        if !counter.name.contains(&self.match_str) {
+            return None;
+        }
+        Some(Point {
+            name: run_config.run_args.url.to_owned() + "/" + self.name.as_str(),
+            no_unit_conversion: self.no_unit_conversion,
+            trace: Some(trace),
+            point_type: PointType::Counter(counter.value),
+        })
+    }
+
+    /// This computes the change of a counter between the two markers of `delta`.
+    /// The counter value at a marker is the last sample at or before the marker.
+    fn filter_delta<'a>(
+        &'a self,
+        run_config: &RunConfig,
+        traces: &'a [Trace],
+    ) -> Result<Point<'a>, (ErrorKind, anyhow::Error)> {
+        let markers = self.delta.as_ref().ok_or_else(|| {
+            (
+                ErrorKind::NoStartMarker,
+                anyhow!("PointFilter is of type Delta but has no delta markers"),
+            )
+        })?;
+        let value_at = |marker: &str| {
+            let marker_index = traces.iter().position(|t| t.function.contains(marker))?;
+            traces[..=marker_index]
+                .iter()
+                .rev()
+                .filter_map(|t| t.counter.as_ref())
+                .find(|c| c.name.contains(&self.match_str))
+                .map(|c| c.value)
+        };
+        let error = |kind| {
+            (
+                kind,
+                anyhow!(
+                    "Could not find counter {} at markers {:?}",
+                    self.match_str,
+                    markers
+                ),
+            )
+        };
+        let before = value_at(&markers.before).ok_or_else(|| error(ErrorKind::NoStartMarker))?;
+        let after = value_at(&markers.after).ok_or_else(|| error(ErrorKind::NoEndMarker))?;
+        Ok(Point {
+            name: run_config.run_args.url.to_owned() + "/" + self.name.as_str(),
+            no_unit_conversion: self.no_unit_conversion,
+            trace: None,
+            point_type: PointType::Delta(after - before),
+        })
+    }
+
+    /// Sums memory reports with an url that were rolled up to the same name by `depth`
+    fn rollup<'a>(&self, points: Vec<Point<'a>>) -> Vec<Point<'a>> {
+        let (memory_url, mut others): (Vec<_>, Vec<_>) = points
+            .into_iter()
+            .partition(|p| matches!(p.point_type, PointType::MemoryUrl(_)));
+        others.extend(
+            memory_url
+                .into_iter()
+                .into_group_map_by(|p| p.name.clone())
+                .into_iter()
+                .map(|(name, mut vals)| {
+                    if vals.len() == 1 {
+                        vals.remove(0)
+                    } else {
+                        Point {
+                            name,
+                            no_unit_conversion: self.no_unit_conversion,
+                            trace: None,
+                            point_type: PointType::MemoryUrl(
+                                vals.iter()
+                                    .filter_map(|p| p.point_type.numeric_value())
+                                    .sum(),
+                            ),
+                        }
+                    }
+                }),
+        );
+        others
+    }
+
     /// Check if there are duplicates for PointType::Testcase and PointType::MemoryReport.
     /// Remove these and print errors.
     fn remove_duplicates(&self, points: &mut Vec<Point>) {
@@ -379,15 +652,55 @@ impl PointFilter {
             );
             points.retain(|p| !matches!(p.point_type, PointType::MemoryReport(_)));
         }
+
+        if points
+            .iter()
+            .filter(|p| matches!(p.point_type, PointType::Counter(_)))
+            .count()
+            > 1
+        {
+            error!(
+                "PointFilter {:?} matched with multiple traces {:?}. Discarding",
+                self,
+                points
+                    .iter()
+                    .filter_map(|p| p.trace)
+                    .collect::<Vec<&Trace>>()
+            );
+            points.retain(|p| !matches!(p.point_type, PointType::Counter(_)));
+        }
     }
 
     /// Takes a a `PointFilter`, an array of traces and a run_config to create a result of matched points.
+    /// Traces that match but cannot be used, i.e., because they are malformed, are returned as errors
+    /// so one broken trace does not abort the whole run.
     pub(crate) fn pointfilter_to_point<'a>(
         &'a self,
         traces: &'a [Trace],
         run_config: &'a RunConfig,
-    ) -> Vec<Point<'a>> {
-        let mut points: Vec<_> = traces
+    ) -> (Vec<Point<'a>>, Vec<(ErrorKind, anyhow::Error)>) {
+        if self.point_filter_type == PointFilterType::Delta {
+            return match self.filter_delta(run_config, traces) {
+                Ok(point) => (vec![point], vec![]),
+                Err(e) => (vec![], vec![e]),
+            };
+        }
+        if self.point_filter_type == PointFilterType::Counter {
+            let mut points: Vec<_> = traces
+                .iter()
+                .filter_map(|t| self.filter_counter(run_config, t))
+                .collect();
+            self.remove_duplicates(&mut points);
+            return (points, vec![]);
+        }
+
+        let navigation_start = match self.navigation_start(traces) {
+            None => None,
+            Some(Ok(start)) => Some(start),
+            Some(Err(e)) => return (vec![], vec![(ErrorKind::NoStartMarker, e)]),
+        };
+
+        let (points, errors): (Vec<Vec<Point>>, Vec<(ErrorKind, anyhow::Error)>) = traces
             .iter()
             .filter(|t| {
                 t.trace_marker == TraceMarker::Dot || t.trace_marker == TraceMarker::StartSync
@@ -399,11 +712,15 @@ impl PointFilter {
                     || t.function.contains(SERVO_FCP_STRING)
             })
             .filter(|t| t.function.contains(&self.match_str))
-            .filter_map(|t| self.filter_trace_to_option_point(t, run_config))
-            .flatten()
-            .collect();
+            .filter_map(|t| {
+                self.filter_trace_to_option_point(t, run_config, navigation_start)
+                    .map_err(|e| (ErrorKind::ParseFailure, e))
+                    .transpose()
+            })
+            .partition_result();
+        let mut points: Vec<Point> = points.into_iter().flatten().collect();
 
-        if !matches!(self.point_filter_type, PointFilterType::Default) {
+        let points = if !matches!(self.point_filter_type, PointFilterType::Default) {
             // we now need to collect points with the same name
             points
                 .into_iter()
@@ -417,58 +734,62 @@ impl PointFilter {
                             name,
                             no_unit_conversion: vals.first().unwrap().no_unit_conversion,
                             trace: None,
-                            point_type: match self.point_filter_type {
-                                PointFilterType::Largest => PointType::LargestContentfulPaint(
-                                    vals.iter()
-                                        .map(|p| p.point_type.numeric_value().unwrap())
-                                        .max()
-                                        .unwrap(),
-                                ),
-
-                                PointFilterType::Combined => PointType::Combined(
-                                    vals.iter()
-                                        .map(|p| p.point_type.numeric_value().unwrap())
-                                        .sum(),
-                                ),
-
-                                PointFilterType::Default => panic!("should not be reachable"),
+                            point_type: {
+                                let values = vals
+                                    .iter()
+                                    .map(|p| p.point_type.numeric_value().unwrap())
+                                    .collect();
+                                let value = self.point_filter_type.aggregate(values);
+                                match self.point_filter_type {
+                                    PointFilterType::Largest => {
+                                        PointType::LargestContentfulPaint(value)
+                                    }
+                                    PointFilterType::Combined => PointType::Combined(value),
+                                    _ => PointType::Aggregated(value),
+                                }
                             },
                         }
                     }
                 })
                 .collect()
         } else {
+            if self.depth.is_some() {
+                points = self.rollup(points);
+            }
             self.remove_duplicates(&mut points);
             points
-        }
+        };
+        (points, errors)
     }
 }
 
 #[derive(PartialEq, Debug)]
 struct LCPTraceValues {
-    paint_time: u64,
-    area: u64,
+    paint_time: i64,
+    area: i64,
 }
 /// This function takes value from the hitrace-sys's start_trace_ex's `key=value,` string
 ///
 /// Example paint_time=CrossProcessInstant { value: 219733332872200 },area=90810,pipeline_id=(1,1)
 fn parse_lcp_trace(input: &str) -> Option<LCPTraceValues> {
-    CROSS_PROCESS_INSTANT
-        .captures(input)
-        .map(|groups| LCPTraceValues {
-            paint_time: groups
-                .get(1)
-                .expect("Could not find paint_time in LCP trace using REGEX")
-                .as_str()
-                .parse()
-                .expect("Count not parse paint_time from LCP trace using REGEX"),
-            area: groups
-                .get(2)
-                .expect("Could not find paint_time in LCP trace using REGEX")
-                .as_str()
-                .parse()
-                .expect("Count not parse paint_time from LCP trace using REGEX"),
-        })
+    let groups = CROSS_PROCESS_INSTANT.captures(input)?;
+    Some(LCPTraceValues {
+        paint_time: groups.get(1)?.as_str().parse().ok()?,
+        area: groups.get(2)?.as_str().parse().ok()?,
+    })
+}
+
+#[test]
+fn test_point_aggregation() {
+    let values = vec![5, 1, 9, 3];
+    assert_eq!(PointFilterType::Largest.aggregate(values.clone()), 9);
+    assert_eq!(PointFilterType::Smallest.aggregate(values.clone()), 1);
+    assert_eq!(PointFilterType::Combined.aggregate(values.clone()), 18);
+    assert_eq!(PointFilterType::First.aggregate(values.clone()), 5);
+    assert_eq!(PointFilterType::Last.aggregate(values.clone()), 3);
+    assert_eq!(PointFilterType::Median.aggregate(values.clone()), 3);
+    assert_eq!(PointFilterType::Median.aggregate(vec![4, 2, 7]), 4);
+    assert_eq!(PointFilterType::Mean.aggregate(values), 4);
 }
 
 #[test]
@@ -486,26 +807,53 @@ fn test_trace_kv_parsing() {
     );
 }
 
+/// The `CrossProcessInstant` value of a paint, relative to `navigation_start` if there is one
+fn paint_time(value: i64, navigation_start: Option<Instant>) -> Result<i64> {
+    match navigation_start {
+        Some(start) => Instant::cross_process(value).since(start, None),
+        None => Ok(value),
+    }
+}
+
+/// This regex parses the pipeline of LCP and FCP traces, i.e., `pipeline_id=(1,1)`
+static PIPELINE_ID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"pipeline_id=\((\d+),\s*(\d+)\)").expect("Could not parse regexp")
+});
+
+/// Returns the pipeline id as `(namespace,index)`
+fn parse_pipeline_id(input: &str) -> Option<String> {
+    PIPELINE_ID
+        .captures(input)
+        .map(|groups| format!("({},{})", &groups[1], &groups[2]))
+}
+
+#[test]
+fn test_pipeline_id_parsing() {
+    assert_eq!(
+        parse_pipeline_id(
+            "paint_time=CrossProcessInstant { value: 231277222481376 },area=4095,lcp_type=Image,pipeline_id=(1, 2)"
+        ),
+        Some(String::from("(1,2)"))
+    );
+    assert_eq!(
+        parse_pipeline_id("paint_time=CrossProcessInstant { value: 1 },"),
+        None
+    );
+}
+
 #[derive(PartialEq, Debug)]
 struct FCPTraceValue {
-    paint_time: u64,
+    paint_time: i64,
 }
 
 /// This function takes value from the hitrace-sys's start_trace_ex's `key=value,` string
 ///
 /// Example "epoch=Epoch(1),paint_time=CrossProcessInstant { value: 271633800350218 },pipeline_id=(1,1)"
 fn parse_fcp_trace(input: &str) -> Option<FCPTraceValue> {
-    if let Some(groups) = CROSS_PROCESS_INSTANT.captures(input) {
-        return Some(FCPTraceValue {
-            paint_time: groups
-                .get(1)
-                .expect("Could not find paint_time in LCP trace using REGEX")
-                .as_str()
-                .parse()
-                .expect("Count not parse paint_time from LCP trace using REGEX"),
-        });
-    }
-    None
+    let groups = CROSS_PROCESS_INSTANT.captures(input)?;
+    Some(FCPTraceValue {
+        paint_time: groups.get(1)?.as_str().parse().ok()?,
+    })
 }
 
 #[test]
@@ -0,0 +1,88 @@
+//! The retry budget of `--max-total-retries`, shared by all runs and devices of a session, so a systematically
+//! broken config can not repeat its tries forever.
+use std::{
+    fmt::Display,
+    sync::{
+        OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use serde::Serialize;
+
+/// The budget of the session, unlimited if not set
+static MAX: OnceLock<usize> = OnceLock::new();
+
+/// Retries of the session so far
+static USED: AtomicUsize = AtomicUsize::new(0);
+
+/// How much of the retry budget a session used
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub(crate) struct RetryUsage {
+    used: usize,
+    max: Option<usize>,
+}
+
+impl Display for RetryUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.max {
+            Some(max) => write!(f, "{} of {max} retries used", self.used),
+            None => write!(f, "{} retries used", self.used),
+        }
+    }
+}
+
+impl RetryUsage {
+    /// Whether the usage tells anything, without a budget and retries there is nothing to report
+    pub(crate) fn is_relevant(&self) -> bool {
+        self.max.is_some() || self.used > 0
+    }
+}
+
+/// Sets the budget from `--max-total-retries`
+pub(crate) fn set_budget(max: Option<usize>) {
+    if let Some(max) = max {
+        let _ = MAX.set(max);
+    }
+}
+
+/// Takes one retry from `used` if it stays within `max`
+fn take_from(used: &AtomicUsize, max: Option<usize>) -> bool {
+    used.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+        max.is_none_or(|max| used < max).then_some(used + 1)
+    })
+    .is_ok()
+}
+
+/// Takes one retry from the budget of the session, `false` if it is used up
+pub(crate) fn take() -> bool {
+    take_from(&USED, MAX.get().copied())
+}
+
+/// How much of the budget the session used so far
+pub(crate) fn usage() -> RetryUsage {
+    RetryUsage {
+        used: USED.load(Ordering::SeqCst),
+        max: MAX.get().copied(),
+    }
+}
+
+#[test]
+fn test_take_from() {
+    let used = AtomicUsize::new(0);
+    assert!(take_from(&used, Some(2)));
+    assert!(take_from(&used, Some(2)));
+    assert!(!take_from(&used, Some(2)));
+    assert_eq!(used.load(Ordering::SeqCst), 2);
+    assert!(take_from(&used, None));
+    assert_eq!(
+        RetryUsage {
+            used: 2,
+            max: Some(5)
+        }
+        .to_string(),
+        "2 of 5 retries used"
+    );
+    assert!(!RetryUsage::default().is_relevant());
+    assert!(RetryUsage { used: 1, max: None }.is_relevant());
+}
@@ -1,12 +1,23 @@
-use std::{fmt::Display, fs::read_to_string, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{Context, Result, anyhow};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use yansi::Paint;
 
 use crate::{
     Filter, Trace,
-    args::{Args, RunArgs},
+    args::{Args, DuplicateMetrics, RunArgs},
+    bencher::sanitize_url,
+    expression::Expression,
+    filter::{ExpectedRange, Predicate},
     point_filters::PointFilter,
+    schema,
+    throughput::Throughput,
 };
 
 /// A RunConfig including the filters
@@ -19,6 +30,112 @@ pub(crate) struct RunConfig {
     pub(crate) filters: Vec<Filter>,
     /// Point filters
     pub(crate) point_filters: Vec<PointFilter>,
+    /// Name other runs can refer to in `depends_on`
+    pub(crate) name: Option<String>,
+    /// Runs with a higher priority run first
+    pub(crate) priority: i64,
+    /// Names of the runs that have to succeed before this one runs
+    pub(crate) depends_on: Vec<String>,
+    /// Metric extractor plugins, see `plugin.rs`
+    pub(crate) plugins: Vec<PathBuf>,
+    /// Rhai scripts computing custom metrics, see `metric_script.rs`
+    pub(crate) metric_scripts: Vec<PathBuf>,
+    /// Short name of the url in the bencher metric names, from the `url_alias` of the run file
+    pub(crate) url_alias: Option<String>,
+    /// Throughputs computed from a point filter and a filter, see `throughput.rs`
+    pub(crate) throughputs: Vec<Throughput>,
+    /// The archived trace of every filter that declared one, by filter name, for `check-filters`
+    pub(crate) reference_traces: BTreeMap<String, PathBuf>,
+    /// Names of the filters and point filters whose metrics have to be in the results
+    pub(crate) expected_metrics: Vec<String>,
+    /// What to do with metrics an earlier run config produced already, `--duplicate-metrics` if not set
+    pub(crate) merge_policy: Option<DuplicateMetrics>,
+}
+
+/// A filter as `--print-config` shows it, its matching is code
+#[derive(Serialize)]
+struct EffectiveFilter<'a> {
+    name: &'a str,
+    measure: &'a Option<String>,
+    expected_range: Option<ExpectedRange>,
+    reference_trace: Option<&'a PathBuf>,
+}
+
+/// A run as `--print-config` shows it
+#[derive(Serialize)]
+struct EffectiveRun<'a> {
+    name: &'a Option<String>,
+    priority: i64,
+    depends_on: &'a [String],
+    run_args: &'a RunArgs,
+    filters: Vec<EffectiveFilter<'a>>,
+    point_filters: &'a [PointFilter],
+    plugins: &'a [PathBuf],
+    metric_scripts: &'a [PathBuf],
+    url_alias: &'a Option<String>,
+    throughputs: &'a [Throughput],
+    expected_metrics: &'a [String],
+    merge_policy: Option<DuplicateMetrics>,
+}
+
+/// The configuration of a session after applying the host config, the run file and the command line
+#[derive(Serialize)]
+struct EffectiveConfig<'a> {
+    args: &'a Args,
+    runs: Vec<EffectiveRun<'a>>,
+}
+
+/// The json of the resolved configuration of a session for `--print-config`
+pub(crate) fn effective_config(args: &Args, run_configs: &[RunConfig]) -> Result<String> {
+    let config = EffectiveConfig {
+        args,
+        runs: run_configs
+            .iter()
+            .map(|r| EffectiveRun {
+                name: &r.name,
+                priority: r.priority,
+                depends_on: &r.depends_on,
+                run_args: &r.run_args,
+                filters: r
+                    .filters
+                    .iter()
+                    .map(|f| EffectiveFilter {
+                        name: &f.name,
+                        measure: &f.measure,
+                        expected_range: f.expected_range,
+                        reference_trace: r.reference_traces.get(&f.name),
+                    })
+                    .collect(),
+                point_filters: &r.point_filters,
+                plugins: &r.plugins,
+                metric_scripts: &r.metric_scripts,
+                url_alias: &r.url_alias,
+                throughputs: &r.throughputs,
+                expected_metrics: &r.expected_metrics,
+                merge_policy: r.merge_policy,
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&config).context("Could not serialize the configuration")
+}
+
+/// The state of a run during a session
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum RunState {
+    Pending,
+    Running,
+    Succeeded,
+    /// The run failed or was skipped because a dependency failed
+    Failed,
+}
+
+/// Whether a run can start given the states of all runs
+#[derive(Debug, PartialEq)]
+pub(crate) enum Dependencies<'a> {
+    Ready,
+    Waiting,
+    /// The named dependency failed
+    Failed(&'a str),
 }
 
 impl Display for RunConfig {
@@ -48,35 +165,190 @@ impl RunConfig {
             run_args,
             filters,
             point_filters,
+            name: None,
+            priority: 0,
+            depends_on: Vec::new(),
+            plugins: Vec::new(),
+            metric_scripts: Vec::new(),
+            url_alias: None,
+            throughputs: Vec::new(),
+            reference_traces: BTreeMap::new(),
+            expected_metrics: Vec::new(),
+            merge_policy: None,
+        }
+    }
+
+    /// Checks the dependencies of this run. `states` are the states of `run_configs` in the same order.
+    pub(crate) fn dependencies<'a>(
+        &'a self,
+        run_configs: &[RunConfig],
+        states: &[RunState],
+    ) -> Dependencies<'a> {
+        let mut dependencies = Dependencies::Ready;
+        for dependency in &self.depends_on {
+            let state = run_configs
+                .iter()
+                .zip(states)
+                .filter(|(r, _)| r.name.as_ref() == Some(dependency))
+                .map(|(_, state)| *state)
+                .next();
+            match state {
+                Some(RunState::Succeeded) => {}
+                Some(RunState::Failed) => return Dependencies::Failed(dependency),
+                _ => dependencies = Dependencies::Waiting,
+            }
         }
+        dependencies
     }
 }
 
 /// The json type to filter
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(crate) struct JsonFilterDescription {
     /// The name the filter should have
     name: String,
     /// We will match the start of the filter to contain this function name
-    start_fn_partial: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start_fn_partial: Option<String>,
     /// We will match the end of the filter to contain this function name
-    end_fn_partial: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end_fn_partial: Option<String>,
+    /// An expression matching the start instead of `start_fn_partial`, see `expression.rs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start: Option<String>,
+    /// An expression matching the end instead of `end_fn_partial`, see `expression.rs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end: Option<String>,
+    /// The bencher measure the result is reported under, i.e., `Latency`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    measure: Option<String>,
+    /// The plausible durations in milliseconds, i.e., `{"min": 50, "max": 60000}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expected_range: Option<ExpectedRange>,
+    /// An archived trace the filter has to match exactly once in, relative to the file of the filter,
+    /// see `check_filters.rs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reference_trace: Option<PathBuf>,
 }
 
-impl From<JsonFilterDescription> for Filter {
-    fn from(value: JsonFilterDescription) -> Self {
-        Filter {
-            name: value.name,
-            first: Box::new(move |trace: &Trace| trace.function.contains(&value.start_fn_partial)),
-            last: Box::new(move |trace: &Trace| trace.function.contains(&value.end_fn_partial)),
+impl JsonFilterDescription {
+    /// A filter from the trace containing `start_fn_partial` to the one containing `end_fn_partial`
+    pub(crate) fn between(
+        name: String,
+        start_fn_partial: String,
+        end_fn_partial: String,
+    ) -> JsonFilterDescription {
+        JsonFilterDescription {
+            name,
+            start_fn_partial: Some(start_fn_partial),
+            end_fn_partial: Some(end_fn_partial),
+            start: None,
+            end: None,
+            measure: None,
+            expected_range: None,
+            reference_trace: None,
         }
     }
+
+    /// A filter from the trace matching the expression `start` to the one matching `end`
+    pub(crate) fn between_expressions(
+        name: String,
+        start: String,
+        end: String,
+    ) -> JsonFilterDescription {
+        JsonFilterDescription {
+            name,
+            start_fn_partial: None,
+            end_fn_partial: None,
+            start: Some(start),
+            end: Some(end),
+            measure: None,
+            expected_range: None,
+            reference_trace: None,
+        }
+    }
+}
+
+/// Makes the reference traces of `filters` relative to `dir`, the directory of the file they are in
+fn resolve_reference_traces(filters: &mut [JsonFilterDescription], dir: &Path) {
+    for filter in filters {
+        filter.reference_trace = filter.reference_trace.take().map(|t| dir.join(t));
+    }
+}
+
+/// The predicate of a filter from either a substring or an expression
+fn predicate(
+    filter: &str,
+    partial: Option<String>,
+    expression: Option<String>,
+    which: &str,
+) -> Result<Box<dyn Predicate>> {
+    match (partial, expression) {
+        (Some(partial), None) => Ok(Box::new(move |trace: &Trace| {
+            trace.function.contains(&partial)
+        })),
+        (None, Some(expression)) => Ok(Box::new(
+            Expression::parse(&expression)
+                .with_context(|| format!("Invalid {which} of filter {filter}"))?,
+        )),
+        (Some(_), Some(_)) => Err(anyhow!(
+            "Filter {filter} has both {which}_fn_partial and {which}, use only one"
+        )),
+        (None, None) => Err(anyhow!(
+            "Filter {filter} needs {which}_fn_partial or {which}"
+        )),
+    }
+}
+
+impl TryFrom<JsonFilterDescription> for Filter {
+    type Error = anyhow::Error;
+
+    fn try_from(value: JsonFilterDescription) -> Result<Self> {
+        Ok(Filter {
+            first: predicate(&value.name, value.start_fn_partial, value.start, "start")?,
+            last: predicate(&value.name, value.end_fn_partial, value.end, "end")?,
+            name: value.name,
+            measure: value.measure,
+            expected_range: value.expected_range,
+        })
+    }
 }
 
 /// A RunConfig which we can read from a file
 /// because we need JsonFilterDescription instead of filters
 #[derive(Debug, Deserialize)]
 pub(crate) struct RunConfigJson {
+    /// Name other runs can refer to in `depends_on`
+    #[serde(default)]
+    pub(crate) name: Option<String>,
+    /// Runs with a higher priority run first, runs with the same priority in the order of the file
+    #[serde(default)]
+    pub(crate) priority: i64,
+    /// Names of runs that have to succeed before this one, otherwise this run is skipped
+    #[serde(default)]
+    pub(crate) depends_on: Vec<String>,
+    /// Filter set files whose filters and point filters come before the ones of this run, relative to the run file
+    #[serde(default)]
+    pub(crate) include: Vec<PathBuf>,
+    /// Filters and point filters of the `filter_library` of the run file, after the included ones
+    #[serde(default)]
+    pub(crate) use_filters: Vec<FilterReference>,
+    /// Shared libraries that extract metrics from the traces, relative to the run file, see `plugin.rs`
+    #[serde(default)]
+    pub(crate) plugins: Vec<PathBuf>,
+    /// Rhai scripts that compute metrics from the traces, relative to the run file, see `metric_script.rs`
+    #[serde(default)]
+    pub(crate) metric_scripts: Vec<PathBuf>,
+    /// Bytes of a point filter per second of a filter, see `throughput.rs`
+    #[serde(default)]
+    pub(crate) throughputs: Vec<Throughput>,
+    /// Names of filters and point filters the session fails without, i.e., after a log string was renamed
+    #[serde(default)]
+    pub(crate) expected_metrics: Vec<String>,
+    /// `separate`, `combine` or `error` for metrics that an earlier run produced already, i.e., when two runs
+    /// measure the same page from the cache and from the network
+    #[serde(default)]
+    pub(crate) merge_policy: Option<DuplicateMetrics>,
     pub(crate) run_args: RunArgs,
     #[serde(default)]
     pub(crate) filters: Vec<JsonFilterDescription>,
@@ -84,47 +356,311 @@ pub(crate) struct RunConfigJson {
     pub(crate) point_filters: Vec<PointFilter>,
 }
 
+/// Filters and point filters shared by several run files, which include it with `include`
+#[derive(Debug, Deserialize)]
+struct FilterSet {
+    /// Other filter sets whose filters come first, relative to this file
+    #[serde(default)]
+    include: Vec<PathBuf>,
+    #[serde(default)]
+    filters: Vec<JsonFilterDescription>,
+    #[serde(default)]
+    point_filters: Vec<PointFilter>,
+}
+
+/// A run file after migrating it to the current `schema_version`, see `schema.rs`
+#[derive(Debug, Deserialize)]
+struct RunFile {
+    /// Filters the runs share
+    #[serde(default)]
+    filter_library: FilterLibrary,
+    /// Short names of urls for the bencher metric names, i.e., `{"https://www.google.com": "google"}`.
+    /// Query, fragment and trailing slashes of the urls do not matter.
+    #[serde(default)]
+    url_alias: BTreeMap<String, String>,
+    runs: Vec<RunConfigJson>,
+}
+
+/// Named filters and point filters runs of the same file use with `use_filters`.
+/// `{url}` in their names and match strings is replaced by the url of the run using them.
+#[derive(Debug, Default, Deserialize)]
+struct FilterLibrary {
+    #[serde(default)]
+    filters: Vec<JsonFilterDescription>,
+    #[serde(default)]
+    point_filters: Vec<PointFilter>,
+}
+
+/// A filter or point filter of the library by name, i.e., `"Load"`, or with overrides, i.e.,
+/// `{"name": "Load", "suffix": " (cold)", "url": "servo.org"}`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum FilterReference {
+    Name(String),
+    WithOverrides {
+        name: String,
+        /// Appended to the name of the filter
+        #[serde(default)]
+        suffix: Option<String>,
+        /// Replaces `{url}` instead of the url of the run
+        #[serde(default)]
+        url: Option<String>,
+    },
+}
+
+impl FilterLibrary {
+    /// The filters and point filters `references` refer to for a run of `url`
+    fn resolve(
+        &self,
+        references: &[FilterReference],
+        url: &str,
+    ) -> Result<(Vec<JsonFilterDescription>, Vec<PointFilter>)> {
+        let mut filters = Vec::new();
+        let mut point_filters = Vec::new();
+        for reference in references {
+            let (name, suffix, url) = match reference {
+                FilterReference::Name(name) => (name, "", url),
+                FilterReference::WithOverrides {
+                    name,
+                    suffix,
+                    url: own_url,
+                } => (
+                    name,
+                    suffix.as_deref().unwrap_or_default(),
+                    own_url.as_deref().unwrap_or(url),
+                ),
+            };
+            let fill = |s: &str| s.replace("{url}", url);
+            if let Some(filter) = self.filters.iter().find(|f| &f.name == name) {
+                filters.push(JsonFilterDescription {
+                    name: fill(&filter.name) + suffix,
+                    start_fn_partial: filter.start_fn_partial.as_deref().map(fill),
+                    end_fn_partial: filter.end_fn_partial.as_deref().map(fill),
+                    start: filter.start.as_deref().map(fill),
+                    end: filter.end.as_deref().map(fill),
+                    measure: filter.measure.clone(),
+                    expected_range: filter.expected_range,
+                    reference_trace: filter.reference_trace.clone(),
+                });
+            } else if let Some(point_filter) = self.point_filters.iter().find(|f| &f.name == name) {
+                point_filters.push(PointFilter {
+                    name: fill(&point_filter.name) + suffix,
+                    match_str: fill(&point_filter.match_str),
+                    ..point_filter.clone()
+                });
+            } else {
+                return Err(anyhow!("There is no filter {name} in the filter library"));
+            }
+        }
+        Ok((filters, point_filters))
+    }
+}
+
+/// Deserializes json5 with the path of the element that failed in the error, see `deserialize_value`
+fn deserialize_json5<T: serde::de::DeserializeOwned>(
+    content: &str,
+    what: &str,
+    strict: bool,
+) -> Result<T> {
+    let value: serde_json::Value =
+        json5::from_str(content).with_context(|| format!("Could not read {what}"))?;
+    deserialize_value(value, what, strict)
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The warnings about run files of the current test
+    pub(crate) static WARNINGS: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Warns about a questionable run file. We read it before the logger is set up and bencher mode only logs errors,
+/// so this goes to stderr directly.
+fn config_warning(message: String) {
+    eprintln!("{} {message}", "Warning:".yellow());
+    #[cfg(test)]
+    WARNINGS.with_borrow_mut(|warnings| warnings.push(message));
+}
+
+/// Deserializes `value` with the path of the element that failed in the error.
+/// Unknown fields, i.e., typos, are an error with `strict` and a warning otherwise.
+fn deserialize_value<T: serde::de::DeserializeOwned>(
+    value: serde_json::Value,
+    what: &str,
+    strict: bool,
+) -> Result<T> {
+    let mut unknown = Vec::new();
+    let mut record = |path: serde_ignored::Path| unknown.push(path.to_string());
+    let deserializer = serde_ignored::Deserializer::new(value, &mut record);
+    let result = serde_path_to_error::deserialize(deserializer).map_err(|err| {
+        anyhow!(
+            "Could not decode {what}: error {:?}, path: {:?}",
+            err.inner(),
+            err.path().to_string()
+        )
+    })?;
+    if !unknown.is_empty() {
+        let unknown = unknown.join(", ");
+        if strict {
+            return Err(anyhow!("Unknown fields in {what}: {unknown}"));
+        }
+        config_warning(format!(
+            "Ignoring unknown fields in {what}, is there a typo? {unknown}"
+        ));
+    }
+    Ok(result)
+}
+
+/// Reads the filter sets `includes` relative to `dir` and everything they include in turn, in order.
+/// `stack` are the filter sets we are in to detect cycles.
+fn read_includes(
+    includes: &[PathBuf],
+    dir: &Path,
+    stack: &mut Vec<PathBuf>,
+    strict: bool,
+) -> Result<(Vec<JsonFilterDescription>, Vec<PointFilter>)> {
+    let mut filters = Vec::new();
+    let mut point_filters = Vec::new();
+    for include in includes {
+        let path = dir.join(include);
+        let canonical = path
+            .canonicalize()
+            .with_context(|| format!("Could not find included file {}", path.display()))?;
+        if stack.contains(&canonical) {
+            return Err(anyhow!("{} includes itself", path.display()));
+        }
+        let mut set: FilterSet = deserialize_json5(
+            &read_to_string(&path)?,
+            &format!("included file {}", path.display()),
+            strict,
+        )?;
+        stack.push(canonical);
+        let (included_filters, included_point_filters) = read_includes(
+            &set.include,
+            path.parent().unwrap_or(Path::new("")),
+            stack,
+            strict,
+        )?;
+        stack.pop();
+        resolve_reference_traces(&mut set.filters, path.parent().unwrap_or(Path::new("")));
+        filters.extend(included_filters);
+        filters.extend(set.filters);
+        point_filters.extend(included_point_filters);
+        point_filters.extend(set.point_filters);
+    }
+    Ok((filters, point_filters))
+}
+
 /// Uses `Args` and `RunConfigJson` to create a `RunConfig`
-pub(crate) fn into_run_config(args: Args, run_config_json: RunConfigJson) -> RunConfig {
-    RunConfig {
+pub(crate) fn into_run_config(args: Args, run_config_json: RunConfigJson) -> Result<RunConfig> {
+    let reference_traces = run_config_json
+        .filters
+        .iter()
+        .filter_map(|f| Some((f.name.clone(), f.reference_trace.clone()?)))
+        .collect();
+    for point_filter in &run_config_json.point_filters {
+        point_filter.check()?;
+    }
+    Ok(RunConfig {
         args,
         run_args: run_config_json.run_args,
         filters: run_config_json
             .filters
             .into_iter()
-            .map(|f| f.into())
-            .collect(),
+            .map(Filter::try_from)
+            .collect::<Result<_>>()?,
         point_filters: run_config_json.point_filters,
+        name: run_config_json.name,
+        priority: run_config_json.priority,
+        depends_on: run_config_json.depends_on,
+        plugins: run_config_json.plugins,
+        metric_scripts: run_config_json.metric_scripts,
+        url_alias: None,
+        throughputs: run_config_json.throughputs,
+        reference_traces,
+        expected_metrics: run_config_json.expected_metrics,
+        merge_policy: run_config_json.merge_policy,
+    })
+}
+
+/// Orders the runs by priority and checks that every dependency exists and runs before its dependents
+fn order_runs(mut run_configs: Vec<RunConfig>) -> Result<Vec<RunConfig>> {
+    // stable, so runs with the same priority stay in the order of the file
+    run_configs.sort_by_key(|r| std::cmp::Reverse(r.priority));
+    for (index, run_config) in run_configs.iter().enumerate() {
+        for dependency in &run_config.depends_on {
+            let position = run_configs
+                .iter()
+                .position(|r| r.name.as_ref() == Some(dependency))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Run {} depends on unknown run {dependency}",
+                        run_config.run_args.url
+                    )
+                })?;
+            if position >= index {
+                return Err(anyhow!(
+                    "Run {} depends on {dependency} which does not have a higher or equal priority and does not come earlier in the file",
+                    run_config.run_args.url
+                ));
+            }
+        }
     }
+    Ok(run_configs)
 }
 
 /// read a run file into runs.
 pub(crate) fn read_run_file(path: &PathBuf, args: &Args) -> Result<Vec<RunConfig>> {
     let file_content = read_to_string(path)?;
-    let jd = &mut json5::Deserializer::from_str(&file_content)
-        .context("Could not read runconfig file")?;
-
-    let result: Result<Vec<RunConfigJson>, _> = serde_path_to_error::deserialize(jd);
-    if let Err(err) = result {
-        let path = err.path().to_string();
-        Err(anyhow!(
-            "Could not decode runfile: error {:?}, path: {:?}",
-            err.inner(),
-            path
-        ))
-    } else {
-        let result = result.unwrap();
-        result
-            .into_iter()
-            .map(|r| {
-                if r.filters.is_empty() && r.point_filters.is_empty() {
-                    Err(anyhow!(
-                        "You did not specify a filter or pointfilter for at least one run."
-                    ))
-                } else {
-                    Ok(into_run_config(args.clone(), r))
-                }
-            })
-            .collect::<Result<Vec<RunConfig>>>()
+    let strict = args.strict_config;
+    let (value, version) = schema::upgrade(deserialize_json5(&file_content, "runfile", strict)?)?;
+    if version < schema::CURRENT_SCHEMA_VERSION {
+        config_warning(format!(
+            "{} has the old schema_version {version}, update it with `hitrace-bench migrate {}`",
+            path.display(),
+            path.display()
+        ));
     }
+    let RunFile {
+        filter_library: library,
+        url_alias,
+        runs,
+    } = deserialize_value(value, "runfile", strict)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    runs.into_iter()
+        .map(|mut r| {
+            let (mut filters, mut point_filters) =
+                read_includes(&r.include, dir, &mut Vec::new(), strict)?;
+            let (mut library_filters, library_point_filters) =
+                library.resolve(&r.use_filters, &r.run_args.url)?;
+            resolve_reference_traces(&mut library_filters, dir);
+            resolve_reference_traces(&mut r.filters, dir);
+            filters.extend(library_filters);
+            point_filters.extend(library_point_filters);
+            filters.append(&mut r.filters);
+            point_filters.append(&mut r.point_filters);
+            r.filters = filters;
+            r.point_filters = point_filters;
+            r.plugins = r.plugins.iter().map(|p| dir.join(p)).collect();
+            r.metric_scripts = r.metric_scripts.iter().map(|p| dir.join(p)).collect();
+            if r.filters.is_empty()
+                && r.point_filters.is_empty()
+                && r.plugins.is_empty()
+                && r.metric_scripts.is_empty()
+                && r.run_args.preset.is_none()
+            {
+                Err(anyhow!(
+                    "You did not specify a filter, pointfilter, plugin, metric script or preset for at least one run."
+                ))
+            } else {
+                let alias = url_alias
+                    .iter()
+                    .find(|(url, _)| sanitize_url(url) == sanitize_url(&r.run_args.url))
+                    .map(|(_, alias)| alias.clone());
+                let mut run_config = into_run_config(args.clone(), r)?;
+                run_config.url_alias = alias;
+                Ok(run_config)
+            }
+        })
+        .collect::<Result<Vec<RunConfig>>>()
+        .and_then(order_runs)
 }
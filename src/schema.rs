@@ -0,0 +1,128 @@
+//! Versions of the run file format and the migrations between them.
+//!
+//! - Version 1: point filters summed up their points with `"combined": true`
+//! - Version 2: point filters have a `point_filter_type`, the file can be an object with `schema_version`,
+//!   `filter_library` and `runs`
+//!
+//! Files without `schema_version` are version 1 if a point filter still has `combined` and version 2 otherwise.
+use std::fs;
+
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Map, Value, json};
+
+use crate::args::MigrateArgs;
+
+/// The version of the run files we write and read without migrating
+pub(crate) const CURRENT_SCHEMA_VERSION: u64 = 2;
+
+/// The point filters of all runs and of the filter library of `file`
+fn point_filters(file: &mut Map<String, Value>) -> impl Iterator<Item = &mut Map<String, Value>> {
+    file.iter_mut()
+        .flat_map(|(key, value)| -> Vec<&mut Vec<Value>> {
+            match key.as_str() {
+                "filter_library" => value
+                    .get_mut("point_filters")
+                    .and_then(Value::as_array_mut)
+                    .into_iter()
+                    .collect(),
+                "runs" => value
+                    .as_array_mut()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|run| run.get_mut("point_filters").and_then(Value::as_array_mut))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        })
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
+/// The version of `file`, see the module documentation
+fn version(file: &mut Map<String, Value>) -> Result<u64> {
+    match file.get("schema_version").cloned() {
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| anyhow!("schema_version {version} is not a number")),
+        None if point_filters(file).any(|f| f.contains_key("combined")) => Ok(1),
+        None => Ok(CURRENT_SCHEMA_VERSION),
+    }
+}
+
+/// `combined` became `point_filter_type`
+fn migrate_1_to_2(file: &mut Map<String, Value>) {
+    for point_filter in point_filters(file) {
+        if let Some(combined) = point_filter.remove("combined")
+            && combined == Value::Bool(true)
+            && !point_filter.contains_key("point_filter_type")
+        {
+            point_filter.insert(String::from("point_filter_type"), json!("Combined"));
+        }
+    }
+}
+
+/// Migrates a run file of any version to the current one, as an object without `schema_version`.
+/// Returns the run file and the version it had.
+pub(crate) fn upgrade(run_file: Value) -> Result<(Value, u64)> {
+    let mut file = match run_file {
+        Value::Array(runs) => Map::from_iter([(String::from("runs"), Value::Array(runs))]),
+        Value::Object(file) => file,
+        _ => return Err(anyhow!("A run file has to be an array or an object")),
+    };
+    let original = version(&mut file)?;
+    file.remove("schema_version");
+    if original > CURRENT_SCHEMA_VERSION {
+        return Err(anyhow!(
+            "The run file has schema_version {original}, we only know up to {CURRENT_SCHEMA_VERSION}"
+        ));
+    }
+    if original < 2 {
+        migrate_1_to_2(&mut file);
+    }
+    Ok((Value::Object(file), original))
+}
+
+/// Rewrites the run file of `args` in the current version. Comments are lost.
+pub(crate) fn migrate_file(args: &MigrateArgs) -> Result<()> {
+    let content = fs::read_to_string(&args.run_file)
+        .with_context(|| format!("Could not read {}", args.run_file.display()))?;
+    let run_file: Value = json5::from_str(&content).context("Could not read runfile")?;
+    let (mut run_file, original) = upgrade(run_file)?;
+    run_file["schema_version"] = json!(CURRENT_SCHEMA_VERSION);
+    let output = args.output.as_ref().unwrap_or(&args.run_file);
+    fs::write(
+        output,
+        serde_json::to_string_pretty(&run_file).context("Could not serialize runfile")? + "\n",
+    )
+    .with_context(|| format!("Could not write {}", output.display()))?;
+    println!(
+        "Migrated {} from schema_version {original} to {CURRENT_SCHEMA_VERSION}",
+        output.display()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_upgrade() {
+    let (file, original) = upgrade(json!([
+        {"run_args": {}, "point_filters": [
+            {"name": "smaps", "match_str": "smaps", "combined": true},
+            {"name": "JS", "match_str": "js", "combined": false},
+        ]}
+    ]))
+    .unwrap();
+    assert_eq!(original, 1);
+    assert_eq!(
+        file["runs"][0]["point_filters"],
+        json!([
+            {"name": "smaps", "match_str": "smaps", "point_filter_type": "Combined"},
+            {"name": "JS", "match_str": "js"},
+        ])
+    );
+
+    assert_eq!(
+        upgrade(json!({"schema_version": 2, "runs": []})).unwrap(),
+        (json!({"runs": []}), 2)
+    );
+    assert!(upgrade(json!({"schema_version": 3, "runs": []})).is_err());
+}
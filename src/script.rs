@@ -0,0 +1,367 @@
+//! Interactions with the app after it started, i.e., scrolling a page to measure jank.
+use std::{fmt::Display, str::FromStr, thread, time::Duration};
+
+use anyhow::{Context, Result, anyhow};
+use log::info;
+use serde::{Deserialize, Deserializer};
+
+use crate::device;
+
+/// How long `wait-for-marker` waits if the step does not say otherwise
+const DEFAULT_MARKER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often `wait-for-marker` looks at the trace
+const MARKER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The trace `input-tap` writes right before tapping, the input to paint latency starts here
+pub(crate) const INPUT_MARKER: &str = "hitrace-bench:input";
+
+/// The trace `reload` and `open` write right before navigating, followed by the label of the navigation
+pub(crate) const NAVIGATION_MARKER: &str = "hitrace-bench:navigation";
+
+/// Writing to this file adds a trace to the running hitrace session
+const TRACE_MARKER_FILE: &str = "/sys/kernel/debug/tracing/trace_marker";
+
+/// A single step of a script, written like `tap 360 800` on the command line and in the run file
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Step {
+    /// `tap X Y`
+    Tap { x: u32, y: u32 },
+    /// `swipe X1 Y1 X2 Y2 [MS]`, the swipe takes `duration` if given
+    Swipe {
+        from: (u32, u32),
+        to: (u32, u32),
+        duration: Option<Duration>,
+    },
+    /// `input-tap X Y`, a tap after writing `INPUT_MARKER` to the trace to measure the input to paint latency
+    InputTap { x: u32, y: u32 },
+    /// `key CODE`, the code or name of a key as `uitest uiInput keyEvent` takes it, i.e., `Back` or `2012`
+    Key(String),
+    /// `wait MS`
+    Wait(Duration),
+    /// `wait-for-marker MARKER [TIMEOUT_MS [COUNT]]`, waits until `COUNT` traces, 1 by default, contain `MARKER`
+    WaitForMarker {
+        marker: String,
+        timeout: Duration,
+        count: u32,
+    },
+    /// `reload`, opens the url of the run again in the running app
+    Reload,
+    /// `open URL`, opens another page in the running app. The app decides if it gets its own webview.
+    Open(String),
+}
+
+/// What steps need to know about the app under test
+pub(crate) struct App<'a> {
+    pub(crate) bundle_name: &'a str,
+    /// The url as the app gets it, i.e., the path of a pushed file
+    pub(crate) url: &'a str,
+}
+
+/// The shell command writing `trace` to the running hitrace session
+fn write_trace_command(trace: &str) -> String {
+    format!("echo 'B|'$$'|H:{trace}' > {TRACE_MARKER_FILE}")
+}
+
+impl FromStr for Step {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let action = words.next().ok_or_else(|| anyhow!("Empty script step"))?;
+        let args: Vec<&str> = words.collect();
+        let number = |i: usize| -> Result<u32> {
+            args.get(i)
+                .with_context(|| format!("Script step `{s}` is missing an argument"))?
+                .parse()
+                .with_context(|| format!("Argument {} of script step `{s}` is not a number", i + 1))
+        };
+        let millis = |i: usize| number(i).map(|ms| Duration::from_millis(ms.into()));
+        let (step, expected) = match action {
+            "tap" => (
+                Step::Tap {
+                    x: number(0)?,
+                    y: number(1)?,
+                },
+                2..=2,
+            ),
+            "input-tap" => (
+                Step::InputTap {
+                    x: number(0)?,
+                    y: number(1)?,
+                },
+                2..=2,
+            ),
+            "swipe" => (
+                Step::Swipe {
+                    from: (number(0)?, number(1)?),
+                    to: (number(2)?, number(3)?),
+                    duration: if args.len() > 4 {
+                        Some(millis(4)?)
+                    } else {
+                        None
+                    },
+                },
+                4..=5,
+            ),
+            "key" => (
+                Step::Key(
+                    args.first()
+                        .with_context(|| format!("Script step `{s}` is missing the key"))?
+                        .to_string(),
+                ),
+                1..=1,
+            ),
+            "wait" => (Step::Wait(millis(0)?), 1..=1),
+            "wait-for-marker" => (
+                Step::WaitForMarker {
+                    marker: args
+                        .first()
+                        .with_context(|| format!("Script step `{s}` is missing the marker"))?
+                        .to_string(),
+                    timeout: if args.len() > 1 {
+                        millis(1)?
+                    } else {
+                        DEFAULT_MARKER_TIMEOUT
+                    },
+                    count: if args.len() > 2 { number(2)? } else { 1 },
+                },
+                1..=3,
+            ),
+            "reload" => (Step::Reload, 0..=0),
+            "open" => (
+                Step::Open(
+                    args.first()
+                        .with_context(|| format!("Script step `{s}` is missing the url"))?
+                        .to_string(),
+                ),
+                1..=1,
+            ),
+            _ => return Err(anyhow!("Unknown script step `{action}`")),
+        };
+        if !expected.contains(&args.len()) {
+            return Err(anyhow!("Script step `{s}` has too many arguments"));
+        }
+        Ok(step)
+    }
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Tap { x, y } => write!(f, "tap {x} {y}"),
+            Step::InputTap { x, y } => write!(f, "input-tap {x} {y}"),
+            Step::Swipe { from, to, duration } => {
+                write!(f, "swipe {} {} {} {}", from.0, from.1, to.0, to.1)?;
+                if let Some(duration) = duration {
+                    write!(f, " {}", duration.as_millis())?;
+                }
+                Ok(())
+            }
+            Step::Key(key) => write!(f, "key {key}"),
+            Step::Wait(duration) => write!(f, "wait {}", duration.as_millis()),
+            Step::WaitForMarker {
+                marker,
+                timeout,
+                count,
+            } => {
+                write!(f, "wait-for-marker {marker} {}", timeout.as_millis())?;
+                if *count != 1 {
+                    write!(f, " {count}")?;
+                }
+                Ok(())
+            }
+            Step::Reload => write!(f, "reload"),
+            Step::Open(url) => write!(f, "open {url}"),
+        }
+    }
+}
+
+/// The shell command writing the navigation marker with `label` and opening `url` in the running app
+fn navigate_command(bundle_name: &str, url: &str, label: &str) -> String {
+    format!(
+        "{}; aa start -a EntryAbility -b {bundle_name} -U '{url}'",
+        write_trace_command(&format!("{NAVIGATION_MARKER} {label}"))
+    )
+}
+
+impl Step {
+    /// The arguments of `hdc shell` for steps that are a single command on the device
+    fn shell_args(&self, app: &App) -> Option<Vec<String>> {
+        let args = match self {
+            Step::Tap { x, y } => vec![
+                String::from("uinput"),
+                String::from("-T"),
+                String::from("-c"),
+                x.to_string(),
+                y.to_string(),
+            ],
+            // One shell command, so nothing runs between the marker and the tap
+            Step::InputTap { x, y } => vec![format!(
+                "{}; uinput -T -c {x} {y}",
+                write_trace_command(INPUT_MARKER)
+            )],
+            Step::Reload => vec![navigate_command(app.bundle_name, app.url, "reload")],
+            Step::Open(url) => vec![navigate_command(app.bundle_name, url, url)],
+            Step::Swipe { from, to, duration } => {
+                let mut args = vec![
+                    String::from("uinput"),
+                    String::from("-T"),
+                    String::from("-m"),
+                    from.0.to_string(),
+                    from.1.to_string(),
+                    to.0.to_string(),
+                    to.1.to_string(),
+                ];
+                if let Some(duration) = duration {
+                    args.push(duration.as_millis().to_string());
+                }
+                args
+            }
+            Step::Key(key) => vec![
+                String::from("uitest"),
+                String::from("uiInput"),
+                String::from("keyEvent"),
+                key.clone(),
+            ],
+            Step::Wait(_) | Step::WaitForMarker { .. } => return None,
+        };
+        Some(args)
+    }
+}
+
+/// Reads the `script` list of the run file, every step is a string as on the command line
+pub(crate) fn deserialize_script<'de, D>(deserializer: D) -> Result<Vec<Step>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| Step::from_str(s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// Waits until `count` lines of the trace that is currently recorded contain `marker`
+fn wait_for_marker(
+    device: Option<&str>,
+    marker: &str,
+    timeout: Duration,
+    count: u32,
+) -> Result<()> {
+    let start = std::time::Instant::now();
+    loop {
+        let dump = device::hdc_output(device, &["shell", "hitrace", "--trace_dump"])?;
+        if dump.lines().filter(|l| l.contains(marker)).count() >= count as usize {
+            return Ok(());
+        }
+        if start.elapsed() > timeout {
+            return Err(anyhow!(
+                "Marker {marker} did not show up within {timeout:?}"
+            ));
+        }
+        thread::sleep(MARKER_POLL_INTERVAL);
+    }
+}
+
+/// Runs all steps in order on the device while it is tracing
+pub(crate) fn run(device: Option<&str>, script: &[Step], app: &App) -> Result<()> {
+    for step in script {
+        info!("Script step {step}");
+        match step {
+            Step::Wait(duration) => thread::sleep(*duration),
+            Step::WaitForMarker {
+                marker,
+                timeout,
+                count,
+            } => wait_for_marker(device, marker, *timeout, *count)?,
+            step => {
+                let args = step.shell_args(app).expect("Step is a device command");
+                let args: Vec<&str> = ["shell"]
+                    .into_iter()
+                    .chain(args.iter().map(String::as_str))
+                    .collect();
+                device::hdc_output(device, &args)
+                    .with_context(|| format!("Could not run script step `{step}`"))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_steps() {
+    let app = App {
+        bundle_name: "org.servo.servo",
+        url: "https://servo.org",
+    };
+    assert_eq!(
+        Step::from_str("tap 360 800").unwrap(),
+        Step::Tap { x: 360, y: 800 }
+    );
+    assert_eq!(
+        Step::from_str("swipe 300 1500 300 300 400").unwrap(),
+        Step::Swipe {
+            from: (300, 1500),
+            to: (300, 300),
+            duration: Some(Duration::from_millis(400))
+        }
+    );
+    assert_eq!(
+        Step::from_str("wait-for-marker LCP").unwrap(),
+        Step::WaitForMarker {
+            marker: String::from("LCP"),
+            timeout: DEFAULT_MARKER_TIMEOUT,
+            count: 1
+        }
+    );
+    assert_eq!(
+        Step::from_str("input-tap 1 2")
+            .unwrap()
+            .shell_args(&app)
+            .unwrap(),
+        [
+            "echo 'B|'$$'|H:hitrace-bench:input' > /sys/kernel/debug/tracing/trace_marker; uinput -T -c 1 2"
+        ]
+    );
+    for s in [
+        "tap 1 2",
+        "input-tap 3 4",
+        "swipe 1 2 3 4",
+        "key Back",
+        "wait 200",
+        "wait-for-marker LCP 5000",
+        "wait-for-marker LCP 5000 3",
+        "reload",
+        "open https://servo.org/blog",
+    ] {
+        assert_eq!(Step::from_str(s).unwrap().to_string(), s);
+    }
+    assert_eq!(
+        Step::from_str("swipe 300 1500 300 300 400")
+            .unwrap()
+            .shell_args(&app)
+            .unwrap()
+            .join(" "),
+        "uinput -T -m 300 1500 300 300 400"
+    );
+    assert_eq!(
+        Step::from_str("key Back")
+            .unwrap()
+            .shell_args(&app)
+            .unwrap()
+            .join(" "),
+        "uitest uiInput keyEvent Back"
+    );
+    assert_eq!(
+        Step::Reload.shell_args(&app).unwrap(),
+        [
+            "echo 'B|'$$'|H:hitrace-bench:navigation reload' > /sys/kernel/debug/tracing/trace_marker; aa start -a EntryAbility -b org.servo.servo -U 'https://servo.org'"
+        ]
+    );
+    assert!(Step::from_str("reload 1").is_err());
+    assert!(Step::from_str("open").is_err());
+    assert!(Step::from_str("tap 1").is_err());
+    assert!(Step::from_str("tap 1 2 3").is_err());
+    assert!(Step::from_str("tap x 2").is_err());
+    assert!(Step::from_str("pinch 1 2").is_err());
+}
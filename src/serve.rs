@@ -0,0 +1,131 @@
+//! A minimal HTTP server serving local test pages to the device, which reaches it through a reverse port forward
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+    thread,
+};
+
+use anyhow::{Context, Result};
+use log::{error, info};
+
+/// Ports of the servers we started by the directory they serve. They run until we exit.
+static SERVERS: Mutex<Option<HashMap<PathBuf, u16>>> = Mutex::new(None);
+
+/// The port of the server for `dir`, starting it if there is none yet
+pub(crate) fn serve(dir: &Path) -> Result<u16> {
+    let mut servers = SERVERS.lock().unwrap();
+    let servers = servers.get_or_insert_default();
+    if let Some(port) = servers.get(dir) {
+        return Ok(*port);
+    }
+    let listener = TcpListener::bind("127.0.0.1:0").context("Could not start http server")?;
+    let port = listener.local_addr()?.port();
+    info!("Serving {dir:?} on port {port}");
+    let root = dir.to_owned();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(e) = handle_connection(&root, stream) {
+                error!("Could not serve request: {e:?}");
+            }
+        }
+    });
+    servers.insert(dir.to_owned(), port);
+    Ok(port)
+}
+
+/// The file below `root` a request for `target` is for. We refuse anything outside of `root`.
+fn resolve(root: &Path, target: &str) -> Option<PathBuf> {
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    let mut resolved = root.to_owned();
+    for component in Path::new(path.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(c) => resolved.push(c),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    if resolved.is_dir() {
+        resolved.push("index.html");
+    }
+    Some(resolved)
+}
+
+/// Content type of a file by its extension
+fn content_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+    {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Answers a single GET request and closes the connection
+fn handle_connection(root: &Path, mut stream: TcpStream) -> Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let file = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => resolve(root, target),
+        _ => None,
+    };
+    let response = file.and_then(|f| std::fs::read(&f).ok().map(|body| (f, body)));
+    match response {
+        Some((file, body)) => {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                content_type(&file),
+                body.len()
+            )?;
+            stream.write_all(&body)?;
+        }
+        None => {
+            stream.write_all(
+                b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_serve() {
+    use std::io::Read;
+
+    let dir = std::env::temp_dir().join(format!("hitrace-bench-serve-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.html"), "<html>").unwrap();
+    assert_eq!(resolve(&dir, "/"), Some(dir.join("index.html")));
+    assert_eq!(resolve(&dir, "/a/../b"), None);
+
+    let port = serve(&dir).unwrap();
+    assert_eq!(serve(&dir).unwrap(), port);
+    let get = |target: &str| {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(stream, "GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    let response = get("/index.html?cache=no");
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("Content-Type: text/html"));
+    assert!(response.ends_with("\r\n\r\n<html>"));
+    assert!(get("/missing.html").starts_with("HTTP/1.1 404"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
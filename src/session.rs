@@ -0,0 +1,208 @@
+//! A summary of a session comparing the same metric across all run configs side by side
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Serialize, Serializer};
+use time::Duration;
+
+use crate::{
+    runconfig::RunConfig,
+    units,
+    utils::{RunResults, avg_min_max},
+};
+
+/// The average of a metric in one run config
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Cell {
+    Duration(Duration),
+    Bytes(i64),
+    Number(i64),
+}
+
+/// In json durations are in nanoseconds
+impl Serialize for Cell {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Cell::Duration(d) => serializer.serialize_i128(d.whole_nanoseconds()),
+            Cell::Bytes(n) | Cell::Number(n) => serializer.serialize_i64(*n),
+        }
+    }
+}
+
+impl Cell {
+    /// Human readable value of the metric `key` for the terminal
+    pub(crate) fn display(&self, key: &str) -> String {
+        match self {
+            Cell::Duration(d) => units::format_duration(key, *d),
+            Cell::Bytes(b) => units::format_memory(key, *b),
+            Cell::Number(n) => n.to_string(),
+        }
+    }
+
+    /// The value to compare cells by, durations are in seconds
+    pub(crate) fn value(&self) -> f64 {
+        match self {
+            Cell::Duration(d) => d.as_seconds_f64(),
+            Cell::Bytes(n) | Cell::Number(n) => *n as f64,
+        }
+    }
+
+    /// Raw value for the csv, durations are in nanoseconds
+    fn raw(&self) -> String {
+        match self {
+            Cell::Duration(d) => d.whole_nanoseconds().to_string(),
+            Cell::Bytes(n) | Cell::Number(n) => n.to_string(),
+        }
+    }
+}
+
+/// The average of every metric of `results` by its key
+pub(crate) fn average_cells(results: &RunResults) -> impl Iterator<Item = (&String, Cell)> {
+    let filter_cells = results.filter_results.iter().map(|(key, durations)| {
+        let avg = avg_min_max::<Duration, u16>(durations).avg;
+        (key, Cell::Duration(avg))
+    });
+    let point_cells = results.point_results.iter().map(|(key, points)| {
+        let avg = avg_min_max::<i64, i64>(&points.result).avg;
+        let cell = if points.no_unit_conversion {
+            Cell::Number(avg)
+        } else {
+            Cell::Bytes(avg)
+        };
+        (key, cell)
+    });
+    filter_cells.chain(point_cells)
+}
+
+/// Rows are metrics without the url of the run, columns are run configs
+#[derive(Debug, Default)]
+pub(crate) struct SessionTable {
+    columns: Vec<String>,
+    rows: BTreeMap<String, Vec<Option<Cell>>>,
+}
+
+impl SessionTable {
+    /// Adds the results of one run config as a new column
+    pub(crate) fn add(&mut self, run_config: &RunConfig, results: &RunResults) {
+        let mut column = run_config
+            .name
+            .clone()
+            .unwrap_or_else(|| run_config.run_args.url.clone());
+        if self.columns.contains(&column) {
+            column = format!("{column} ({})", self.columns.len() + 1);
+        }
+        self.columns.push(column);
+        let index = self.columns.len() - 1;
+
+        let prefix = format!("{}/", run_config.run_args.url);
+        let metric = |key: &str| key.strip_prefix(&prefix).unwrap_or(key).to_owned();
+        for (key, cell) in average_cells(results) {
+            let row = self.rows.entry(metric(key)).or_default();
+            row.resize(index + 1, None);
+            row[index] = Some(cell);
+        }
+    }
+
+    /// Cells of a row, one for every column
+    fn cells<'a>(&'a self, row: &'a [Option<Cell>]) -> impl Iterator<Item = Option<Cell>> + 'a {
+        (0..self.columns.len()).map(|i| row.get(i).copied().flatten())
+    }
+
+    /// Prints the table with aligned columns
+    pub(crate) fn print(&self) {
+        let lines: Vec<Vec<String>> = std::iter::once(
+            std::iter::once(String::from("metric"))
+                .chain(self.columns.iter().cloned())
+                .collect(),
+        )
+        .chain(self.rows.iter().map(|(metric, row)| {
+            std::iter::once(metric.clone())
+                .chain(self.cells(row).map(|c| {
+                    c.map(|c| c.display(metric))
+                        .unwrap_or_else(|| String::from("-"))
+                }))
+                .collect()
+        }))
+        .collect();
+        let widths: Vec<usize> = (0..=self.columns.len())
+            .map(|i| lines.iter().map(|l| l[i].len()).max().unwrap_or(0))
+            .collect();
+        println!("-----------Session summary-------------------------");
+        for line in lines {
+            let line: Vec<String> = line
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect();
+            println!("{}", line.join(" | ").trim_end());
+        }
+        println!();
+    }
+
+    /// The table as csv, durations in nanoseconds and memory in bytes
+    fn to_csv(&self) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+        let mut csv = std::iter::once(String::from("metric"))
+            .chain(self.columns.iter().map(|c| quote(c)))
+            .collect::<Vec<_>>()
+            .join(",");
+        csv.push('\n');
+        for (metric, row) in &self.rows {
+            let line: Vec<String> = std::iter::once(quote(metric))
+                .chain(
+                    self.cells(row)
+                        .map(|c| c.map(|c| c.raw()).unwrap_or_default()),
+                )
+                .collect();
+            csv.push_str(&line.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Writes the table as csv to `path`
+    pub(crate) fn write_csv(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_csv()).context("Could not write session summary")
+    }
+}
+
+#[test]
+fn test_session_table() {
+    use crate::{args::RunArgs, utils::PointResult};
+
+    let args = crate::args::Args::test_default(std::path::PathBuf::new());
+    let run_config = |url: &str| {
+        RunConfig::new(
+            args.clone(),
+            RunArgs {
+                url: String::from(url),
+                ..Default::default()
+            },
+            vec![],
+            vec![],
+        )
+    };
+    let results = |url: &str, value: i64| {
+        let mut results = RunResults::default();
+        results.point_results.insert(
+            format!("{url}/Resident"),
+            PointResult {
+                no_unit_conversion: true,
+                result: vec![value],
+            },
+        );
+        results
+    };
+    let mut table = SessionTable::default();
+    table.add(&run_config("a.org"), &results("a.org", 1));
+    table.add(&run_config("b.org"), &results("b.org", 2));
+    let mut only_b = RunResults::default();
+    only_b
+        .filter_results
+        .insert(String::from("Load"), vec![Duration::nanoseconds(5)]);
+    table.add(&run_config("b.org"), &only_b);
+    assert_eq!(
+        table.to_csv(),
+        "metric,\"a.org\",\"b.org\",\"b.org (3)\"\n\"Load\",,,5\n\"Resident\",1,2,\n"
+    );
+}
@@ -0,0 +1,211 @@
+//! Cleaning up the devices and saving what we have when the user interrupts a session.
+//!
+//! On SIGINT or SIGTERM we stop right away unless a try is pulling its trace from a device. Then we let the try
+//! finish so its results end up in the partial report. Interrupting a second time stops right away.
+use std::{
+    fs,
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
+
+use anyhow::{Context, Result};
+use log::error;
+use serde::Serialize;
+use time::Duration;
+
+use crate::{
+    bencher, device, lock, units,
+    utils::{RunResults, avg_min_max},
+};
+
+/// Where we write the results of an interrupted session
+const PARTIAL_RESULTS_PATH: &str = "bench.partial.json";
+
+/// What we need to clean up and report when the session is interrupted
+#[derive(Debug, Default)]
+struct Session {
+    bundle_names: Vec<String>,
+    trace_buffer: u64,
+    /// All tries of the session that finished so far
+    results: RunResults,
+    tries: usize,
+}
+
+impl Session {
+    /// Adds the results of a finished try
+    fn record_try(&mut self, results: &RunResults) {
+        self.results.merge(results.clone());
+        self.tries += 1;
+    }
+}
+
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+
+/// Set once the user asked us to stop
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Set once we started cleaning up so we only do it once
+static FINISHING: AtomicBool = AtomicBool::new(false);
+
+/// Number of tries currently pulling their trace from a device
+static PULLING: AtomicUsize = AtomicUsize::new(0);
+
+/// The partial report we write to disk, the results are in bencher format
+#[derive(Debug, Serialize)]
+struct PartialReport {
+    partial: bool,
+    completed_tries: usize,
+    results: serde_json::Value,
+}
+
+/// Starts collecting the tries of a new session
+pub(crate) fn start_session(prepend: Option<String>, bundle_names: Vec<String>, trace_buffer: u64) {
+    *SESSION.lock().unwrap() = Some(Session {
+        bundle_names,
+        trace_buffer,
+        results: RunResults {
+            prepend,
+            ..Default::default()
+        },
+        tries: 0,
+    });
+}
+
+/// Called from the signal handler
+pub(crate) fn request() {
+    // The second time the user does not want to wait anymore
+    if REQUESTED.swap(true, Ordering::SeqCst) || PULLING.load(Ordering::SeqCst) == 0 {
+        finish();
+    } else {
+        println!(
+            "Stopping after the trace was pulled from the device. Interrupt again to stop right away."
+        );
+    }
+}
+
+/// A try pulling its trace. We let it finish before stopping the session, so drop this after `record_try`.
+#[derive(Debug)]
+pub(crate) struct TracePull(());
+
+/// Marks that a try started pulling its trace
+pub(crate) fn begin_trace_pull() -> TracePull {
+    PULLING.fetch_add(1, Ordering::SeqCst);
+    TracePull(())
+}
+
+impl Drop for TracePull {
+    /// Stops the session if the user asked for it while this was the last try pulling its trace
+    fn drop(&mut self) {
+        if PULLING.fetch_sub(1, Ordering::SeqCst) == 1 && REQUESTED.load(Ordering::SeqCst) {
+            finish();
+        }
+    }
+}
+
+/// Adds the results of a finished try. Does nothing outside of a session, i.e., in tests.
+pub(crate) fn record_try(results: &RunResults) {
+    if let Some(session) = SESSION.lock().unwrap().as_mut() {
+        session.record_try(results);
+    }
+}
+
+/// Writes the tries that finished so far to `path`
+fn write_partial_report(session: &Session, path: &Path) -> Result<()> {
+    let report = PartialReport {
+        partial: true,
+        completed_tries: session.tries,
+        results: bencher::generate_result_json_value(&session.results, false)?,
+    };
+    fs::write(
+        path,
+        serde_json::to_string_pretty(&report).context("Could not serialize results")?,
+    )
+    .context("Could not write partial results")
+}
+
+/// Prints the average of every metric of the tries that finished so far
+fn print_partial_report(session: &Session) {
+    println!(
+        "----Partial results, interrupted after {} tries------------------------",
+        session.tries
+    );
+    let mut lines: Vec<String> = session
+        .results
+        .filter_results
+        .iter()
+        .map(|(key, durations)| {
+            let avg_min_max = avg_min_max::<Duration, u16>(durations);
+            format!(
+                "{key}: {} ({} runs)",
+                units::format_duration(key, avg_min_max.avg),
+                avg_min_max.number
+            )
+        })
+        .chain(session.results.point_results.iter().map(|(key, points)| {
+            let avg_min_max = avg_min_max::<i64, i64>(&points.result);
+            if points.no_unit_conversion {
+                format!("{key}: {} ({} runs)", avg_min_max.avg, avg_min_max.number)
+            } else {
+                format!(
+                    "{key}: {} ({} runs)",
+                    units::format_memory(key, avg_min_max.avg),
+                    avg_min_max.number
+                )
+            }
+        }))
+        .collect();
+    lines.sort();
+    for line in lines {
+        println!("{line}");
+    }
+}
+
+/// Leaves all devices as we found them, reports the partial results and exits
+fn finish() {
+    if FINISHING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    // A try might still hold the lock, we do not want to wait on it forever
+    let session = match SESSION.try_lock() {
+        Ok(mut session) => session.take().unwrap_or_default(),
+        Err(_) => Session::default(),
+    };
+    let devices = device::list_devices().unwrap_or_default();
+    for device in &devices {
+        if let Err(e) = device::cleanup(Some(device), &session.bundle_names, session.trace_buffer) {
+            error!("Could not clean up {device}: {e:?}");
+        }
+    }
+    device::remove_host_traces();
+    lock::release_all(&devices);
+    print_partial_report(&session);
+    match write_partial_report(&session, Path::new(PARTIAL_RESULTS_PATH)) {
+        Ok(()) => println!("Wrote partial results to {PARTIAL_RESULTS_PATH}"),
+        Err(e) => error!("{e:?}"),
+    }
+    std::process::exit(130)
+}
+
+#[test]
+fn test_partial_report() {
+    let mut results = RunResults::default();
+    results
+        .filter_results
+        .insert(String::from("Load"), vec![Duration::nanoseconds(5)]);
+    // Not the global session, tests running in parallel record their tries into it
+    let mut session = Session::default();
+    session.record_try(&results);
+    session.record_try(&results);
+    let path =
+        std::env::temp_dir().join(format!("hitrace-bench-partial-{}.json", std::process::id()));
+    write_partial_report(&session, &path).unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(json["partial"], true);
+    assert_eq!(json["completed_tries"], 2);
+    assert_eq!(json["results"]["E2E/Load"]["Latency"]["value"], 5.0);
+}
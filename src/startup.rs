@@ -0,0 +1,140 @@
+//! The startup time and the time to the first frame of any app from the ability lifecycle markers of the OS and the
+//! frames RenderService consumed, in the `app`, `ohos` and `graphic` categories we always trace. Unlike filters on
+//! engine markers they do not depend on what the app traces itself.
+use crate::{
+    device::ENTRY_ABILITY,
+    trace::{Trace, TraceMarker, difference_of_traces},
+    utils::RunResults,
+};
+
+/// The app manager forks the process of the app
+const PROCESS_CREATE_MARKER: &str = "AppMgrServiceInner::StartProcess";
+
+/// The ability of the app came to the foreground, `JsUIAbility::OnForeground` for ArkTS abilities
+const ABILITY_FOREGROUND_MARKER: &str = "UIAbility::OnForeground";
+
+/// The ability of the app starts, `JsUIAbility::OnStart` for ArkTS abilities
+const ABILITY_START_MARKER: &str = "UIAbility::OnStart";
+
+/// RenderService consumed a buffer an app submitted, followed by the name of the surface, which starts with the
+/// name of the ability
+const BUFFER_CONSUMED_MARKER: &str = "ConsumeAndUpdateBuffer";
+
+/// Name of the startup metric
+pub(crate) const APP_STARTUP: &str = "App startup";
+
+/// Name of the first frame metric
+pub(crate) const TIME_TO_FIRST_FRAME: &str = "Time to first frame";
+
+/// Whether `trace` begins the lifecycle step `marker`
+fn is_start(trace: &Trace, marker: &str) -> bool {
+    trace.trace_marker == TraceMarker::StartSync && trace.function.contains(marker)
+}
+
+/// Adds the time from the first trace matching `from` to the next trace matching `to` as `name` to `results`.
+/// Returns the number of durations.
+fn record_first(
+    traces: &[Trace],
+    results: &mut RunResults,
+    name: &str,
+    from: impl Fn(&Trace) -> bool,
+    to: impl Fn(&Trace) -> bool,
+) -> usize {
+    let Some(i) = traces.iter().position(from) else {
+        return 0;
+    };
+    let start = &traces[i];
+    match traces[i + 1..].iter().find(|t| to(t)) {
+        Some(end) => {
+            results
+                .filter_results
+                .entry(String::from(name))
+                .or_default()
+                .push(difference_of_traces(end, start));
+            1
+        }
+        None => {
+            log::error!("{name}: nothing ended what started at {}", start.timestamp);
+            0
+        }
+    }
+}
+
+/// Adds the time from the creation of the app process to its ability coming to the foreground to `results`.
+/// Only the first launch of the traces counts. Returns the number of durations.
+pub(crate) fn record_startup_time(traces: &[Trace], results: &mut RunResults) -> usize {
+    record_first(
+        traces,
+        results,
+        APP_STARTUP,
+        |t| is_start(t, PROCESS_CREATE_MARKER),
+        |t| is_start(t, ABILITY_FOREGROUND_MARKER),
+    )
+}
+
+/// Adds the time from the start of the ability to the first frame of its surface RenderService consumed to
+/// `results`. Returns the number of durations.
+pub(crate) fn record_first_frame(traces: &[Trace], results: &mut RunResults) -> usize {
+    record_first(
+        traces,
+        results,
+        TIME_TO_FIRST_FRAME,
+        |t| is_start(t, ABILITY_START_MARKER),
+        |t| {
+            t.function
+                .split_once(BUFFER_CONSUMED_MARKER)
+                .is_some_and(|(_, surface)| surface.contains(ENTRY_ABILITY))
+        },
+    )
+}
+
+#[test]
+fn test_startup_time() {
+    use crate::parser::{HitraceVersion, parse_line};
+    use time::Duration;
+
+    let traces = |markers: &[(&str, &str)]| -> Vec<Trace> {
+        markers
+            .iter()
+            .map(|(timestamp, marker)| {
+                let line = format!(
+                    "<...>-1   (-------) [010] .... {timestamp}: tracing_mark_write: {marker}"
+                );
+                parse_line(HitraceVersion::V5, &line).unwrap().unwrap()
+            })
+            .collect()
+    };
+    let launch = traces(&[
+        ("10.000", "B|620|H:AppMgrServiceInner::StartProcess|M62"),
+        ("10.010", "E|620|"),
+        ("10.100", "B|7|H:MainThread::HandleLaunchApplication|M62"),
+        ("10.200", "B|7|H:JsUIAbility::OnStart|M62"),
+        (
+            "10.300",
+            "B|1370|H:RSBaseRenderUtil::ConsumeAndUpdateBuffer SCBDesktop1|M62",
+        ),
+        ("10.450", "B|7|H:JsUIAbility::OnForeground|M62"),
+        (
+            "10.480",
+            "B|1370|H:RSBaseRenderUtil::ConsumeAndUpdateBuffer EntryAbility0|M62",
+        ),
+        ("11.000", "B|7|H:JsUIAbility::OnForeground|M62"),
+    ]);
+    let mut results = RunResults::default();
+    assert_eq!(record_startup_time(&launch, &mut results), 1);
+    assert_eq!(
+        results.filter_results[APP_STARTUP],
+        vec![Duration::milliseconds(450)]
+    );
+    assert_eq!(record_first_frame(&launch, &mut results), 1);
+    assert_eq!(
+        results.filter_results[TIME_TO_FIRST_FRAME],
+        vec![Duration::milliseconds(280)]
+    );
+
+    let mut results = RunResults::default();
+    assert_eq!(record_startup_time(&launch[..5], &mut results), 0);
+    assert_eq!(record_startup_time(&launch[2..], &mut results), 0);
+    assert_eq!(record_first_frame(&launch[..6], &mut results), 0);
+    assert!(results.filter_results.is_empty());
+}
@@ -0,0 +1,170 @@
+//! The `suggest` subcommand proposing filters for a trace of a new app, as json5 to paste into a run file.
+//!
+//! Candidates are the time between two consecutive markers that each occur once in the trace, and async spans
+//! whose start and end occur once. Sync spans end with a nameless `E`, so a filter can not find their end.
+//! The longest candidates come first.
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use time::Duration;
+
+use crate::{
+    args::SuggestArgs,
+    parser::{self, ParseErrorPolicy},
+    runconfig::JsonFilterDescription,
+    trace::{Trace, TraceMarker, difference_of_traces},
+};
+
+/// A suggested filter and the duration it measured in the trace
+#[derive(Debug)]
+struct Suggestion {
+    filter: JsonFilterDescription,
+    duration: Duration,
+}
+
+/// The part of the function of `trace` a filter can match on, without the trace level hitrace appends,
+/// i.e., `load status changed Head` from `load status changed Head|M62`
+fn partial(trace: &Trace) -> &str {
+    trace
+        .function
+        .rsplit_once("|M")
+        .filter(|(_, level)| level.chars().all(|c| c.is_ascii_digit()))
+        .map_or(&trace.function, |(function, _)| function)
+}
+
+/// How many traces with `marker` contain `partial`
+fn count(traces: &[Trace], marker: &TraceMarker, partial: &str) -> usize {
+    traces
+        .iter()
+        .filter(|t| t.trace_marker == *marker && t.function.contains(partial))
+        .count()
+}
+
+/// Filters between consecutive sync markers that occur once and are at least `min` apart
+fn marker_pairs(traces: &[Trace], min: Duration) -> Vec<Suggestion> {
+    let unique: Vec<(&Trace, &str)> = traces
+        .iter()
+        .filter(|t| t.trace_marker == TraceMarker::StartSync)
+        .map(|t| (t, partial(t)))
+        .filter(|(_, p)| {
+            !p.is_empty() && traces.iter().filter(|t| t.function.contains(p)).count() == 1
+        })
+        .collect();
+    unique
+        .windows(2)
+        .filter_map(|pair| {
+            let [(start, from), (end, to)] = pair else {
+                return None;
+            };
+            let duration = difference_of_traces(end, start);
+            (duration >= min).then(|| Suggestion {
+                filter: JsonFilterDescription::between(
+                    format!("{from}->{to}"),
+                    (*from).to_owned(),
+                    (*to).to_owned(),
+                ),
+                duration,
+            })
+        })
+        .collect()
+}
+
+/// Filters over async spans of at least `min` whose start and end occur once
+fn async_spans(traces: &[Trace], min: Duration) -> Vec<Suggestion> {
+    let mut starts: HashMap<&str, &Trace> = HashMap::new();
+    let mut suggestions = Vec::new();
+    for trace in traces {
+        let name = partial(trace);
+        if name.is_empty() || name.contains('"') {
+            continue;
+        }
+        match trace.trace_marker {
+            TraceMarker::StartAsync if count(traces, &TraceMarker::StartAsync, name) == 1 => {
+                starts.insert(name, trace);
+            }
+            TraceMarker::EndAsync if count(traces, &TraceMarker::EndAsync, name) == 1 => {
+                let Some(start) = starts.remove(name) else {
+                    continue;
+                };
+                let duration = difference_of_traces(trace, start);
+                if duration >= min {
+                    suggestions.push(Suggestion {
+                        filter: JsonFilterDescription::between_expressions(
+                            name.to_owned(),
+                            format!("marker == S && function ~ \"{name}\""),
+                            format!("marker == F && function ~ \"{name}\""),
+                        ),
+                        duration,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    suggestions
+}
+
+/// At most `limit` suggestions of at least `min` for `traces`, the longest first
+fn suggestions(traces: &[Trace], min: Duration, limit: usize) -> Vec<Suggestion> {
+    let mut suggestions = marker_pairs(traces, min);
+    suggestions.extend(async_spans(traces, min));
+    suggestions.sort_by(|a, b| b.duration.cmp(&a.duration));
+    suggestions.truncate(limit);
+    suggestions
+}
+
+/// The suggestions as a json5 array of filters with the duration they measured as comment
+fn to_json5(suggestions: &[Suggestion]) -> Result<String> {
+    let mut json5 = String::from("[\n");
+    for suggestion in suggestions {
+        json5.push_str(&format!("    // {} in the trace\n", suggestion.duration));
+        let filter = serde_json::to_string_pretty(&suggestion.filter)
+            .context("Could not serialize the filter")?;
+        json5.push_str(&format!("    {},\n", filter.replace('\n', "\n    ")));
+    }
+    json5.push_str("]\n");
+    Ok(json5)
+}
+
+/// Prints the filters we suggest for the trace of `args`
+pub(crate) fn run(args: &SuggestArgs, policy: ParseErrorPolicy) -> Result<()> {
+    let trace_file = parser::read_file(&args.trace, policy)?;
+    let suggestions = suggestions(
+        &trace_file.traces,
+        Duration::milliseconds(args.min_ms),
+        args.limit,
+    );
+    print!("{}", to_json5(&suggestions)?);
+    Ok(())
+}
+
+#[test]
+fn test_suggest() {
+    use crate::parser::read_file;
+
+    let trace_file = read_file(
+        std::path::Path::new("testdata/v5_1_1.ftrace"),
+        ParseErrorPolicy::Fail,
+    )
+    .unwrap();
+    let found = suggestions(&trace_file.traces, Duration::milliseconds(10), 10);
+    let json5 = to_json5(&found).unwrap();
+    let filters: Vec<JsonFilterDescription> = json5::from_str(&json5).unwrap();
+    assert_eq!(filters.len(), found.len());
+    let first = serde_json::to_value(&found[0].filter).unwrap();
+    assert_eq!(
+        first,
+        serde_json::json!({
+            "name": "load status changed HeadParsed->PageLoadEndedPrompt",
+            "start_fn_partial": "load status changed HeadParsed",
+            "end_fn_partial": "PageLoadEndedPrompt",
+        })
+    );
+    assert_eq!(found[0].duration, Duration::microseconds(2_074_614));
+    assert!(
+        found
+            .iter()
+            .all(|s| s.duration >= Duration::milliseconds(10))
+    );
+    assert!(suggestions(&trace_file.traces, Duration::hours(1), 10).is_empty());
+}
@@ -0,0 +1,164 @@
+//! Synthetic ftrace files from a scenario for the `synthesize` subcommand, to test filters without a device.
+//!
+//! A scenario is a json5 file like
+//! ```json5
+//! {
+//!     events: [
+//!         {type: "span", at_ms: 0, duration_ms: 1000, start: "load status changed Head", end: "PageLoadEndedPrompt"},
+//!         {type: "counter", at_ms: 1100, name: "servo_memory_profiling:resident", value: 300000000},
+//!     ],
+//! }
+//! ```
+//! The traces are written in the v5 format.
+use std::{fmt::Write, fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::args::SynthesizeArgs;
+
+fn default_pid() -> u32 {
+    4242
+}
+
+/// Seconds since boot of the first event
+fn default_start() -> f64 {
+    1000.0
+}
+
+/// What happens in a synthetic trace
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Scenario {
+    /// The pid of the app
+    #[serde(default = "default_pid")]
+    pid: u32,
+    /// Timestamp of `at_ms` 0 in seconds
+    #[serde(default = "default_start")]
+    start: f64,
+    events: Vec<Event>,
+}
+
+/// An event of a scenario, `at_ms` is relative to the start of the scenario
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+enum Event {
+    /// A single trace marker
+    Marker { at_ms: f64, name: String },
+    /// A marker `start` and a marker `end` `duration_ms` later, what a filter measures
+    Span {
+        at_ms: f64,
+        duration_ms: f64,
+        start: String,
+        end: String,
+    },
+    /// A counter trace
+    Counter {
+        at_ms: f64,
+        name: String,
+        value: i64,
+    },
+}
+
+impl Scenario {
+    /// Reads the json5 scenario at `path`
+    pub(crate) fn read(path: &Path) -> Result<Scenario> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        json5::from_str(&content)
+            .with_context(|| format!("Could not parse scenario {}", path.display()))
+    }
+
+    /// All traces of the scenario as `(at_ms, trace)` in order
+    fn traces(&self) -> Vec<(f64, String)> {
+        let pid = self.pid;
+        let mut traces: Vec<(f64, String)> = self
+            .events
+            .iter()
+            .flat_map(|event| match event {
+                Event::Marker { at_ms, name } => vec![(*at_ms, format!("B|{pid}|H:{name}|M62"))],
+                Event::Span {
+                    at_ms,
+                    duration_ms,
+                    start,
+                    end,
+                } => vec![
+                    (*at_ms, format!("B|{pid}|H:{start}|M62")),
+                    (at_ms + duration_ms, format!("B|{pid}|H:{end}|M62")),
+                ],
+                Event::Counter { at_ms, name, value } => {
+                    vec![(*at_ms, format!("C|{pid}|H:{name}|{value}|M62"))]
+                }
+            })
+            .collect();
+        traces.sort_by(|a, b| a.0.total_cmp(&b.0));
+        traces
+    }
+
+    /// The scenario as an ftrace file
+    pub(crate) fn to_ftrace(&self) -> String {
+        let traces = self.traces();
+        let mut ftrace = format!(
+            "# tracer: nop\n#\n# entries-in-buffer/entries-written: {0}/{0}   #P:8\n#\n",
+            traces.len()
+        );
+        for (at_ms, trace) in traces {
+            let micros = (self.start * 1e6 + at_ms * 1e3).round() as u64;
+            writeln!(
+                ftrace,
+                "           <...>-{}   (-------) [000] .... {}.{:06}: tracing_mark_write: {trace}",
+                self.pid + 1,
+                micros / 1_000_000,
+                micros % 1_000_000
+            )
+            .unwrap();
+        }
+        ftrace
+    }
+}
+
+/// Writes the ftrace of the scenario of `args` to its output or stdout
+pub(crate) fn run(args: &SynthesizeArgs) -> Result<()> {
+    let scenario = Scenario::read(&args.scenario)?;
+    match args.output {
+        Some(ref output) => fs::write(output, scenario.to_ftrace())
+            .with_context(|| format!("Could not write {}", output.display())),
+        None => {
+            print!("{}", scenario.to_ftrace());
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn test_synthesize() {
+    use crate::parser::{HitraceVersion, ParseErrorPolicy, read_file};
+
+    let scenario: Scenario = json5::from_str(
+        r#"{events: [
+            {type: "counter", at_ms: 1500, name: "resident", value: 7},
+            {type: "span", at_ms: 0, duration_ms: 1200.5, start: "Start", end: "End"},
+        ]}"#,
+    )
+    .unwrap();
+    let path = std::env::temp_dir().join(format!(
+        "hitrace-bench-synthesize-{}.ftrace",
+        std::process::id()
+    ));
+    fs::write(&path, scenario.to_ftrace()).unwrap();
+    let trace_file = read_file(&path, ParseErrorPolicy::Fail);
+    fs::remove_file(&path).unwrap();
+    let trace_file = trace_file.unwrap();
+    assert_eq!(trace_file.header.version, HitraceVersion::V5);
+    let functions: Vec<&str> = trace_file
+        .traces
+        .iter()
+        .map(|t| t.function.as_str())
+        .collect();
+    assert_eq!(functions, ["Start|M62", "End|M62", "resident|7|M62"]);
+    assert_eq!(
+        crate::trace::difference_of_traces(&trace_file.traces[1], &trace_file.traces[0]),
+        time::Duration::microseconds(1_200_500)
+    );
+    assert_eq!(trace_file.traces[2].counter.as_ref().unwrap().value, 7);
+}
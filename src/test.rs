@@ -1,20 +1,24 @@
 #![cfg(test)]
+// parse_pointfilter_json compares flags with `true`
+#![allow(clippy::bool_assert_comparison)]
 use serde_json::json;
 
-use crate::args::Args;
+use crate::args::{Args, DuplicateMetrics, Preset, SortBy};
 use crate::bencher::{self, generate_result_json_str};
-use crate::point_filters::PointFilterType;
-use crate::runconfig::read_run_file;
-use crate::utils::RunResults;
+use crate::filter::ExpectedRange;
+use crate::parser::{self, HitraceVersion, ParseErrorPolicy};
+use crate::point_filters::{DeltaMarkers, PointFilterType, RelativeTo};
+use crate::runconfig::{Dependencies, RunState, read_run_file};
+use crate::utils::{ErrorCounts, ErrorKind, RunResults};
 use crate::{
     args::RunArgs, filter::Filter, point_filters::PointFilter, runconfig::RunConfig, trace::Trace,
 };
-use crate::{run_runconfig, runconfig};
-use std::collections::HashMap;
+use crate::{frames, navigation, record_stability, run_runconfig, run_sequential, runconfig};
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::LazyLock;
 use std::vec;
+use time::Duration;
 
 static V1_INPUT_PATH: LazyLock<PathBuf> = LazyLock::new(|| PathBuf::from("testdata/v1.ftrace"));
 static V5_INPUT_PATH: LazyLock<PathBuf> = LazyLock::new(|| PathBuf::from("testdata/v5_1_1.ftrace"));
@@ -22,17 +26,66 @@ static V5_LCP_INPUT_PATH: LazyLock<PathBuf> =
     LazyLock::new(|| PathBuf::from("testdata/v5_1_1_LCP.ftrace"));
 static V5_FCP_INPUT_PATH: LazyLock<PathBuf> =
     LazyLock::new(|| PathBuf::from("testdata/v5_1_1_FCP.ftrace"));
+static V5_INPUT_TAP_PATH: LazyLock<PathBuf> =
+    LazyLock::new(|| PathBuf::from("testdata/v5_1_1_input.ftrace"));
+static V5_RELOAD_INPUT_PATH: LazyLock<PathBuf> =
+    LazyLock::new(|| PathBuf::from("testdata/v5_1_1_reload.ftrace"));
+static V5_OPEN_INPUT_PATH: LazyLock<PathBuf> =
+    LazyLock::new(|| PathBuf::from("testdata/v5_1_1_open.ftrace"));
+static V5_RESTART_INPUT_PATH: LazyLock<PathBuf> =
+    LazyLock::new(|| PathBuf::from("testdata/v5_1_1_restart.ftrace"));
+static V5_SCROLL_INPUT_PATH: LazyLock<PathBuf> =
+    LazyLock::new(|| PathBuf::from("testdata/v5_1_1_scroll.ftrace"));
+
+/// A path in the temp dir that is unique to this test process, so concurrent test runs do not clash
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("hitrace-bench-{}-{name}", std::process::id()))
+}
+
+/// Reads a run file with `content`, removing it again afterwards
+fn read_temp_run_file(name: &str, content: &str, args: &Args) -> anyhow::Result<Vec<RunConfig>> {
+    let path = temp_path(name);
+    std::fs::write(&path, content).unwrap();
+    let result = read_run_file(&path, args);
+    std::fs::remove_file(&path).unwrap();
+    result
+}
 
 const V1_OUTPUT: &str = include_str!("../testdata/v1_output.json");
 const V5_OUTPUT: &str = include_str!("../testdata/v5_1_1_output.json");
 const V5_LCP_OUTPUT: &str = include_str!("../testdata/v5_1_1_LCP_output.json");
 const V5_FCP_OUTPUT: &str = include_str!("../testdata/v5_1_1_FCP_output.json");
 
+/// Every trace file we have together with the version it was produced by and the number of traces in it.
+/// When adding a new hitrace version, add a file here so we notice if parsing breaks.
+const TRACE_CORPUS: &[(&str, HitraceVersion, usize)] = &[
+    ("testdata/v1.ftrace", HitraceVersion::V1, 56),
+    ("testdata/v5_1_1.ftrace", HitraceVersion::V5, 61),
+    ("testdata/v5_1_1_LCP.ftrace", HitraceVersion::V5, 64),
+    ("testdata/v5_1_1_FCP.ftrace", HitraceVersion::V5, 65),
+    ("testdata/v5_1_1_generic.ftrace", HitraceVersion::V5, 12),
+    ("testdata/v5_1_1_delta.ftrace", HitraceVersion::V5, 8),
+    ("testdata/v5_1_1_iframe.ftrace", HitraceVersion::V5, 6),
+    ("testdata/v5_1_1_LCP_iframe.ftrace", HitraceVersion::V5, 4),
+    ("testdata/v5_1_1_LCP_relative.ftrace", HitraceVersion::V5, 4),
+    ("testdata/v5_1_1_malformed.ftrace", HitraceVersion::V5, 3),
+    ("testdata/v5_1_1_restart.ftrace", HitraceVersion::V5, 6),
+];
+
 struct Testcase<'a> {
     input_file_path: PathBuf,
     output_file_str: &'a str,
 }
 
+#[test]
+fn parse_trace_corpus() {
+    for (path, version, count) in TRACE_CORPUS {
+        let trace_file = parser::read_file(&PathBuf::from(path), ParseErrorPolicy::Fail).unwrap();
+        assert_eq!(trace_file.header.version, *version, "{path}");
+        assert_eq!(trace_file.traces.len(), *count, "{path}");
+    }
+}
+
 #[test]
 fn parse_pointfilter_json() -> anyhow::Result<()> {
     let runs_json_path = PathBuf::from_str("testdata/runs.json")?;
@@ -98,6 +151,7 @@ fn full_v5_with_lcp() {
             match_str: String::from("LargestContentfulPaint"),
             no_unit_conversion: true,
             point_filter_type: PointFilterType::Largest,
+            ..Default::default()
         }],
     );
 }
@@ -115,6 +169,7 @@ fn full_v5_with_fcp() {
             match_str: String::from("FirstContentfulPaint"),
             no_unit_conversion: true,
             point_filter_type: PointFilterType::Default,
+            ..Default::default()
         }],
     );
 }
@@ -140,6 +195,7 @@ fn test_testcaseprofiling_v1_v5() {
                 match_str: String::from("generatehtml"),
                 no_unit_conversion: true,
                 point_filter_type: PointFilterType::Default,
+                ..Default::default()
             }]
         )
         .unwrap(),
@@ -154,6 +210,7 @@ fn test_testcaseprofiling_v1_v5() {
                 match_str: String::from("generatehtml"),
                 no_unit_conversion: true,
                 point_filter_type: PointFilterType::Default,
+                ..Default::default()
             }]
         )
         .unwrap(),
@@ -168,6 +225,7 @@ fn test_lcp_v5() {
         match_str: String::from("LargestContentfulPaint"),
         no_unit_conversion: true,
         point_filter_type: PointFilterType::Largest,
+        ..Default::default()
     }];
 
     let expected_json = json!({
@@ -201,6 +259,7 @@ fn test_fcp_v5() {
         match_str: String::from("FirstContentfulPaint"),
         no_unit_conversion: true,
         point_filter_type: PointFilterType::Default,
+        ..Default::default()
     }];
 
     let expected_json = json!({
@@ -219,6 +278,1096 @@ fn test_fcp_v5() {
     );
 }
 
+#[test]
+fn test_counter_v1_v5() {
+    let point_filters = || {
+        vec![PointFilter {
+            name: String::from("Vsize"),
+            match_str: String::from("servo_memory_profiling:vsize"),
+            no_unit_conversion: true,
+            point_filter_type: PointFilterType::Counter,
+            ..Default::default()
+        }]
+    };
+
+    let expected_json = json!({
+        "E2E/https://servo.org/Vsize": {
+            "Data": {
+                "lower_value": 45766221824.0,
+                "upper_value": 45766221824.0,
+                "value": 45766221824.0
+            }
+        }
+    });
+
+    assert_eq!(
+        test_filters(V5_INPUT_PATH.to_path_buf(), vec![], point_filters()).unwrap(),
+        expected_json
+    );
+    assert_eq!(
+        test_filters(V1_INPUT_PATH.to_path_buf(), vec![], point_filters()).unwrap(),
+        json!({
+            "E2E/https://servo.org/Vsize": {
+                "Data": {
+                    "lower_value": 12803051520.0,
+                    "upper_value": 12803051520.0,
+                    "value": 12803051520.0
+                }
+            }
+        })
+    );
+}
+
+#[test]
+fn test_delta_v5() {
+    let delta_filter = |name: &str, before: &str, after: &str| PointFilter {
+        name: String::from(name),
+        match_str: String::from("servo_memory_profiling:resident"),
+        point_filter_type: PointFilterType::Delta,
+        delta: Some(DeltaMarkers {
+            before: String::from(before),
+            after: String::from(after),
+        }),
+        ..Default::default()
+    };
+
+    let expected_json = json!({
+        "E2E/https://servo.org/LoadGrowth": {
+            "Memory": {
+                "lower_value": 103623936.0,
+                "upper_value": 103623936.0,
+                "value": 103623936.0
+            }
+        },
+        "E2E/https://servo.org/Shrink": {
+            "Memory": {
+                "lower_value": -200000000.0,
+                "upper_value": -200000000.0,
+                "value": -200000000.0
+            }
+        }
+    });
+
+    assert_eq!(
+        test_filters(
+            PathBuf::from("testdata/v5_1_1_delta.ftrace"),
+            vec![],
+            vec![
+                delta_filter("LoadGrowth", "on_surface_created_cb", "PageLoadEndedPrompt"),
+                delta_filter(
+                    "Shrink",
+                    "PageLoadEndedPrompt",
+                    "servo_memory_profiling:resident|203623936"
+                ),
+                delta_filter("Missing", "on_surface_created_cb", "does_not_exist"),
+            ]
+        )
+        .unwrap(),
+        expected_json
+    );
+}
+
+#[test]
+fn test_memory_rollup_v1() {
+    let expected_json = json!({
+        "E2E/https://servo.org/JS": {
+            "Memory": {
+                "lower_value": 6807763.0,
+                "upper_value": 6807763.0,
+                "value": 6807763.0
+            }
+        }
+    });
+    assert_eq!(
+        test_filters(
+            V1_INPUT_PATH.to_path_buf(),
+            vec![],
+            vec![PointFilter {
+                name: String::from("JS"),
+                match_str: String::from("js"),
+                depth: Some(1),
+                ..Default::default()
+            }]
+        )
+        .unwrap(),
+        expected_json
+    );
+
+    let rolled_up = test_filters(
+        V1_INPUT_PATH.to_path_buf(),
+        vec![],
+        vec![PointFilter {
+            name: String::from("JS"),
+            match_str: String::from("js"),
+            depth: Some(2),
+            ..Default::default()
+        }],
+    )
+    .unwrap();
+    assert_eq!(
+        rolled_up["E2E/https://servo.org/JS/gc-heap"]["Memory"]["value"],
+        json!(1048576.0)
+    );
+    assert_eq!(
+        rolled_up["E2E/https://servo.org/JS/malloc-heap"]["Memory"]["value"],
+        json!(5497043.0)
+    );
+    assert_eq!(rolled_up.as_object().unwrap().len(), 3);
+}
+
+#[test]
+fn test_memory_url_matching_v5() {
+    let input = PathBuf::from("testdata/v5_1_1_iframe.ftrace");
+    let js_filter = |urls: Vec<String>, all_urls: bool| {
+        vec![PointFilter {
+            name: String::from("JS"),
+            match_str: String::from("js"),
+            urls,
+            all_urls,
+            ..Default::default()
+        }]
+    };
+    let keys = |json: serde_json::Value| {
+        let mut keys: Vec<String> = json.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    };
+
+    assert_eq!(
+        keys(test_filters(input.clone(), vec![], js_filter(vec![], false)).unwrap()),
+        vec!["E2E/https://servo.org/JS/malloc-heap"]
+    );
+    assert_eq!(
+        keys(
+            test_filters(
+                input.clone(),
+                vec![],
+                js_filter(vec![String::from("youtube.com")], false)
+            )
+            .unwrap()
+        ),
+        vec![
+            "E2E/https://servo.org/JS/malloc-heap",
+            "E2E/https://servo.org/JS/url(https://www.youtube.com/embed/abc)/malloc-heap",
+        ]
+    );
+    assert_eq!(
+        keys(test_filters(input, vec![], js_filter(vec![], true)).unwrap()).len(),
+        3
+    );
+}
+
+#[test]
+fn test_lcp_pipelines_v5() {
+    let input = PathBuf::from("testdata/v5_1_1_LCP_iframe.ftrace");
+    let lcp_filter = |per_pipeline: bool, pipeline_id: Option<&str>| {
+        vec![PointFilter {
+            name: String::from("LargestContentfulPaint"),
+            match_str: String::from("LargestContentfulPaint"),
+            no_unit_conversion: true,
+            point_filter_type: PointFilterType::Largest,
+            per_pipeline,
+            pipeline_id: pipeline_id.map(String::from),
+            ..Default::default()
+        }]
+    };
+
+    let collapsed = test_filters(input.clone(), vec![], lcp_filter(false, None)).unwrap();
+    assert_eq!(
+        collapsed["E2E/https://servo.org/LargestContentfulPaint/area"]["Pixels"]["value"],
+        json!(120000.0)
+    );
+
+    let root = test_filters(input.clone(), vec![], lcp_filter(false, Some("(1,1)"))).unwrap();
+    assert_eq!(
+        root["E2E/https://servo.org/LargestContentfulPaint/area"]["Pixels"]["value"],
+        json!(90810.0)
+    );
+
+    let per_pipeline = test_filters(input, vec![], lcp_filter(true, None)).unwrap();
+    assert_eq!(
+        per_pipeline["E2E/https://servo.org/LargestContentfulPaint/area/pipeline(1,1)"]["Pixels"]["value"],
+        json!(90810.0)
+    );
+    assert_eq!(
+        per_pipeline["E2E/https://servo.org/LargestContentfulPaint/paint_time/pipeline(1,3)"]["Nanoseconds"]
+            ["value"],
+        json!(231277290000000.0)
+    );
+    assert_eq!(per_pipeline.as_object().unwrap().len(), 4);
+}
+
+#[test]
+fn test_paint_relative_to_navigation_start_v5() {
+    let input = PathBuf::from("testdata/v5_1_1_LCP_relative.ftrace");
+    let paint_filter = |name: &str, relative_to: RelativeTo| PointFilter {
+        name: String::from(name),
+        match_str: String::from(name),
+        no_unit_conversion: true,
+        point_filter_type: PointFilterType::Largest,
+        relative_to: Some(relative_to),
+        ..Default::default()
+    };
+
+    let result = test_filters(
+        input.clone(),
+        vec![],
+        vec![
+            paint_filter(
+                "LargestContentfulPaint",
+                RelativeTo::Marker(String::from("on_surface_created_cb")),
+            ),
+            paint_filter(
+                "FirstContentfulPaint",
+                RelativeTo::Marker(String::from("on_surface_created_cb")),
+            ),
+        ],
+    )
+    .unwrap();
+    assert_eq!(
+        result["E2E/https://servo.org/LargestContentfulPaint/paint_time"]["Nanoseconds"]["value"],
+        json!(480160000.0)
+    );
+    assert_eq!(
+        result["E2E/https://servo.org/FirstContentfulPaint/paint_time"]["Nanoseconds"]["value"],
+        json!(200099978.0)
+    );
+    // The area must not be touched
+    assert_eq!(
+        result["E2E/https://servo.org/LargestContentfulPaint/area"]["Pixels"]["value"],
+        json!(90810.0)
+    );
+
+    let result = test_filters(
+        input,
+        vec![],
+        vec![paint_filter(
+            "LargestContentfulPaint",
+            RelativeTo::FirstContentfulPaint,
+        )],
+    )
+    .unwrap();
+    assert_eq!(
+        result["E2E/https://servo.org/LargestContentfulPaint/paint_time"]["Nanoseconds"]["value"],
+        json!(280060022.0)
+    );
+}
+
+#[test]
+fn test_point_aggregations_v5() {
+    let input = PathBuf::from("testdata/v5_1_1_LCP_relative.ftrace");
+    let lcp_filter = |name: &str, point_filter_type: PointFilterType| PointFilter {
+        name: String::from(name),
+        match_str: String::from("LargestContentfulPaint"),
+        no_unit_conversion: true,
+        point_filter_type,
+        ..Default::default()
+    };
+
+    let result = test_filters(
+        input,
+        vec![],
+        vec![
+            lcp_filter("LCPFirst", PointFilterType::First),
+            lcp_filter("LCPLast", PointFilterType::Last),
+            lcp_filter("LCPSmallest", PointFilterType::Smallest),
+            lcp_filter("LCPMean", PointFilterType::Mean),
+        ],
+    )
+    .unwrap();
+    assert_eq!(
+        result["E2E/https://servo.org/LCPFirst/area"]["Data"]["value"],
+        json!(4095.0)
+    );
+    assert_eq!(
+        result["E2E/https://servo.org/LCPLast/area"]["Data"]["value"],
+        json!(90810.0)
+    );
+    assert_eq!(
+        result["E2E/https://servo.org/LCPSmallest/area"]["Data"]["value"],
+        json!(4095.0)
+    );
+    assert_eq!(
+        result["E2E/https://servo.org/LCPMean/area"]["Data"]["value"],
+        json!(47452.0)
+    );
+}
+
+#[test]
+fn test_custom_measures_v5() {
+    let result = test_filters(
+        V5_INPUT_PATH.clone(),
+        vec![Filter {
+            name: String::from("Surface->LoadStart"),
+            first: Box::new(|t: &Trace| t.function.contains("on_surface_created_cb")),
+            last: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
+            measure: Some(String::from("Startup")),
+            expected_range: None,
+        }],
+        vec![PointFilter {
+            name: String::from("Resident"),
+            match_str: String::from("resident"),
+            measure: Some(String::from("Heap")),
+            ..Default::default()
+        }],
+    )
+    .unwrap();
+    let measures = |key: &str| {
+        result[key]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(measures("E2E/Surface->LoadStart"), vec!["Startup"]);
+    assert_eq!(measures("E2E/https://servo.org/Resident"), vec!["Heap"]);
+}
+
+#[test]
+fn test_run_priority_and_dependencies() {
+    let args = Args::test_default(V5_INPUT_PATH.clone());
+    let run_configs = read_run_file(&PathBuf::from("testdata/runs_priority.json"), &args).unwrap();
+    let names: Vec<_> = run_configs
+        .iter()
+        .map(|r| r.name.clone().unwrap())
+        .collect();
+    assert_eq!(names, vec!["smoke", "soak"]);
+
+    let soak = &run_configs[1];
+    assert_eq!(
+        soak.dependencies(&run_configs, &[RunState::Running, RunState::Pending]),
+        Dependencies::Waiting
+    );
+    assert_eq!(
+        soak.dependencies(&run_configs, &[RunState::Succeeded, RunState::Pending]),
+        Dependencies::Ready
+    );
+    assert_eq!(
+        soak.dependencies(&run_configs, &[RunState::Failed, RunState::Pending]),
+        Dependencies::Failed("smoke")
+    );
+
+    // a dependency has to run first
+    let result = read_temp_run_file(
+        "runs-wrong-priority.json",
+        r#"[
+            {"name": "smoke", "run_args": {}, "point_filters": [{"name": "Resident", "match_str": "resident"}]},
+            {"name": "soak", "priority": 1, "depends_on": ["smoke"], "run_args": {}, "point_filters": [{"name": "Resident", "match_str": "resident"}]},
+        ]"#,
+        &args,
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_run_file_includes() {
+    let args = Args::test_default(V5_INPUT_PATH.clone());
+    let run_configs = read_run_file(&PathBuf::from("testdata/runs_include.json"), &args).unwrap();
+    let names = |r: &RunConfig| {
+        (
+            r.filters.iter().map(|f| f.name.clone()).collect::<Vec<_>>(),
+            r.point_filters
+                .iter()
+                .map(|f| f.name.clone())
+                .collect::<Vec<_>>(),
+        )
+    };
+    assert_eq!(
+        names(&run_configs[0]),
+        (
+            vec![String::from("Surface->LoadStart")],
+            vec![String::from("Resident"), String::from("JS")]
+        )
+    );
+    assert_eq!(
+        names(&run_configs[1]),
+        (
+            vec![String::from("Surface->LoadStart")],
+            vec![String::from("Resident")]
+        )
+    );
+
+    // a filter set including itself
+    let dir = temp_path("include-cycle");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("set.json5"), r#"{"include": ["set.json5"]}"#).unwrap();
+    std::fs::write(
+        dir.join("runs.json"),
+        r#"[{"include": ["set.json5"], "run_args": {}}]"#,
+    )
+    .unwrap();
+    let result = read_run_file(&dir.join("runs.json"), &args);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let Err(err) = result else {
+        panic!("A cycle of includes should fail");
+    };
+    assert!(err.to_string().contains("includes itself"), "{err}");
+}
+
+#[test]
+fn test_run_file_filter_library() {
+    let args = Args::test_default(V5_INPUT_PATH.clone());
+    let run_configs = read_run_file(&PathBuf::from("testdata/runs_library.json"), &args).unwrap();
+    let names = |r: &RunConfig| {
+        r.filters
+            .iter()
+            .map(|f| f.name.clone())
+            .chain(r.point_filters.iter().map(|f| f.name.clone()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(&run_configs[0]),
+        ["Load->Compl", "Resident https://servo.org"]
+    );
+    assert_eq!(
+        names(&run_configs[1]),
+        ["Load->Compl (cold)", "Resident example"]
+    );
+    assert_eq!(run_configs[0].url_alias.as_deref(), Some("servo"));
+    assert_eq!(run_configs[1].url_alias, None);
+
+    let result = read_temp_run_file(
+        "runs-unknown-library-filter.json",
+        r#"{"filter_library": {}, "runs": [{"run_args": {}, "use_filters": ["Load"]}]}"#,
+        &args,
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_run_file_expression_filter() {
+    let args = Args::test_default(V5_INPUT_PATH.clone());
+    let run_configs =
+        read_run_file(&PathBuf::from("testdata/runs_expression.json"), &args).unwrap();
+    let mut results = RunResults::default();
+    run_runconfig(&run_configs[0], &mut results, None).unwrap();
+    assert_eq!(
+        results.filter_results["Load->Compl"],
+        vec![Duration::microseconds(2_074_614)]
+    );
+
+    let result = read_temp_run_file(
+        "runs-expression-and-partial.json",
+        r#"{"runs": [{"run_args": {}, "filters": [
+            {"name": "Load", "start_fn_partial": "load", "start": "pid == 1", "end_fn_partial": "end"}
+        ]}]}"#,
+        &args,
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_run_file_delta_without_markers() {
+    let args = Args::test_default(V5_INPUT_PATH.clone());
+    let result = read_temp_run_file(
+        "runs-delta-without-markers.json",
+        r#"{"runs": [{"run_args": {}, "point_filters": [
+            {"name": "Growth", "match_str": "resident", "point_filter_type": "Delta"}
+        ]}]}"#,
+        &args,
+    );
+    assert_eq!(
+        result.err().unwrap().to_string(),
+        "Point filter Growth is of type Delta but has no delta markers"
+    );
+}
+
+#[test]
+fn test_sort_by() {
+    let rows = |sort_by| {
+        let mut rows: Vec<_> = [("a", vec![5]), ("b", vec![1, 30]), ("c", vec![9])]
+            .into_iter()
+            .map(|(key, val)| (key, val.clone(), crate::avg_min_max::<i64, i64>(&val)))
+            .collect();
+        crate::sort_rows(&mut rows, sort_by);
+        rows.into_iter().map(|(key, _, _)| key).collect::<Vec<_>>()
+    };
+    assert_eq!(rows(SortBy::Name), ["a", "b", "c"]);
+    assert_eq!(rows(SortBy::Value), ["b", "c", "a"]);
+}
+
+#[test]
+fn test_merge_policy() {
+    let args = Args::test_default(V5_INPUT_PATH.clone());
+    let run_configs = read_run_file(&PathBuf::from("testdata/runs_priority.json"), &args).unwrap();
+    let soak = run_configs
+        .iter()
+        .find(|r| r.name.as_deref() == Some("soak"))
+        .unwrap();
+    assert_eq!(soak.merge_policy, Some(DuplicateMetrics::Combine));
+    assert_eq!(
+        serde_json::from_str::<DuplicateMetrics>("\"separate\"").unwrap(),
+        DuplicateMetrics::Suffix
+    );
+
+    let load = |ms: i64| {
+        let mut results = RunResults::default();
+        results
+            .filter_results
+            .insert(String::from("Load"), vec![Duration::milliseconds(ms)]);
+        results
+    };
+    let merged = |policy| {
+        let mut bencher_results = load(1);
+        crate::merge_run_results(
+            &mut bencher_results,
+            load(2),
+            1,
+            "https://servo.org",
+            policy,
+        )
+        .map(|()| bencher_results)
+    };
+    let combined = merged(DuplicateMetrics::Combine).unwrap();
+    assert_eq!(combined.filter_results["Load"].len(), 2);
+    let separate = merged(DuplicateMetrics::Suffix).unwrap();
+    assert_eq!(separate.filter_results["Load"].len(), 1);
+    assert_eq!(separate.filter_results["Load #2"].len(), 1);
+    assert!(merged(DuplicateMetrics::Error).is_err());
+}
+
+#[test]
+fn test_run_file_throughput() {
+    let args = Args::test_default(V5_INPUT_PATH.clone());
+    let run_configs =
+        read_run_file(&PathBuf::from("testdata/runs_throughput.json"), &args).unwrap();
+    let mut results = RunResults::default();
+    run_runconfig(&run_configs[0], &mut results, None).unwrap();
+    let url = &run_configs[0].run_args.url;
+    let resident = *results.point_results[&format!("{url}/Resident")]
+        .result
+        .last()
+        .unwrap();
+    let key = format!("{url}/Resident per load time");
+    assert_eq!(
+        results.point_results[&key].result,
+        vec![(resident as f64 / 2.074614).round() as i64]
+    );
+    assert_eq!(results.measures[&key], "Throughput");
+    assert!(crate::missing_metrics(&run_configs[0], &results).is_empty());
+    let url = url.clone();
+
+    let mut run_config = run_configs.into_iter().next().unwrap();
+    run_config.expected_metrics.push(String::from("Renamed"));
+    assert_eq!(
+        crate::missing_metrics(&run_config, &results),
+        vec![format!("Renamed of {url}")]
+    );
+}
+
+#[test]
+fn test_check_filters() {
+    let args = Args::test_default(V5_INPUT_PATH.clone());
+    let run_configs = read_run_file(&PathBuf::from("testdata/runs_reference.json"), &args).unwrap();
+    assert_eq!(
+        run_configs[0].reference_traces.get("Load"),
+        Some(&PathBuf::from("testdata/v5_1_1.ftrace"))
+    );
+    assert!(!run_configs[0].reference_traces.contains_key("Unchecked"));
+    let error = crate::check_filters::run(&run_configs).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "1 of 2 filters do not match their reference trace"
+    );
+}
+
+#[test]
+fn test_error_log() {
+    let args = Args::test_default(V5_INPUT_PATH.clone());
+    let run_configs = read_run_file(&PathBuf::from("testdata/runs_reference.json"), &args).unwrap();
+    let mut results = RunResults::default();
+    run_runconfig(&run_configs[0], &mut results, None).unwrap();
+    let json = serde_json::to_value(&results.error_log).unwrap();
+    assert_eq!(
+        json,
+        json!([{
+            "url": "https://servo.org",
+            "metric": "Renamed",
+            "try": 1,
+            "category": "no-end-marker",
+            "message": "No trace matched the end of the filter",
+            "count": 1,
+            "trace_started": null,
+            "trace": "testdata/v5_1_1.ftrace",
+            "log": null,
+        }])
+    );
+
+    let dir = temp_path("error-log");
+    std::fs::create_dir_all(&dir).unwrap();
+    let failure = crate::error_log::run_failure(&run_configs[0], &anyhow::anyhow!("No device"));
+    crate::error_log::write(&dir, &[failure], false).unwrap();
+    let written: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("errors.json")).unwrap()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(written[0]["category"], "run-failure");
+    assert_eq!(written[0]["try"], serde_json::Value::Null);
+    assert_eq!(written[0]["message"], "No device");
+}
+
+#[test]
+fn test_strict_config() {
+    let mut args = Args::test_default(V5_INPUT_PATH.clone());
+    let content = r#"[{"run_args": {"tires": 3}, "point_filters": [{"name": "Resident", "match_str": "resident"}], "point_fliters": []}]"#;
+    assert!(read_temp_run_file("runs-typo.json", content, &args).is_ok());
+    let warnings = runconfig::WARNINGS.take();
+    assert!(
+        warnings
+            .iter()
+            .any(|w| w.starts_with("Ignoring unknown fields") && w.contains("point_fliters")),
+        "{warnings:?}"
+    );
+    args.strict_config = true;
+    let Err(err) = read_temp_run_file("runs-typo.json", content, &args) else {
+        panic!("Unknown fields should fail with strict_config");
+    };
+    let err = err.to_string();
+    assert!(err.contains("point_fliters"), "{err}");
+    assert!(err.contains("run_args.tires"), "{err}");
+    assert!(read_run_file(&PathBuf::from("testdata/runs_library.json"), &args).is_ok());
+}
+
+#[test]
+fn test_run_file_schema_v1() {
+    let args = Args::test_default(V5_INPUT_PATH.clone());
+    let run_configs = read_run_file(&PathBuf::from("testdata/runs_v1.json"), &args).unwrap();
+    let warnings = runconfig::WARNINGS.take();
+    assert!(
+        warnings.iter().any(|w| w.contains("old schema_version 1")),
+        "{warnings:?}"
+    );
+    assert_eq!(
+        run_configs[0].point_filters[0].point_filter_type,
+        PointFilterType::Combined
+    );
+}
+
+#[test]
+fn test_effective_config() {
+    let mut args = Args::test_default(V5_INPUT_PATH.clone());
+    args.webhook_url = Some(String::from("https://hooks.example.com/secret"));
+    let run_configs = read_run_file(&PathBuf::from("testdata/runs_library.json"), &args).unwrap();
+    let config: serde_json::Value =
+        serde_json::from_str(&runconfig::effective_config(&args, &run_configs).unwrap()).unwrap();
+    assert_eq!(config["args"]["webhook_url"], "<redacted>");
+    assert_eq!(config["args"]["format"], "text");
+    assert_eq!(config["runs"][1]["run_args"]["url"], "https://example.com");
+    assert_eq!(
+        config["runs"][1]["filters"][0]["name"],
+        "Load->Compl (cold)"
+    );
+    assert_eq!(
+        config["runs"][1]["point_filters"][0]["name"],
+        "Resident example"
+    );
+}
+
+#[test]
+fn test_run_sequential_skips_failed_runs() {
+    let args = Args::test_default(V5_INPUT_PATH.clone());
+    let run_configs = vec![
+        RunConfig::new(
+            args.clone(),
+            RunArgs::default(),
+            vec![],
+            vec![PointFilter::new(
+                String::from("Resident"),
+                String::from("resident"),
+            )],
+        ),
+        RunConfig::new(
+            args.clone(),
+            RunArgs::default(),
+            vec![],
+            vec![PointFilter::new(
+                String::from("Resident"),
+                String::from("resident"),
+            )],
+        ),
+    ];
+    let results =
+        run_sequential(&args, &run_configs, &[RunState::Pending, RunState::Failed]).unwrap();
+    assert_eq!(
+        results[0].as_ref().unwrap().as_ref().unwrap().point_results["https://servo.org/Resident"]
+            .result
+            .len(),
+        1
+    );
+    assert!(results[1].is_none());
+}
+
+#[test]
+fn test_fail_fast() {
+    let mut args = Args::test_default(V5_INPUT_PATH.clone());
+    let run_configs = |args: &Args| {
+        vec![RunConfig::new(
+            args.clone(),
+            RunArgs::default(),
+            vec![],
+            vec![PointFilter::new(
+                String::from("Nothing"),
+                String::from("does-not-exist"),
+            )],
+        )]
+    };
+    assert!(run_sequential(&args, &run_configs(&args), &[RunState::Pending]).is_ok());
+    args.fail_fast = true;
+    let err = run_sequential(&args, &run_configs(&args), &[RunState::Pending]).unwrap_err();
+    assert!(err.is::<crate::BrokenRunConfig>());
+}
+
+#[test]
+fn test_pipelined_tries_v5() {
+    let mut args = Args::test_default(V5_INPUT_PATH.clone());
+    let run = |args: &Args| {
+        let run_config = RunConfig::new(
+            args.clone(),
+            RunArgs {
+                tries: 3,
+                ..Default::default()
+            },
+            vec![],
+            vec![PointFilter::new(
+                String::from("Resident"),
+                String::from("resident"),
+            )],
+        );
+        let mut results = RunResults::default();
+        run_runconfig(&run_config, &mut results, None).unwrap();
+        results
+    };
+    let sequential = run(&args);
+    args.pipeline = true;
+    let pipelined = run(&args);
+    assert_eq!(
+        pipelined.point_results["https://servo.org/Resident"].result,
+        sequential.point_results["https://servo.org/Resident"].result
+    );
+    assert_eq!(
+        pipelined.point_results["https://servo.org/Resident"]
+            .result
+            .len(),
+        3
+    );
+}
+
+#[test]
+fn test_scroll_jank_preset() {
+    let run_config = RunConfig::new(
+        Args::test_default(V5_SCROLL_INPUT_PATH.clone()),
+        RunArgs {
+            preset: Some(Preset::ScrollJank),
+            ..Default::default()
+        },
+        vec![],
+        vec![],
+    );
+    let mut results = RunResults::default();
+    run_runconfig(&run_config, &mut results, None).unwrap();
+    assert_eq!(
+        results.filter_results["Frame time p50"],
+        vec![Duration::microseconds(16667)]
+    );
+    assert_eq!(
+        results.filter_results["Frame time p99"],
+        vec![Duration::microseconds(50000)]
+    );
+    assert_eq!(
+        results.point_results[frames::DROPPED_FRAMES].result,
+        vec![2]
+    );
+}
+
+#[test]
+fn test_input_to_paint() {
+    let run_config = RunConfig::new(
+        Args::test_default(V5_INPUT_TAP_PATH.clone()),
+        RunArgs::default(),
+        vec![],
+        vec![],
+    );
+    let mut results = RunResults::default();
+    run_runconfig(&run_config, &mut results, None).unwrap();
+    assert_eq!(
+        results.filter_results[frames::INPUT_TO_PAINT],
+        vec![Duration::milliseconds(45), Duration::milliseconds(30)]
+    );
+}
+
+#[test]
+fn test_reloads() {
+    let run_config = RunConfig::new(
+        Args::test_default(V5_RELOAD_INPUT_PATH.clone()),
+        RunArgs::default(),
+        vec![Filter {
+            name: String::from("Load"),
+            first: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
+            last: Box::new(|t: &Trace| t.function.contains("PageLoadEndedPrompt")),
+            measure: None,
+            expected_range: None,
+        }],
+        vec![],
+    );
+    let mut results = RunResults::default();
+    run_runconfig(&run_config, &mut results, None).unwrap();
+    assert_eq!(
+        results.filter_results["Load"],
+        vec![Duration::milliseconds(1000)]
+    );
+    assert_eq!(
+        results.filter_results["Load (reload 1)"],
+        vec![Duration::milliseconds(500)]
+    );
+    assert_eq!(
+        results.filter_results["Load (reload 2)"],
+        vec![Duration::milliseconds(400)]
+    );
+    assert!(results.errors.is_empty());
+    assert_eq!(
+        results.point_results[navigation::MEMORY_GROWTH].result,
+        vec![50000000]
+    );
+}
+
+#[test]
+fn test_expected_range() {
+    let run_config = RunConfig::new(
+        Args::test_default(V5_RELOAD_INPUT_PATH.clone()),
+        RunArgs::default(),
+        vec![Filter {
+            name: String::from("Load"),
+            first: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
+            last: Box::new(|t: &Trace| t.function.contains("PageLoadEndedPrompt")),
+            measure: None,
+            expected_range: Some(ExpectedRange {
+                min: Some(450.0),
+                max: Some(60000.0),
+            }),
+        }],
+        vec![],
+    );
+    let mut results = RunResults::default();
+    run_runconfig(&run_config, &mut results, None).unwrap();
+    assert_eq!(
+        results.filter_results["Load (reload 1)"],
+        vec![Duration::milliseconds(500)]
+    );
+    assert!(!results.filter_results.contains_key("Load (reload 2)"));
+    assert_eq!(results.errors["Load (reload 2)"][&ErrorKind::OutOfRange], 1);
+}
+
+#[test]
+fn test_app_restart() {
+    let run_config = RunConfig::new(
+        Args::test_default(V5_RESTART_INPUT_PATH.clone()),
+        RunArgs::default(),
+        vec![Filter {
+            name: String::from("Load"),
+            first: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
+            last: Box::new(|t: &Trace| t.function.contains("PageLoadEndedPrompt")),
+            measure: None,
+            expected_range: None,
+        }],
+        vec![],
+    );
+    let mut results = RunResults::default();
+    run_runconfig(&run_config, &mut results, None).unwrap();
+    assert_eq!(results.filter_results["Load"], vec![Duration::seconds(1)]);
+    assert_eq!(
+        results.filter_results["Load (session 2)"],
+        vec![Duration::milliseconds(700)]
+    );
+    assert_eq!(
+        results.errors[&run_config.run_args.url],
+        ErrorCounts::from([(ErrorKind::AppCrash, 1)])
+    );
+}
+
+#[test]
+fn test_stability_metrics() {
+    let run_config = RunConfig::new(
+        Args::test_default(V5_RESTART_INPUT_PATH.clone()),
+        RunArgs {
+            tries: 3,
+            ..Default::default()
+        },
+        vec![Filter {
+            name: String::from("Load"),
+            first: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
+            last: Box::new(|t: &Trace| t.function.contains("PageLoadEndedPrompt")),
+            measure: None,
+            expected_range: None,
+        }],
+        vec![],
+    );
+    let mut results = RunResults::default();
+    run_runconfig(&run_config, &mut results, None).unwrap();
+    record_stability(&run_config, &mut results);
+    let json = bencher::generate_result_json_value(&results, false).unwrap();
+    // Every try of the trace has one restart
+    assert_eq!(json["E2E/Stability/crash_rate"]["Percent"]["value"], 100.0);
+    assert_eq!(json["E2E/Stability/crashes"]["Crashes"]["value"], 3.0);
+    assert_eq!(json["E2E/Stability/failed_tries"]["Tries"]["value"], 3.0);
+
+    let mut results = RunResults::default();
+    record_stability(&run_config, &mut results);
+    assert!(results.point_results.is_empty());
+}
+
+#[test]
+fn test_open_urls() {
+    let run_config = RunConfig::new(
+        Args::test_default(V5_OPEN_INPUT_PATH.clone()),
+        RunArgs::default(),
+        vec![Filter {
+            name: String::from("Load"),
+            first: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
+            last: Box::new(|t: &Trace| t.function.contains("PageLoadEndedPrompt")),
+            measure: None,
+            expected_range: None,
+        }],
+        vec![],
+    );
+    let mut results = RunResults::default();
+    run_runconfig(&run_config, &mut results, None).unwrap();
+    assert_eq!(
+        results.filter_results["Load (https://servo.org/blog)"],
+        vec![Duration::milliseconds(500)]
+    );
+    assert_eq!(
+        results.filter_results["Load (https://servo.org/contribute)"],
+        vec![Duration::milliseconds(400)]
+    );
+}
+
+#[test]
+fn test_session_budget() {
+    let mut args = Args::test_default(V5_INPUT_PATH.clone());
+    args.max_session_minutes = Some(0);
+    args.start_session_budget();
+    assert!(args.budget_exhausted());
+    let run_config = RunConfig::new(
+        args,
+        RunArgs {
+            tries: 3,
+            ..Default::default()
+        },
+        vec![],
+        vec![PointFilter::new(
+            String::from("Resident"),
+            String::from("resident"),
+        )],
+    );
+    let mut results = RunResults::default();
+    run_runconfig(&run_config, &mut results, None).unwrap();
+    assert!(results.point_results.is_empty());
+
+    let mut args = Args::test_default(V5_INPUT_PATH.clone());
+    args.max_session_minutes = Some(1);
+    args.start_session_budget();
+    assert!(!args.budget_exhausted());
+
+    args.max_session_minutes = Some(u64::MAX);
+    args.start_session_budget();
+    assert_eq!(args.session_deadline, None);
+    assert!(!args.budget_exhausted());
+}
+
+#[test]
+fn test_try_artifacts_dir() {
+    let artifacts =
+        std::env::temp_dir().join(format!("hitrace-bench-artifacts-{}", std::process::id()));
+    let run_config = RunConfig::new(
+        Args::test_default(V5_INPUT_PATH.clone()),
+        RunArgs::default(),
+        vec![],
+        vec![],
+    );
+    let first = crate::try_artifacts_dir(&artifacts, &run_config, 1);
+    assert_eq!(first, artifacts.join("https___servo.org").join("try-1"));
+    std::fs::create_dir_all(&first).unwrap();
+    assert_eq!(
+        crate::try_artifacts_dir(&artifacts, &run_config, 1),
+        artifacts.join("https___servo.org").join("try-1-2")
+    );
+    std::fs::remove_dir_all(artifacts).unwrap();
+}
+
+#[test]
+fn test_filter_error_kinds_v5() {
+    let args = Args::test_default(V5_INPUT_PATH.clone());
+    let filter = |name: &str, first: &'static str, last: &'static str| Filter {
+        name: String::from(name),
+        first: Box::new(move |t: &Trace| t.function.contains(first)),
+        last: Box::new(move |t: &Trace| t.function.contains(last)),
+        measure: None,
+        expected_range: None,
+    };
+    let mut run_results = RunResults::default();
+    run_runconfig(
+        &RunConfig::new(
+            args,
+            RunArgs::default(),
+            vec![
+                filter("NoStart", "does-not-exist", "PageLoadEndedPrompt"),
+                filter("NoEnd", "on_surface_created_cb", "does-not-exist"),
+                filter("Ambiguous", "servo_memory_profiling", "PageLoadEndedPrompt"),
+            ],
+            vec![],
+        ),
+        &mut run_results,
+        None,
+    )
+    .unwrap();
+    let kinds = |name: &str| run_results.errors[name].keys().copied().collect::<Vec<_>>();
+    assert_eq!(kinds("NoStart"), vec![ErrorKind::NoStartMarker]);
+    assert_eq!(kinds("NoEnd"), vec![ErrorKind::NoEndMarker]);
+    assert_eq!(kinds("Ambiguous"), vec![ErrorKind::AmbiguousMatch]);
+}
+
+#[test]
+fn test_malformed_point_trace_v5() {
+    let args = Args::test_default(PathBuf::from("testdata/v5_1_1_malformed.ftrace"));
+    let paint_filter = |name: &str| PointFilter {
+        name: String::from(name),
+        match_str: String::from(name),
+        no_unit_conversion: true,
+        point_filter_type: PointFilterType::Largest,
+        ..Default::default()
+    };
+    let mut run_results = RunResults::default();
+    run_runconfig(
+        &RunConfig::new(
+            args,
+            RunArgs::default(),
+            vec![],
+            vec![
+                paint_filter("LargestContentfulPaint"),
+                paint_filter("FirstContentfulPaint"),
+            ],
+        ),
+        &mut run_results,
+        None,
+    )
+    .expect("A malformed point trace must not abort the run");
+
+    assert_eq!(
+        run_results
+            .errors
+            .get("https://servo.org/LargestContentfulPaint"),
+        Some(&ErrorCounts::from([(ErrorKind::ParseFailure, 1)]))
+    );
+    assert_eq!(
+        run_results.point_results["https://servo.org/LargestContentfulPaint/area"].result,
+        vec![90810]
+    );
+    assert_eq!(
+        run_results.point_results["https://servo.org/FirstContentfulPaint/paint_time"].result,
+        vec![231277100000000]
+    );
+}
+
 fn test_filters(
     input_file: PathBuf,
     filter: Vec<Filter>,
@@ -226,25 +1375,18 @@ fn test_filters(
 ) -> Option<serde_json::Value> {
     let args = Args::test_default(input_file);
 
-    let mut filter_results = HashMap::new();
-    let mut filter_errors = HashMap::new();
-    let mut point_results = HashMap::new();
+    let mut run_results = RunResults {
+        prepend: args.prepend.clone(),
+        ..Default::default()
+    };
 
     run_runconfig(
         &RunConfig::new(args.clone(), RunArgs::default(), filter, point_filters),
-        &mut filter_results,
-        &mut filter_errors,
-        &mut point_results,
+        &mut run_results,
+        None,
     )
     .expect("Could not create run_config");
 
-    let run_results = RunResults {
-        prepend: args.prepend.clone(),
-        filter_results,
-        errors: filter_errors,
-        point_results,
-    };
-
     Some(
         serde_json::from_str(
             &generate_result_json_str(run_results).expect("Error generating json result"),
@@ -269,11 +1411,15 @@ fn parsing_common_with_extra_filters(
             name: String::from("Surface->LoadStart"),
             first: Box::new(|t: &Trace| t.function.contains("on_surface_created_cb")),
             last: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
+            measure: None,
+            expected_range: None,
         },
         Filter {
             name: String::from("Load->Compl"),
             first: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
             last: Box::new(|t: &Trace| t.function.contains("PageLoadEndedPrompt")),
+            measure: None,
+            expected_range: None,
         },
     ];
     let point_filters = vec![
@@ -282,6 +1428,7 @@ fn parsing_common_with_extra_filters(
             match_str: String::from("explicit"),
             no_unit_conversion: false,
             point_filter_type: PointFilterType::Default,
+            ..Default::default()
         },
         PointFilter::new(String::from("Resident"), String::from("resident")),
         PointFilter::new(String::from("LayoutThread"), String::from("layout-thread")),
@@ -292,6 +1439,7 @@ fn parsing_common_with_extra_filters(
             match_str: String::from("resident-according-to-smaps"),
             no_unit_conversion: false,
             point_filter_type: PointFilterType::Combined,
+            ..Default::default()
         },
     ];
 
@@ -328,29 +1476,81 @@ fn test_run_with_old_json() {
 
     env_logger::builder().filter_level(be_loud_filter).init();
 
-    let mut filter_results = HashMap::new();
-    let mut filter_errors = HashMap::new();
-    let mut point_results = HashMap::new();
+    let mut results = RunResults {
+        prepend: args.prepend.clone(),
+        ..Default::default()
+    };
     for run_config in run_configs {
-        run_runconfig(
-            &run_config,
-            &mut filter_results,
-            &mut filter_errors,
-            &mut point_results,
-        )
-        .unwrap();
+        run_runconfig(&run_config, &mut results, None).unwrap();
     }
 
-    let result = bencher::generate_result_json_str(RunResults {
-        prepend: args.prepend.clone(),
-        filter_results,
-        errors: filter_errors,
-        point_results,
-    })
-    .unwrap();
+    let result = bencher::generate_result_json_str(results).unwrap();
 
     assert_eq!(
         serde_json::from_str::<serde_json::Value>(&result).unwrap(),
         serde_json::from_str::<serde_json::Value>(runs_output).expect("Could not parse json")
     )
 }
+
+#[test]
+fn test_synthesized_scenario() {
+    let scenario =
+        crate::synthesize::Scenario::read(&PathBuf::from("testdata/scenario_load.json5")).unwrap();
+    let path = temp_path("scenario-load.ftrace");
+    std::fs::write(&path, scenario.to_ftrace()).unwrap();
+    let run_config = RunConfig::new(
+        Args::test_default(path.clone()),
+        RunArgs::default(),
+        vec![Filter {
+            name: String::from("Load"),
+            first: Box::new(|t: &Trace| t.function.contains("load status changed Head")),
+            last: Box::new(|t: &Trace| t.function.contains("PageLoadEndedPrompt")),
+            measure: None,
+            expected_range: None,
+        }],
+        vec![PointFilter::new(
+            String::from("Resident"),
+            String::from("resident"),
+        )],
+    );
+    let mut results = RunResults::default();
+    let result = run_runconfig(&run_config, &mut results, None);
+    std::fs::remove_file(&path).unwrap();
+    result.unwrap();
+    assert_eq!(
+        results.filter_results["Load"],
+        vec![Duration::milliseconds(1200)]
+    );
+    assert_eq!(
+        results.point_results["https://servo.org/Resident"].result,
+        vec![300000000]
+    );
+}
+
+#[test]
+fn test_testcase_markers() {
+    let scenario =
+        crate::synthesize::Scenario::read(&PathBuf::from("testdata/scenario_testcase.json5"))
+            .unwrap();
+    let path = temp_path("scenario-testcase.ftrace");
+    std::fs::write(&path, scenario.to_ftrace()).unwrap();
+    let run_config = RunConfig::new(
+        Args::test_default(path.clone()),
+        RunArgs::default(),
+        vec![],
+        vec![],
+    );
+    let mut results = RunResults::default();
+    let result = run_runconfig(&run_config, &mut results, None);
+    std::fs::remove_file(&path).unwrap();
+    result.unwrap();
+    assert_eq!(
+        results.filter_results["load"],
+        vec![Duration::milliseconds(100)]
+    );
+    assert_eq!(
+        results.filter_results["parse"],
+        vec![Duration::milliseconds(20), Duration::microseconds(30500)]
+    );
+    assert!(!results.filter_results.contains_key("render"));
+}
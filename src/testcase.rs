@@ -0,0 +1,91 @@
+//! Durations of app defined testcases. Apps emit `TESTCASE_PROFILING_START:<name>` and
+//! `TESTCASE_PROFILING_END:<name>` markers and get the time between them as the metric `<name>` without writing
+//! a filter for it.
+use std::{collections::HashMap, sync::LazyLock};
+
+use regex::Regex;
+
+use crate::{
+    trace::{Trace, TraceMarker, difference_of_traces},
+    utils::RunResults,
+};
+
+/// `TESTCASE_PROFILING_START:generatehtml`, v5 appends a tag like `|M62`
+static TESTCASE_MARKER_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^TESTCASE_PROFILING_(START|END):\s*(.*?)\s*(?:\|[A-Z]\d+)?\s*$")
+        .expect("Could not parse regexp")
+});
+
+/// Whether `trace` starts or ends a testcase, and its name
+fn testcase_marker(trace: &Trace) -> Option<(bool, String)> {
+    if trace.trace_marker != TraceMarker::StartSync {
+        return None;
+    }
+    // Without a `H:` prefix the parser takes `TESTCASE_PROFILING_START` for the shorthand
+    let marker = if trace.shorthand.starts_with("TESTCASE_PROFILING_") {
+        format!("{}:{}", trace.shorthand, trace.function)
+    } else {
+        trace.function.clone()
+    };
+    let groups = TESTCASE_MARKER_REGEX.captures(&marker)?;
+    Some((&groups[1] == "START", groups.get(2)?.as_str().to_owned()))
+}
+
+/// Records the duration of every pair of testcase markers. An end belongs to the last open start of the same
+/// name, so testcases can nest. Returns the number of durations.
+pub(crate) fn record_testcase_durations(traces: &[Trace], results: &mut RunResults) -> usize {
+    let mut open: HashMap<String, Vec<&Trace>> = HashMap::new();
+    let mut found = 0;
+    for (trace, (start, name)) in traces
+        .iter()
+        .filter_map(|t| testcase_marker(t).map(|m| (t, m)))
+    {
+        if start {
+            open.entry(name).or_default().push(trace);
+            continue;
+        }
+        match open.get_mut(&name).and_then(Vec::pop) {
+            Some(start) => {
+                results
+                    .filter_results
+                    .entry(name)
+                    .or_default()
+                    .push(difference_of_traces(trace, start));
+                found += 1;
+            }
+            None => log::warn!(
+                "Testcase {name} ended at {} without a start",
+                trace.timestamp
+            ),
+        }
+    }
+    for (name, starts) in open {
+        for start in starts {
+            log::warn!(
+                "Testcase {name} started at {} but never ended",
+                start.timestamp
+            );
+        }
+    }
+    found
+}
+
+#[test]
+fn test_testcase_marker() {
+    use crate::parser::{HitraceVersion, parse_line};
+
+    let marker = |function: &str| {
+        let line =
+            format!("<...>-1   (-------) [010] .... 10.5: tracing_mark_write: B|1|{function}");
+        testcase_marker(&parse_line(HitraceVersion::V5, &line).unwrap().unwrap())
+    };
+    assert_eq!(
+        marker("H:TESTCASE_PROFILING_START:generate html|M62"),
+        Some((true, String::from("generate html")))
+    );
+    assert_eq!(
+        marker("TESTCASE_PROFILING_END:load"),
+        Some((false, String::from("load")))
+    );
+    assert_eq!(marker("H:TESTCASE_PROFILING: generatehtml|1720|M62"), None);
+}
@@ -0,0 +1,191 @@
+//! Thresholds of every metric from its variance over the sessions of an `analyze` history, so they follow the
+//! real noise of the metric. The `thresholds` subcommand writes them in the boundary names of bencher and
+//! `--thresholds` fails a session whose metrics are outside of them.
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{args::ThresholdsArgs, canonical, utils::RunResults};
+
+/// We need this many sessions with a metric to know its variance
+const MIN_SESSIONS: usize = 2;
+
+/// The threshold of one metric, durations are in milliseconds
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Threshold {
+    /// Mean of the means of all sessions
+    mean: f64,
+    /// Standard deviation of the means of all sessions
+    stddev: f64,
+    lower_boundary: f64,
+    upper_boundary: f64,
+    /// Number of sessions with the metric
+    sessions: usize,
+}
+
+/// The thresholds of all metrics by result key
+pub(crate) type Thresholds = BTreeMap<String, Threshold>;
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// The mean of every metric of every run of `session`, durations in milliseconds
+fn session_means(session: &Value) -> impl Iterator<Item = (&String, f64)> {
+    session["runs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|run| run["metrics"].as_object())
+        .flatten()
+        .filter_map(|(key, metric)| {
+            let scale = if metric["unit"] == "nanoseconds" {
+                1e-6
+            } else {
+                1.0
+            };
+            let samples: Vec<f64> = metric["samples"]
+                .as_array()?
+                .iter()
+                .filter_map(Value::as_f64)
+                .collect();
+            (!samples.is_empty()).then(|| (key, mean(&samples) * scale))
+        })
+}
+
+/// Thresholds of `sigmas` standard deviations around the mean of every metric of `history`
+fn thresholds(history: &Value, sigmas: f64) -> Thresholds {
+    let mut means: BTreeMap<&String, Vec<f64>> = BTreeMap::new();
+    for session in history.as_array().into_iter().flatten() {
+        for (key, value) in session_means(session) {
+            means.entry(key).or_default().push(value);
+        }
+    }
+    means
+        .into_iter()
+        .filter(|(_, values)| values.len() >= MIN_SESSIONS)
+        .map(|(key, values)| {
+            let mean = mean(&values);
+            let variance =
+                values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+            let stddev = variance.sqrt();
+            let threshold = Threshold {
+                mean,
+                stddev,
+                lower_boundary: mean - sigmas * stddev,
+                upper_boundary: mean + sigmas * stddev,
+                sessions: values.len(),
+            };
+            (key.clone(), threshold)
+        })
+        .collect()
+}
+
+/// Reads the thresholds file at `path`
+pub(crate) fn read(path: &Path) -> Result<Thresholds> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Could not read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Could not parse {}", path.display()))
+}
+
+/// The metrics of `results` whose mean is outside of their threshold, with why
+pub(crate) fn violations(thresholds: &Thresholds, results: &RunResults) -> Vec<String> {
+    let durations = results.filter_results.iter().map(|(key, durations)| {
+        let values: Vec<f64> = durations
+            .iter()
+            .map(|d| d.as_seconds_f64() * 1000.0)
+            .collect();
+        (key, values)
+    });
+    let points = results.point_results.iter().map(|(key, points)| {
+        let values: Vec<f64> = points.result.iter().map(|&p| p as f64).collect();
+        (key, values)
+    });
+    durations
+        .chain(points)
+        .filter(|(_, values)| !values.is_empty())
+        .filter_map(|(key, values)| {
+            let threshold = thresholds.get(key)?;
+            let value = mean(&values);
+            (value < threshold.lower_boundary || value > threshold.upper_boundary).then(|| {
+                format!(
+                    "{key}: {value:.2} is outside of {:.2}..={:.2}",
+                    threshold.lower_boundary, threshold.upper_boundary
+                )
+            })
+        })
+        .collect()
+}
+
+/// Writes the thresholds of the history of `args`
+pub(crate) fn run(args: &ThresholdsArgs, canonical_json: bool) -> Result<()> {
+    let content = fs::read_to_string(&args.history)
+        .with_context(|| format!("Could not read {}", args.history.display()))?;
+    let history: Value = serde_json::from_str(&content)
+        .with_context(|| format!("Could not parse {}", args.history.display()))?;
+    let thresholds = canonical::to_string(&thresholds(&history, args.sigmas), canonical_json)?;
+    match args.output {
+        Some(ref output) => fs::write(output, thresholds)
+            .with_context(|| format!("Could not write {}", output.display())),
+        None => {
+            println!("{thresholds}");
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn test_thresholds() {
+    use crate::utils::PointResult;
+    use time::Duration;
+
+    let session = |load_ms: i64, resident: i64| {
+        serde_json::json!({"runs": [{"metrics": {
+            "Load": {"unit": "nanoseconds", "samples": [load_ms * 1_000_000]},
+            "https://servo.org/Resident": {"unit": "bytes", "samples": [resident, resident]},
+        }}]})
+    };
+    let history = serde_json::json!([
+        session(100, 1000),
+        session(110, 1000),
+        session(120, 1000),
+        {"runs": [{"metrics": {"New": {"unit": "none", "samples": [1]}}}]},
+    ]);
+    let thresholds = thresholds(&history, 2.0);
+    assert_eq!(
+        thresholds["Load"],
+        Threshold {
+            mean: 110.0,
+            stddev: 10.0,
+            lower_boundary: 90.0,
+            upper_boundary: 130.0,
+            sessions: 3,
+        }
+    );
+    assert_eq!(
+        thresholds["https://servo.org/Resident"].upper_boundary,
+        1000.0
+    );
+    assert!(!thresholds.contains_key("New"));
+
+    let mut results = RunResults::default();
+    results
+        .filter_results
+        .insert(String::from("Load"), vec![Duration::milliseconds(125)]);
+    results.point_results.insert(
+        String::from("https://servo.org/Resident"),
+        PointResult {
+            no_unit_conversion: false,
+            result: vec![1001],
+        },
+    );
+    assert_eq!(
+        violations(&thresholds, &results),
+        vec![String::from(
+            "https://servo.org/Resident: 1001.00 is outside of 1000.00..=1000.00"
+        )]
+    );
+}
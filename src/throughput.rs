@@ -0,0 +1,113 @@
+//! Throughputs computed from a point filter measuring bytes and a filter measuring the time they took, i.e., the
+//! bytes a page fetched divided by its load time. They are reported in bytes per second with the `Throughput`
+//! measure.
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    runconfig::RunConfig,
+    utils::{PointResult, RunResults},
+};
+
+/// The bencher measure of throughputs
+pub(crate) const THROUGHPUT_MEASURE: &str = "Throughput";
+
+/// A throughput of the run file
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Throughput {
+    pub(crate) name: String,
+    /// Name of the point filter whose last value are the bytes
+    pub(crate) bytes: String,
+    /// Name of the filter whose first duration is the time
+    pub(crate) duration: String,
+}
+
+/// Whether the result `key` belongs to the filter or point filter `name`, keys can have the url of the run in front
+pub(crate) fn is_metric(key: &str, name: &str) -> bool {
+    key == name || key.ends_with(&format!("/{name}"))
+}
+
+/// Computes the throughputs of `run_config` from the filter and point results of one navigation and adds them to
+/// `results`. Returns the number of throughputs.
+pub(crate) fn record_throughputs(run_config: &RunConfig, results: &mut RunResults) -> usize {
+    let mut found = 0;
+    for throughput in &run_config.throughputs {
+        let bytes = results
+            .point_results
+            .iter()
+            .find(|(key, _)| is_metric(key, &throughput.bytes))
+            .and_then(|(_, points)| points.result.last());
+        let duration = results
+            .filter_results
+            .iter()
+            .find(|(key, _)| is_metric(key, &throughput.duration))
+            .and_then(|(_, durations)| durations.first());
+        let (Some(bytes), Some(duration)) = (bytes, duration) else {
+            log::warn!(
+                "Throughput {}: missing {} or {}",
+                throughput.name,
+                throughput.bytes,
+                throughput.duration
+            );
+            continue;
+        };
+        if !duration.is_positive() {
+            log::warn!(
+                "Throughput {}: the duration {duration} is not positive",
+                throughput.name
+            );
+            continue;
+        }
+        let per_second = (*bytes as f64 / duration.as_seconds_f64()).round() as i64;
+        let key = format!("{}/{}", run_config.run_args.url, throughput.name);
+        results
+            .measures
+            .insert(key.clone(), String::from(THROUGHPUT_MEASURE));
+        results
+            .point_results
+            .entry(key)
+            .or_insert(PointResult {
+                no_unit_conversion: false,
+                result: Vec::new(),
+            })
+            .result
+            .push(per_second);
+        found += 1;
+    }
+    found
+}
+
+#[test]
+fn test_throughput() {
+    use crate::args::{Args, RunArgs};
+    use time::Duration;
+
+    let mut run_config = RunConfig::new(
+        Args::test_default(std::path::PathBuf::new()),
+        RunArgs::default(),
+        vec![],
+        vec![],
+    );
+    run_config.throughputs = vec![Throughput {
+        name: String::from("Fetch throughput"),
+        bytes: String::from("Fetched"),
+        duration: String::from("Load"),
+    }];
+    let url = &run_config.run_args.url;
+    let mut results = RunResults::default();
+    results
+        .filter_results
+        .insert(format!("{url}/Load"), vec![Duration::milliseconds(500)]);
+    assert_eq!(record_throughputs(&run_config, &mut results), 0);
+    results.point_results.insert(
+        format!("{url}/Fetched"),
+        PointResult {
+            no_unit_conversion: false,
+            result: vec![1_000, 3_000],
+        },
+    );
+    assert_eq!(record_throughputs(&run_config, &mut results), 1);
+    let key = format!("{url}/Fetch throughput");
+    assert_eq!(results.point_results[&key].result, vec![6_000]);
+    assert_eq!(results.measures[&key], THROUGHPUT_MEASURE);
+}
@@ -1,29 +1,54 @@
 /// Functions about the traces
 use anyhow::{Context, Result, anyhow};
-use log::error;
 use regex::Regex;
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use serde::{Deserialize, Serialize};
 use std::{
     fmt::{Debug, Display, write},
-    fs::File,
-    io::{BufRead, BufReader},
-    path::Path,
+    sync::LazyLock,
 };
-use time::Duration;
+use time::{Duration, OffsetDateTime, format_description::well_known::Rfc3339};
 
-#[derive(Clone, Debug)]
-pub(crate) struct TimeStamp {
-    pub(crate) seconds: u64,
-    pub(crate) micro: u64,
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TimeStamp {
+    pub seconds: u64,
+    /// The fraction of the second in nanoseconds, whatever number of digits the trace had
+    pub nanos: u64,
+}
+
+impl TimeStamp {
+    /// Converts the device boot clock timestamp into an absolute wall-clock time.
+    pub(crate) fn to_wall_clock(&self, offset: ClockOffset) -> OffsetDateTime {
+        offset.0 + Duration::seconds(self.seconds as i64) + Duration::nanoseconds(self.nanos as i64)
+    }
+}
+
+/// The wall-clock time at which the device boot clock, which hitrace uses for its timestamps, was zero.
+/// Adding a `TimeStamp` to it gives the absolute time of a trace.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ClockOffset(pub(crate) OffsetDateTime);
+
+impl ClockOffset {
+    /// Computes the offset from the device realtime clock and the uptime, both in seconds, read at the same instant.
+    pub(crate) fn from_realtime_and_uptime(realtime: Decimal, uptime: Decimal) -> Result<Self> {
+        let nanos = ((realtime - uptime) * Decimal::from(1_000_000_000))
+            .trunc()
+            .to_i128()
+            .context("Device clock is out of range")?;
+        let boot = OffsetDateTime::from_unix_timestamp_nanos(nanos)
+            .context("Device clock is out of range")?;
+        Ok(ClockOffset(boot))
+    }
 }
 
 impl Display for TimeStamp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write(f, format_args!("{}.{:6}", self.seconds, self.micro))
+        write(f, format_args!("{}.{:09}", self.seconds, self.nanos))
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub(crate) enum TraceMarker {
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TraceMarker {
     StartSync,
     EndSync,
     StartAsync,
@@ -44,31 +69,68 @@ impl TraceMarker {
     }
 }
 
-#[derive(Clone)]
+/// The name and value of a counter trace, i.e., a trace with `TraceMarker::Dot`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Counter {
+    /// Name of the counter, i.e., `servo_memory_profiling:resident`
+    pub name: String,
+    /// The value the counter was set to
+    pub value: i64,
+}
+
+/// Newer hitrace versions separate the value with `|` and append a tag, i.e., `servo_memory_profiling:vsize|45766221824|M62`
+static COUNTER_TAGGED_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(.*?)\|(-?\d+)\|\w*$").expect("Could not parse regexp"));
+
+/// Older hitrace versions separate the value with a space, i.e., `servo_memory_profiling:system-fonts 28896`
+static COUNTER_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(.*?)\s+(-?\d+)$").expect("Could not parse regexp"));
+
+impl Counter {
+    /// Parse the function part of a counter trace into name and value
+    pub(crate) fn parse(function: &str) -> Option<Self> {
+        let function = function.trim_end();
+        COUNTER_TAGGED_REGEX
+            .captures(function)
+            .or_else(|| COUNTER_REGEX.captures(function))
+            .and_then(|groups| {
+                Some(Counter {
+                    name: groups.get(1)?.as_str().to_owned(),
+                    value: groups.get(2)?.as_str().parse().ok()?,
+                })
+            })
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 /// A parsed trace
-pub(crate) struct Trace {
+pub struct Trace {
     /// Name of the thread, i.e., `org.servo.servo`` or `Constellation`
     #[allow(unused)]
-    pub(crate) name: String,
+    pub name: String,
     /// tid, aka thread id
     #[allow(unused)]
-    pub(crate) tid: u64,
+    pub tid: u64,
     /// the cpu it ran on
     #[allow(unused)]
-    pub(crate) cpu: u64,
+    pub cpu: u64,
     /// timestamp of the trace
-    pub(crate) timestamp: TimeStamp,
+    pub timestamp: TimeStamp,
     /// Tells us if the trace ended and when
     #[allow(unused)]
-    pub(crate) trace_marker: TraceMarker,
+    pub trace_marker: TraceMarker,
     /// No idea what this is
     #[allow(unused)]
-    pub(crate) number: String,
+    pub number: String,
     /// Some shorthand code
     #[allow(unused)]
-    pub(crate) shorthand: String,
+    pub shorthand: String,
     /// Full function name
-    pub(crate) function: String,
+    pub function: String,
+    /// The counter name and value if this is a `TraceMarker::Dot` trace
+    pub counter: Option<Counter>,
+    /// Absolute time of the trace if we know the clock offset of the device
+    pub wall_clock: Option<OffsetDateTime>,
 }
 
 impl Debug for Trace {
@@ -77,98 +139,64 @@ impl Debug for Trace {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Trace: {}-{} ... {}.{}: {}",
-            self.name, self.tid, self.timestamp.seconds, self.timestamp.micro, self.function,
-        )
+            "Trace: {}-{} ... {}.{:09}: {}",
+            self.name, self.tid, self.timestamp.seconds, self.timestamp.nanos, self.function,
+        )?;
+        if let Some(wall_clock) = self.wall_clock {
+            let wall_clock = wall_clock.format(&Rfc3339).map_err(|_| std::fmt::Error)?;
+            write!(f, " ({wall_clock})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Annotates all traces with their absolute wall-clock time
+pub(crate) fn apply_clock_offset(traces: &mut [Trace], offset: ClockOffset) {
+    for trace in traces {
+        trace.wall_clock = Some(trace.timestamp.to_wall_clock(offset));
     }
 }
 
 /// Calculates the timestamp difference equaivalent to trace1-trace2
-pub(crate) fn difference_of_traces(trace1: &Trace, trace2: &Trace) -> Duration {
+pub fn difference_of_traces(trace1: &Trace, trace2: &Trace) -> Duration {
     Duration::new(
         trace1.timestamp.seconds as i64 - trace2.timestamp.seconds as i64,
-        (trace1.timestamp.micro as i32 - trace2.timestamp.micro as i32) * 1000,
+        trace1.timestamp.nanos as i32 - trace2.timestamp.nanos as i32,
     )
 }
 
-/// There is always one trace per line
-/// This means that having no matched lines is ok and returns None. Having a parsing error returns Some(Err)
-fn line_to_trace(regex: &Regex, line: &str) -> Option<Result<Trace>> {
-    regex
-        .captures_iter(line)
-        .map(|c| c.extract())
-        .map(match_to_trace)
-        .next()
-}
-
-/// Read a regex matched line into a trace
-fn match_to_trace(
-    (
-        _line,
-        [
-            name,
-            tid,
-            cpu,
-            time1,
-            time2,
-            trace_marker,
-            number,
-            shorthand,
-            msg,
-        ],
-    ): (&str, [&str; 9]),
-) -> Result<Trace> {
-    let seconds = time1.parse()?;
-    let microseconds = time2.parse()?;
+#[test]
+fn test_wall_clock_from_offset() {
+    let offset = ClockOffset::from_realtime_and_uptime(
+        Decimal::from_str_exact("1700000100.5").unwrap(),
+        Decimal::from_str_exact("100.25").unwrap(),
+    )
+    .unwrap();
     let timestamp = TimeStamp {
-        seconds,
-        micro: microseconds,
+        seconds: 50,
+        nanos: 250_000_000,
     };
-    let trace_marker = TraceMarker::from(trace_marker)?;
-    Ok(Trace {
-        name: name.to_owned(),
-        tid: tid.parse().unwrap_or(0),
-        cpu: cpu.parse().unwrap_or(0),
-        trace_marker,
-        number: number.to_string(),
-        timestamp,
-        shorthand: shorthand.to_owned(),
-        function: msg.to_owned(),
-    })
+    assert_eq!(
+        timestamp.to_wall_clock(offset),
+        OffsetDateTime::from_unix_timestamp(1_700_000_050).unwrap() + Duration::milliseconds(500)
+    );
 }
 
-const REGEX_STRING: &str = concat!(
-    r"^\s*(.*?)\-(\d+)\s*\(\s*(\d+)\).*?(\d+)\.(\d+): tracing_mark_write: (.)\|(\d+?)\|(.*?):(.*)\s*$",
-    "|",
-    r"^\s*(.*?)\-(\d+)\s*\((.*?)\).*?(\d+)\.(\d+): tracing_mark_write: (.)\|(\d+?)\|(.*?):(.*)\s*$"
-);
-
-/// Read a file into traces
-pub(crate) fn read_file(f: &Path) -> Result<Vec<Trace>> {
-    // This is more specific servo tracing with the tracing_mark_write
-    // Example trace: ` org.servo.servo-44962   (  44682) [010] .... 17864.716645: tracing_mark_write: B|44682|ML: do_single_part3_compilation`
-    let regex = Regex::new(REGEX_STRING).expect("Could not read regex");
-    let f = File::open(f).context("Could not find hitrace file")?;
-    let reader = BufReader::new(f);
-
-    let (valid_lines, invalid_lines): (Vec<_>, Vec<_>) = reader
-        .lines()
-        .enumerate()
-        .partition(|(_index, l)| l.is_ok());
-
-    if !invalid_lines.is_empty() {
-        error!(
-            "Could not read lines {:?}",
-            invalid_lines
-                .iter()
-                .map(|(index, _l)| index)
-                .collect::<Vec<_>>()
-        );
-    }
-
-    valid_lines
-        .into_iter()
-        .filter_map(|(_index, l)| line_to_trace(&regex, &l.unwrap()))
-        .collect::<Result<Vec<Trace>>>()
-        .context("Could not parse one thing")
+#[test]
+fn test_counter_parsing() {
+    assert_eq!(
+        Counter::parse("servo_memory_profiling:vsize|45766221824|M62"),
+        Some(Counter {
+            name: String::from("servo_memory_profiling:vsize"),
+            value: 45766221824
+        })
+    );
+    assert_eq!(
+        Counter::parse("TESTCASE_PROFILING: generatehtml 1720 "),
+        Some(Counter {
+            name: String::from("TESTCASE_PROFILING: generatehtml"),
+            value: 1720
+        })
+    );
+    assert_eq!(Counter::parse("no value here"), None);
 }
@@ -0,0 +1,260 @@
+//! The `trend` subcommand charting a metric over the sessions of a history written by `analyze`, as text or as
+//! svg, with the change points where the metric moved to a new level.
+use std::{fmt::Write, fs};
+
+use anyhow::{Context, Result, anyhow};
+use serde_json::Value;
+
+use crate::{args::TrendArgs, change_point::e_divisive, throughput::is_metric, utils::sparkline};
+
+/// Width of the bars of the text chart in characters
+const BAR_WIDTH: usize = 40;
+
+/// Size of the svg chart in pixels
+const SVG_WIDTH: f64 = 800.0;
+const SVG_HEIGHT: f64 = 300.0;
+const SVG_MARGIN: f64 = 40.0;
+
+/// The mean of the metric in one session of the history
+#[derive(Debug, PartialEq)]
+struct Point {
+    session: String,
+    /// When the session started, if the history knows
+    started: Option<String>,
+    value: f64,
+}
+
+/// The mean of `metric` in every session of `history` that has it, durations in milliseconds.
+/// Returns the points and the unit.
+fn points(history: &Value, metric: &str) -> (Vec<Point>, &'static str) {
+    let mut unit = "";
+    let points = history
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|session| {
+            let (_, found) = session["runs"]
+                .as_array()?
+                .iter()
+                .filter_map(|run| run["metrics"].as_object())
+                .flatten()
+                .find(|(key, _)| is_metric(key, metric))?;
+            let scale = if found["unit"] == "nanoseconds" {
+                unit = "ms";
+                1e-6
+            } else {
+                1.0
+            };
+            let samples: Vec<f64> = found["samples"]
+                .as_array()?
+                .iter()
+                .filter_map(Value::as_f64)
+                .collect();
+            (!samples.is_empty()).then(|| Point {
+                session: session["session"].as_str().unwrap_or_default().to_owned(),
+                started: session["started"].as_str().map(str::to_owned),
+                value: samples.iter().sum::<f64>() / samples.len() as f64 * scale,
+            })
+        })
+        .collect();
+    (points, unit)
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// The change points of `values` by e-divisive with `significance` where the level moved by more than
+/// `min_change` percent
+fn change_points(values: &[f64], significance: f64, min_change: f64) -> Vec<usize> {
+    let changes = e_divisive(values, significance);
+    changes
+        .iter()
+        .copied()
+        .filter(|&i| level_change(values, &changes, i).abs() > min_change)
+        .collect()
+}
+
+/// The relative change at change point `i` from the previous to the next segment
+fn level_change(values: &[f64], changes: &[usize], i: usize) -> f64 {
+    let position = changes.iter().position(|&c| c == i).unwrap_or_default();
+    let start = position.checked_sub(1).map_or(0, |p| changes[p]);
+    let end = changes.get(position + 1).copied().unwrap_or(values.len());
+    let (before, after) = (mean(&values[start..i]), mean(&values[i..end]));
+    if before == 0.0 {
+        0.0
+    } else {
+        (after - before) / before * 100.0
+    }
+}
+
+/// A line per session with a bar of its value, change points are marked with their change
+fn text_chart(metric: &str, unit: &str, points: &[Point], changes: &[usize]) -> String {
+    let values: Vec<f64> = points.iter().map(|p| p.value).collect();
+    let max = values.iter().copied().fold(0.0, f64::max);
+    let label_width = points.iter().map(|p| p.session.len()).max().unwrap_or(0);
+    let mut chart = format!("{metric} {}\n", sparkline(&values));
+    for (i, point) in points.iter().enumerate() {
+        let bar = if max > 0.0 {
+            (point.value / max * BAR_WIDTH as f64).round() as usize
+        } else {
+            0
+        };
+        let _ = write!(
+            chart,
+            "{:label_width$} {:>12.2}{unit} {}",
+            point.session,
+            point.value,
+            "█".repeat(bar)
+        );
+        if changes.contains(&i) {
+            let _ = write!(
+                chart,
+                " <- change {:+.1}%",
+                level_change(&values, changes, i)
+            );
+        }
+        chart.push('\n');
+    }
+    if let Some(point) = changes.first().map(|&i| &points[i]) {
+        let _ = writeln!(
+            chart,
+            "First shift in session {}{}",
+            point.session,
+            point
+                .started
+                .as_ref()
+                .map_or_else(String::new, |started| format!(" started {started}"))
+        );
+    }
+    chart
+}
+
+/// The chart as svg, a line through the sessions with a dashed vertical line at every change point
+fn svg_chart(metric: &str, unit: &str, points: &[Point], changes: &[usize]) -> String {
+    let values: Vec<f64> = points.iter().map(|p| p.value).collect();
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let x = |i: usize| {
+        SVG_MARGIN + i as f64 * (SVG_WIDTH - 2.0 * SVG_MARGIN) / (points.len().max(2) - 1) as f64
+    };
+    let y = |v: f64| {
+        let position = if max > min {
+            (v - min) / (max - min)
+        } else {
+            0.5
+        };
+        SVG_HEIGHT - SVG_MARGIN - position * (SVG_HEIGHT - 2.0 * SVG_MARGIN)
+    };
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{SVG_WIDTH}\" height=\"{SVG_HEIGHT}\" font-family=\"sans-serif\" font-size=\"12\">\n"
+    );
+    let _ = writeln!(
+        svg,
+        "<text x=\"{SVG_MARGIN}\" y=\"20\">{} ({min:.2}{unit} .. {max:.2}{unit})</text>",
+        escape(metric)
+    );
+    for &i in changes {
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{0:.1}\" y1=\"{SVG_MARGIN}\" x2=\"{0:.1}\" y2=\"{1:.1}\" stroke=\"red\" stroke-dasharray=\"4\"/>\n<text x=\"{0:.1}\" y=\"{2:.1}\" fill=\"red\">{3:+.1}%</text>",
+            x(i),
+            SVG_HEIGHT - SVG_MARGIN,
+            SVG_MARGIN - 4.0,
+            level_change(&values, changes, i)
+        );
+    }
+    let line = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| format!("{:.1},{:.1}", x(i), y(*v)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let _ = writeln!(
+        svg,
+        "<polyline points=\"{line}\" fill=\"none\" stroke=\"steelblue\" stroke-width=\"2\"/>"
+    );
+    for (i, point) in points.iter().enumerate() {
+        let _ = writeln!(
+            svg,
+            "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"steelblue\"><title>{}: {:.2}{unit}</title></circle>",
+            x(i),
+            y(point.value),
+            escape(&point.session),
+            point.value
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// `text` with the characters xml gives a meaning escaped
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Charts the metric of `args` from its history as text, or as svg with `--svg`
+pub(crate) fn run(args: &TrendArgs) -> Result<()> {
+    let content = fs::read_to_string(&args.history)
+        .with_context(|| format!("Could not read {}", args.history.display()))?;
+    let history: Value = serde_json::from_str(&content)
+        .with_context(|| format!("Could not parse {}", args.history.display()))?;
+    let (points, unit) = points(&history, &args.metric);
+    if points.is_empty() {
+        return Err(anyhow!(
+            "No session of {} has {}",
+            args.history.display(),
+            args.metric
+        ));
+    }
+    let values: Vec<f64> = points.iter().map(|p| p.value).collect();
+    let changes = change_points(&values, args.significance, args.min_change);
+    match args.svg {
+        Some(ref path) => fs::write(path, svg_chart(&args.metric, unit, &points, &changes))
+            .with_context(|| format!("Could not write {}", path.display())),
+        None => {
+            print!("{}", text_chart(&args.metric, unit, &points, &changes));
+            Ok(())
+        }
+    }
+}
+
+#[test]
+fn test_trend() {
+    let milliseconds = [100, 120, 108, 0, 112, 104, 150, 152, 146, 154, 148];
+    let history: Value = milliseconds
+        .iter()
+        .enumerate()
+        .map(|(i, ms)| {
+            let metrics = if *ms == 0 {
+                serde_json::json!({})
+            } else {
+                serde_json::json!({"https://servo.org/Load": {"unit": "nanoseconds", "samples": [ms * 1_000_000]}})
+            };
+            serde_json::json!({
+                "session": format!("s{i:02}"),
+                "started": format!("2025-06-{:02}T12:00:00Z", i + 1),
+                "runs": [{"metrics": metrics}],
+            })
+        })
+        .collect();
+    let (points, unit) = points(&history, "Load");
+    assert_eq!(unit, "ms");
+    assert_eq!(points.len(), 10);
+    assert_eq!(points[3].value, 112.0);
+    let values: Vec<f64> = points.iter().map(|p| p.value).collect();
+    assert_eq!(change_points(&values, 0.05, 5.0), vec![5]);
+    assert_eq!(change_points(&values, 0.05, 50.0), Vec::<usize>::new());
+
+    let chart = text_chart("Load", unit, &points, &[5]);
+    let lines: Vec<&str> = chart.lines().collect();
+    assert!(lines[6].starts_with("s06") && lines[6].ends_with("<- change +37.9%"));
+    assert_eq!(
+        lines[11],
+        "First shift in session s06 started 2025-06-07T12:00:00Z"
+    );
+    let svg = svg_chart("Load", unit, &points, &[5]);
+    assert!(svg.starts_with("<svg") && svg.contains("+37.9%"));
+}
@@ -0,0 +1,159 @@
+//! The table of `--per-try` with one row per try and one column per metric
+use std::collections::{BTreeMap, BTreeSet};
+
+use log::{error, warn};
+use serde::Serialize;
+use time::OffsetDateTime;
+use yansi::Paint;
+
+use crate::{
+    args::ErrorPolicy,
+    session::{Cell, average_cells},
+    utils::RunResults,
+};
+
+/// The metrics of a single try
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct TryRow {
+    /// When the try started on the host, to correlate it with logs of other machines, i.e., power meters
+    #[serde(with = "time::serde::rfc3339")]
+    started: OffsetDateTime,
+    /// When the first trace of the try happened on the device, if we know the clock offset of the device
+    #[serde(with = "time::serde::rfc3339::option")]
+    trace_started: Option<OffsetDateTime>,
+    /// The errors of the try, i.e., `app-crash`, `None` if it had none
+    failure: Option<String>,
+    #[serde(rename = "metrics")]
+    cells: BTreeMap<String, Cell>,
+}
+
+impl TryRow {
+    /// The row of a try from its results
+    pub(crate) fn new(results: &RunResults, started: OffsetDateTime) -> TryRow {
+        let kinds: BTreeSet<String> = results
+            .errors
+            .values()
+            .flat_map(|counts| counts.keys())
+            .map(|kind| kind.to_string())
+            .collect();
+        TryRow {
+            started,
+            trace_started: results.trace_started,
+            failure: (!kinds.is_empty()).then(|| kinds.into_iter().collect::<Vec<_>>().join(", ")),
+            cells: average_cells(results)
+                .map(|(key, cell)| (key.clone(), cell))
+                .collect(),
+        }
+    }
+}
+
+impl TryRow {
+    /// Whether the try had any error
+    pub(crate) fn failed(&self) -> bool {
+        self.failure.is_some()
+    }
+}
+
+/// Whether the tries `rows` of the run of `url` fail the session under `policy`, logs the tries with errors
+pub(crate) fn fail_on_errors(policy: ErrorPolicy, url: &str, rows: &[TryRow]) -> bool {
+    let failed = rows.iter().filter(|r| r.failed()).count();
+    if failed == 0 {
+        return false;
+    }
+    let fail = match policy {
+        ErrorPolicy::Ignore => return false,
+        ErrorPolicy::Warn => false,
+        ErrorPolicy::FailAny => true,
+        ErrorPolicy::FailAll => failed == rows.len(),
+    };
+    if fail {
+        error!("{failed} of {} tries of {url} had errors", rows.len());
+    } else {
+        warn!("{failed} of {} tries of {url} had errors", rows.len());
+    }
+    fail
+}
+
+/// The header and the rows of the table, `-` for metrics a try did not produce
+fn lines(rows: &[TryRow]) -> Vec<Vec<String>> {
+    let metrics: BTreeSet<&String> = rows.iter().flat_map(|r| r.cells.keys()).collect();
+    let header = [String::from("try"), String::from("status")]
+        .into_iter()
+        .chain(metrics.iter().map(|m| m.to_string()))
+        .collect();
+    std::iter::once(header)
+        .chain(rows.iter().enumerate().map(|(i, row)| {
+            [
+                (i + 1).to_string(),
+                row.failure.clone().unwrap_or_else(|| String::from("ok")),
+            ]
+            .into_iter()
+            .chain(metrics.iter().map(|m| {
+                row.cells
+                    .get(*m)
+                    .map(|c| c.display(m))
+                    .unwrap_or_else(|| String::from("-"))
+            }))
+            .collect()
+        }))
+        .collect()
+}
+
+/// Prints the table with aligned columns and the failed tries in red
+pub(crate) fn print(rows: &[TryRow]) {
+    let lines = lines(rows);
+    let widths: Vec<usize> = (0..lines[0].len())
+        .map(|i| lines.iter().map(|l| l[i].len()).max().unwrap_or(0))
+        .collect();
+    println!("-----------Tries-------------------------");
+    for (line, row) in lines
+        .iter()
+        .zip(std::iter::once(None).chain(rows.iter().map(Some)))
+    {
+        let line: Vec<String> = line
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        let line = line.join(" | ");
+        if row.is_some_and(|r| r.failure.is_some()) {
+            println!("{}", line.trim_end().red());
+        } else {
+            println!("{}", line.trim_end());
+        }
+    }
+}
+
+#[test]
+fn test_try_table() {
+    use crate::utils::{ErrorKind, record_error};
+    use time::Duration;
+
+    let mut ok = RunResults::default();
+    ok.filter_results
+        .insert(String::from("Load"), vec![Duration::milliseconds(5)]);
+    let mut crashed = RunResults::default();
+    record_error(
+        &mut crashed.errors,
+        String::from("servo.org"),
+        ErrorKind::AppCrash,
+    );
+    let started = OffsetDateTime::UNIX_EPOCH;
+    let rows = [TryRow::new(&ok, started), TryRow::new(&crashed, started)];
+    assert_eq!(
+        lines(&rows),
+        [
+            ["try", "status", "Load"],
+            ["1", "ok", "5ms"],
+            ["2", "app-crash", "-"]
+        ]
+    );
+
+    let url = "servo.org";
+    assert!(!fail_on_errors(ErrorPolicy::FailAny, url, &rows[..1]));
+    assert!(fail_on_errors(ErrorPolicy::FailAny, url, &rows));
+    assert!(!fail_on_errors(ErrorPolicy::FailAll, url, &rows));
+    assert!(fail_on_errors(ErrorPolicy::FailAll, url, &rows[1..]));
+    assert!(!fail_on_errors(ErrorPolicy::Warn, url, &rows[1..]));
+    assert!(!fail_on_errors(ErrorPolicy::Ignore, url, &rows[1..]));
+}
@@ -0,0 +1,511 @@
+//! The interactive `--tui` mode showing the progress of the tries and the metrics while the session runs.
+//! The session runs on its own thread and reports to the TUI through a channel. The normal report is printed
+//! after the TUI was closed.
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, mpsc},
+    thread,
+};
+
+use anyhow::Result;
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    widgets::{Block, List, Paragraph, Row, Table, TableState},
+};
+use time::Duration;
+
+use crate::{
+    filter::TraceContext,
+    runconfig::RunConfig,
+    session::{Cell, average_cells},
+    shutdown,
+    trace::Trace,
+    utils::{RunResults, sparkline},
+};
+
+/// How many matched traces of a filter we keep for the drill-down
+const MAX_MATCHED_TRACES: usize = 200;
+
+/// How often we redraw while nothing happens
+const TICK: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// What the session tells the TUI
+#[derive(Debug)]
+enum Progress {
+    TryStarted {
+        url: String,
+        try_number: usize,
+        tries: usize,
+    },
+    TryFinished {
+        url: String,
+        results: Box<RunResults>,
+    },
+    /// The traces the filters of the last try matched, by filter name
+    Matched(BTreeMap<String, Vec<String>>),
+}
+
+/// Set while the TUI runs
+static PROGRESS: Mutex<Option<mpsc::Sender<Progress>>> = Mutex::new(None);
+
+/// Sends `progress` to the TUI, does nothing if it is not running
+fn report(progress: impl FnOnce() -> Progress) {
+    if let Some(sender) = PROGRESS.lock().unwrap().as_ref() {
+        // The TUI might already be closed because the user interrupted the session
+        let _ = sender.send(progress());
+    }
+}
+
+/// Tells the TUI that try `try_number` of `tries` of the run with `url` started
+pub(crate) fn try_started(url: &str, try_number: usize, tries: usize) {
+    report(|| Progress::TryStarted {
+        url: url.to_owned(),
+        try_number,
+        tries,
+    });
+}
+
+/// Tells the TUI about the results of a finished try
+pub(crate) fn try_finished(url: &str, results: &RunResults) {
+    report(|| Progress::TryFinished {
+        url: url.to_owned(),
+        results: Box::new(results.clone()),
+    });
+}
+
+/// Tells the TUI which traces the filters and point filters of `run_config` matched
+pub(crate) fn matched(run_config: &RunConfig, traces: &[Trace]) {
+    report(|| Progress::Matched(matched_traces(run_config, traces)));
+}
+
+/// The traces every filter and point filter of `run_config` matched, by name
+fn matched_traces(run_config: &RunConfig, traces: &[Trace]) -> BTreeMap<String, Vec<String>> {
+    let collect = |matches: &dyn Fn(&Trace) -> bool| -> Vec<String> {
+        traces
+            .iter()
+            .filter(|t| matches(t))
+            .take(MAX_MATCHED_TRACES)
+            .map(|t| format!("{t:?}"))
+            .collect()
+    };
+    let context = TraceContext::new(traces);
+    let filters = run_config.filters.iter().map(|f| {
+        (
+            f.name.clone(),
+            collect(&|t: &Trace| f.first.matches(t, &context) || f.last.matches(t, &context)),
+        )
+    });
+    let point_filters = run_config.point_filters.iter().map(|f| {
+        (
+            f.name.clone(),
+            collect(&|t: &Trace| t.function.contains(&f.match_str)),
+        )
+    });
+    filters.chain(point_filters).collect()
+}
+
+/// The column the metric table is sorted by
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum SortColumn {
+    #[default]
+    Name,
+    Tries,
+    Avg,
+    Min,
+    Max,
+}
+
+impl SortColumn {
+    fn next(self) -> SortColumn {
+        match self {
+            SortColumn::Name => SortColumn::Tries,
+            SortColumn::Tries => SortColumn::Avg,
+            SortColumn::Avg => SortColumn::Min,
+            SortColumn::Min => SortColumn::Max,
+            SortColumn::Max => SortColumn::Name,
+        }
+    }
+}
+
+/// A row of the metric table
+#[derive(Debug, PartialEq)]
+struct MetricRow<'a> {
+    name: &'a str,
+    values: &'a [Cell],
+    avg: Cell,
+    min: Cell,
+    max: Cell,
+}
+
+/// The average of cells of the same metric
+fn average(cells: &[Cell]) -> Cell {
+    let n = cells.len() as i64;
+    match cells[0] {
+        Cell::Duration(_) => Cell::Duration(
+            cells
+                .iter()
+                .map(|c| match c {
+                    Cell::Duration(d) => *d,
+                    _ => Duration::ZERO,
+                })
+                .sum::<Duration>()
+                / n as i32,
+        ),
+        Cell::Bytes(_) => Cell::Bytes(cells.iter().map(|c| c.value() as i64).sum::<i64>() / n),
+        Cell::Number(_) => Cell::Number(cells.iter().map(|c| c.value() as i64).sum::<i64>() / n),
+    }
+}
+
+/// What the user does with a key
+#[derive(Debug, PartialEq)]
+enum Action {
+    None,
+    Quit,
+    Interrupt,
+}
+
+/// The progress of a run
+#[derive(Debug)]
+struct RunProgress {
+    url: String,
+    current: usize,
+    finished: usize,
+    tries: usize,
+}
+
+/// The state of the TUI
+#[derive(Debug, Default)]
+struct App {
+    runs: Vec<RunProgress>,
+    /// The values of every metric, one per try
+    metrics: BTreeMap<String, Vec<Cell>>,
+    matched: BTreeMap<String, Vec<String>>,
+    sort: SortColumn,
+    descending: bool,
+    table: TableState,
+    /// The metric whose matched traces we show
+    drill_down: Option<String>,
+    scroll: u16,
+    finished: bool,
+}
+
+impl App {
+    fn update(&mut self, progress: Progress) {
+        match progress {
+            Progress::TryStarted {
+                url,
+                try_number,
+                tries,
+            } => match self.runs.iter_mut().find(|r| r.url == url) {
+                Some(run) => run.current = try_number,
+                None => self.runs.push(RunProgress {
+                    url,
+                    current: try_number,
+                    finished: 0,
+                    tries,
+                }),
+            },
+            Progress::TryFinished { url, results } => {
+                if let Some(run) = self.runs.iter_mut().find(|r| r.url == url) {
+                    run.finished += 1;
+                }
+                for (key, cell) in average_cells(&results) {
+                    self.metrics.entry(key.clone()).or_default().push(cell);
+                }
+            }
+            Progress::Matched(matched) => self.matched.extend(matched),
+        }
+    }
+
+    /// The metrics in the order of the sort column
+    fn rows(&self) -> Vec<MetricRow<'_>> {
+        let compare = |a: &&Cell, b: &&Cell| a.value().total_cmp(&b.value());
+        let mut rows: Vec<MetricRow> = self
+            .metrics
+            .iter()
+            .map(|(name, values)| MetricRow {
+                name,
+                values,
+                avg: average(values),
+                min: *values.iter().min_by(compare).expect("A metric has a value"),
+                max: *values.iter().max_by(compare).expect("A metric has a value"),
+            })
+            .collect();
+        rows.sort_by(|a, b| match self.sort {
+            SortColumn::Name => a.name.cmp(b.name),
+            SortColumn::Tries => a.values.len().cmp(&b.values.len()),
+            SortColumn::Avg => a.avg.value().total_cmp(&b.avg.value()),
+            SortColumn::Min => a.min.value().total_cmp(&b.min.value()),
+            SortColumn::Max => a.max.value().total_cmp(&b.max.value()),
+        });
+        if self.descending {
+            rows.reverse();
+        }
+        rows
+    }
+
+    /// The matched traces of a metric, metrics can carry a suffix after the filter name
+    fn matched_traces(&self, metric: &str) -> &[String] {
+        self.matched
+            .iter()
+            .filter(|(name, _)| metric.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, traces)| traces.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Action::Interrupt;
+        }
+        if self.drill_down.is_some() {
+            match key.code {
+                KeyCode::Esc | KeyCode::Backspace | KeyCode::Char('q') => self.drill_down = None,
+                KeyCode::Down | KeyCode::Char('j') => self.scroll = self.scroll.saturating_add(1),
+                KeyCode::Up | KeyCode::Char('k') => self.scroll = self.scroll.saturating_sub(1),
+                _ => {}
+            }
+            return Action::None;
+        }
+        match key.code {
+            KeyCode::Char('q') if self.finished => return Action::Quit,
+            KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+            KeyCode::Char('s') => self.sort = self.sort.next(),
+            KeyCode::Char('r') => self.descending = !self.descending,
+            KeyCode::Enter => {
+                let rows = self.rows();
+                if let Some(row) = self.table.selected().and_then(|i| rows.get(i)) {
+                    self.drill_down = Some(row.name.to_owned());
+                    self.scroll = 0;
+                }
+            }
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [progress_area, main_area, help_area] = Layout::vertical([
+            Constraint::Length(self.runs.len() as u16 + 2),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let progress: Vec<String> = self
+            .runs
+            .iter()
+            .map(|r| {
+                format!(
+                    "{}: {}/{} tries done, running try {}",
+                    r.url, r.finished, r.tries, r.current
+                )
+            })
+            .collect();
+        let title = if self.finished {
+            "Session finished"
+        } else {
+            "Session running"
+        };
+        frame.render_widget(
+            List::new(progress).block(Block::bordered().title(title)),
+            progress_area,
+        );
+
+        if let Some(ref metric) = self.drill_down {
+            let traces = self.matched_traces(metric).join("\n");
+            let traces = if traces.is_empty() {
+                String::from("No matched traces")
+            } else {
+                traces
+            };
+            frame.render_widget(
+                Paragraph::new(traces)
+                    .scroll((self.scroll, 0))
+                    .block(Block::bordered().title(format!("Traces of {metric}"))),
+                main_area,
+            );
+            frame.render_widget(
+                Paragraph::new("j/k scroll  esc back  ctrl-c interrupt"),
+                help_area,
+            );
+            return;
+        }
+
+        let sort = self.sort;
+        let header = Row::new(
+            [
+                (SortColumn::Name, "metric"),
+                (SortColumn::Tries, "tries"),
+                (SortColumn::Avg, "avg"),
+                (SortColumn::Min, "min"),
+                (SortColumn::Max, "max"),
+            ]
+            .map(|(column, name)| {
+                if column == sort {
+                    format!("{name}{}", if self.descending { "▼" } else { "▲" })
+                } else {
+                    name.to_owned()
+                }
+            })
+            .into_iter()
+            .chain(std::iter::once(String::from("per try"))),
+        )
+        .style(Style::new().add_modifier(Modifier::BOLD));
+        let rows: Vec<Row> = self
+            .rows()
+            .iter()
+            .map(|row| {
+                Row::new([
+                    row.name.to_owned(),
+                    row.values.len().to_string(),
+                    row.avg.display(row.name),
+                    row.min.display(row.name),
+                    row.max.display(row.name),
+                    sparkline(&row.values.iter().map(Cell::value).collect::<Vec<_>>()),
+                ])
+            })
+            .collect();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(3),
+                Constraint::Length(6),
+                Constraint::Length(14),
+                Constraint::Length(14),
+                Constraint::Length(14),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(header)
+        .block(Block::bordered().title("Metrics"))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, main_area, &mut self.table);
+        let help = if self.finished {
+            "j/k select  enter traces  s sort  r reverse  q show report"
+        } else {
+            "j/k select  enter traces  s sort  r reverse  ctrl-c interrupt"
+        };
+        frame.render_widget(Paragraph::new(help), help_area);
+    }
+
+    /// Draws and handles keys until the user quits after the session finished or interrupts it
+    fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        progress: &mpsc::Receiver<Progress>,
+        session: &thread::ScopedJoinHandle<'_, impl Send>,
+    ) -> Result<()> {
+        loop {
+            while let Ok(p) = progress.try_recv() {
+                self.update(p);
+            }
+            self.finished = session.is_finished();
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(TICK)?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                match self.handle_key(key) {
+                    Action::None => {}
+                    Action::Quit => return Ok(()),
+                    Action::Interrupt => {
+                        // Stopping the session might exit right away
+                        ratatui::restore();
+                        shutdown::request();
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs `session` on its own thread while showing its progress.
+/// Returns its result once it finished and the user closed the TUI.
+pub(crate) fn run<T: Send>(session: impl FnOnce() -> T + Send) -> Result<T> {
+    let (sender, receiver) = mpsc::channel();
+    *PROGRESS.lock().unwrap() = Some(sender);
+    let mut terminal = ratatui::init();
+    let result = thread::scope(|s| {
+        let session = s.spawn(session);
+        let ui = App::default().run(&mut terminal, &receiver, &session);
+        ratatui::restore();
+        ui.map(|()| session.join().expect("The session panicked"))
+    });
+    *PROGRESS.lock().unwrap() = None;
+    result
+}
+
+#[test]
+fn test_app() {
+    let results = |load: i64, resident: i64| {
+        let mut results = RunResults::default();
+        results
+            .filter_results
+            .insert(String::from("Load"), vec![Duration::milliseconds(load)]);
+        results.point_results.insert(
+            String::from("Resident"),
+            crate::utils::PointResult {
+                no_unit_conversion: true,
+                result: vec![resident],
+            },
+        );
+        results
+    };
+    let mut app = App::default();
+    app.update(Progress::TryStarted {
+        url: String::from("servo.org"),
+        try_number: 1,
+        tries: 2,
+    });
+    for (load, resident) in [(10, 300), (30, 100)] {
+        app.update(Progress::TryFinished {
+            url: String::from("servo.org"),
+            results: Box::new(results(load, resident)),
+        });
+    }
+    app.update(Progress::Matched(BTreeMap::from([(
+        String::from("Load"),
+        vec![String::from("trace")],
+    )])));
+    assert_eq!(app.runs[0].finished, 2);
+
+    let rows = app.rows();
+    assert_eq!(rows[0].name, "Load");
+    assert_eq!(rows[0].avg, Cell::Duration(Duration::milliseconds(20)));
+    assert_eq!(rows[0].min, Cell::Duration(Duration::milliseconds(10)));
+    assert_eq!(rows[1].max, Cell::Number(300));
+
+    // sorting by avg puts the 20ms load before the 200 resident
+    app.sort = SortColumn::Avg;
+    app.descending = true;
+    assert_eq!(app.rows()[0].name, "Resident");
+
+    app.table.select(Some(1));
+    assert_eq!(app.handle_key(KeyEvent::from(KeyCode::Enter)), Action::None);
+    assert_eq!(app.drill_down.as_deref(), Some("Load"));
+    assert_eq!(app.matched_traces("Load"), ["trace"]);
+    app.handle_key(KeyEvent::from(KeyCode::Esc));
+    assert_eq!(app.drill_down, None);
+    // the session still runs
+    assert_eq!(
+        app.handle_key(KeyEvent::from(KeyCode::Char('q'))),
+        Action::None
+    );
+    app.finished = true;
+    assert_eq!(
+        app.handle_key(KeyEvent::from(KeyCode::Char('q'))),
+        Action::Quit
+    );
+
+    let mut terminal = ratatui::Terminal::new(ratatui::backend::TestBackend::new(100, 20)).unwrap();
+    terminal.draw(|frame| app.draw(frame)).unwrap();
+    let screen = format!("{:?}", terminal.backend().buffer());
+    assert!(screen.contains("Session finished"));
+    assert!(screen.contains("Resident"));
+}
@@ -0,0 +1,201 @@
+//! The units metrics are displayed in on the console, set with `--unit`.
+//! The json output and bencher always keep nanoseconds and bytes, bencher rounds them with `--bencher-precision`.
+use std::{fmt::Display, str::FromStr, sync::OnceLock};
+
+use anyhow::{Result, anyhow};
+use time::Duration;
+
+use crate::utils::format_bytes;
+
+/// A unit to display a metric in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum DisplayUnit {
+    Nanoseconds,
+    Microseconds,
+    Milliseconds,
+    Seconds,
+    KiB,
+    MiB,
+    KB,
+    MB,
+    /// The plain number, nanoseconds for durations
+    Raw,
+}
+
+impl FromStr for DisplayUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "ns" => DisplayUnit::Nanoseconds,
+            "us" => DisplayUnit::Microseconds,
+            "ms" => DisplayUnit::Milliseconds,
+            "s" => DisplayUnit::Seconds,
+            "KiB" => DisplayUnit::KiB,
+            "MiB" => DisplayUnit::MiB,
+            "kB" => DisplayUnit::KB,
+            "MB" => DisplayUnit::MB,
+            "raw" => DisplayUnit::Raw,
+            _ => {
+                return Err(anyhow!(
+                    "Unknown unit `{s}`, expected one of ns, us, ms, s, KiB, MiB, kB, MB, raw"
+                ));
+            }
+        })
+    }
+}
+
+impl DisplayUnit {
+    /// The number of nanoseconds or bytes in one of this unit
+    pub(crate) fn step(self) -> i64 {
+        match self {
+            DisplayUnit::Nanoseconds | DisplayUnit::Raw => 1,
+            DisplayUnit::Microseconds | DisplayUnit::KB => 1_000,
+            DisplayUnit::Milliseconds | DisplayUnit::MB => 1_000_000,
+            DisplayUnit::Seconds => 1_000_000_000,
+            DisplayUnit::KiB => 1024,
+            DisplayUnit::MiB => 1024 * 1024,
+        }
+    }
+
+    /// `d` in this unit, `None` if this is not a unit of time
+    fn duration(self, d: Duration) -> Option<String> {
+        let ns = d.whole_nanoseconds();
+        Some(match self {
+            DisplayUnit::Nanoseconds => format!("{ns}ns"),
+            DisplayUnit::Microseconds => format!("{:.3}us", ns as f64 / 1e3),
+            DisplayUnit::Milliseconds => format!("{:.3}ms", ns as f64 / 1e6),
+            DisplayUnit::Seconds => format!("{:.3}s", ns as f64 / 1e9),
+            DisplayUnit::Raw => ns.to_string(),
+            _ => return None,
+        })
+    }
+
+    /// `bytes` in this unit, `None` if this is not a unit of memory
+    fn bytes(self, bytes: i64) -> Option<String> {
+        let scaled = |factor: f64, unit: &str| format!("{:.2} {unit}", bytes as f64 / factor);
+        Some(match self {
+            DisplayUnit::KiB => scaled(1024.0, "KiB"),
+            DisplayUnit::MiB => scaled(1024.0 * 1024.0, "MiB"),
+            DisplayUnit::KB => scaled(1e3, "kB"),
+            DisplayUnit::MB => scaled(1e6, "MB"),
+            DisplayUnit::Raw => bytes.to_string(),
+            _ => return None,
+        })
+    }
+}
+
+/// One `--unit`, `[METRIC=]UNIT`. Without a metric it applies to all metrics it fits.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct UnitSetting {
+    metric: Option<String>,
+    unit: DisplayUnit,
+}
+
+impl FromStr for UnitSetting {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (metric, unit) = match s.rsplit_once('=') {
+            Some((metric, unit)) => (Some(metric.to_owned()), unit),
+            None => (None, s),
+        };
+        Ok(UnitSetting {
+            metric,
+            unit: unit.parse()?,
+        })
+    }
+}
+
+impl Display for UnitSetting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(ref metric) = self.metric {
+            write!(f, "{metric}=")?;
+        }
+        write!(f, "{}", self.unit)
+    }
+}
+
+impl Display for DisplayUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = match self {
+            DisplayUnit::Nanoseconds => "ns",
+            DisplayUnit::Microseconds => "us",
+            DisplayUnit::Milliseconds => "ms",
+            DisplayUnit::Seconds => "s",
+            DisplayUnit::KiB => "KiB",
+            DisplayUnit::MiB => "MiB",
+            DisplayUnit::KB => "kB",
+            DisplayUnit::MB => "MB",
+            DisplayUnit::Raw => "raw",
+        };
+        write!(f, "{unit}")
+    }
+}
+
+impl UnitSetting {
+    /// Whether the setting is for the result `key`. Keys can be prefixed with the url of their run and suffixed
+    /// with the label of their navigation.
+    fn matches(&self, key: &str) -> bool {
+        let Some(ref metric) = self.metric else {
+            return true;
+        };
+        let name = key.rsplit_once(" (").map_or(key, |(name, _)| name);
+        [key, name]
+            .iter()
+            .any(|k| *k == metric || k.ends_with(&format!("/{metric}")))
+    }
+}
+
+static UNITS: OnceLock<Vec<UnitSetting>> = OnceLock::new();
+
+/// Sets the units from `--unit`
+pub(crate) fn set_units(units: Vec<UnitSetting>) {
+    let _ = UNITS.set(units);
+}
+
+/// The units that could apply to `key`, settings for the metric before the ones for all metrics
+fn units_for(key: &str) -> impl Iterator<Item = DisplayUnit> + '_ {
+    let units = UNITS.get().map(Vec::as_slice).unwrap_or_default();
+    let for_metric = units.iter().rev().filter(|u| u.metric.is_some());
+    let for_all = units.iter().rev().filter(|u| u.metric.is_none());
+    for_metric
+        .chain(for_all)
+        .filter(move |u| u.matches(key))
+        .map(|u| u.unit)
+}
+
+/// The duration of the metric `key` in its unit
+pub(crate) fn format_duration(key: &str, d: Duration) -> String {
+    units_for(key)
+        .find_map(|u| u.duration(d))
+        .unwrap_or_else(|| d.to_string())
+}
+
+/// The memory of the metric `key` in its unit
+pub(crate) fn format_memory(key: &str, bytes: i64) -> String {
+    units_for(key)
+        .find_map(|u| u.bytes(bytes))
+        .unwrap_or_else(|| format_bytes(bytes))
+}
+
+#[test]
+fn test_units() {
+    let d = Duration::microseconds(1500);
+    assert_eq!(DisplayUnit::Milliseconds.duration(d).unwrap(), "1.500ms");
+    assert_eq!(DisplayUnit::Seconds.duration(d).unwrap(), "0.002s");
+    assert_eq!(DisplayUnit::Raw.duration(d).unwrap(), "1500000");
+    assert_eq!(DisplayUnit::KiB.duration(d), None);
+    assert_eq!(DisplayUnit::MB.bytes(2_500_000).unwrap(), "2.50 MB");
+    assert_eq!(DisplayUnit::KiB.bytes(1536).unwrap(), "1.50 KiB");
+    assert_eq!(DisplayUnit::Milliseconds.bytes(1536), None);
+
+    let setting: UnitSetting = "Resident=MiB".parse().unwrap();
+    assert_eq!(setting.unit, DisplayUnit::MiB);
+    assert!(setting.matches("Resident"));
+    assert!(setting.matches("https://servo.org/Resident"));
+    assert!(setting.matches("Resident (reload 1)"));
+    assert!(!setting.matches("Peak Resident"));
+    assert!("ms".parse::<UnitSetting>().unwrap().matches("Load"));
+    assert!("Load=hours".parse::<UnitSetting>().is_err());
+}
@@ -1,6 +1,18 @@
-use std::{collections::HashMap, iter::Sum};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    iter::Sum,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use time::Duration;
+use humanize_bytes::humanize_bytes_binary;
+
+use log::warn;
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+
+use crate::{error_log::ErrorEntry, parser::TraceHeader, tries::TryRow};
 
 /// Nice struct for having average, minimum, maximum and number in a handle value
 pub(crate) struct AvgMingMax<T> {
@@ -30,23 +42,193 @@ where
     }
 }
 
+/// Cleared by `--no-humanize` to print raw numbers of bytes
+static HUMANIZE: AtomicBool = AtomicBool::new(true);
+
+/// Sets whether `format_bytes` uses binary units
+pub(crate) fn set_humanize(humanize: bool) {
+    HUMANIZE.store(humanize, Ordering::Relaxed);
+}
+
+/// Bytes in binary units, i.e., `1.5 KiB`, or the raw number with `--no-humanize`
+pub(crate) fn format_bytes(bytes: i64) -> String {
+    if HUMANIZE.load(Ordering::Relaxed) {
+        humanize_bytes_binary!(bytes).to_string()
+    } else {
+        bytes.to_string()
+    }
+}
+
+/// The bars of a sparkline from the smallest to the largest value
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A bar per value in order, scaled between the smallest and the largest value.
+/// Empty for fewer than two values as there is nothing to see.
+pub(crate) fn sparkline(values: &[f64]) -> String {
+    if values.len() < 2 {
+        return String::new();
+    }
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|v| {
+            let position = if max > min {
+                (v - min) / (max - min)
+            } else {
+                0.5
+            };
+            SPARKS[(position * (SPARKS.len() - 1) as f64).round() as usize]
+        })
+        .collect()
+}
+
+/// We only call a series drifting if at least this many tries in a row got steadily larger or smaller
+const MIN_DRIFT_TRIES: usize = 3;
+
+/// The tail of the tries of a metric whose values got steadily larger or smaller,
+/// i.e., because of thermal throttling or a leak.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Drift {
+    /// Index of the first try of the drifting tail
+    pub(crate) start: usize,
+    /// The relative change per try of the drifting tail, i.e., 0.05 if each try got 5% slower
+    pub(crate) coefficient: f64,
+}
+
+/// Finds the longest strictly monotone tail of `values`
+pub(crate) fn find_drift(values: &[f64]) -> Option<Drift> {
+    let monotone_tail = |cmp: fn(&f64, &f64) -> bool| {
+        values.len()
+            - values
+                .windows(2)
+                .rev()
+                .take_while(|w| cmp(&w[1], &w[0]))
+                .count()
+            - 1
+    };
+    if values.len() < MIN_DRIFT_TRIES {
+        return None;
+    }
+    let start = monotone_tail(f64::gt).min(monotone_tail(f64::lt));
+    let tail = &values[start..];
+    if tail.len() < MIN_DRIFT_TRIES {
+        return None;
+    }
+    // least squares slope relative to the mean of the tail
+    let n = tail.len() as f64;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = tail.iter().sum::<f64>() / n;
+    let (covariance, variance) = tail.iter().enumerate().fold((0.0, 0.0), |(c, v), (x, y)| {
+        let dx = x as f64 - mean_x;
+        (c + dx * (y - mean_y), v + dx * dx)
+    });
+    let coefficient = if mean_y == 0.0 {
+        0.0
+    } else {
+        covariance / variance / mean_y
+    };
+    Some(Drift { start, coefficient })
+}
+
+/// Warns about every metric whose tries drift and drops the drifting tail except its first try if `discard` is set
+fn check_drift<T>(key: &str, values: &mut Vec<T>, to_f64: impl Fn(&T) -> f64, discard: bool) {
+    let series: Vec<f64> = values.iter().map(to_f64).collect();
+    if let Some(drift) = find_drift(&series) {
+        warn!(
+            "{key} drifted by {:+.2}% per try over tries {}..={}, is the device throttling or leaking?",
+            drift.coefficient * 100.0,
+            drift.start + 1,
+            values.len()
+        );
+        if discard {
+            values.truncate(drift.start + 1);
+        }
+    }
+}
+
 /// Results for filters
-pub(crate) type FilterResults = HashMap<String, Vec<Duration>>;
-/// Errors we found in filters
-pub(crate) type FilterErrors = HashMap<String, u32>;
+pub(crate) type FilterResults = BTreeMap<String, Vec<Duration>>;
+/// The reason a filter or a try did not produce a result
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ErrorKind {
+    /// No trace matched the start of a filter
+    NoStartMarker,
+    /// No trace matched the end of a filter
+    NoEndMarker,
+    /// More than one trace matched the start or the end of a filter
+    AmbiguousMatch,
+    /// A trace matched but we could not parse it
+    ParseFailure,
+    /// The app did not start or crashed during a try
+    AppCrash,
+    /// hitrace dropped entries because the trace buffer was too small
+    TraceOverflow,
+    /// Other processes loaded the device too much during the try
+    Contaminated,
+    /// The value was outside of the expected range of the filter
+    OutOfRange,
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ErrorKind::NoStartMarker => "no-start-marker",
+            ErrorKind::NoEndMarker => "no-end-marker",
+            ErrorKind::AmbiguousMatch => "ambiguous-match",
+            ErrorKind::ParseFailure => "parse-failure",
+            ErrorKind::AppCrash => "app-crash",
+            ErrorKind::TraceOverflow => "trace-overflow",
+            ErrorKind::Contaminated => "contaminated",
+            ErrorKind::OutOfRange => "out-of-range",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl ErrorKind {
+    /// What went wrong, for people reading the error log
+    pub(crate) fn description(&self) -> &'static str {
+        match self {
+            ErrorKind::NoStartMarker => "No trace matched the start of the filter",
+            ErrorKind::NoEndMarker => "No trace matched the end of the filter",
+            ErrorKind::AmbiguousMatch => {
+                "More than one trace matched the start or the end of the filter"
+            }
+            ErrorKind::ParseFailure => "A trace matched but could not be parsed",
+            ErrorKind::AppCrash => "The app did not start or crashed during the try",
+            ErrorKind::TraceOverflow => {
+                "hitrace dropped entries because the trace buffer was too small"
+            }
+            ErrorKind::Contaminated => "Other processes loaded the device too much during the try",
+            ErrorKind::OutOfRange => "The value was outside of the expected range of the filter",
+        }
+    }
+}
+
+/// Number of errors per kind
+pub(crate) type ErrorCounts = BTreeMap<ErrorKind, u32>;
+/// Errors we found in filters and tries, by filter name or run url
+pub(crate) type FilterErrors = BTreeMap<String, ErrorCounts>;
+
+/// Counts one error of `kind` for `key`
+pub(crate) fn record_error(errors: &mut FilterErrors, key: String, kind: ErrorKind) {
+    *errors.entry(key).or_default().entry(kind).or_default() += 1;
+}
 /// Results for point filters
-pub(crate) type PointResults = HashMap<String, PointResult>;
+pub(crate) type PointResults = BTreeMap<String, PointResult>;
 
 /// Results for point filters
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct PointResult {
     pub(crate) no_unit_conversion: bool,
-    pub(crate) result: Vec<u64>,
+    pub(crate) result: Vec<i64>,
 }
 
 /// The results of a run given by filter.name, Vec<duration>
 /// Notice that not all vectors will have the same length as some runs might fail.
-#[derive(Debug)]
+#[derive(Clone, Debug, Default)]
 pub(crate) struct RunResults {
     /// A string that should be prepended to all names.
     pub(crate) prepend: Option<String>,
@@ -56,4 +238,232 @@ pub(crate) struct RunResults {
     pub(crate) errors: FilterErrors,
     /// Point Results
     pub(crate) point_results: PointResults,
+    /// Bencher measures that filters and point filters declared, by result key
+    pub(crate) measures: BTreeMap<String, String>,
+    /// Header of the last trace file we read
+    pub(crate) trace_header: Option<TraceHeader>,
+    /// Absolute time of the first trace of the last trace file we read, if we know the clock offset of the device
+    pub(crate) trace_started: Option<OffsetDateTime>,
+    /// The metrics of every try in order, for `--per-try`
+    pub(crate) tries: Vec<TryRow>,
+    /// The `--artifacts` directory of the last try
+    pub(crate) artifacts: Option<PathBuf>,
+    /// Every error of the tries with where it happened, for the `errors.json` of the session
+    pub(crate) error_log: Vec<ErrorEntry>,
+}
+
+impl RunResults {
+    /// Removes all metrics with fewer than `min_samples` samples and returns their keys and number of samples
+    pub(crate) fn remove_insufficient(&mut self, min_samples: usize) -> Vec<(String, usize)> {
+        let mut insufficient: Vec<(String, usize)> = self
+            .filter_results
+            .iter()
+            .map(|(key, durations)| (key, durations.len()))
+            .chain(
+                self.point_results
+                    .iter()
+                    .map(|(key, points)| (key, points.result.len())),
+            )
+            .filter(|(_, samples)| *samples < min_samples)
+            .map(|(key, samples)| (key.to_owned(), samples))
+            .collect();
+        insufficient.sort();
+        for (key, _) in &insufficient {
+            self.filter_results.remove(key);
+            self.point_results.remove(key);
+        }
+        insufficient
+    }
+
+    /// Appends all results of `other`, i.e., of a run config that ran on another device
+    pub(crate) fn merge(&mut self, other: RunResults) {
+        for (key, mut durations) in other.filter_results {
+            self.filter_results
+                .entry(key)
+                .or_default()
+                .append(&mut durations);
+        }
+        for (key, mut points) in other.point_results {
+            self.point_results
+                .entry(key)
+                .and_modify(|v| v.result.append(&mut points.result))
+                .or_insert(points);
+        }
+        for (key, counts) in other.errors {
+            let own = self.errors.entry(key).or_default();
+            for (kind, count) in counts {
+                *own.entry(kind).or_default() += count;
+            }
+        }
+        self.measures.extend(other.measures);
+        if other.trace_header.is_some() {
+            self.trace_header = other.trace_header;
+        }
+        self.tries.extend(other.tries);
+        if other.trace_started.is_some() {
+            self.trace_started = other.trace_started;
+        }
+        if other.artifacts.is_some() {
+            self.artifacts = other.artifacts;
+        }
+        self.error_log.extend(other.error_log);
+    }
+
+    /// The metrics of `other` that we have already, sorted
+    pub(crate) fn duplicate_keys(&self, other: &RunResults) -> Vec<String> {
+        let mut duplicates: Vec<String> = other
+            .filter_results
+            .keys()
+            .filter(|key| self.filter_results.contains_key(*key))
+            .chain(
+                other
+                    .point_results
+                    .keys()
+                    .filter(|key| self.point_results.contains_key(*key)),
+            )
+            .cloned()
+            .collect();
+        duplicates.sort();
+        duplicates
+    }
+
+    /// Renames every metric, its errors and its measure with `rename`
+    pub(crate) fn rename_keys(&mut self, rename: impl Fn(String) -> String) {
+        self.filter_results = std::mem::take(&mut self.filter_results)
+            .into_iter()
+            .map(|(k, v)| (rename(k), v))
+            .collect();
+        self.point_results = std::mem::take(&mut self.point_results)
+            .into_iter()
+            .map(|(k, v)| (rename(k), v))
+            .collect();
+        self.errors = std::mem::take(&mut self.errors)
+            .into_iter()
+            .map(|(k, v)| (rename(k), v))
+            .collect();
+        self.measures = std::mem::take(&mut self.measures)
+            .into_iter()
+            .map(|(k, v)| (rename(k), v))
+            .collect();
+    }
+
+    /// Warns about metrics that drift over the tries, see `find_drift`.
+    /// With `discard` only the first try of a drifting tail is kept.
+    pub(crate) fn check_drift(&mut self, discard: bool) {
+        for (key, durations) in self.filter_results.iter_mut() {
+            check_drift(key, durations, |d| d.as_seconds_f64(), discard);
+        }
+        for (key, points) in self.point_results.iter_mut() {
+            check_drift(key, &mut points.result, |v| *v as f64, discard);
+        }
+    }
+}
+
+#[test]
+fn test_duplicate_keys() {
+    let mut first = RunResults::default();
+    first
+        .filter_results
+        .insert(String::from("Load"), vec![Duration::milliseconds(1)]);
+    let mut second = first.clone();
+    second
+        .filter_results
+        .insert(String::from("Paint"), vec![Duration::milliseconds(2)]);
+    let duplicates = first.duplicate_keys(&second);
+    assert_eq!(duplicates, ["Load"]);
+    second.rename_keys(|key| {
+        if duplicates.contains(&key) {
+            format!("{key} #2")
+        } else {
+            key
+        }
+    });
+    assert!(first.duplicate_keys(&second).is_empty());
+    first.merge(second);
+    let mut keys: Vec<_> = first.filter_results.keys().collect();
+    keys.sort();
+    assert_eq!(keys, ["Load", "Load #2", "Paint"]);
+}
+
+#[test]
+fn test_format_bytes() {
+    assert_eq!(format_bytes(1536), "1.5 KiB");
+    set_humanize(false);
+    assert_eq!(format_bytes(1536), "1536");
+    set_humanize(true);
+}
+
+#[test]
+fn test_sparkline() {
+    assert_eq!(sparkline(&[1.0]), "");
+    assert_eq!(sparkline(&[1.0, 8.0, 4.5]), "▁█▅");
+    assert_eq!(sparkline(&[2.0, 2.0]), "▅▅");
+}
+
+#[test]
+fn test_find_drift() {
+    assert_eq!(find_drift(&[1.0, 2.0]), None);
+    assert_eq!(find_drift(&[3.0, 1.0, 2.0, 1.5]), None);
+    assert_eq!(
+        find_drift(&[100.0, 110.0, 120.0]),
+        Some(Drift {
+            start: 0,
+            coefficient: 10.0 / 110.0
+        })
+    );
+    // only the tail drifts
+    assert_eq!(find_drift(&[5.0, 1.0, 9.0, 8.0, 7.0]).unwrap().start, 2);
+}
+
+#[test]
+fn test_merge_results() {
+    let run = |value: i64| {
+        let mut results = RunResults::default();
+        results
+            .filter_results
+            .insert(String::from("Load"), vec![Duration::nanoseconds(value)]);
+        results.point_results.insert(
+            String::from("Resident"),
+            PointResult {
+                no_unit_conversion: false,
+                result: vec![value],
+            },
+        );
+        record_error(
+            &mut results.errors,
+            String::from("Load"),
+            ErrorKind::NoEndMarker,
+        );
+        results
+    };
+    let mut results = run(1);
+    results.merge(run(2));
+    assert_eq!(
+        results.filter_results["Load"],
+        vec![Duration::nanoseconds(1), Duration::nanoseconds(2)]
+    );
+    assert_eq!(results.point_results["Resident"].result, vec![1, 2]);
+    assert_eq!(results.errors["Load"][&ErrorKind::NoEndMarker], 2);
+}
+
+#[test]
+fn test_remove_insufficient() {
+    let mut results = RunResults::default();
+    results.filter_results.insert(
+        String::from("Load"),
+        vec![Duration::nanoseconds(1), Duration::nanoseconds(2)],
+    );
+    results.point_results.insert(
+        String::from("Resident"),
+        PointResult {
+            no_unit_conversion: false,
+            result: vec![1],
+        },
+    );
+    assert_eq!(
+        results.remove_insufficient(2),
+        vec![(String::from("Resident"), 1)]
+    );
+    assert!(results.point_results.is_empty());
+    assert_eq!(results.filter_results.len(), 1);
 }
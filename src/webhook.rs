@@ -0,0 +1,175 @@
+//! Notify a webhook with a summary of the results of a session
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result, anyhow};
+use serde::Serialize;
+use time::Duration;
+
+use crate::{
+    device_state::DeviceStates,
+    retries::RetryUsage,
+    utils::{ErrorCounts, RunResults, avg_min_max},
+};
+
+/// Average, minimum and maximum of one metric over all tries.
+/// Filter results are in nanoseconds, point results in the unit of the point.
+#[derive(Debug, PartialEq, Serialize)]
+struct MetricSummary {
+    avg: i128,
+    min: i128,
+    max: i128,
+    samples: u16,
+}
+
+/// The JSON body we post to the webhook
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct Summary {
+    prepend: Option<String>,
+    metrics: BTreeMap<String, MetricSummary>,
+    /// Number of failed tries per metric and kind of error
+    errors: BTreeMap<String, ErrorCounts>,
+    /// State of the devices before and after the session
+    device_states: DeviceStates,
+    /// How many tries were repeated of the `--max-total-retries`
+    retries: RetryUsage,
+    /// Metrics outside of their `--thresholds`, with why
+    violations: Vec<String>,
+    /// `expected_metrics` of the runs that are not in the results
+    missing_metrics: Vec<String>,
+}
+
+impl Summary {
+    /// Adds metrics that are outside of their thresholds
+    pub(crate) fn add_violations(&mut self, violations: Vec<String>) {
+        self.violations.extend(violations);
+    }
+
+    /// Sets the expected metrics the results lack
+    pub(crate) fn set_missing_metrics(&mut self, missing_metrics: Vec<String>) {
+        self.missing_metrics = missing_metrics;
+    }
+
+    /// Adds the state of the devices before and after the session
+    pub(crate) fn set_device_states(&mut self, device_states: DeviceStates) {
+        self.device_states = device_states;
+    }
+
+    /// Adds how much of the retry budget the session used
+    pub(crate) fn set_retries(&mut self, retries: RetryUsage) {
+        self.retries = retries;
+    }
+
+    /// Adds all metrics and errors of `results` to the summary
+    pub(crate) fn add(&mut self, results: &RunResults) {
+        self.prepend = results.prepend.clone();
+        for (key, durations) in results.filter_results.iter() {
+            let avg_min_max = avg_min_max::<Duration, u16>(durations);
+            self.metrics.insert(
+                key.to_owned(),
+                MetricSummary {
+                    avg: avg_min_max.avg.whole_nanoseconds(),
+                    min: avg_min_max.min.whole_nanoseconds(),
+                    max: avg_min_max.max.whole_nanoseconds(),
+                    samples: avg_min_max.number,
+                },
+            );
+        }
+        for (key, points) in results.point_results.iter() {
+            let avg_min_max = avg_min_max::<i64, i64>(&points.result);
+            self.metrics.insert(
+                key.to_owned(),
+                MetricSummary {
+                    avg: avg_min_max.avg.into(),
+                    min: avg_min_max.min.into(),
+                    max: avg_min_max.max.into(),
+                    samples: avg_min_max.number,
+                },
+            );
+        }
+        for (key, counts) in results.errors.iter() {
+            let own = self.errors.entry(key.to_owned()).or_default();
+            for (kind, count) in counts {
+                *own.entry(*kind).or_default() += count;
+            }
+        }
+    }
+}
+
+/// POSTs the summary as JSON to `url`. We use curl so we do not need a http client.
+pub(crate) fn post_summary(url: &str, summary: &Summary) -> Result<()> {
+    let curl = which::which("curl").context("Is curl in the path?")?;
+    let body = serde_json::to_vec(summary).context("Could not serialize summary")?;
+    let mut child = Command::new(curl)
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "-X",
+            "POST",
+            "-H",
+            "Content-Type: application/json",
+            "--data-binary",
+            "@-",
+            url,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .context("Could not start curl")?;
+    child
+        .stdin
+        .take()
+        .context("Could not open stdin of curl")?
+        .write_all(&body)
+        .context("Could not write summary to curl")?;
+    let status = child.wait().context("curl did not run")?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("Posting the summary to {url} failed with {status}"))
+    }
+}
+
+#[test]
+fn test_summary() {
+    let mut results = RunResults::default();
+    results.filter_results.insert(
+        String::from("Load"),
+        vec![Duration::nanoseconds(2), Duration::nanoseconds(4)],
+    );
+    crate::utils::record_error(
+        &mut results.errors,
+        String::from("Load"),
+        crate::utils::ErrorKind::AppCrash,
+    );
+    let mut summary = Summary::default();
+    summary.add(&results);
+    summary.add(&results);
+    assert_eq!(
+        summary.metrics["Load"],
+        MetricSummary {
+            avg: 3,
+            min: 2,
+            max: 4,
+            samples: 2
+        }
+    );
+    assert_eq!(
+        serde_json::to_value(&summary.errors).unwrap(),
+        serde_json::json!({"Load": {"app-crash": 2}})
+    );
+    summary.add_violations(vec![String::from("Load: 3.00 is outside of 0.00..=1.00")]);
+    assert_eq!(
+        serde_json::to_value(&summary).unwrap()["violations"],
+        serde_json::json!(["Load: 3.00 is outside of 0.00..=1.00"])
+    );
+    summary.set_missing_metrics(vec![String::from("Paint of https://servo.org")]);
+    assert_eq!(
+        serde_json::to_value(&summary).unwrap()["missing_metrics"],
+        serde_json::json!(["Paint of https://servo.org"])
+    );
+}